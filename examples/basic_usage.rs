@@ -1,10 +1,10 @@
-/// Basic usage example for the URL shortener API
-/// This example demonstrates how to interact with the URL shortener service
-/// using reqwest for HTTP requests.
-///
-/// To run this example:
-/// 1. Make sure the server is running: `cargo run`
-/// 2. In another terminal: `cargo run --example basic_usage`
+//! Basic usage example for the URL shortener API
+//! This example demonstrates how to interact with the URL shortener service
+//! using reqwest for HTTP requests.
+//!
+//! To run this example:
+//! 1. Make sure the server is running: `cargo run`
+//! 2. In another terminal: `cargo run --example basic_usage`
 
 use serde::{Deserialize, Serialize};
 
@@ -14,6 +14,7 @@ struct CreateUrlRequest {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct UrlResponse {
     id: i32,
     original_url: String,
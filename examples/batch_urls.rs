@@ -1,7 +1,7 @@
-/// Example: Creating multiple URLs in batch
-/// This demonstrates how to create multiple short URLs efficiently
-///
-/// Run with: cargo run --example batch_urls
+//! Example: Creating multiple URLs in batch
+//! This demonstrates how to create multiple short URLs efficiently
+//!
+//! Run with: cargo run --example batch_urls
 
 use serde::{Deserialize, Serialize};
 
@@ -11,6 +11,7 @@ struct CreateUrlRequest {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct UrlResponse {
     short_code: String,
     original_url: String,
@@ -24,7 +25,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Batch URL Creation Example ===\n");
 
     // List of URLs to shorten
-    let urls_to_shorten = vec![
+    let urls_to_shorten = [
         "https://www.rust-lang.org/",
         "https://docs.rs/",
         "https://crates.io/",
@@ -1,7 +1,7 @@
-/// Example: Checking server health and listing URLs
-/// Demonstrates how to verify server status and retrieve URL statistics
-///
-/// Run with: cargo run --example stats_check
+//! Example: Checking server health and listing URLs
+//! Demonstrates how to verify server status and retrieve URL statistics
+//!
+//! Run with: cargo run --example stats_check
 
 use serde::Deserialize;
 
@@ -13,6 +13,7 @@ struct HealthResponse {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct UrlEntry {
     id: i32,
     original_url: String,
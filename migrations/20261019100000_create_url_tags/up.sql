@@ -0,0 +1,8 @@
+CREATE TABLE url_tags (
+    url_id INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (url_id, tag),
+    FOREIGN KEY (url_id) REFERENCES urls(id)
+);
+
+CREATE INDEX idx_url_tags_tag ON url_tags (tag);
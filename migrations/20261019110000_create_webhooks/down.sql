@@ -0,0 +1,4 @@
+DROP INDEX idx_redirect_stats_url_id;
+DROP TABLE webhook_deliveries;
+DROP TABLE webhooks;
+ALTER TABLE urls DROP COLUMN expiry_notified;
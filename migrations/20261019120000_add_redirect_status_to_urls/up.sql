@@ -0,0 +1,2 @@
+-- NULL means the server-wide default from DEFAULT_REDIRECT_STATUS
+ALTER TABLE urls ADD COLUMN redirect_status INTEGER;
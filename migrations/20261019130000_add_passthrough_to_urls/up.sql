@@ -0,0 +1,3 @@
+-- Opt-in forwarding of the visitor's query string and of any path after the code
+ALTER TABLE urls ADD COLUMN forward_query BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE urls ADD COLUMN forward_path BOOLEAN NOT NULL DEFAULT 0;
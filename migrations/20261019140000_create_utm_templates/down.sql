@@ -0,0 +1,2 @@
+DROP TABLE tag_utm_templates;
+ALTER TABLE urls DROP COLUMN utm_template;
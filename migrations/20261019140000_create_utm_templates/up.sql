@@ -0,0 +1,8 @@
+-- UTM templates as JSON objects, set per link or per tag
+ALTER TABLE urls ADD COLUMN utm_template TEXT;
+
+CREATE TABLE tag_utm_templates (
+    tag TEXT PRIMARY KEY NOT NULL,
+    template TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+ALTER TABLE redirect_stats DROP COLUMN branch;
+ALTER TABLE urls DROP COLUMN platform_destinations;
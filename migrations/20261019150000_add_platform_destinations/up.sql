@@ -0,0 +1,5 @@
+-- Per-platform destinations as a JSON object, e.g. {"ios": "...", "android": "..."}
+ALTER TABLE urls ADD COLUMN platform_destinations TEXT;
+
+-- Which destination a click was sent to; NULL for clicks recorded before this column
+ALTER TABLE redirect_stats ADD COLUMN branch TEXT;
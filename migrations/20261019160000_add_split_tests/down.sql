@@ -0,0 +1,2 @@
+ALTER TABLE redirect_stats DROP COLUMN variant;
+ALTER TABLE urls DROP COLUMN split_test;
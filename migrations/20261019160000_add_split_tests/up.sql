@@ -0,0 +1,5 @@
+-- Weighted A/B destinations as JSON, e.g. {"variants": [{"name": "a", "url": "...", "weight": 3}], "sticky": true}
+ALTER TABLE urls ADD COLUMN split_test TEXT;
+
+-- Variant a click was sent to, for links with a split
+ALTER TABLE redirect_stats ADD COLUMN variant TEXT;
@@ -0,0 +1,2 @@
+-- Ordered conditional redirect rules as a JSON array
+ALTER TABLE urls ADD COLUMN rules TEXT;
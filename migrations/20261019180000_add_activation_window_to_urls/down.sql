@@ -0,0 +1,3 @@
+ALTER TABLE urls DROP COLUMN fallback_url;
+ALTER TABLE urls DROP COLUMN deactivates_at;
+ALTER TABLE urls DROP COLUMN activates_at;
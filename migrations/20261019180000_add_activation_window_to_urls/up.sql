@@ -0,0 +1,4 @@
+-- Optional activation window; outside it visitors see a coming-soon page or are sent to fallback_url
+ALTER TABLE urls ADD COLUMN activates_at TIMESTAMP;
+ALTER TABLE urls ADD COLUMN deactivates_at TIMESTAMP;
+ALTER TABLE urls ADD COLUMN fallback_url TEXT;
@@ -0,0 +1,3 @@
+-- Click-limited links: clicks_used is only counted while max_clicks is set
+ALTER TABLE urls ADD COLUMN max_clicks INTEGER;
+ALTER TABLE urls ADD COLUMN clicks_used INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+DROP TABLE interstitial_domains;
+ALTER TABLE urls DROP COLUMN interstitial;
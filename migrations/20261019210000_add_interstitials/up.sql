@@ -0,0 +1,8 @@
+-- Warning pages shown before redirecting, forced per link or per destination domain
+ALTER TABLE urls ADD COLUMN interstitial BOOLEAN NOT NULL DEFAULT 0;
+
+CREATE TABLE interstitial_domains (
+    domain TEXT PRIMARY KEY NOT NULL,
+    reason TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
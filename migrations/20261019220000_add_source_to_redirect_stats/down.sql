@@ -0,0 +1 @@
+ALTER TABLE redirect_stats DROP COLUMN source;
@@ -0,0 +1,2 @@
+-- How the visitor arrived, e.g. 'qr' for QR code scans
+ALTER TABLE redirect_stats ADD COLUMN source TEXT;
@@ -0,0 +1,4 @@
+DROP INDEX idx_urls_owner_id;
+ALTER TABLE api_keys DROP COLUMN user_id;
+ALTER TABLE urls DROP COLUMN owner_id;
+DROP TABLE users;
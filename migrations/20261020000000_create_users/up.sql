@@ -0,0 +1,11 @@
+-- User accounts. Links and API keys created before this have no owner.
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    username TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE urls ADD COLUMN owner_id INTEGER REFERENCES users (id);
+ALTER TABLE api_keys ADD COLUMN user_id INTEGER REFERENCES users (id);
+
+CREATE INDEX idx_urls_owner_id ON urls (owner_id);
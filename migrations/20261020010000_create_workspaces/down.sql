@@ -0,0 +1,4 @@
+DROP INDEX idx_urls_workspace_id;
+ALTER TABLE urls DROP COLUMN workspace_id;
+DROP TABLE workspace_members;
+DROP TABLE workspaces;
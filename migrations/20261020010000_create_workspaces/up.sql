@@ -0,0 +1,23 @@
+-- Workspaces own links; members act on them according to their role
+CREATE TABLE workspaces (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    default_code_length INTEGER,
+    default_expiry_secs BIGINT,
+    -- JSON array of domains links may point to; NULL allows any
+    allowed_domains TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE workspace_members (
+    workspace_id INTEGER NOT NULL REFERENCES workspaces (id),
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    role TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (workspace_id, user_id)
+);
+
+CREATE INDEX idx_workspace_members_user_id ON workspace_members (user_id);
+
+ALTER TABLE urls ADD COLUMN workspace_id INTEGER REFERENCES workspaces (id);
+CREATE INDEX idx_urls_workspace_id ON urls (workspace_id);
@@ -0,0 +1,10 @@
+-- Token buckets of the rate limiter when RATE_LIMIT_STORE=database, shared
+-- by every instance using this database
+CREATE TABLE rate_limit_buckets (
+    key TEXT PRIMARY KEY NOT NULL,
+    tokens DOUBLE NOT NULL,
+    -- Milliseconds since the Unix epoch
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX idx_rate_limit_buckets_updated_at ON rate_limit_buckets (updated_at);
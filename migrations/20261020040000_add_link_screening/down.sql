@@ -0,0 +1,2 @@
+DROP TABLE link_screenings;
+ALTER TABLE urls DROP COLUMN quarantined;
@@ -0,0 +1,19 @@
+-- Links whose destinations matched a local threat feed; they show a warning
+-- page instead of redirecting until an admin releases them
+ALTER TABLE urls ADD COLUMN quarantined BOOLEAN NOT NULL DEFAULT 0;
+
+-- Review queue of threat feed matches
+CREATE TABLE link_screenings (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    url_id INTEGER NOT NULL REFERENCES urls (id),
+    destination TEXT NOT NULL,
+    feed TEXT NOT NULL,
+    -- pending, confirmed or released
+    status TEXT NOT NULL DEFAULT 'pending',
+    flagged_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    reviewed_at TIMESTAMP,
+    reviewed_by TEXT
+);
+
+CREATE INDEX idx_link_screenings_url_id ON link_screenings (url_id);
+CREATE INDEX idx_link_screenings_status ON link_screenings (status);
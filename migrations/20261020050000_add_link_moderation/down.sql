@@ -0,0 +1,4 @@
+DROP TABLE moderation_actions;
+DROP TABLE link_reports;
+ALTER TABLE urls DROP COLUMN disabled_reason;
+ALTER TABLE urls DROP COLUMN disabled_at;
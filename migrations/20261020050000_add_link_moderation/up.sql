@@ -0,0 +1,34 @@
+-- Links switched off by a moderator; visitors get the suspended page
+ALTER TABLE urls ADD COLUMN disabled_at TIMESTAMP;
+ALTER TABLE urls ADD COLUMN disabled_reason TEXT;
+
+-- Abuse reports sent by visitors
+CREATE TABLE link_reports (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    url_id INTEGER NOT NULL REFERENCES urls (id),
+    reason TEXT NOT NULL,
+    reporter_ip TEXT,
+    -- open, actioned (the link was disabled) or dismissed
+    status TEXT NOT NULL DEFAULT 'open',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved_at TIMESTAMP,
+    resolved_by TEXT
+);
+
+CREATE INDEX idx_link_reports_url_id ON link_reports (url_id);
+CREATE INDEX idx_link_reports_status ON link_reports (status);
+
+-- Trail of reports and moderation decisions, kept after the link is deleted
+CREATE TABLE moderation_actions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    url_id INTEGER NOT NULL,
+    short_code TEXT NOT NULL,
+    -- reported, disabled, enabled or dismissed
+    action TEXT NOT NULL,
+    reason TEXT,
+    report_id INTEGER,
+    actor TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_moderation_actions_url_id ON moderation_actions (url_id);
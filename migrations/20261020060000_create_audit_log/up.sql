@@ -0,0 +1,31 @@
+-- Who changed or exported which link, when and from where
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Name of the API key, token subject or session user
+    actor TEXT NOT NULL,
+    actor_user_id INTEGER,
+    ip_address TEXT,
+    -- e.g. link.created, link.updated, link.deleted, link.disabled, clicks.exported
+    action TEXT NOT NULL,
+    url_id INTEGER,
+    short_code TEXT,
+    -- JSON of the link before and after the change
+    old_value TEXT,
+    new_value TEXT
+);
+
+CREATE INDEX idx_audit_log_created_at ON audit_log (created_at);
+CREATE INDEX idx_audit_log_short_code ON audit_log (short_code);
+CREATE INDEX idx_audit_log_actor ON audit_log (actor);
+
+-- Entries are never changed or removed
+CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
@@ -0,0 +1,235 @@
+// src/audit.rs
+// Append-only audit log of management actions.
+//
+// Every action that changes or exports links is recorded with who took it,
+// from which address and, for changes, the link before and after. Entries are
+// written in the same transaction as the action, so the log never misses a
+// change that was committed. The table refuses updates and deletes; it lives
+// in the main database and is therefore part of every database backup.
+
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Principal;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::models::{AuditEntry, NewAuditEntry, Url};
+use crate::utils::parse_timestamp;
+use crate::visitor::client_ip;
+
+pub const LINK_CREATED: &str = "link.created";
+pub const LINK_UPDATED: &str = "link.updated";
+pub const LINK_DELETED: &str = "link.deleted";
+pub const LINK_TRANSFERRED: &str = "link.transferred";
+pub const LINK_DISABLED: &str = "link.disabled";
+pub const LINK_ENABLED: &str = "link.enabled";
+pub const LINK_RELEASED: &str = "link.quarantine_released";
+pub const LINK_CONFIRMED: &str = "link.quarantine_confirmed";
+pub const CLICKS_EXPORTED: &str = "clicks.exported";
+
+/// Page size of the audit log API unless the request asks for another.
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 500;
+
+/// Who is acting, as recorded in the audit log.
+#[derive(Clone, Debug)]
+pub struct Actor {
+    /// Name of the API key, token subject or session user.
+    pub name: String,
+    pub user_id: Option<i32>,
+    pub ip_address: Option<String>,
+}
+
+impl Actor {
+    /// The operator running a command-line tool.
+    pub fn cli() -> Self {
+        Actor {
+            name: "cli".to_string(),
+            user_id: None,
+            ip_address: None,
+        }
+    }
+
+    /// A new entry for `action` taken by this actor.
+    pub fn entry(&self, action: &str) -> NewAuditEntry {
+        NewAuditEntry {
+            actor: self.name.clone(),
+            actor_user_id: self.user_id,
+            ip_address: self.ip_address.clone(),
+            action: action.to_string(),
+            url_id: None,
+            short_code: None,
+            old_value: None,
+            new_value: None,
+        }
+    }
+}
+
+/// Takes the authenticated principal, so it is only available on routes
+/// that require credentials.
+impl FromRequest for Actor {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let actor = req.extensions().get::<Principal>().map(|principal| Actor {
+            name: principal.name.clone(),
+            user_id: principal.user_id,
+            ip_address: client_ip(req),
+        });
+        ready(actor.ok_or_else(|| AppError::Unauthorized("an API key is required".to_string())))
+    }
+}
+
+impl NewAuditEntry {
+    /// The link the action concerns.
+    pub fn link(mut self, url_entry: &Url) -> Self {
+        self.url_id = Some(url_entry.id);
+        self.short_code = Some(url_entry.short_code.clone());
+        self
+    }
+
+    /// A link given by short code only.
+    pub fn short_code(mut self, code: Option<&str>) -> Self {
+        self.short_code = code.map(str::to_string);
+        self
+    }
+
+    pub fn before(mut self, value: &serde_json::Value) -> Self {
+        self.old_value = Some(value.to_string());
+        self
+    }
+
+    pub fn after(mut self, value: &serde_json::Value) -> Self {
+        self.new_value = Some(value.to_string());
+        self
+    }
+
+    pub fn record(&self, conn: &mut SqliteConnection) -> QueryResult<()> {
+        use crate::schema::audit_log;
+
+        diesel::insert_into(audit_log::table)
+            .values(self)
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    /// Only entries by this actor.
+    pub actor: Option<String>,
+    /// Only entries of this action, e.g. `link.updated`.
+    pub action: Option<String>,
+    /// Only entries concerning the link with this short code.
+    pub code: Option<String>,
+    /// Only entries at or after this RFC 3339 timestamp.
+    pub from: Option<String>,
+    /// Only entries before this RFC 3339 timestamp.
+    pub to: Option<String>,
+    /// Page number, starting at 1.
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+/// An entry as returned by the API, with the recorded values parsed back
+/// into JSON.
+#[derive(Serialize)]
+struct AuditEntryBody {
+    #[serde(flatten)]
+    entry: AuditEntry,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+}
+
+impl From<AuditEntry> for AuditEntryBody {
+    fn from(entry: AuditEntry) -> Self {
+        let parse = |value: &Option<String>| {
+            value
+                .as_deref()
+                .and_then(|value| serde_json::from_str(value).ok())
+        };
+        let before = parse(&entry.old_value);
+        let after = parse(&entry.new_value);
+        AuditEntryBody {
+            entry,
+            before,
+            after,
+        }
+    }
+}
+
+/// Handler for the audit log, newest first, one page at a time.
+pub async fn list_audit_log_handler(
+    pool: web::Data<DbPool>,
+    params: web::Query<AuditQuery>,
+) -> Result<HttpResponse, AppError> {
+    let params = params.into_inner();
+    let bound = |name: &str, value: Option<&str>| match value {
+        Some(value) => parse_timestamp(value).map(Some).ok_or_else(|| {
+            AppError::InvalidInput(format!("'{}' must be an RFC 3339 timestamp", name))
+        }),
+        None => Ok(None),
+    };
+    let from = bound("from", params.from.as_deref())?;
+    let to = bound("to", params.to.as_deref())?;
+    let page = params.page.unwrap_or(1);
+    let per_page = params.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if page < 1 {
+        return Err(AppError::InvalidInput(
+            "page must be at least 1".to_string(),
+        ));
+    }
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return Err(AppError::InvalidInput(format!(
+            "per_page must be between 1 and {}",
+            MAX_PER_PAGE
+        )));
+    }
+    let offset = (page - 1)
+        .checked_mul(per_page)
+        .ok_or_else(|| AppError::InvalidInput("page is too large".to_string()))?;
+
+    let (entries, total) = web::block(move || {
+        use crate::schema::audit_log::dsl::*;
+        let mut conn = pool.get()?;
+        let filtered = || {
+            let mut query = audit_log.into_boxed();
+            if let Some(wanted) = &params.actor {
+                query = query.filter(actor.eq(wanted.clone()));
+            }
+            if let Some(wanted) = &params.action {
+                query = query.filter(action.eq(wanted.clone()));
+            }
+            if let Some(wanted) = &params.code {
+                query = query.filter(short_code.eq(wanted.clone()));
+            }
+            if let Some(from) = from {
+                query = query.filter(created_at.ge(from));
+            }
+            if let Some(to) = to {
+                query = query.filter(created_at.lt(to));
+            }
+            query
+        };
+        let total: i64 = filtered().count().get_result(&mut conn)?;
+        let entries = filtered()
+            .order(id.desc())
+            .limit(per_page)
+            .offset(offset)
+            .load::<AuditEntry>(&mut conn)?;
+        Ok::<_, AppError>((entries, total))
+    })
+    .await??;
+
+    let entries: Vec<AuditEntryBody> = entries.into_iter().map(AuditEntryBody::from).collect();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "entries": entries,
+        "page": page,
+        "per_page": per_page,
+        "total": total,
+    })))
+}
@@ -0,0 +1,556 @@
+// src/auth.rs
+// API keys for the management endpoints.
+//
+// Keys look like `usk_<prefix>_<secret>`. Only the prefix, used to find the
+// row, and a SHA-256 hash of the whole key are stored, so a key is shown once
+// when it is created and cannot be recovered afterwards. Every request passes
+// through `authenticate`, which looks up the scope its route needs; redirects
+// and the other visitor-facing pages need none. Bearer tokens that are JWTs
+// are checked by `crate::jwt` instead.
+
+use std::{
+    fmt,
+    future::{ready, Ready},
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::{header, Method},
+    middleware::Next,
+    web, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    db::DbPool,
+    error::AppError,
+    jwt::{self, JwtVerifier},
+    models::{ApiKey, NewApiKey, User},
+    oidc,
+    utils::{generate_short_code, parse_timestamp},
+};
+
+/// Start of every key, so leaked keys are easy to recognize.
+pub const KEY_PREFIX: &str = "usk";
+const PREFIX_LENGTH: usize = 8;
+const SECRET_LENGTH: usize = 32;
+
+/// `last_used_at` is only written when it is older than this, so busy keys
+/// do not cause a write on every request.
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+/// What a key may do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// List links and read their settings.
+    #[serde(rename = "links:read")]
+    LinksRead,
+    /// Create, edit and delete links.
+    #[serde(rename = "links:write")]
+    LinksWrite,
+    /// Click statistics, the live event stream and click exports.
+    #[serde(rename = "stats:read")]
+    StatsRead,
+    /// Everything, including webhooks, interstitial domains, users and API
+    /// keys. Admins also see and manage the links of every user.
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::LinksRead,
+        Scope::LinksWrite,
+        Scope::StatsRead,
+        Scope::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::LinksRead => "links:read",
+            Scope::LinksWrite => "links:write",
+            Scope::StatsRead => "stats:read",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|scope| scope.as_str() == value)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The caller of a request, identified by an API key or a JWT. Stored in the
+/// request extensions by [`authenticate`], and available to handlers as an
+/// extractor.
+#[derive(Clone, Debug)]
+pub struct Principal {
+    /// API key the request was made with; `None` for JWTs and sessions.
+    pub key_id: Option<i32>,
+    /// Session of a user signed in through single sign-on.
+    pub session_id: Option<i32>,
+    /// Name of the key, subject of the token or user of the session.
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// User the key acts for.
+    pub user_id: Option<i32>,
+    /// Workspace named by a token, where links go unless the request names
+    /// another one.
+    pub workspace_id: Option<i32>,
+}
+
+impl Principal {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.is_admin() || self.scopes.contains(&scope)
+    }
+
+    /// Admins see and manage every link, whoever owns it.
+    pub fn is_admin(&self) -> bool {
+        self.scopes.contains(&Scope::Admin)
+    }
+}
+
+impl FromRequest for Principal {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Principal>()
+                .cloned()
+                .ok_or_else(|| AppError::Unauthorized("an API key is required".to_string())),
+        )
+    }
+}
+
+impl ApiKey {
+    pub fn scope_list(&self) -> Vec<Scope> {
+        serde_json::from_str(&self.scopes).unwrap_or_default()
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    pub fn is_expired(&self, now: NaiveDateTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    pub fn status(&self, now: NaiveDateTime) -> &'static str {
+        if self.is_revoked() {
+            "revoked"
+        } else if self.is_expired(now) {
+            "expired"
+        } else {
+            "active"
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "prefix": format!("{}_{}", KEY_PREFIX, self.prefix),
+            "scopes": self.scope_list(),
+            "status": self.status(Utc::now().naive_utc()),
+            "created_at": self.created_at,
+            "expires_at": self.expires_at,
+            "revoked_at": self.revoked_at,
+            "last_used_at": self.last_used_at,
+            "user_id": self.user_id
+        })
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Splits `usk_<prefix>_<secret>` and returns the prefix.
+fn key_prefix(key: &str) -> Option<&str> {
+    let rest = key.strip_prefix(KEY_PREFIX)?.strip_prefix('_')?;
+    let (prefix, secret) = rest.split_once('_')?;
+    (prefix.len() == PREFIX_LENGTH && secret.len() == SECRET_LENGTH).then_some(prefix)
+}
+
+/// Stores a new key and returns it along with the key itself, which is not
+/// kept anywhere.
+pub fn create_key(
+    conn: &mut SqliteConnection,
+    key_name: &str,
+    key_scopes: &[Scope],
+    key_expires_at: Option<NaiveDateTime>,
+    key_user_id: Option<i32>,
+) -> QueryResult<(ApiKey, String)> {
+    use crate::schema::api_keys::dsl::*;
+
+    let key_prefix = generate_short_code(PREFIX_LENGTH);
+    let key = format!(
+        "{}_{}_{}",
+        KEY_PREFIX,
+        key_prefix,
+        generate_short_code(SECRET_LENGTH)
+    );
+    let new_key = NewApiKey {
+        name: key_name.to_string(),
+        prefix: key_prefix.clone(),
+        key_hash: hash_key(&key),
+        scopes: serde_json::to_string(key_scopes).unwrap_or_default(),
+        expires_at: key_expires_at,
+        user_id: key_user_id,
+    };
+    diesel::insert_into(api_keys)
+        .values(&new_key)
+        .execute(conn)?;
+    let row = api_keys.filter(prefix.eq(&key_prefix)).first(conn)?;
+    Ok((row, key))
+}
+
+/// Looks up the active key `key` and notes that it was used.
+pub fn verify_key(conn: &mut SqliteConnection, key: &str) -> QueryResult<Option<ApiKey>> {
+    use crate::schema::api_keys::dsl::*;
+
+    let Some(key_prefix) = key_prefix(key) else {
+        return Ok(None);
+    };
+    let Some(row) = api_keys
+        .filter(prefix.eq(key_prefix))
+        .first::<ApiKey>(conn)
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let now = Utc::now().naive_utc();
+    if row.key_hash != hash_key(key) || row.is_revoked() || row.is_expired(now) {
+        return Ok(None);
+    }
+    let stale = row.last_used_at.is_none_or(|used| {
+        now.signed_duration_since(used).num_seconds() >= LAST_USED_RESOLUTION_SECS
+    });
+    if stale {
+        diesel::update(api_keys.find(row.id))
+            .set(last_used_at.eq(now))
+            .execute(conn)?;
+    }
+    Ok(Some(row))
+}
+
+/// The scope a request needs, by method and route pattern. `None` means the
+/// route is public.
+pub fn required_scope(method: &Method, pattern: &str) -> Option<Scope> {
+    let read = method == Method::GET || method == Method::HEAD;
+    match pattern {
+        "/" if read => Some(Scope::LinksRead),
+        "/" => Some(Scope::LinksWrite),
+        "/stats/{code}" | "/api/events" | "/api/export/clicks" => Some(Scope::StatsRead),
+        "/api/urls/{code}" => Some(Scope::LinksWrite),
+        "/api/urls/{code}/rules/dry-run" => Some(Scope::LinksRead),
+        "/api/tags/{tag}/utm" if read => Some(Scope::LinksRead),
+        "/api/tags/{tag}/utm" => Some(Scope::LinksWrite),
+        // Roles decide what members may do in a workspace.
+        pattern if pattern.starts_with("/api/workspaces") && read => Some(Scope::LinksRead),
+        pattern if pattern.starts_with("/api/workspaces") => Some(Scope::LinksWrite),
+        pattern if pattern.starts_with("/api/") => Some(Scope::Admin),
+        _ => None,
+    }
+}
+
+/// The key sent with `Authorization: Bearer <key>` or `X-API-Key: <key>`.
+fn presented_key(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+    if let Some(value) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+    {
+        let (scheme, token) = value.split_once(' ')?;
+        return scheme
+            .eq_ignore_ascii_case("bearer")
+            .then(|| token.trim().to_string());
+    }
+    headers
+        .get("X-API-Key")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+}
+
+/// Middleware requiring a key or token with the right scope on management
+/// routes.
+///
+/// Answers `401 Unauthorized` when the key is missing, unknown, expired or
+/// revoked, or the token does not verify, and `403 Forbidden` when it lacks
+/// the scope of the route.
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(scope) = req
+        .match_pattern()
+        .and_then(|pattern| required_scope(req.method(), &pattern))
+    else {
+        return next.call(req).await;
+    };
+    let key = presented_key(&req);
+    let session = req
+        .cookie(oidc::SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    if key.is_none() && session.is_none() {
+        return Err(AppError::Unauthorized("an API key is required".to_string()).into());
+    }
+    let pool = req
+        .app_data::<web::Data<DbPool>>()
+        .cloned()
+        .ok_or_else(|| AppError::InternalError("database pool missing".to_string()))?;
+    let verifier = req
+        .app_data::<web::Data<JwtVerifier>>()
+        .filter(|verifier| verifier.is_enabled())
+        .cloned();
+    let principal = match (key, verifier) {
+        (Some(key), Some(verifier)) if jwt::looks_like_jwt(&key) => {
+            let claims = verifier.verify(&key, Utc::now().timestamp())?;
+            web::block(move || {
+                let mut conn = pool.get()?;
+                jwt::principal_for(&mut conn, claims)
+            })
+            .await
+            .map_err(|err| AppError::InternalError(err.to_string()))??
+        },
+        (Some(key), _) => {
+            let row = web::block(move || {
+                let mut conn = pool.get()?;
+                verify_key(&mut conn, &key).map_err(AppError::from)
+            })
+            .await
+            .map_err(|err| AppError::InternalError(err.to_string()))??
+            .ok_or_else(|| {
+                AppError::Unauthorized("invalid, expired or revoked API key".to_string())
+            })?;
+            Principal {
+                key_id: Some(row.id),
+                session_id: None,
+                name: row.name.clone(),
+                scopes: row.scope_list(),
+                user_id: row.user_id,
+                workspace_id: None,
+            }
+        },
+        (None, _) => {
+            let token = session.unwrap_or_default();
+            web::block(move || {
+                let mut conn = pool.get()?;
+                oidc::session_principal(&mut conn, &token).map_err(AppError::from)
+            })
+            .await
+            .map_err(|err| AppError::InternalError(err.to_string()))??
+            .ok_or_else(|| {
+                AppError::Unauthorized("the session has expired; please sign in again".to_string())
+            })?
+        },
+    };
+    if !principal.has_scope(scope) {
+        let credential = match (principal.key_id, principal.session_id) {
+            (Some(_), _) => "API key",
+            (None, Some(_)) => "session",
+            (None, None) => "token",
+        };
+        return Err(
+            AppError::Forbidden(format!("this {} lacks the {} scope", credential, scope)).into(),
+        );
+    }
+    req.extensions_mut().insert(principal);
+    next.call(req).await
+}
+
+/// Checks the scopes of a new key: at least one, all known, no duplicates.
+pub fn parse_scopes(values: &[String]) -> Result<Vec<Scope>, AppError> {
+    if values.is_empty() {
+        return Err(AppError::InvalidInput(
+            "scopes must not be empty".to_string(),
+        ));
+    }
+    let mut scopes = Vec::new();
+    for value in values {
+        let scope = Scope::parse(value.trim())
+            .ok_or_else(|| AppError::InvalidInput(format!("unknown scope '{}'", value)))?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    Ok(scopes)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// RFC 3339 timestamp after which the key stops working.
+    pub expires_at: Option<String>,
+    /// User the key acts for.
+    pub user_id: Option<i32>,
+}
+
+/// Handler for creating a key. The key is only returned here.
+pub async fn create_api_key_handler(
+    pool: web::Data<DbPool>,
+    item: web::Json<CreateApiKeyRequest>,
+) -> Result<HttpResponse, AppError> {
+    let item = item.into_inner();
+    let name = item.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput("name must not be empty".to_string()));
+    }
+    let scopes = parse_scopes(&item.scopes)?;
+    let expires_at = match item.expires_at.as_deref() {
+        Some(value) => {
+            let parsed = parse_timestamp(value).ok_or_else(|| {
+                AppError::InvalidInput(format!("expires_at '{}' is not a timestamp", value))
+            })?;
+            if parsed <= Utc::now().naive_utc() {
+                return Err(AppError::InvalidInput(
+                    "expires_at must be in the future".to_string(),
+                ));
+            }
+            Some(parsed)
+        },
+        None => None,
+    };
+
+    let user_id = item.user_id;
+    let (row, key) = web::block(move || {
+        use crate::schema::users;
+        let mut conn = pool.get()?;
+        if let Some(user_id) = user_id {
+            users::table
+                .find(user_id)
+                .first::<User>(&mut conn)
+                .optional()?
+                .ok_or_else(|| {
+                    AppError::InvalidInput(format!("user {} does not exist", user_id))
+                })?;
+        }
+        create_key(&mut conn, &name, &scopes, expires_at, user_id).map_err(AppError::from)
+    })
+    .await??;
+
+    let mut body = row.to_json();
+    body["key"] = serde_json::Value::String(key);
+    Ok(HttpResponse::Created().json(body))
+}
+
+/// Handler for listing keys (without the keys themselves).
+pub async fn list_api_keys_handler(pool: web::Data<DbPool>) -> Result<HttpResponse, AppError> {
+    let keys = web::block(move || {
+        use crate::schema::api_keys::dsl::*;
+        let mut conn = pool.get()?;
+        api_keys
+            .order(id.asc())
+            .load::<ApiKey>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+    let body: Vec<serde_json::Value> = keys.iter().map(ApiKey::to_json).collect();
+    Ok(HttpResponse::Ok().json(body))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RotateApiKeyRequest {
+    /// How long the old key keeps working; it is revoked at once when
+    /// omitted or zero.
+    #[serde(default)]
+    pub grace_period_secs: u64,
+}
+
+/// Handler for replacing a key with a new one with the same name, scopes
+/// and expiry. The new key is only returned here.
+pub async fn rotate_api_key_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    item: Option<web::Json<RotateApiKeyRequest>>,
+) -> Result<HttpResponse, AppError> {
+    let key_id = path.into_inner();
+    let grace = item.map(|item| item.into_inner()).unwrap_or_default();
+    let grace = Duration::try_seconds(grace.grace_period_secs.min(i64::MAX as u64) as i64)
+        .ok_or_else(|| AppError::InvalidInput("grace_period_secs is too large".to_string()))?;
+
+    let (row, key) = web::block(move || {
+        use crate::schema::api_keys::dsl::*;
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let old = api_keys
+                .find(key_id)
+                .first::<ApiKey>(conn)
+                .optional()?
+                .ok_or_else(|| AppError::NotFound(format!("API key {}", key_id)))?;
+            if old.is_revoked() {
+                return Err(AppError::InvalidInput(format!(
+                    "API key {} is revoked",
+                    key_id
+                )));
+            }
+            let now = Utc::now().naive_utc();
+            let (row, key) = create_key(
+                conn,
+                &old.name,
+                &old.scope_list(),
+                old.expires_at,
+                old.user_id,
+            )?;
+            if grace.is_zero() {
+                diesel::update(api_keys.find(key_id))
+                    .set(revoked_at.eq(now))
+                    .execute(conn)?;
+            } else {
+                let until = now + grace;
+                let until = old.expires_at.map_or(until, |current| current.min(until));
+                diesel::update(api_keys.find(key_id))
+                    .set(expires_at.eq(until))
+                    .execute(conn)?;
+            }
+            Ok((row, key))
+        })
+    })
+    .await??;
+
+    let mut body = row.to_json();
+    body["key"] = serde_json::Value::String(key);
+    Ok(HttpResponse::Created().json(body))
+}
+
+/// Handler for revoking a key. Revoked keys stay listed.
+pub async fn revoke_api_key_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let key_id = path.into_inner();
+    web::block(move || {
+        use crate::schema::api_keys::dsl::*;
+        let mut conn = pool.get()?;
+        let updated = diesel::update(api_keys.find(key_id).filter(revoked_at.is_null()))
+            .set(revoked_at.eq(Utc::now().naive_utc()))
+            .execute(&mut conn)?;
+        if updated == 0
+            && api_keys
+                .find(key_id)
+                .first::<ApiKey>(&mut conn)
+                .optional()?
+                .is_none()
+        {
+            return Err(AppError::NotFound(format!("API key {}", key_id)));
+        }
+        Ok(())
+    })
+    .await??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
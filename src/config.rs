@@ -1,16 +1,280 @@
-use std::env;
+use std::{env, net::IpAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::{
+    oidc::{parse_group_roles, GroupRole},
+    ratelimit::{RateLimit, StoreKind},
+    redirect::RedirectStatus,
+    screening::{parse_feed_sources, FeedSource},
+};
 
 pub struct Config {
     pub database_url: String,
     pub base_url: String,
+    /// How often the webhook worker looks for due deliveries.
+    pub webhook_poll_interval: Duration,
+    /// Delay before the first webhook retry; doubled after every failure.
+    pub webhook_retry_base: Duration,
+    /// Attempts after which a webhook delivery is marked dead.
+    pub webhook_max_attempts: i32,
+    /// Status used by links that do not set their own.
+    pub default_redirect_status: RedirectStatus,
+    /// `max-age`, in seconds, sent with permanent (301/308) redirects.
+    pub redirect_cache_max_age: u64,
+    /// MaxMind country database used to locate visitors by IP address.
+    pub geoip_database: Option<PathBuf>,
+    /// Key signing the cookies of unlocked password-protected links. A random
+    /// key is generated when none is configured, so those cookies stop working
+    /// after a restart and are not accepted by other instances.
+    pub cookie_secret: String,
+    /// How long a visitor stays unlocked after entering a link's password.
+    pub password_cookie_ttl: Duration,
+    /// Failed password attempts a client may make per link within
+    /// `password_attempt_window`.
+    pub password_max_attempts: u32,
+    /// Failed password attempts all clients together may make per link
+    /// within `password_attempt_window`.
+    pub password_max_link_attempts: u32,
+    pub password_attempt_window: Duration,
+    /// PNG placed in the middle of QR codes requested with `logo=true`.
+    pub qr_logo: Option<PathBuf>,
+    /// Directory with custom error page templates.
+    pub error_pages_dir: Option<PathBuf>,
+    /// Shared secret of HS256 bearer tokens.
+    pub jwt_secret: Option<String>,
+    /// PEM file with the RSA or P-256 public key of RS256 or ES256 tokens.
+    pub jwt_public_key: Option<PathBuf>,
+    /// JWKS file with the keys of bearer tokens, read again when it changes.
+    pub jwt_jwks_file: Option<PathBuf>,
+    /// Required `iss` claim of bearer tokens.
+    pub jwt_issuer: Option<String>,
+    /// Required `aud` claim of bearer tokens.
+    pub jwt_audience: Option<String>,
+    /// Clock skew tolerated when checking `exp` and `nbf`.
+    pub jwt_leeway: Duration,
+    /// Claim holding the username of a token's bearer.
+    pub jwt_user_claim: String,
+    /// Claim holding the id or name of a token's workspace.
+    pub jwt_workspace_claim: String,
+    /// OpenID Connect provider people sign in with; single sign-on is off
+    /// unless both it and `oidc_client_id` are set.
+    pub oidc_issuer: Option<String>,
+    pub oidc_client_id: Option<String>,
+    /// Secret of confidential clients; public clients rely on PKCE alone.
+    pub oidc_client_secret: Option<String>,
+    /// Where the provider sends browsers back; `{base_url}/auth/callback`
+    /// when not set.
+    pub oidc_redirect_url: Option<String>,
+    /// Scopes asked of the provider.
+    pub oidc_scopes: String,
+    /// ID token claim holding the username of people signing in.
+    pub oidc_username_claim: String,
+    /// ID token claim listing the groups of people signing in.
+    pub oidc_groups_claim: String,
+    /// Workspace roles given to members of provider groups.
+    pub oidc_group_roles: Vec<GroupRole>,
+    /// Provider groups whose members get the admin scope.
+    pub oidc_admin_groups: Vec<String>,
+    /// How long a sign-in lasts.
+    pub session_ttl: Duration,
+    /// Links each API key, token user or session user may create.
+    pub rate_limit_create_per_key: Option<RateLimit>,
+    /// Links each client IP address may create.
+    pub rate_limit_create_per_ip: Option<RateLimit>,
+    /// Redirects, previews and QR codes each client IP address may request.
+    pub rate_limit_redirect_per_ip: Option<RateLimit>,
+    /// Abuse reports each client IP address may send.
+    pub rate_limit_report_per_ip: Option<RateLimit>,
+    /// Where rate limit buckets are kept.
+    pub rate_limit_store: StoreKind,
+    /// Addresses of the reverse proxies in front of the service. Client
+    /// addresses are taken from `Forwarded` or `X-Forwarded-For` only on
+    /// connections from these.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Domains links may not point to, one pattern per line.
+    pub domain_blocklist_file: Option<PathBuf>,
+    /// When set, the only domains links may point to.
+    pub domain_allowlist_file: Option<PathBuf>,
+    /// How often the domain list files are checked for changes.
+    pub domain_list_reload_interval: Duration,
+    /// Local threat feeds link destinations are screened against.
+    pub threat_feeds: Vec<FeedSource>,
+    /// How often every link is screened again, after reloading changed feeds.
+    pub threat_scan_interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            database_url: String::new(),
+            base_url: "http://localhost:8080".to_string(),
+            webhook_poll_interval: Duration::from_secs(5),
+            webhook_retry_base: Duration::from_secs(30),
+            webhook_max_attempts: 8,
+            default_redirect_status: RedirectStatus::Found,
+            redirect_cache_max_age: 3600,
+            geoip_database: None,
+            cookie_secret: rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect(),
+            password_cookie_ttl: Duration::from_secs(30 * 60),
+            password_max_attempts: 5,
+            password_max_link_attempts: 50,
+            password_attempt_window: Duration::from_secs(15 * 60),
+            qr_logo: None,
+            error_pages_dir: None,
+            jwt_secret: None,
+            jwt_public_key: None,
+            jwt_jwks_file: None,
+            jwt_issuer: None,
+            jwt_audience: None,
+            jwt_leeway: Duration::from_secs(60),
+            jwt_user_claim: "sub".to_string(),
+            jwt_workspace_claim: "workspace".to_string(),
+            oidc_issuer: None,
+            oidc_client_id: None,
+            oidc_client_secret: None,
+            oidc_redirect_url: None,
+            oidc_scopes: "openid profile email".to_string(),
+            oidc_username_claim: "email".to_string(),
+            oidc_groups_claim: "groups".to_string(),
+            oidc_group_roles: Vec::new(),
+            oidc_admin_groups: Vec::new(),
+            session_ttl: Duration::from_secs(8 * 60 * 60),
+            rate_limit_create_per_key: None,
+            rate_limit_create_per_ip: None,
+            rate_limit_redirect_per_ip: None,
+            rate_limit_report_per_ip: None,
+            rate_limit_store: StoreKind::Memory,
+            trusted_proxies: Vec::new(),
+            domain_blocklist_file: None,
+            domain_allowlist_file: None,
+            domain_list_reload_interval: Duration::from_secs(60),
+            threat_feeds: Vec::new(),
+            threat_scan_interval: Duration::from_secs(3600),
+        }
+    }
 }
 
 impl Config {
     /// Loads configuration from environment variables.
     /// Panics if DATABASE_URL is not set.
     pub fn from_env() -> Self {
+        let defaults = Config::default();
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let base_url = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
-        Config { database_url, base_url }
+        let base_url = env::var("BASE_URL").unwrap_or(defaults.base_url);
+        Config {
+            database_url,
+            base_url,
+            webhook_poll_interval: env_secs("WEBHOOK_POLL_INTERVAL_SECS")
+                .unwrap_or(defaults.webhook_poll_interval),
+            webhook_retry_base: env_secs("WEBHOOK_RETRY_BASE_SECS")
+                .unwrap_or(defaults.webhook_retry_base),
+            webhook_max_attempts: env_parse("WEBHOOK_MAX_ATTEMPTS")
+                .unwrap_or(defaults.webhook_max_attempts),
+            default_redirect_status: env_parse::<u16>("DEFAULT_REDIRECT_STATUS")
+                .map(|code| {
+                    RedirectStatus::from_code(code).unwrap_or_else(|| {
+                        panic!("DEFAULT_REDIRECT_STATUS must be one of 301, 302, 307 or 308")
+                    })
+                })
+                .unwrap_or(defaults.default_redirect_status),
+            redirect_cache_max_age: env_parse("REDIRECT_CACHE_MAX_AGE_SECS")
+                .unwrap_or(defaults.redirect_cache_max_age),
+            geoip_database: env::var("GEOIP_DATABASE").ok().map(PathBuf::from),
+            cookie_secret: env::var("COOKIE_SECRET").unwrap_or(defaults.cookie_secret),
+            password_cookie_ttl: env_secs("PASSWORD_COOKIE_TTL_SECS")
+                .unwrap_or(defaults.password_cookie_ttl),
+            password_max_attempts: env_parse("PASSWORD_MAX_ATTEMPTS")
+                .unwrap_or(defaults.password_max_attempts),
+            password_max_link_attempts: env_parse("PASSWORD_MAX_LINK_ATTEMPTS")
+                .unwrap_or(defaults.password_max_link_attempts),
+            password_attempt_window: env_secs("PASSWORD_ATTEMPT_WINDOW_SECS")
+                .unwrap_or(defaults.password_attempt_window),
+            qr_logo: env::var("QR_LOGO").ok().map(PathBuf::from),
+            error_pages_dir: env::var("ERROR_PAGES_DIR").ok().map(PathBuf::from),
+            jwt_secret: env::var("JWT_SECRET").ok(),
+            jwt_public_key: env::var("JWT_PUBLIC_KEY").ok().map(PathBuf::from),
+            jwt_jwks_file: env::var("JWT_JWKS_FILE").ok().map(PathBuf::from),
+            jwt_issuer: env::var("JWT_ISSUER").ok(),
+            jwt_audience: env::var("JWT_AUDIENCE").ok(),
+            jwt_leeway: env_secs("JWT_LEEWAY_SECS").unwrap_or(defaults.jwt_leeway),
+            jwt_user_claim: env::var("JWT_USER_CLAIM").unwrap_or(defaults.jwt_user_claim),
+            jwt_workspace_claim: env::var("JWT_WORKSPACE_CLAIM")
+                .unwrap_or(defaults.jwt_workspace_claim),
+            oidc_issuer: env::var("OIDC_ISSUER").ok(),
+            oidc_client_id: env::var("OIDC_CLIENT_ID").ok(),
+            oidc_client_secret: env::var("OIDC_CLIENT_SECRET").ok(),
+            oidc_redirect_url: env::var("OIDC_REDIRECT_URL").ok(),
+            oidc_scopes: env::var("OIDC_SCOPES").unwrap_or(defaults.oidc_scopes),
+            oidc_username_claim: env::var("OIDC_USERNAME_CLAIM")
+                .unwrap_or(defaults.oidc_username_claim),
+            oidc_groups_claim: env::var("OIDC_GROUPS_CLAIM").unwrap_or(defaults.oidc_groups_claim),
+            oidc_group_roles: env::var("OIDC_GROUP_ROLES")
+                .map(|value| {
+                    parse_group_roles(&value)
+                        .unwrap_or_else(|err| panic!("OIDC_GROUP_ROLES is invalid: {}", err))
+                })
+                .unwrap_or(defaults.oidc_group_roles),
+            oidc_admin_groups: env::var("OIDC_ADMIN_GROUPS")
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|group| !group.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or(defaults.oidc_admin_groups),
+            session_ttl: env_secs("SESSION_TTL_SECS").unwrap_or(defaults.session_ttl),
+            rate_limit_create_per_key: env_parse("RATE_LIMIT_CREATE_PER_KEY"),
+            rate_limit_create_per_ip: env_parse("RATE_LIMIT_CREATE_PER_IP"),
+            rate_limit_redirect_per_ip: env_parse("RATE_LIMIT_REDIRECT_PER_IP"),
+            rate_limit_report_per_ip: env_parse("RATE_LIMIT_REPORT_PER_IP"),
+            rate_limit_store: env_parse("RATE_LIMIT_STORE").unwrap_or(defaults.rate_limit_store),
+            trusted_proxies: env::var("TRUSTED_PROXIES")
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|address| !address.is_empty())
+                        .map(|address| {
+                            address.parse().unwrap_or_else(|_| {
+                                panic!("TRUSTED_PROXIES has an invalid address: {}", address)
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or(defaults.trusted_proxies),
+            domain_blocklist_file: env::var("DOMAIN_BLOCKLIST_FILE").ok().map(PathBuf::from),
+            domain_allowlist_file: env::var("DOMAIN_ALLOWLIST_FILE").ok().map(PathBuf::from),
+            domain_list_reload_interval: env_secs("DOMAIN_LIST_RELOAD_SECS")
+                .unwrap_or(defaults.domain_list_reload_interval),
+            threat_feeds: env::var("THREAT_FEEDS")
+                .map(|value| {
+                    parse_feed_sources(&value)
+                        .unwrap_or_else(|err| panic!("THREAT_FEEDS is invalid: {}", err))
+                })
+                .unwrap_or(defaults.threat_feeds),
+            threat_scan_interval: env_secs("THREAT_SCAN_INTERVAL_SECS")
+                .unwrap_or(defaults.threat_scan_interval),
+        }
     }
 }
+
+/// Reads and parses an optional environment variable.
+/// Panics if it is set but cannot be parsed, so typos are caught at startup.
+fn env_parse<T: FromStr>(name: &str) -> Option<T> {
+    let value = env::var(name).ok()?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => panic!("{} has an invalid value: {}", name, value),
+    }
+}
+
+fn env_secs(name: &str) -> Option<Duration> {
+    env_parse::<u64>(name).map(Duration::from_secs)
+}
@@ -1,11 +1,50 @@
+use std::time::Duration;
+
 use diesel::prelude::*;
-use diesel::r2d2::{self, ConnectionManager};
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
 pub type DbPool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
 
+/// Migrations from the `migrations/` directory, compiled into the binary.
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// How long a write waits for another connection's write lock.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Per-connection settings applied when the pool opens a connection.
+#[derive(Debug)]
+struct ConnectionOptions {
+    busy_timeout: Duration,
+}
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        // Concurrent writers, from this process or another worker sharing the
+        // database file, wait for the lock instead of failing with SQLITE_BUSY.
+        diesel::sql_query(format!(
+            "PRAGMA busy_timeout = {}",
+            self.busy_timeout.as_millis()
+        ))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(r2d2::Error::QueryError)
+    }
+}
+
 pub fn establish_connection_pool(database_url: &str) -> DbPool {
     let manager = ConnectionManager::<SqliteConnection>::new(database_url);
     r2d2::Pool::builder()
+        .connection_customizer(Box::new(ConnectionOptions {
+            busy_timeout: BUSY_TIMEOUT,
+        }))
         .build(manager)
         .expect("Failed to create pool.")
 }
+
+/// Applies any migrations that have not yet been run against the database.
+pub fn run_migrations(pool: &DbPool) {
+    let mut conn = pool.get().expect("Couldn't get db connection from pool");
+    conn.run_pending_migrations(MIGRATIONS)
+        .expect("Failed to run database migrations");
+}
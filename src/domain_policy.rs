@@ -0,0 +1,245 @@
+// src/domain_policy.rs
+// Server-wide blocklist and allowlist of destination domains.
+//
+// Both lists are files with one pattern per line, read at startup and read
+// again whenever they change, so abusive domains can be blocked without a
+// restart. Destinations are checked when links are created or edited, and
+// again on every redirect so existing links to a newly blocked domain stop
+// working.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use actix_web::{rt::time::interval, web};
+use regex::Regex;
+
+use crate::config::Config;
+
+/// One line of a domain list:
+/// - `example.com` matches that host only;
+/// - `*.example.com` or `.example.com` match it and all its subdomains;
+/// - `regex:<pattern>` matches hosts the pattern matches in full.
+#[derive(Clone, Debug)]
+pub enum DomainPattern {
+    Exact(String),
+    Suffix(String),
+    Regex(Regex),
+}
+
+impl FromStr for DomainPattern {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        if let Some(pattern) = line.strip_prefix("regex:") {
+            return Regex::new(&format!("^(?:{})$", pattern.trim()))
+                .map(DomainPattern::Regex)
+                .map_err(|err| format!("invalid regex '{}': {}", pattern.trim(), err));
+        }
+        let domain = line.trim_end_matches('.').to_ascii_lowercase();
+        let (suffix, domain) = match domain
+            .strip_prefix("*.")
+            .or_else(|| domain.strip_prefix('.'))
+        {
+            Some(parent) => (true, parent.to_string()),
+            None => (false, domain.clone()),
+        };
+        if domain.is_empty() || domain.contains(['/', ':', '*', ' ']) {
+            return Err(format!("'{}' is not a domain", line));
+        }
+        Ok(if suffix {
+            DomainPattern::Suffix(domain)
+        } else {
+            DomainPattern::Exact(domain)
+        })
+    }
+}
+
+impl DomainPattern {
+    /// Whether `host` (lowercase, without a trailing dot) matches.
+    pub fn matches(&self, host: &str) -> bool {
+        match self {
+            DomainPattern::Exact(domain) => host == domain,
+            DomainPattern::Suffix(domain) => {
+                host == domain
+                    || host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            },
+            DomainPattern::Regex(regex) => regex.is_match(host),
+        }
+    }
+}
+
+/// The patterns of a list file. Blank lines and lines starting with `#`
+/// are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct DomainList {
+    patterns: Vec<DomainPattern>,
+}
+
+impl DomainList {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let patterns = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(index, line)| {
+                line.parse()
+                    .map_err(|err| format!("line {}: {}", index + 1, err))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(DomainList { patterns })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("cannot read {}: {}", path.display(), err))?;
+        DomainList::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.matches(host))
+    }
+}
+
+/// A list and the file it is read from.
+struct ListFile {
+    path: PathBuf,
+    list: RwLock<DomainList>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+pub(crate) fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+impl ListFile {
+    fn open(path: &Path) -> Result<Self, String> {
+        let modified = modified(path);
+        Ok(ListFile {
+            path: path.to_path_buf(),
+            list: RwLock::new(DomainList::load(path)?),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// Reads the file again if it changed. A file that became unreadable or
+    /// invalid keeps its previous patterns.
+    fn reload(&self) {
+        let current = modified(&self.path);
+        let mut loaded = self.modified.lock().unwrap();
+        if *loaded == current {
+            return;
+        }
+        match DomainList::load(&self.path) {
+            Ok(list) => {
+                log::info!(
+                    "Reloaded {} domain patterns from {}",
+                    list.len(),
+                    self.path.display()
+                );
+                *self.list.write().unwrap() = list;
+                *loaded = current;
+            },
+            Err(err) => log::warn!("Keeping the previous domain list: {}", err),
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        self.list.read().unwrap().matches(host)
+    }
+}
+
+/// Which destinations links may point to. With neither list configured,
+/// every destination is accepted.
+#[derive(Default)]
+pub struct DomainPolicy {
+    blocklist: Option<ListFile>,
+    allowlist: Option<ListFile>,
+}
+
+impl DomainPolicy {
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let open = |path: &Option<PathBuf>| path.as_deref().map(ListFile::open).transpose();
+        Ok(DomainPolicy {
+            blocklist: open(&config.domain_blocklist_file)?,
+            allowlist: open(&config.domain_allowlist_file)?,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.blocklist.is_some() || self.allowlist.is_some()
+    }
+
+    /// Rejects a destination on a blocked domain, or, when an allowlist is
+    /// configured, one not on an allowed domain. The blocklist wins when a
+    /// host is on both. Destinations without a host are left to URL
+    /// validation.
+    pub fn check(&self, destination: &str) -> Result<(), String> {
+        let Some(host) = url::Url::parse(destination)
+            .ok()
+            .and_then(|parsed| parsed.host_str().map(str::to_ascii_lowercase))
+        else {
+            return Ok(());
+        };
+        let host = host.trim_end_matches('.');
+        if self
+            .blocklist
+            .as_ref()
+            .is_some_and(|list| list.matches(host))
+        {
+            return Err(format!("{} is on a blocked domain", destination));
+        }
+        if self
+            .allowlist
+            .as_ref()
+            .is_some_and(|list| !list.matches(host))
+        {
+            return Err(format!("{} is not on an allowed domain", destination));
+        }
+        Ok(())
+    }
+
+    /// Checks every destination of a link.
+    pub fn check_all(&self, destinations: &[String]) -> Result<(), String> {
+        destinations
+            .iter()
+            .try_for_each(|destination| self.check(destination))
+    }
+
+    /// Reads the list files again if they changed.
+    pub fn reload(&self) {
+        self.blocklist
+            .iter()
+            .chain(&self.allowlist)
+            .for_each(ListFile::reload);
+    }
+}
+
+/// Reloads changed list files every `period`, for the lifetime of the server.
+pub async fn run_reloader(policy: web::Data<DomainPolicy>, period: Duration) {
+    let mut ticker = interval(period);
+    // The first tick completes at once; the lists were just loaded.
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let policy = policy.clone();
+        if let Err(err) = web::block(move || policy.reload()).await {
+            log::warn!("Domain list reload failed: {}", err);
+        }
+    }
+}
@@ -1,11 +1,20 @@
 use std::fmt;
 
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse, ResponseError,
+};
+
 #[derive(Debug)]
 pub enum AppError {
     DbError(String),
     NotFound(String),
     InvalidInput(String),
     InternalError(String),
+    /// Missing, unknown, expired or revoked credentials.
+    Unauthorized(String),
+    /// Valid credentials without the required scope.
+    Forbidden(String),
 }
 
 impl std::error::Error for AppError {}
@@ -17,6 +26,52 @@ impl fmt::Display for AppError {
             AppError::NotFound(msg) => write!(f, "Not found: {}", msg),
             AppError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
             AppError::InternalError(msg) => write!(f, "Internal error: {}", msg),
+            AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+        }
+    }
+}
+
+/// Renders errors using the `{"error": "..."}` format described in `docs/API.md`.
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::DbError(_) | AppError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let AppError::Unauthorized(_) = self {
+            response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        response.json(serde_json::json!({
+            "error": self.to_string()
+        }))
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for AppError {
+    fn from(err: diesel::r2d2::PoolError) -> Self {
+        AppError::DbError(err.to_string())
+    }
+}
+
+impl From<actix_web::error::BlockingError> for AppError {
+    fn from(err: actix_web::error::BlockingError) -> Self {
+        AppError::InternalError(err.to_string())
+    }
+}
+
+impl From<diesel::result::Error> for AppError {
+    fn from(err: diesel::result::Error) -> Self {
+        match err {
+            diesel::result::Error::NotFound => AppError::NotFound("record not found".to_string()),
+            other => AppError::DbError(other.to_string()),
         }
     }
 }
@@ -0,0 +1,181 @@
+// src/error_pages.rs
+// Branded error pages for visitors, with JSON for API clients.
+//
+// Each page is an HTML template with `{{status}}`, `{{title}}`, `{{message}}`
+// and `{{short_code}}` placeholders. Templates are read once at startup from
+// `config.error_pages_dir`; pages without a file there use the built-in ones
+// from `templates/errors/`.
+
+use std::{io, path::Path};
+
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse,
+};
+
+use crate::utils::escape_html;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPage {
+    /// No link with the short code exists.
+    NotFound,
+    /// The link is past its expiration date or used up.
+    Expired,
+    /// The link was switched off.
+    Disabled,
+    /// The client made too many requests.
+    RateLimited,
+    /// The link's destination is listed by a threat feed and awaits review.
+    Quarantined,
+    /// A moderator disabled the link.
+    Suspended,
+}
+
+impl ErrorPage {
+    pub const ALL: [ErrorPage; 6] = [
+        ErrorPage::NotFound,
+        ErrorPage::Expired,
+        ErrorPage::Disabled,
+        ErrorPage::RateLimited,
+        ErrorPage::Quarantined,
+        ErrorPage::Suspended,
+    ];
+
+    /// Template file name, without the `.html` extension.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorPage::NotFound => "not_found",
+            ErrorPage::Expired => "expired",
+            ErrorPage::Disabled => "disabled",
+            ErrorPage::RateLimited => "rate_limited",
+            ErrorPage::Quarantined => "quarantined",
+            ErrorPage::Suspended => "suspended",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorPage::NotFound => StatusCode::NOT_FOUND,
+            ErrorPage::Expired | ErrorPage::Disabled | ErrorPage::Suspended => StatusCode::GONE,
+            ErrorPage::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorPage::Quarantined => StatusCode::FORBIDDEN,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            ErrorPage::NotFound => "Link not found",
+            ErrorPage::Expired => "Link expired",
+            ErrorPage::Disabled => "Link disabled",
+            ErrorPage::RateLimited => "Too many requests",
+            ErrorPage::Quarantined => "Suspicious link",
+            ErrorPage::Suspended => "Link suspended",
+        }
+    }
+
+    fn builtin(&self) -> &'static str {
+        match self {
+            ErrorPage::NotFound => include_str!("../templates/errors/not_found.html"),
+            ErrorPage::Expired => include_str!("../templates/errors/expired.html"),
+            ErrorPage::Disabled => include_str!("../templates/errors/disabled.html"),
+            ErrorPage::RateLimited => include_str!("../templates/errors/rate_limited.html"),
+            ErrorPage::Quarantined => include_str!("../templates/errors/quarantined.html"),
+            ErrorPage::Suspended => include_str!("../templates/errors/suspended.html"),
+        }
+    }
+
+    fn index(&self) -> usize {
+        Self::ALL.iter().position(|page| page == self).unwrap_or(0)
+    }
+}
+
+/// The templates in use, one per [`ErrorPage`].
+pub struct ErrorPages {
+    templates: Vec<String>,
+}
+
+impl Default for ErrorPages {
+    fn default() -> Self {
+        ErrorPages {
+            templates: ErrorPage::ALL
+                .iter()
+                .map(|page| page.builtin().to_string())
+                .collect(),
+        }
+    }
+}
+
+impl ErrorPages {
+    /// Reads `<dir>/<name>.html` for every page that has such a file. Fails
+    /// if `dir` itself cannot be read, so a mistyped path is caught at startup.
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        if !dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "not a directory",
+            ));
+        }
+        let mut pages = ErrorPages::default();
+        for page in ErrorPage::ALL {
+            let path = dir.join(format!("{}.html", page.name()));
+            match std::fs::read_to_string(&path) {
+                Ok(template) => pages.templates[page.index()] = template,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(pages)
+    }
+
+    /// Fills in the template of `page`. Values are HTML-escaped.
+    pub fn render(&self, page: ErrorPage, message: &str, short_code: &str) -> String {
+        self.templates[page.index()]
+            .replace("{{status}}", page.status().as_str())
+            .replace("{{title}}", &escape_html(page.title()))
+            .replace("{{message}}", &escape_html(message))
+            .replace("{{short_code}}", &escape_html(short_code))
+    }
+}
+
+/// Whether the client asked for HTML, as browsers do. Everything else,
+/// including clients sending no `Accept` header or `*/*`, gets JSON.
+pub fn prefers_html(req: &HttpRequest) -> bool {
+    let Some(accept) = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    accept.split(',').any(|range| {
+        let mut params = range.split(';').map(str::trim);
+        let media_type = params.next().unwrap_or("").to_ascii_lowercase();
+        (media_type == "text/html" || media_type == "application/xhtml+xml")
+            && !params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    == Some(0.0)
+            })
+    })
+}
+
+/// Error response for `page`: the branded HTML page for browsers, and the
+/// `{"error": "..."}` body described in `docs/API.md` for everyone else.
+pub fn error_response(req: &HttpRequest, page: ErrorPage, message: &str) -> HttpResponse {
+    let mut response = HttpResponse::build(page.status());
+    response
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .insert_header((header::VARY, "Accept"));
+    if !prefers_html(req) {
+        return response.json(serde_json::json!({ "error": message }));
+    }
+    let short_code = req.match_info().get("code").unwrap_or("");
+    let body = match req.app_data::<web::Data<ErrorPages>>() {
+        Some(pages) => pages.render(page, message, short_code),
+        None => ErrorPages::default().render(page, message, short_code),
+    };
+    response
+        .content_type("text/html; charset=utf-8")
+        .body(body)
+}
@@ -0,0 +1,214 @@
+// src/events.rs
+// Live stream of redirect events for dashboards.
+//
+// Every subscriber gets its own bounded queue. Publishing never waits: when a
+// subscriber's queue is full the event is dropped for that subscriber only,
+// so a slow client can never hold up a redirect.
+
+use std::{sync::Mutex, time::Duration};
+
+use actix_web::{
+    http::header,
+    rt::time::interval,
+    web::{self, Bytes},
+    HttpRequest, HttpResponse,
+};
+use actix_ws::Message;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::auth::Principal;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::visitor::{DeviceClass, Visitor};
+use crate::workspaces::{visible_links, LinkVisibility, Permission};
+
+/// Number of events buffered per subscriber before new events are dropped.
+pub const SUBSCRIBER_BUFFER: usize = 256;
+
+/// Interval between SSE keep-alive comments.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A single redirect, as pushed to subscribers.
+#[derive(Clone, Debug, Serialize)]
+pub struct ClickEvent {
+    pub short_code: String,
+    pub timestamp: DateTime<Utc>,
+    pub country: Option<String>,
+    pub device: DeviceClass,
+    pub tags: Vec<String>,
+    /// Owner and workspace of the link, used to keep users to the clicks
+    /// on links they can see.
+    #[serde(skip)]
+    pub owner_id: Option<i32>,
+    #[serde(skip)]
+    pub workspace_id: Option<i32>,
+}
+
+impl ClickEvent {
+    pub fn new(short_code: &str, visitor: &Visitor, tags: Vec<String>) -> Self {
+        ClickEvent {
+            short_code: short_code.to_string(),
+            timestamp: Utc::now(),
+            country: visitor.country.clone(),
+            device: visitor.device,
+            tags,
+            owner_id: None,
+            workspace_id: None,
+        }
+    }
+}
+
+/// Restricts a subscription to one short code and/or one tag.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct EventFilter {
+    pub code: Option<String>,
+    pub tag: Option<String>,
+    /// Only clicks on links the caller can see; set from the caller, never
+    /// from the query string.
+    #[serde(skip)]
+    pub visibility: Option<LinkVisibility>,
+}
+
+impl EventFilter {
+    fn matches(&self, event: &ClickEvent) -> bool {
+        self.code.as_ref().is_none_or(|code| *code == event.short_code)
+            && self.tag.as_ref().is_none_or(|tag| event.tags.contains(tag))
+            && self
+                .visibility
+                .as_ref()
+                .is_none_or(|visibility| visibility.allows(event.owner_id, event.workspace_id))
+    }
+}
+
+struct Subscriber {
+    filter: EventFilter,
+    tx: mpsc::Sender<ClickEvent>,
+}
+
+/// Fan-out point between `redirect_handler` and the connected subscribers.
+#[derive(Default)]
+pub struct EventHub {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl EventHub {
+    pub fn subscribe(&self, filter: EventFilter) -> mpsc::Receiver<ClickEvent> {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_BUFFER);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { filter, tx });
+        rx
+    }
+
+    /// Whether anyone is listening; lets callers skip building events.
+    pub fn has_subscribers(&self) -> bool {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| !subscriber.tx.is_closed());
+        !subscribers.is_empty()
+    }
+
+    pub fn publish(&self, event: &ClickEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| !subscriber.tx.is_closed());
+        for subscriber in subscribers.iter().filter(|s| s.filter.matches(event)) {
+            if let Err(TrySendError::Full(_)) = subscriber.tx.try_send(event.clone()) {
+                log::debug!("Dropping click event for slow subscriber");
+            }
+        }
+    }
+}
+
+/// Handler for the live click stream.
+///
+/// Serves a WebSocket when the request asks for an upgrade and Server-Sent
+/// Events otherwise. Both accept the optional `code` and `tag` filters.
+/// Callers without the `admin` scope only get clicks on their own links and
+/// those of the workspaces where their role allows reading statistics.
+pub async fn events_handler(
+    req: HttpRequest,
+    body: web::Payload,
+    pool: web::Data<DbPool>,
+    hub: web::Data<EventHub>,
+    principal: Principal,
+    filter: web::Query<EventFilter>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut filter = filter.into_inner();
+    filter.visibility = web::block(move || {
+        let mut conn = pool.get()?;
+        visible_links(&mut conn, &principal, Permission::ViewStats).map_err(AppError::from)
+    })
+    .await??;
+    let rx = hub.subscribe(filter);
+    let wants_websocket = req
+        .headers()
+        .get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+
+    if wants_websocket {
+        websocket_stream(&req, body, rx)
+    } else {
+        Ok(sse_stream(rx))
+    }
+}
+
+fn sse_stream(rx: mpsc::Receiver<ClickEvent>) -> HttpResponse {
+    let heartbeat = interval(HEARTBEAT_INTERVAL);
+    let body =
+        futures_util::stream::unfold((rx, heartbeat), |(mut rx, mut heartbeat)| async move {
+            let chunk = tokio::select! {
+                event = rx.recv() => {
+                    let event = event?;
+                    let data = serde_json::to_string(&event).ok()?;
+                    format!("event: click\ndata: {}\n\n", data)
+                }
+                _ = heartbeat.tick() => ": keep-alive\n\n".to_string(),
+            };
+            Some((
+                Ok::<_, actix_web::Error>(Bytes::from(chunk)),
+                (rx, heartbeat),
+            ))
+        });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(body)
+}
+
+fn websocket_stream(
+    req: &HttpRequest,
+    body: web::Payload,
+    mut rx: mpsc::Receiver<ClickEvent>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (response, mut session, mut messages) = actix_ws::handle(req, body)?;
+
+    actix_web::rt::spawn(async move {
+        loop {
+            tokio::select! {
+                event = rx.recv() => {
+                    let Some(event) = event else { break };
+                    let Ok(data) = serde_json::to_string(&event) else { continue };
+                    if session.text(data).await.is_err() {
+                        return;
+                    }
+                }
+                message = messages.recv() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
@@ -0,0 +1,471 @@
+// src/export.rs
+// Export of recorded clicks for the data warehouse.
+//
+// Rows are read from `redirect_stats` (joined with `urls`) in fixed-size
+// batches ordered by id, so an export never holds more than one batch in
+// memory regardless of how large the click log has grown.
+
+use std::{io::Write, str::FromStr, sync::Arc};
+
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web::{self, Bytes},
+    HttpResponse,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use parquet::{
+    basic::Compression,
+    data_type::{ByteArray, ByteArrayType, Int64Type},
+    errors::ParquetError,
+    file::{
+        properties::WriterProperties,
+        writer::{SerializedColumnWriter, SerializedFileWriter},
+    },
+    schema::parser::parse_message_type,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{self, Actor},
+    auth::Principal,
+    db::DbPool,
+    error::AppError,
+    utils::parse_timestamp,
+    workspaces::{visible_links, LinkVisibility, Permission},
+};
+
+/// Number of rows fetched from the database per round trip.
+pub const BATCH_SIZE: i64 = 1000;
+
+/// Column names, in output order, shared by every format.
+const COLUMNS: [&str; 6] = [
+    "id",
+    "short_code",
+    "original_url",
+    "ip_address",
+    "user_agent",
+    "accessed_at",
+];
+
+const PARQUET_SCHEMA: &str = "
+    message click {
+        REQUIRED INT64 id;
+        REQUIRED BYTE_ARRAY short_code (UTF8);
+        REQUIRED BYTE_ARRAY original_url (UTF8);
+        OPTIONAL BYTE_ARRAY ip_address (UTF8);
+        OPTIONAL BYTE_ARRAY user_agent (UTF8);
+        REQUIRED INT64 accessed_at (TIMESTAMP(MILLIS,true));
+    }
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl FromStr for ExportFormat {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            "parquet" => Ok(ExportFormat::Parquet),
+            other => Err(AppError::InvalidInput(format!(
+                "unsupported export format '{}', expected csv, ndjson or parquet",
+                other
+            ))),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Restricts which clicks are exported. `from` is inclusive, `to` exclusive.
+#[derive(Clone, Debug, Default)]
+pub struct ExportFilter {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub short_code: Option<String>,
+    /// Only clicks on links the caller can see; `None` means all links.
+    pub visibility: Option<LinkVisibility>,
+}
+
+/// Export parameters as accepted by both the HTTP endpoint and the CLI.
+#[derive(Deserialize, Serialize, Default)]
+pub struct ExportQuery {
+    pub format: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub code: Option<String>,
+}
+
+impl ExportQuery {
+    /// Validates the raw parameters. The format defaults to CSV.
+    pub fn parse(self) -> Result<(ExportFormat, ExportFilter), AppError> {
+        let format = match self.format {
+            Some(format) => format.parse()?,
+            None => ExportFormat::Csv,
+        };
+        let parse_bound = |name: &str, value: Option<String>| match value {
+            Some(value) => parse_timestamp(&value).map(Some).ok_or_else(|| {
+                AppError::InvalidInput(format!("'{}' must be an RFC 3339 timestamp", name))
+            }),
+            None => Ok(None),
+        };
+        let filter = ExportFilter {
+            from: parse_bound("from", self.from)?,
+            to: parse_bound("to", self.to)?,
+            short_code: self.code.filter(|code| !code.is_empty()),
+            visibility: None,
+        };
+        Ok((format, filter))
+    }
+}
+
+/// One exported click, with the short code and destination it was recorded for.
+#[derive(Queryable, Serialize)]
+pub struct ClickRecord {
+    pub id: i32,
+    pub short_code: String,
+    pub original_url: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub accessed_at: NaiveDateTime,
+}
+
+/// Loads up to `limit` clicks matching `filter` with an id greater than `after_id`.
+pub fn load_batch(
+    conn: &mut SqliteConnection,
+    filter: &ExportFilter,
+    after_id: i32,
+    limit: i64,
+) -> QueryResult<Vec<ClickRecord>> {
+    use crate::schema::{redirect_stats, urls};
+
+    let mut query = redirect_stats::table
+        .inner_join(urls::table)
+        .select((
+            redirect_stats::id,
+            urls::short_code,
+            urls::original_url,
+            redirect_stats::ip_address,
+            redirect_stats::user_agent,
+            redirect_stats::accessed_at,
+        ))
+        .filter(redirect_stats::id.gt(after_id))
+        .into_boxed();
+
+    if let Some(from) = filter.from {
+        query = query.filter(redirect_stats::accessed_at.ge(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(redirect_stats::accessed_at.lt(to));
+    }
+    if let Some(code) = &filter.short_code {
+        query = query.filter(urls::short_code.eq(code.clone()));
+    }
+    if let Some(visibility) = &filter.visibility {
+        query = query.filter(
+            urls::workspace_id
+                .is_null()
+                .and(urls::owner_id.is(visibility.user_id))
+                .or(urls::workspace_id.eq_any(visibility.workspace_ids.clone())),
+        );
+    }
+
+    query
+        .order(redirect_stats::id.asc())
+        .limit(limit)
+        .load::<ClickRecord>(conn)
+}
+
+enum Sink {
+    Csv { header_written: bool },
+    Ndjson,
+    Parquet(Box<SerializedFileWriter<Vec<u8>>>),
+}
+
+/// Incrementally encodes batches of clicks. Each call returns the bytes that
+/// are ready to be sent; `finish` returns whatever trails the last batch
+/// (the CSV header for an empty export, or the Parquet footer).
+pub struct ExportWriter {
+    sink: Sink,
+}
+
+impl ExportWriter {
+    pub fn new(format: ExportFormat) -> Result<Self, AppError> {
+        let sink = match format {
+            ExportFormat::Csv => Sink::Csv {
+                header_written: false,
+            },
+            ExportFormat::Ndjson => Sink::Ndjson,
+            ExportFormat::Parquet => {
+                let schema = parse_message_type(PARQUET_SCHEMA).map_err(parquet_error)?;
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                let writer =
+                    SerializedFileWriter::new(Vec::new(), Arc::new(schema), Arc::new(props))
+                        .map_err(parquet_error)?;
+                Sink::Parquet(Box::new(writer))
+            },
+        };
+        Ok(ExportWriter { sink })
+    }
+
+    pub fn write_batch(&mut self, rows: &[ClickRecord]) -> Result<Vec<u8>, AppError> {
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        match &mut self.sink {
+            Sink::Csv { header_written } => {
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(Vec::new());
+                if !*header_written {
+                    writer.write_record(COLUMNS).map_err(csv_error)?;
+                    *header_written = true;
+                }
+                for row in rows {
+                    writer.serialize(row).map_err(csv_error)?;
+                }
+                writer
+                    .into_inner()
+                    .map_err(|err| AppError::InternalError(err.to_string()))
+            },
+            Sink::Ndjson => {
+                let mut out = Vec::new();
+                for row in rows {
+                    serde_json::to_writer(&mut out, row)
+                        .map_err(|err| AppError::InternalError(err.to_string()))?;
+                    out.push(b'\n');
+                }
+                Ok(out)
+            },
+            Sink::Parquet(writer) => {
+                write_row_group(writer, rows).map_err(parquet_error)?;
+                Ok(std::mem::take(writer.inner_mut()))
+            },
+        }
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>, AppError> {
+        match self.sink {
+            Sink::Csv {
+                header_written: false,
+            } => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                writer.write_record(COLUMNS).map_err(csv_error)?;
+                writer
+                    .into_inner()
+                    .map_err(|err| AppError::InternalError(err.to_string()))
+            },
+            Sink::Csv {
+                header_written: true,
+            }
+            | Sink::Ndjson => Ok(Vec::new()),
+            Sink::Parquet(writer) => writer.into_inner().map_err(parquet_error),
+        }
+    }
+}
+
+/// Writes every click matching `filter` to `out`, returning the number of rows.
+/// Used by the `export` CLI command.
+pub fn export_clicks(
+    conn: &mut SqliteConnection,
+    filter: &ExportFilter,
+    format: ExportFormat,
+    out: &mut dyn Write,
+) -> Result<usize, AppError> {
+    let mut writer = ExportWriter::new(format)?;
+    let mut after_id = 0;
+    let mut total = 0;
+    loop {
+        let rows = load_batch(conn, filter, after_id, BATCH_SIZE)?;
+        let Some(last) = rows.last() else { break };
+        after_id = last.id;
+        total += rows.len();
+        write_chunk(out, &writer.write_batch(&rows)?)?;
+    }
+    write_chunk(out, &writer.finish()?)?;
+    Ok(total)
+}
+
+/// Handler for streaming the click log.
+///
+/// Query parameters: `format` (csv, ndjson or parquet), `from` and `to`
+/// (RFC 3339 timestamps) and `code` (restrict to one short code). Callers
+/// without the `admin` scope only get clicks on their own links and those of
+/// the workspaces where their role allows reading statistics.
+pub async fn export_clicks_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    actor: Actor,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, AppError> {
+    let query = query.into_inner();
+    let entry = actor
+        .entry(audit::CLICKS_EXPORTED)
+        .short_code(query.code.as_deref())
+        .after(&serde_json::json!(query));
+    let (format, mut filter) = query.parse()?;
+    let visibility_pool = pool.clone();
+    filter.visibility = web::block(move || {
+        let mut conn = visibility_pool.get()?;
+        entry.record(&mut conn)?;
+        visible_links(&mut conn, &principal, Permission::ViewStats).map_err(AppError::from)
+    })
+    .await??;
+    let writer = ExportWriter::new(format)?;
+
+    let body = futures_util::stream::try_unfold(Some((writer, 0)), move |state| {
+        let pool = pool.clone();
+        let filter = filter.clone();
+        async move {
+            let Some((mut writer, after_id)) = state else {
+                return Ok::<_, AppError>(None);
+            };
+            web::block(move || {
+                let mut conn = pool
+                    .get()
+                    .map_err(|err| AppError::DbError(err.to_string()))?;
+                let rows = load_batch(&mut conn, &filter, after_id, BATCH_SIZE)?;
+                match rows.last() {
+                    Some(last) => {
+                        let next_id = last.id;
+                        let chunk = writer.write_batch(&rows)?;
+                        Ok(Some((Bytes::from(chunk), Some((writer, next_id)))))
+                    },
+                    None => Ok(Some((Bytes::from(writer.finish()?), None))),
+                }
+            })
+            .await
+            .map_err(|err| AppError::InternalError(err.to_string()))?
+        }
+    });
+
+    let disposition = ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(format!(
+            "clicks.{}",
+            format.file_extension()
+        ))],
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(disposition)
+        .streaming(body))
+}
+
+fn write_row_group(
+    writer: &mut SerializedFileWriter<Vec<u8>>,
+    rows: &[ClickRecord],
+) -> Result<(), ParquetError> {
+    let mut row_group = writer.next_row_group()?;
+    let mut index = 0;
+    while let Some(mut column) = row_group.next_column()? {
+        match COLUMNS[index] {
+            "id" => {
+                let values: Vec<i64> = rows.iter().map(|row| i64::from(row.id)).collect();
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&values, None, None)?;
+            },
+            "short_code" => write_text(
+                &mut column,
+                rows.iter().map(|row| Some(&*row.short_code)),
+                false,
+            )?,
+            "original_url" => write_text(
+                &mut column,
+                rows.iter().map(|row| Some(&*row.original_url)),
+                false,
+            )?,
+            "ip_address" => write_text(
+                &mut column,
+                rows.iter().map(|row| row.ip_address.as_deref()),
+                true,
+            )?,
+            "user_agent" => write_text(
+                &mut column,
+                rows.iter().map(|row| row.user_agent.as_deref()),
+                true,
+            )?,
+            "accessed_at" => {
+                let values: Vec<i64> = rows
+                    .iter()
+                    .map(|row| row.accessed_at.and_utc().timestamp_millis())
+                    .collect();
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(&values, None, None)?;
+            },
+            other => {
+                return Err(ParquetError::General(format!(
+                    "unexpected column {}",
+                    other
+                )))
+            },
+        }
+        column.close()?;
+        index += 1;
+    }
+    row_group.close()?;
+    Ok(())
+}
+
+fn write_text<'a>(
+    column: &mut SerializedColumnWriter<'_>,
+    values: impl Iterator<Item = Option<&'a str>>,
+    optional: bool,
+) -> Result<(), ParquetError> {
+    let mut data = Vec::new();
+    let mut def_levels = Vec::new();
+    for value in values {
+        match value {
+            Some(value) => {
+                data.push(ByteArray::from(value));
+                def_levels.push(1);
+            },
+            None => def_levels.push(0),
+        }
+    }
+    let def_levels = optional.then_some(def_levels.as_slice());
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&data, def_levels, None)?;
+    Ok(())
+}
+
+fn write_chunk(out: &mut dyn Write, chunk: &[u8]) -> Result<(), AppError> {
+    out.write_all(chunk)
+        .map_err(|err| AppError::InternalError(err.to_string()))
+}
+
+fn csv_error(err: csv::Error) -> AppError {
+    AppError::InternalError(err.to_string())
+}
+
+fn parquet_error(err: ParquetError) -> AppError {
+    AppError::InternalError(err.to_string())
+}
@@ -0,0 +1,44 @@
+// src/geo.rs
+// Country lookup from a local MaxMind (GeoLite2 / GeoIP2) database.
+
+use std::{net::IpAddr, path::Path};
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+
+/// An optional geo database. Lookups return `None` when none is configured.
+#[derive(Default)]
+pub struct GeoIp {
+    reader: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// Reads a `.mmdb` Country or City database into memory.
+    pub fn open(path: &Path) -> Result<Self, MaxMindDBError> {
+        Ok(GeoIp {
+            reader: Some(Reader::open_readfile(path)?),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.reader.is_some()
+    }
+
+    /// ISO 3166 country code of `address`, if the database knows it.
+    pub fn country(&self, address: &str) -> Option<String> {
+        let reader = self.reader.as_ref()?;
+        let ip: IpAddr = parse_ip(address)?;
+        let record: geoip2::Country = reader.lookup(ip).ok()?;
+        record
+            .country
+            .and_then(|country| country.iso_code)
+            .map(str::to_ascii_uppercase)
+    }
+}
+
+/// Accepts a bare address or one with a port, as found in `realip_remote_addr`.
+fn parse_ip(address: &str) -> Option<IpAddr> {
+    address
+        .parse()
+        .ok()
+        .or_else(|| address.parse::<std::net::SocketAddr>().ok().map(|s| s.ip()))
+}
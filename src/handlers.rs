@@ -1,88 +1,779 @@
 // src/handlers.rs
-use actix_web::{web, HttpResponse, Responder, HttpRequest};
+use actix_web::{http::header, web, HttpResponse, Responder, HttpRequest, ResponseError};
 use diesel::prelude::*;
+use crate::audit::{self, Actor};
+use crate::auth::Principal;
+use crate::config::Config;
 use crate::db::DbPool;
-use crate::models::{Url, NewUrl};
+use crate::domain_policy::DomainPolicy;
+use crate::error::AppError;
+use crate::error_pages::{error_response, ErrorPage};
+use crate::events::{ClickEvent, EventHub};
+use crate::limits::claim_click;
+use crate::models::{Url, NewUrl, NewRedirectStat, UrlChanges, UrlTag};
+use crate::password::{hash_password, is_unlocked, password_required_response, unlock_cookie_name};
+use crate::preview::{
+    confirmation_cookie, confirmation_cookie_name, interstitial_for, interstitial_response,
+    is_confirmed, Interstitial,
+};
+use crate::qr::{strip_scan_marker, SCAN_SOURCE};
+use crate::redirect::{
+    destination, has_dot_segments, path_tail, validate_redirect_status, RedirectStatus,
+};
+use crate::rules::{rules_to_json_string, validate_rules, Rule, RuleContext};
+use crate::routing::{
+    self, validate_destination, variant_cookie, variant_cookie_name, PlatformDestinations, Route,
+    SplitTest,
+};
+use crate::schedule::{unavailable_response, validate_window, LinkState};
+use crate::screening::{self, ThreatFeeds};
+use crate::utils::{deserialize_some, normalize_tags, parse_timestamp};
+use chrono::{NaiveDateTime, Utc};
+use crate::utm::{self, UtmTemplate};
+use crate::visitor::Visitor;
+use crate::webhooks;
+use crate::workspaces::{
+    authorize_url, authorize_workspace, link_destinations, visible_links, Permission,
+    DEFAULT_CODE_LENGTH,
+};
 use serde::Deserialize;
-use std::env;
 use rand::{distributions::Alphanumeric, Rng};
 
 #[derive(Deserialize)]
 pub struct CreateUrlRequest {
     pub original_url: String,
+    /// Optional labels used to group links, e.g. by campaign.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Optional RFC 3339 timestamp after which the link expires.
+    pub expiration_date: Option<String>,
+    /// Optional redirect status (301, 302, 307 or 308) overriding the default.
+    pub redirect_status: Option<u16>,
+    /// Forward the visitor's query string to the destination.
+    #[serde(default)]
+    pub forward_query: bool,
+    /// Append any path after the short code to the destination.
+    #[serde(default)]
+    pub forward_path: bool,
+    /// UTM parameters added to the destination on every redirect.
+    pub utm: Option<UtmTemplate>,
+    /// Alternate destinations for iOS, Android and desktop visitors.
+    pub platform_destinations: Option<PlatformDestinations>,
+    /// Weighted A/B destinations.
+    pub split: Option<SplitTest>,
+    /// Ordered conditional redirect rules.
+    pub rules: Option<Vec<Rule>>,
+    /// Optional RFC 3339 timestamp before which the link does not redirect.
+    pub activates_at: Option<String>,
+    /// Optional RFC 3339 timestamp from which the link no longer redirects.
+    pub deactivates_at: Option<String>,
+    /// Where visitors are sent while the link is not active.
+    pub fallback_url: Option<String>,
+    /// Optional number of redirects after which the link is used up.
+    pub max_clicks: Option<i32>,
+    /// Optional password visitors must enter before being redirected.
+    pub password: Option<String>,
+    /// Show a warning page before every redirect.
+    #[serde(default)]
+    pub interstitial: bool,
+    /// Workspace the link belongs to; its settings apply to the link.
+    /// Defaults to the workspace named by the caller's token, if any.
+    pub workspace_id: Option<i32>,
 }
 
 /// Handler for creating a shortened URL.
 pub async fn create_url_handler(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    domain_policy: web::Data<DomainPolicy>,
+    threat_feeds: web::Data<ThreatFeeds>,
+    principal: Principal,
+    actor: Actor,
     item: web::Json<CreateUrlRequest>,
 ) -> impl Responder {
-    use crate::schema::urls;
+    use crate::schema::{url_tags, urls};
     use crate::schema::urls::dsl::*;
 
     if item.original_url.trim().is_empty() {
         return HttpResponse::BadRequest().body("Original URL is required");
     }
 
+    let (expires_at, starts_at, ends_at) = match (
+        parse_optional_timestamp("expiration_date", item.expiration_date.as_deref()),
+        parse_optional_timestamp("activates_at", item.activates_at.as_deref()),
+        parse_optional_timestamp("deactivates_at", item.deactivates_at.as_deref()),
+    ) {
+        (Ok(expires_at), Ok(starts_at), Ok(ends_at)) => (expires_at, starts_at, ends_at),
+        (Err(message), _, _) | (_, Err(message), _) | (_, _, Err(message)) => {
+            return HttpResponse::BadRequest().body(message)
+        }
+    };
+    if let Err(message) = validate_window(starts_at, ends_at) {
+        return HttpResponse::BadRequest().body(message);
+    }
+    if let Some(Err(message)) = item.fallback_url.as_deref().map(validate_destination) {
+        return HttpResponse::BadRequest().body(format!("fallback_url: {}", message));
+    }
+    if item.max_clicks.is_some_and(|limit| limit < 1) {
+        return HttpResponse::BadRequest().body("max_clicks must be at least 1");
+    }
+
+    if let Some(Err(message)) = item.redirect_status.map(validate_redirect_status) {
+        return HttpResponse::BadRequest().body(message);
+    }
+    if let Some(Err(err)) = item.utm.as_ref().map(UtmTemplate::validate) {
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+    if let Some(Err(err)) = item.platform_destinations.as_ref().map(PlatformDestinations::validate) {
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+    if let Some(Err(err)) = item.split.as_ref().map(SplitTest::validate) {
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+    if let Some(Err(err)) = item.rules.as_deref().map(validate_rules) {
+        return HttpResponse::BadRequest().body(err.to_string());
+    }
+
+    let destinations = link_destinations(
+        &item.original_url,
+        item.platform_destinations.as_ref(),
+        item.split.as_ref(),
+        item.rules.as_deref().unwrap_or_default(),
+        item.fallback_url.as_deref(),
+    );
+    if let Err(message) = domain_policy.check_all(&destinations) {
+        return HttpResponse::BadRequest().body(message);
+    }
+
+    let workspace = match item.workspace_id.or(principal.workspace_id) {
+        Some(target) => {
+            let pool = pool.clone();
+            let principal = principal.clone();
+            match web::block(move || {
+                let mut conn = pool.get()?;
+                authorize_workspace(&mut conn, &principal, target, Permission::EditLinks)
+            })
+            .await
+            {
+                Ok(Ok(workspace)) => Some(workspace),
+                Ok(Err(err)) => return err.error_response(),
+                Err(_) => return HttpResponse::InternalServerError().body("Error creating short URL"),
+            }
+        }
+        None => None,
+    };
+    if let Some(workspace) = &workspace {
+        if let Err(message) = workspace.check_destinations(&destinations) {
+            return HttpResponse::BadRequest().body(message);
+        }
+    }
+    let expires_at = expires_at.or_else(|| {
+        workspace
+            .as_ref()
+            .and_then(|workspace| workspace.default_expiration(Utc::now().naive_utc()))
+    });
+
+    let hashed_password = match item.password.clone() {
+        Some(password) => match hash_password(password).await {
+            Ok(hash) => Some(hash),
+            Err(AppError::InvalidInput(message)) => return HttpResponse::BadRequest().body(message),
+            Err(_) => return HttpResponse::InternalServerError().body("Error creating short URL"),
+        },
+        None => None,
+    };
+
     let generated_code: String = rand::thread_rng()
         .sample_iter(&Alphanumeric)
-        .take(7)
+        .take(workspace.as_ref().map_or(DEFAULT_CODE_LENGTH, |workspace| workspace.code_length()))
         .map(char::from)
         .collect();
 
     let new_url = NewUrl {
         original_url: item.original_url.clone(),
         short_code: generated_code.clone(),
+        expiration_date: expires_at,
+        redirect_status: item.redirect_status.map(i32::from),
+        forward_query: item.forward_query,
+        forward_path: item.forward_path,
+        utm_template: item.utm.as_ref().map(UtmTemplate::to_json_string),
+        platform_destinations: item
+            .platform_destinations
+            .as_ref()
+            .map(PlatformDestinations::to_json_string),
+        split_test: item.split.as_ref().map(SplitTest::to_json_string),
+        rules: item.rules.as_deref().map(rules_to_json_string),
+        activates_at: starts_at,
+        deactivates_at: ends_at,
+        fallback_url: item.fallback_url.clone(),
+        max_clicks: item.max_clicks,
+        password_hash: hashed_password,
+        interstitial: item.interstitial,
+        owner_id: principal.user_id,
+        workspace_id: workspace.as_ref().map(|workspace| workspace.id),
     };
 
+    let tags = normalize_tags(&item.tags);
+    let new_tags = tags.clone();
+    let base_url = config.base_url.clone();
+
     let mut conn = pool.get().expect("Couldn't get db connection from pool");
 
     match web::block(move || {
-        diesel::insert_into(urls::table)
-            .values(&new_url)
-            .execute(&mut conn)
-            .and_then(|_| {
-                urls.filter(short_code.eq(&generated_code))
-                    .first::<Url>(&mut conn)
-            })
+        conn.transaction(|conn| {
+            diesel::insert_into(urls::table)
+                .values(&new_url)
+                .execute(conn)?;
+            let mut url_entry = urls.filter(short_code.eq(&generated_code)).first::<Url>(conn)?;
+            if screening::quarantine(conn, &threat_feeds.screen(url_entry.id, &destinations))? {
+                url_entry = urls.find(url_entry.id).first::<Url>(conn)?;
+            }
+            let tag_rows: Vec<UrlTag> = new_tags
+                .iter()
+                .map(|tag| UrlTag { url_id: url_entry.id, tag: tag.clone() })
+                .collect();
+            diesel::insert_into(url_tags::table)
+                .values(&tag_rows)
+                .execute(conn)?;
+            let body = url_entry.to_json(&new_tags, &base_url);
+            webhooks::enqueue(conn, webhooks::LINK_CREATED, &body)?;
+            actor.entry(audit::LINK_CREATED).link(&url_entry).after(&body).record(conn)?;
+            Ok::<_, diesel::result::Error>(url_entry)
+        })
     }).await {
         Ok(Ok(url_entry)) => {
-            let base = env::var("BASE_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
-            let short_url = format!("{}/{}", base, url_entry.short_code);
-            HttpResponse::Created().json(serde_json::json!({
-                "original_url": url_entry.original_url,
-                "short_code": url_entry.short_code,
-                "short_url": short_url,
-                "created_at": url_entry.created_at
-            }))
+            HttpResponse::Created().json(url_entry.to_json(&tags, &config.base_url))
         }
         _ => HttpResponse::InternalServerError().body("Error creating short URL"),
     }
 }
 
-/// Handler for listing all shortened URLs.
-pub async fn list_urls_handler(pool: web::Data<DbPool>) -> impl Responder {
+#[derive(Deserialize)]
+pub struct ListUrlsQuery {
+    /// Only links of this workspace.
+    pub workspace_id: Option<i32>,
+}
+
+/// Handler for listing shortened URLs: all of them for admins, and
+/// otherwise the caller's own and those of their workspaces. Each link has
+/// the same shape as in the other link responses.
+pub async fn list_urls_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    principal: Principal,
+    params: web::Query<ListUrlsQuery>,
+) -> impl Responder {
     use crate::schema::urls::dsl::*;
+    let in_workspace = params.into_inner().workspace_id;
     let mut conn = pool.get().expect("Couldn't get db connection from pool");
-    match web::block(move || urls.order(created_at.desc()).load::<Url>(&mut conn)).await {
+    match web::block(move || {
+        let mut query = urls.order(created_at.desc()).into_boxed();
+        if let Some(visibility) = visible_links(&mut conn, &principal, Permission::ViewLinks)? {
+            query = query.filter(
+                workspace_id
+                    .is_null()
+                    .and(owner_id.is(visibility.user_id))
+                    .or(workspace_id.eq_any(visibility.workspace_ids)),
+            );
+        }
+        if let Some(workspace) = in_workspace {
+            query = query.filter(workspace_id.eq(workspace));
+        }
+        let urls_list = query.load::<Url>(&mut conn)?;
+        let ids: Vec<i32> = urls_list.iter().map(|url_entry| url_entry.id).collect();
+        let mut tags = UrlTag::for_urls(&mut conn, &ids)?;
+        Ok::<_, AppError>(
+            urls_list
+                .iter()
+                .map(|url_entry| {
+                    let link_tags = tags.remove(&url_entry.id).unwrap_or_default();
+                    url_entry.to_json(&link_tags, &config.base_url)
+                })
+                .collect::<Vec<_>>(),
+        )
+    })
+    .await
+    {
         Ok(Ok(urls_list)) => HttpResponse::Ok().json(urls_list),
         _ => HttpResponse::InternalServerError().body("Error loading URLs"),
     }
 }
 
+#[derive(Deserialize)]
+pub struct UpdateUrlRequest {
+    pub original_url: Option<String>,
+    /// Replaces all tags when present.
+    pub tags: Option<Vec<String>>,
+    /// RFC 3339 timestamp, or `null` to remove the expiration.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub expiration_date: Option<Option<String>>,
+    /// Redirect status, or `null` to fall back to the configured default.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub redirect_status: Option<Option<u16>>,
+    pub forward_query: Option<bool>,
+    pub forward_path: Option<bool>,
+    /// UTM template, or `null` to remove it.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub utm: Option<Option<UtmTemplate>>,
+    /// Alternate destinations by platform, or `null` to remove them.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub platform_destinations: Option<Option<PlatformDestinations>>,
+    /// Weighted A/B destinations, or `null` to remove them.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub split: Option<Option<SplitTest>>,
+    /// Replaces all rules, or `null` to remove them.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub rules: Option<Option<Vec<Rule>>>,
+    /// RFC 3339 timestamps, or `null` to remove them.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub activates_at: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub deactivates_at: Option<Option<String>>,
+    /// Fallback destination, or `null` to remove it.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub fallback_url: Option<Option<String>>,
+    /// Click limit, or `null` to remove it. Clicks already used still count.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub max_clicks: Option<Option<i32>>,
+    /// New password, or `null` to remove the protection.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub password: Option<Option<String>>,
+    pub interstitial: Option<bool>,
+    /// Workspace to move the link to, or `null` to make it a personal link
+    /// of the caller.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub workspace_id: Option<Option<i32>>,
+}
+
+/// Handler for editing a link. Only the fields present in the body change.
+#[allow(clippy::too_many_arguments)]
+pub async fn update_url_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    domain_policy: web::Data<DomainPolicy>,
+    threat_feeds: web::Data<ThreatFeeds>,
+    principal: Principal,
+    actor: Actor,
+    path: web::Path<String>,
+    item: web::Json<UpdateUrlRequest>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::{url_tags, urls};
+
+    let code = path.into_inner();
+    let item = item.into_inner();
+    if item.original_url.as_deref().is_some_and(|value| value.trim().is_empty()) {
+        return Err(AppError::InvalidInput("original_url must not be empty".to_string()));
+    }
+    let expiration_date = parse_timestamp_change("expiration_date", item.expiration_date)?;
+    let activates_at = parse_timestamp_change("activates_at", item.activates_at)?;
+    let deactivates_at = parse_timestamp_change("deactivates_at", item.deactivates_at)?;
+    if let Some(Some(fallback)) = &item.fallback_url {
+        validate_destination(fallback)
+            .map_err(|message| AppError::InvalidInput(format!("fallback_url: {}", message)))?;
+    }
+    if item.max_clicks.flatten().is_some_and(|limit| limit < 1) {
+        return Err(AppError::InvalidInput("max_clicks must be at least 1".to_string()));
+    }
+    let redirect_status = match item.redirect_status {
+        Some(Some(value)) => Some(Some(
+            validate_redirect_status(value).map_err(AppError::InvalidInput)?.code().into(),
+        )),
+        Some(None) => Some(None),
+        None => None,
+    };
+    if let Some(Some(template)) = &item.utm {
+        template.validate()?;
+    }
+    if let Some(Some(destinations)) = &item.platform_destinations {
+        destinations.validate()?;
+    }
+    if let Some(Some(split)) = &item.split {
+        split.validate()?;
+    }
+    if let Some(Some(rules)) = &item.rules {
+        validate_rules(rules)?;
+    }
+    let password_hash = match item.password {
+        Some(Some(password)) => Some(Some(hash_password(password).await?)),
+        Some(None) => Some(None),
+        None => None,
+    };
+    let mut changes = UrlChanges {
+        original_url: item.original_url,
+        // A new expiration date should produce a new `link.expired` event.
+        expiry_notified: expiration_date.map(|_| false),
+        expiration_date,
+        redirect_status,
+        forward_query: item.forward_query,
+        forward_path: item.forward_path,
+        utm_template: item.utm.map(|template| template.as_ref().map(UtmTemplate::to_json_string)),
+        platform_destinations: item
+            .platform_destinations
+            .map(|destinations| destinations.as_ref().map(PlatformDestinations::to_json_string)),
+        split_test: item.split.map(|split| split.as_ref().map(SplitTest::to_json_string)),
+        rules: item.rules.map(|rules| rules.as_deref().map(rules_to_json_string)),
+        activates_at,
+        deactivates_at,
+        fallback_url: item.fallback_url,
+        max_clicks: item.max_clicks,
+        password_hash,
+        interstitial: item.interstitial,
+        owner_id: None,
+        workspace_id: item.workspace_id,
+    };
+    let new_tags = item.tags.as_deref().map(normalize_tags);
+    let base_url = config.base_url.clone();
+
+    let body = web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let url_entry = authorize_url(conn, &principal, &code, Permission::EditLinks)?;
+            let before = url_entry.to_json(&UrlTag::for_url(conn, url_entry.id)?, &base_url);
+            match changes.workspace_id {
+                Some(Some(target)) => {
+                    authorize_workspace(conn, &principal, target, Permission::EditLinks)?;
+                },
+                Some(None) if url_entry.workspace_id.is_some() => {
+                    changes.owner_id = Some(principal.user_id);
+                },
+                _ => {},
+            }
+            // Diesel rejects an update without any column to set.
+            if changes != UrlChanges::default() {
+                diesel::update(urls::table.find(url_entry.id))
+                    .set(&changes)
+                    .execute(conn)?;
+            }
+            if let Some(new_tags) = &new_tags {
+                diesel::delete(url_tags::table.filter(url_tags::url_id.eq(url_entry.id)))
+                    .execute(conn)?;
+                let tag_rows: Vec<UrlTag> = new_tags
+                    .iter()
+                    .map(|tag| UrlTag { url_id: url_entry.id, tag: tag.clone() })
+                    .collect();
+                diesel::insert_into(url_tags::table)
+                    .values(&tag_rows)
+                    .execute(conn)?;
+            }
+            let mut updated = urls::table.find(url_entry.id).first::<Url>(conn)?;
+            validate_window(updated.activates_at, updated.deactivates_at)
+                .map_err(AppError::InvalidInput)?;
+            // Destinations the link already had are checked on redirect
+            let previous = url_entry.destinations();
+            let added: Vec<String> = updated
+                .destinations()
+                .into_iter()
+                .filter(|destination| !previous.contains(destination))
+                .collect();
+            domain_policy.check_all(&added).map_err(AppError::InvalidInput)?;
+            if screening::quarantine(conn, &threat_feeds.screen(updated.id, &added))? {
+                updated = urls::table.find(updated.id).first::<Url>(conn)?;
+            }
+            if let Some(workspace) = updated.workspace_id {
+                authorize_workspace(conn, &principal, workspace, Permission::EditLinks)?
+                    .check_destinations(&updated.destinations())
+                    .map_err(AppError::InvalidInput)?;
+            }
+            let tags = UrlTag::for_url(conn, updated.id)?;
+            let body = updated.to_json(&tags, &base_url);
+            webhooks::enqueue(conn, webhooks::LINK_UPDATED, &body)?;
+            actor
+                .entry(audit::LINK_UPDATED)
+                .link(&updated)
+                .before(&before)
+                .after(&body)
+                .record(conn)?;
+            Ok::<_, AppError>(body)
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(body))
+}
+
+fn parse_optional_timestamp(field: &str, value: Option<&str>) -> Result<Option<NaiveDateTime>, String> {
+    match value {
+        Some(value) => parse_timestamp(value)
+            .map(Some)
+            .ok_or_else(|| format!("{} must be an RFC 3339 timestamp", field)),
+        None => Ok(None),
+    }
+}
+
+/// Parses a timestamp field of an update, where `null` clears the column.
+fn parse_timestamp_change(
+    field: &str,
+    value: Option<Option<String>>,
+) -> Result<Option<Option<NaiveDateTime>>, AppError> {
+    match value {
+        Some(value) => parse_optional_timestamp(field, value.as_deref())
+            .map(Some)
+            .map_err(AppError::InvalidInput),
+        None => Ok(None),
+    }
+}
+
+/// Handler for deleting a link together with its tags, recorded clicks,
+/// threat feed screenings and abuse reports. Its moderation trail and audit
+/// log entries are kept.
+pub async fn delete_url_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    principal: Principal,
+    actor: Actor,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::{link_reports, link_screenings, redirect_stats, url_tags, urls, usage_logs};
+
+    let code = path.into_inner();
+    let base_url = config.base_url.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let url_entry = authorize_url(conn, &principal, &code, Permission::EditLinks)?;
+            let tags = UrlTag::for_url(conn, url_entry.id)?;
+            diesel::delete(url_tags::table.filter(url_tags::url_id.eq(url_entry.id))).execute(conn)?;
+            diesel::delete(redirect_stats::table.filter(redirect_stats::url_id.eq(url_entry.id)))
+                .execute(conn)?;
+            diesel::delete(usage_logs::table.filter(usage_logs::url_id.eq(url_entry.id))).execute(conn)?;
+            diesel::delete(link_screenings::table.filter(link_screenings::url_id.eq(url_entry.id)))
+                .execute(conn)?;
+            diesel::delete(link_reports::table.filter(link_reports::url_id.eq(url_entry.id)))
+                .execute(conn)?;
+            diesel::delete(urls::table.find(url_entry.id)).execute(conn)?;
+            let body = url_entry.to_json(&tags, &base_url);
+            webhooks::enqueue(conn, webhooks::LINK_DELETED, &body)?;
+            actor.entry(audit::LINK_DELETED).link(&url_entry).before(&body).record(conn)?;
+            Ok::<_, AppError>(())
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Hands a request for a link's own page under `/{code}/`, like `/preview`
+/// or `/qr`, to [`redirect_handler`], for links whose forwarded paths take
+/// precedence.
+pub async fn forward_to_redirect(req: HttpRequest) -> Result<HttpResponse, AppError> {
+    fn data<T: 'static>(req: &HttpRequest) -> Result<web::Data<T>, AppError> {
+        req.app_data::<web::Data<T>>().cloned().ok_or_else(|| {
+            AppError::InternalError(format!("{} missing", std::any::type_name::<T>()))
+        })
+    }
+
+    let response =
+        redirect_handler(data(&req)?, data(&req)?, data(&req)?, data(&req)?, req.clone()).await;
+    Ok(response.respond_to(&req).map_into_boxed_body())
+}
+
+/// A visit that ends in a redirect.
+struct Redirection {
+    url_entry: Url,
+    route: Route,
+    template: Option<UtmTemplate>,
+    tags: Vec<String>,
+    /// Warning to show instead of redirecting straight away.
+    warning: Option<Interstitial>,
+}
+
+/// Outcome of looking up a short code for a visit.
+enum Visit {
+    Redirect(Box<Redirection>),
+    /// The link exists but is outside its activation window or used up.
+    Unavailable(Box<Url>, LinkState),
+    /// The link is password-protected and the visitor has not unlocked it.
+    Locked(Box<Url>),
+    /// The destination's domain has been blocked since the link was created.
+    Blocked,
+    /// A threat feed lists a destination; the link awaits admin review.
+    Quarantined,
+    /// A moderator disabled the link.
+    Suspended,
+}
+
 /// Handler for redirecting a short URL to its original URL.
+///
+/// Also serves `/{code}/rest/of/path`, which only resolves for links with
+/// path forwarding enabled and never with `.` or `..` segments.
 pub async fn redirect_handler(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    domain_policy: web::Data<DomainPolicy>,
+    events: web::Data<EventHub>,
     req: HttpRequest,
 ) -> impl Responder {
     let code = req.match_info().get("code").unwrap_or("").to_string();
+    let tail = path_tail(req.path()).to_string();
+    let sticky_variant = req
+        .cookie(&variant_cookie_name(&code))
+        .map(|cookie| cookie.value().to_string());
+    let unlock = req
+        .cookie(&unlock_cookie_name(&code))
+        .map(|cookie| cookie.value().to_string());
+    let confirmation = req
+        .cookie(&confirmation_cookie_name(&code))
+        .map(|cookie| cookie.value().to_string());
+    let confirming = confirmation.is_some();
+    let unlock_config = config.clone();
+    let visitor = Visitor::from_request(&req);
+    let click_visitor = visitor.clone();
+    // Scans of the link's QR code carry a marker that is not part of the query.
+    let (query_string, scanned) = strip_scan_marker(req.query_string());
+    let forwarded_query = query_string.clone();
+    // Tags are only needed for the live event stream.
+    let load_tags = events.has_subscribers();
     let mut conn = pool.get().expect("Couldn't get db connection from pool");
     use crate::schema::urls::dsl::*;
-    match web::block(move || urls.filter(short_code.eq(code)).first::<Url>(&mut conn)).await {
-        Ok(Ok(url_entry)) => HttpResponse::Found()
-            .append_header(("Location", url_entry.original_url))
-            .finish(),
-        _ => HttpResponse::NotFound().body("URL not found"),
+    match web::block(move || {
+        let url_entry = urls.filter(short_code.eq(code)).first::<Url>(&mut conn)?;
+        if !tail.is_empty() && (!url_entry.forward_path || has_dot_segments(&tail)) {
+            return Err(diesel::result::Error::NotFound);
+        }
+        if url_entry.disabled_at.is_some() {
+            return Ok(Visit::Suspended);
+        }
+        match url_entry.state(Utc::now().naive_utc()) {
+            LinkState::Active => {}
+            state => return Ok(Visit::Unavailable(Box::new(url_entry), state)),
+        }
+        if url_entry.quarantined {
+            return Ok(Visit::Quarantined);
+        }
+        if !is_unlocked(&unlock_config, &url_entry, unlock.as_deref()) {
+            return Ok(Visit::Locked(Box::new(url_entry)));
+        }
+        let ctx = RuleContext::new(&click_visitor, &query_string);
+        let route = routing::route(&url_entry, &ctx, sticky_variant.as_deref());
+        if domain_policy.check(&route.target).is_err() {
+            return Ok(Visit::Blocked);
+        }
+        let exhausted = url_entry.max_clicks.is_some_and(|max| url_entry.clicks_used >= max);
+        if exhausted {
+            return Ok(Visit::Unavailable(Box::new(url_entry), LinkState::Exhausted));
+        }
+        // A warning page is not a redirect: the click is only claimed and
+        // counted when the visitor continues past it.
+        let warning = interstitial_for(&mut conn, &url_entry, &route.target)?.filter(|_| {
+            !is_confirmed(&unlock_config, &url_entry, &route.target, confirmation.as_deref())
+        });
+        if warning.is_none() {
+            if !claim_click(&mut conn, &url_entry)? {
+                return Ok(Visit::Unavailable(Box::new(url_entry), LinkState::Exhausted));
+            }
+            record_click(&mut conn, &url_entry, &click_visitor, &route, scanned.then_some(SCAN_SOURCE));
+        }
+        let template = utm::template_for_url(&mut conn, &url_entry)?;
+        let tags = if load_tags { UrlTag::for_url(&mut conn, url_entry.id)? } else { Vec::new() };
+        Ok::<_, diesel::result::Error>(Visit::Redirect(Box::new(Redirection {
+            url_entry,
+            route,
+            template,
+            tags,
+            warning,
+        })))
+    }).await {
+        Ok(Ok(Visit::Redirect(redirection))) => {
+            let Redirection { url_entry, route, template, tags, warning } = *redirection;
+            let location = destination(
+                &url_entry,
+                &route.target,
+                template.as_ref(),
+                path_tail(req.path()),
+                &forwarded_query,
+            );
+            let status = url_entry
+                .redirect_status
+                .and_then(|value| u16::try_from(value).ok())
+                .and_then(RedirectStatus::from_code)
+                .unwrap_or(config.default_redirect_status);
+            let mut response = match &warning {
+                Some(warning) => {
+                    let mut response =
+                        interstitial_response(&location, &req.uri().to_string(), warning);
+                    let _ = response.add_cookie(&confirmation_cookie(&config, &url_entry, &route.target));
+                    response
+                }
+                None => {
+                    let mut event = ClickEvent::new(&url_entry.short_code, &visitor, tags);
+                    event.owner_id = url_entry.owner_id;
+                    event.workspace_id = url_entry.workspace_id;
+                    events.publish(&event);
+                    let mut response =
+                        status.respond(&location, config.redirect_cache_max_age, route.personalized);
+                    if confirming {
+                        // Warn again on the next visit
+                        let _ = response.add_removal_cookie(&confirmation_cookie(
+                            &config,
+                            &url_entry,
+                            &route.target,
+                        ));
+                    }
+                    response
+                }
+            };
+            if url_entry.max_clicks.is_some() {
+                // A cached redirect would let visitors past the click limit
+                response.headers_mut().insert(
+                    header::CACHE_CONTROL,
+                    header::HeaderValue::from_static("no-store"),
+                );
+            }
+            if let (true, Some(variant)) = (route.sticky, &route.variant) {
+                let _ = response.add_cookie(&variant_cookie(&url_entry.short_code, variant));
+            }
+            response
+        }
+        Ok(Ok(Visit::Unavailable(url_entry, state))) => unavailable_response(&req, &url_entry, state),
+        Ok(Ok(Visit::Locked(url_entry))) => password_required_response(&url_entry.short_code),
+        Ok(Ok(Visit::Blocked)) => error_response(
+            &req,
+            ErrorPage::Disabled,
+            "This link points to a blocked domain",
+        ),
+        Ok(Ok(Visit::Quarantined)) => error_response(
+            &req,
+            ErrorPage::Quarantined,
+            "This link may lead to a harmful site and is held for review",
+        ),
+        Ok(Ok(Visit::Suspended)) => error_response(
+            &req,
+            ErrorPage::Suspended,
+            "This link has been disabled by a moderator",
+        ),
+        _ => error_response(&req, ErrorPage::NotFound, "URL not found"),
+    }
+}
+
+/// Stores a row in `redirect_stats` for a successful redirect and queues any
+/// click milestone webhooks it triggers.
+/// Failures are logged rather than surfaced so that analytics never block a redirect.
+fn record_click(
+    conn: &mut SqliteConnection,
+    url_entry: &Url,
+    visitor: &Visitor,
+    route: &Route,
+    source: Option<&str>,
+) {
+    use crate::schema::redirect_stats;
+
+    let click = NewRedirectStat {
+        url_id: url_entry.id,
+        ip_address: visitor.ip_address.clone(),
+        user_agent: visitor.user_agent.clone(),
+        branch: Some(route.branch.clone()),
+        variant: route.variant.clone(),
+        source: source.map(str::to_string),
+    };
+    // Counting inside the same write transaction keeps milestone checks
+    // accurate when clicks arrive concurrently.
+    let recorded = conn.immediate_transaction(|conn| {
+        diesel::insert_into(redirect_stats::table)
+            .values(&click)
+            .execute(conn)?;
+        webhooks::on_click(conn, url_entry)
+    });
+    if let Err(err) = recorded {
+        log::warn!("Failed to record click for {}: {}", url_entry.short_code, err);
     }
 }
 
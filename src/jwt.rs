@@ -0,0 +1,619 @@
+// src/jwt.rs
+// JSON Web Tokens issued by a trusted gateway, accepted in place of API keys.
+//
+// Tokens are signed with HS256, RS256 or ES256 and verified against keys
+// from any of the configured sources: a shared secret, a PEM public key, or
+// a JWKS file, which is read again when a token names a key it does not
+// know yet. The subject claim names the user, who is created on first use,
+// and an optional claim names the workspace new links go to.
+
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+    time::SystemTime,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use openssl::{
+    bn::BigNum,
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{Id, PKey, Public},
+    rsa::Rsa,
+    sign::Verifier,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::Sha256;
+
+use crate::{
+    auth::{Principal, Scope},
+    config::Config,
+    error::AppError,
+    models::Workspace,
+    users::{find_or_create_user, validate_username},
+};
+
+/// Scopes of tokens without a `scope` or `scopes` claim. What their user
+/// may do with each link still depends on ownership and workspace roles.
+pub const DEFAULT_SCOPES: [Scope; 3] = [Scope::LinksRead, Scope::LinksWrite, Scope::StatsRead];
+
+/// Smallest accepted RSA modulus, in bits.
+const MIN_RSA_BITS: u32 = 2048;
+
+/// A signature algorithm accepted in the `alg` header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Hs256,
+    Rs256,
+    Es256,
+}
+
+impl Algorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Hs256 => "HS256",
+            Algorithm::Rs256 => "RS256",
+            Algorithm::Es256 => "ES256",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Algorithm> {
+        [Algorithm::Hs256, Algorithm::Rs256, Algorithm::Es256]
+            .into_iter()
+            .find(|algorithm| algorithm.as_str() == value)
+    }
+}
+
+/// A key that could not be loaded.
+#[derive(Debug)]
+pub struct KeyError(String);
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+impl From<openssl::error::ErrorStack> for KeyError {
+    fn from(err: openssl::error::ErrorStack) -> Self {
+        KeyError(err.to_string())
+    }
+}
+
+enum KeyMaterial {
+    Hmac(Vec<u8>),
+    Rsa(PKey<Public>),
+    Ec(EcKey<Public>),
+}
+
+/// A key tokens may be signed with, optionally identified by a key id.
+pub struct VerificationKey {
+    kid: Option<String>,
+    material: KeyMaterial,
+}
+
+impl VerificationKey {
+    /// An HS256 shared secret.
+    pub fn secret(secret: &[u8]) -> Self {
+        VerificationKey {
+            kid: None,
+            material: KeyMaterial::Hmac(secret.to_vec()),
+        }
+    }
+
+    /// An RSA or P-256 public key in PEM format.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, KeyError> {
+        let key = PKey::public_key_from_pem(pem)?;
+        let material = match key.id() {
+            Id::RSA => rsa_key(key.rsa()?)?,
+            Id::EC => ec_key(key.ec_key()?)?,
+            _ => {
+                return Err(KeyError(
+                    "only RSA and P-256 public keys are supported".to_string(),
+                ))
+            },
+        };
+        Ok(VerificationKey {
+            kid: None,
+            material,
+        })
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self.material {
+            KeyMaterial::Hmac(_) => Algorithm::Hs256,
+            KeyMaterial::Rsa(_) => Algorithm::Rs256,
+            KeyMaterial::Ec(_) => Algorithm::Es256,
+        }
+    }
+
+    /// Whether this key may have signed a token with `algorithm` and `kid`.
+    /// Keys without an id match any token, and tokens without one match any
+    /// key.
+    fn matches(&self, algorithm: Algorithm, kid: Option<&str>) -> bool {
+        self.algorithm() == algorithm
+            && match (self.kid(), kid) {
+                (Some(own), Some(wanted)) => own == wanted,
+                _ => true,
+            }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match &self.material {
+            KeyMaterial::Hmac(secret) => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+                mac.update(message);
+                mac.verify_slice(signature).is_ok()
+            },
+            KeyMaterial::Rsa(key) => Verifier::new(MessageDigest::sha256(), key)
+                .and_then(|mut verifier| {
+                    verifier.update(message)?;
+                    verifier.verify(signature)
+                })
+                .unwrap_or(false),
+            KeyMaterial::Ec(key) => {
+                // JWS carries the raw `r || s` pair rather than DER.
+                if signature.len() != 64 {
+                    return false;
+                }
+                let (r, s) = signature.split_at(32);
+                BigNum::from_slice(r)
+                    .and_then(|r| EcdsaSig::from_private_components(r, BigNum::from_slice(s)?))
+                    .and_then(|sig| sig.verify(&openssl::sha::sha256(message), key))
+                    .unwrap_or(false)
+            },
+        }
+    }
+}
+
+fn rsa_key(rsa: Rsa<Public>) -> Result<KeyMaterial, KeyError> {
+    if rsa.n().num_bits() < MIN_RSA_BITS as i32 {
+        return Err(KeyError(format!(
+            "RSA keys must have at least {} bits",
+            MIN_RSA_BITS
+        )));
+    }
+    Ok(KeyMaterial::Rsa(PKey::from_rsa(rsa)?))
+}
+
+fn ec_key(key: EcKey<Public>) -> Result<KeyMaterial, KeyError> {
+    if key.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+        return Err(KeyError("EC keys must be on the P-256 curve".to_string()));
+    }
+    key.check_key()?;
+    Ok(KeyMaterial::Ec(key))
+}
+
+/// One key of a JWKS document.
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    #[serde(rename = "use")]
+    usage: Option<String>,
+    crv: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+    k: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+fn jwk_field(value: &Option<String>, name: &str) -> Result<Vec<u8>, KeyError> {
+    let value = value
+        .as_deref()
+        .ok_or_else(|| KeyError(format!("JWK without '{}'", name)))?;
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| KeyError(format!("JWK '{}' is not base64url", name)))
+}
+
+/// Parses a JWKS document. Keys meant for encryption (`"use": "enc"`) are
+/// left out.
+pub fn parse_jwks(json: &str) -> Result<Vec<VerificationKey>, KeyError> {
+    let set: JwkSet =
+        serde_json::from_str(json).map_err(|err| KeyError(format!("invalid JWKS: {}", err)))?;
+    let mut keys = Vec::new();
+    for jwk in set.keys {
+        if jwk.usage.as_deref().is_some_and(|usage| usage != "sig") {
+            continue;
+        }
+        let material = match jwk.kty.as_str() {
+            "oct" => KeyMaterial::Hmac(jwk_field(&jwk.k, "k")?),
+            "RSA" => rsa_key(Rsa::from_public_components(
+                BigNum::from_slice(&jwk_field(&jwk.n, "n")?)?,
+                BigNum::from_slice(&jwk_field(&jwk.e, "e")?)?,
+            )?)?,
+            "EC" if jwk.crv.as_deref() == Some("P-256") => {
+                let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+                let x = BigNum::from_slice(&jwk_field(&jwk.x, "x")?)?;
+                let y = BigNum::from_slice(&jwk_field(&jwk.y, "y")?)?;
+                ec_key(EcKey::from_public_key_affine_coordinates(&group, &x, &y)?)?
+            },
+            other => {
+                return Err(KeyError(format!(
+                    "unsupported JWK type {} {}",
+                    other,
+                    jwk.crv.unwrap_or_default()
+                )))
+            },
+        };
+        keys.push(VerificationKey {
+            kid: jwk.kid,
+            material,
+        });
+    }
+    Ok(keys)
+}
+
+/// Where verification keys come from.
+pub enum KeySource {
+    /// An HS256 shared secret.
+    Secret(String),
+    /// A PEM file with one RSA or P-256 public key.
+    PemFile(PathBuf),
+    /// A JWKS file, read again when its modification time changes.
+    JwksFile(PathBuf),
+}
+
+impl KeySource {
+    pub fn load(&self) -> Result<Vec<VerificationKey>, KeyError> {
+        let read = |path: &PathBuf| {
+            fs::read(path)
+                .map_err(|err| KeyError(format!("cannot read {}: {}", path.display(), err)))
+        };
+        match self {
+            KeySource::Secret(secret) => Ok(vec![VerificationKey::secret(secret.as_bytes())]),
+            KeySource::PemFile(path) => Ok(vec![VerificationKey::from_pem(&read(path)?)?]),
+            KeySource::JwksFile(path) => parse_jwks(&String::from_utf8_lossy(&read(path)?)),
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        match self {
+            KeySource::JwksFile(path) => fs::metadata(path).and_then(|meta| meta.modified()).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// The workspace named by a token, by id or by name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WorkspaceClaim {
+    Id(i32),
+    Name(String),
+}
+
+/// What a verified token says about its bearer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenClaims {
+    pub subject: String,
+    pub workspace: Option<WorkspaceClaim>,
+    /// `None` when the token has no scope claim.
+    pub scopes: Option<Vec<Scope>>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+/// A token split into its parts. Its signature is not checked yet.
+pub struct SignedToken<'a> {
+    pub algorithm: Algorithm,
+    pub kid: Option<String>,
+    /// The `header.payload` part the signature covers.
+    signed: &'a str,
+    payload: &'a str,
+    signature: Vec<u8>,
+}
+
+impl<'a> SignedToken<'a> {
+    pub fn parse(token: &'a str) -> Result<Self, AppError> {
+        let parts: Vec<&str> = token.split('.').collect();
+        let [encoded_header, payload, signature] = parts[..] else {
+            return Err(unauthorized("malformed token"));
+        };
+        let header: Header = decode_part(encoded_header)?;
+        let algorithm = Algorithm::parse(&header.alg).ok_or_else(|| {
+            AppError::Unauthorized(format!("unsupported token algorithm {}", header.alg))
+        })?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| unauthorized("malformed token"))?;
+        Ok(SignedToken {
+            algorithm,
+            kid: header.kid,
+            signed: &token[..encoded_header.len() + 1 + payload.len()],
+            payload,
+            signature,
+        })
+    }
+
+    /// Whether one of the `keys` matching the token's algorithm and key id
+    /// signed it; `None` when no key matches.
+    pub fn check(&self, keys: &[VerificationKey]) -> Option<bool> {
+        let candidates: Vec<_> = keys
+            .iter()
+            .filter(|key| key.matches(self.algorithm, self.kid.as_deref()))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(
+            candidates
+                .iter()
+                .any(|key| key.verify(self.signed.as_bytes(), &self.signature)),
+        )
+    }
+
+    pub fn claims(&self) -> Result<Map<String, Value>, AppError> {
+        decode_part(self.payload)
+    }
+}
+
+/// Checks the required `exp` claim and the optional `nbf` claim at `now`,
+/// tolerating `leeway` seconds of clock skew.
+pub fn check_lifetime(claims: &Map<String, Value>, now: i64, leeway: i64) -> Result<(), AppError> {
+    let expires = claims
+        .get("exp")
+        .and_then(Value::as_i64)
+        .ok_or_else(|| unauthorized("token has no exp claim"))?;
+    if now > expires.saturating_add(leeway) {
+        return Err(unauthorized("token has expired"));
+    }
+    if let Some(not_before) = claims.get("nbf").and_then(Value::as_i64) {
+        if now.saturating_add(leeway) < not_before {
+            return Err(unauthorized("token is not valid yet"));
+        }
+    }
+    Ok(())
+}
+
+/// Whether the `aud` claim is or contains `audience`.
+pub fn has_audience(claims: &Map<String, Value>, audience: &str) -> bool {
+    match claims.get("aud") {
+        Some(Value::String(aud)) => aud == audience,
+        Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+        _ => false,
+    }
+}
+
+/// Verifies bearer tokens. Disabled, accepting no token, when no key source
+/// is configured.
+#[derive(Default)]
+pub struct JwtVerifier {
+    sources: Vec<KeySource>,
+    keys: RwLock<Vec<VerificationKey>>,
+    /// Modification times of the sources when their keys were loaded.
+    loaded: Mutex<Vec<Option<SystemTime>>>,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    /// Clock skew tolerated on `exp` and `nbf`, in seconds.
+    pub leeway: i64,
+    pub user_claim: String,
+    pub workspace_claim: String,
+}
+
+fn unauthorized(message: &str) -> AppError {
+    AppError::Unauthorized(message.to_string())
+}
+
+fn decode_part<T: serde::de::DeserializeOwned>(part: &str) -> Result<T, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(|| unauthorized("malformed token"))
+}
+
+impl JwtVerifier {
+    /// Loads every source once. Fails if any of them cannot be read.
+    pub fn new(sources: Vec<KeySource>) -> Result<Self, KeyError> {
+        let mut keys = Vec::new();
+        for source in &sources {
+            keys.extend(source.load()?);
+        }
+        let loaded = sources.iter().map(KeySource::modified).collect();
+        Ok(JwtVerifier {
+            sources,
+            keys: RwLock::new(keys),
+            loaded: Mutex::new(loaded),
+            leeway: 60,
+            user_claim: "sub".to_string(),
+            workspace_claim: "workspace".to_string(),
+            ..JwtVerifier::default()
+        })
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, KeyError> {
+        let mut sources = Vec::new();
+        if let Some(secret) = &config.jwt_secret {
+            sources.push(KeySource::Secret(secret.clone()));
+        }
+        if let Some(path) = &config.jwt_public_key {
+            sources.push(KeySource::PemFile(path.clone()));
+        }
+        if let Some(path) = &config.jwt_jwks_file {
+            sources.push(KeySource::JwksFile(path.clone()));
+        }
+        Ok(JwtVerifier {
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            leeway: i64::try_from(config.jwt_leeway.as_secs()).unwrap_or(i64::MAX),
+            user_claim: config.jwt_user_claim.clone(),
+            workspace_claim: config.jwt_workspace_claim.clone(),
+            ..JwtVerifier::new(sources)?
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.sources.is_empty()
+    }
+
+    /// Reloads the keys if a JWKS file changed since it was last read.
+    /// Returns whether the keys were reloaded.
+    fn refresh(&self) -> bool {
+        let current: Vec<_> = self.sources.iter().map(KeySource::modified).collect();
+        let mut loaded = self.loaded.lock().unwrap();
+        if *loaded == current {
+            return false;
+        }
+        let mut keys = Vec::new();
+        for source in &self.sources {
+            match source.load() {
+                Ok(source_keys) => keys.extend(source_keys),
+                Err(err) => {
+                    // Keep the keys we have until the file is fixed.
+                    log::warn!("Cannot reload JWT keys: {}", err);
+                    return false;
+                },
+            }
+        }
+        *self.keys.write().unwrap() = keys;
+        *loaded = current;
+        true
+    }
+
+    /// Checks the signature of `token`, reloading the keys once if none
+    /// matches it.
+    fn check_signature(&self, token: &SignedToken) -> Result<(), AppError> {
+        // Release the read lock before a refresh takes the write lock
+        let checked = token.check(&self.keys.read().unwrap());
+        let verified = match checked {
+            Some(verified) => verified,
+            None if self.refresh() => token.check(&self.keys.read().unwrap()).unwrap_or(false),
+            None => return Err(unauthorized("no key matches the token")),
+        };
+        if !verified {
+            return Err(unauthorized("invalid token signature"));
+        }
+        Ok(())
+    }
+
+    /// Verifies `token` at `now` (seconds since the epoch) and returns its
+    /// claims.
+    pub fn verify(&self, token: &str, now: i64) -> Result<TokenClaims, AppError> {
+        let token = SignedToken::parse(token)?;
+        self.check_signature(&token)?;
+        let claims = token.claims()?;
+        check_lifetime(&claims, now, self.leeway)?;
+        if let Some(issuer) = &self.issuer {
+            if claims.get("iss").and_then(Value::as_str) != Some(issuer.as_str()) {
+                return Err(unauthorized("token issuer is not accepted"));
+            }
+        }
+        if let Some(audience) = &self.audience {
+            if !has_audience(&claims, audience) {
+                return Err(unauthorized("token audience is not accepted"));
+            }
+        }
+
+        let subject = claims
+            .get(&self.user_claim)
+            .and_then(Value::as_str)
+            .filter(|subject| !subject.is_empty())
+            .ok_or_else(|| {
+                AppError::Unauthorized(format!("token has no {} claim", self.user_claim))
+            })?
+            .to_string();
+        let workspace = match claims.get(&self.workspace_claim) {
+            None | Some(Value::Null) => None,
+            Some(Value::String(name)) => Some(WorkspaceClaim::Name(name.clone())),
+            Some(value) => Some(WorkspaceClaim::Id(
+                value
+                    .as_i64()
+                    .and_then(|id| i32::try_from(id).ok())
+                    .ok_or_else(|| {
+                        AppError::Unauthorized(format!(
+                            "token {} claim must be a workspace id or name",
+                            self.workspace_claim
+                        ))
+                    })?,
+            )),
+        };
+        let scopes = match (claims.get("scope"), claims.get("scopes")) {
+            (Some(Value::String(scope)), _) => {
+                Some(scope.split_whitespace().filter_map(Scope::parse).collect())
+            },
+            (_, Some(Value::Array(scopes))) => Some(
+                scopes
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .filter_map(Scope::parse)
+                    .collect(),
+            ),
+            _ => None,
+        };
+        Ok(TokenClaims {
+            subject,
+            workspace,
+            scopes,
+        })
+    }
+}
+
+/// Whether a presented credential is a JWT rather than an API key.
+pub fn looks_like_jwt(credential: &str) -> bool {
+    credential.matches('.').count() == 2
+}
+
+/// The caller described by verified claims. The user is created on first
+/// use; the workspace must exist.
+pub fn principal_for(
+    conn: &mut SqliteConnection,
+    claims: TokenClaims,
+) -> Result<Principal, AppError> {
+    use crate::schema::workspaces;
+
+    validate_username(&claims.subject)
+        .map_err(|_| unauthorized("token subject is not a valid username"))?;
+    let user = find_or_create_user(conn, &claims.subject)?;
+    let workspace_id = match &claims.workspace {
+        Some(claim) => {
+            let found = match claim {
+                WorkspaceClaim::Id(id) => workspaces::table
+                    .find(*id)
+                    .first::<Workspace>(conn)
+                    .optional()?,
+                WorkspaceClaim::Name(name) => workspaces::table
+                    .filter(workspaces::name.eq(name))
+                    .first::<Workspace>(conn)
+                    .optional()?,
+            };
+            Some(
+                found
+                    .ok_or_else(|| unauthorized("token names an unknown workspace"))?
+                    .id,
+            )
+        },
+        None => None,
+    };
+    Ok(Principal {
+        key_id: None,
+        session_id: None,
+        name: claims.subject,
+        scopes: claims.scopes.unwrap_or_else(|| DEFAULT_SCOPES.to_vec()),
+        user_id: Some(user.id),
+        workspace_id,
+    })
+}
@@ -1,12 +1,39 @@
 #[macro_use]
 extern crate diesel;
 
+pub mod audit;
+pub mod auth;
 pub mod config;
 pub mod db;
+pub mod domain_policy;
 pub mod error;
+pub mod error_pages;
+pub mod events;
+pub mod export;
+pub mod geo;
 pub mod handlers;
+pub mod jwt;
+pub mod limits;
 pub mod loggers;
 pub mod models;
+pub mod moderation;
+pub mod oidc;
+pub mod password;
+pub mod preview;
+pub mod qr;
+pub mod ratelimit;
+pub mod redirect;
 pub mod routes;
+pub mod routing;
+pub mod rules;
+pub mod schedule;
 pub mod schema;
-pub mod utils;
\ No newline at end of file
+pub mod screening;
+pub mod server;
+pub mod stats;
+pub mod users;
+pub mod utils;
+pub mod utm;
+pub mod visitor;
+pub mod webhooks;
+pub mod workspaces;
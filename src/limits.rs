@@ -0,0 +1,38 @@
+// src/limits.rs
+// Click-limited links, e.g. single-use download links.
+
+use diesel::prelude::*;
+
+use crate::models::Url;
+
+impl Url {
+    /// Clicks left before the link stops redirecting, if it is limited.
+    pub fn remaining_clicks(&self) -> Option<i32> {
+        self.max_clicks
+            .map(|max_clicks| (max_clicks - self.clicks_used).max(0))
+    }
+}
+
+/// Takes one click from a limited link's budget. Returns `false` when the
+/// budget is spent.
+///
+/// The check and the increment are a single `UPDATE`, which SQLite runs
+/// under its write lock, so concurrent requests, including ones from other
+/// processes sharing the database, can never use more clicks than allowed.
+pub fn claim_click(conn: &mut SqliteConnection, url_entry: &Url) -> QueryResult<bool> {
+    use crate::schema::urls::dsl::*;
+
+    if url_entry.max_clicks.is_none() {
+        return Ok(true);
+    }
+    let claimed = diesel::update(
+        urls.find(url_entry.id).filter(
+            max_clicks
+                .is_null()
+                .or(clicks_used.lt(max_clicks.assume_not_null())),
+        ),
+    )
+    .set(clicks_used.eq(clicks_used + 1))
+    .execute(conn)?;
+    Ok(claimed == 1)
+}
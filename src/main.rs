@@ -1,20 +1,68 @@
-#[macro_use]
-extern crate diesel;
-
-mod config;
-mod db;
-mod error;
-mod handlers;
-mod loggers;
-mod models;
-mod routes;
-mod schema;
-mod utils;
-
-use actix_web::{middleware::Logger, web, App, HttpServer};
-use db::establish_connection_pool;
+use std::{
+    fs::File,
+    io::{self, Write},
+    net::TcpListener,
+    path::PathBuf,
+};
+
+use clap::{Parser, Subcommand};
 use dotenvy::dotenv;
-use std::env;
+use rust_url_shortener::{
+    audit::{self, Actor},
+    auth::{create_key, parse_scopes},
+    config::Config,
+    db::{establish_connection_pool, run_migrations},
+    export::{export_clicks, ExportQuery},
+    loggers, server,
+    users::{find_or_create_user, validate_username},
+    utils::parse_timestamp,
+};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the HTTP server (the default when no command is given)
+    Serve,
+    /// Export recorded clicks as CSV, NDJSON or Parquet
+    Export {
+        /// Output format: csv, ndjson or parquet
+        #[arg(long, default_value = "csv")]
+        format: String,
+        /// Only include clicks at or after this RFC 3339 timestamp
+        #[arg(long)]
+        from: Option<String>,
+        /// Only include clicks before this RFC 3339 timestamp
+        #[arg(long)]
+        to: Option<String>,
+        /// Only include clicks on this short code
+        #[arg(long)]
+        code: Option<String>,
+        /// File to write to; defaults to standard output
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Create an API key and print it; use this to get the first admin key
+    CreateApiKey {
+        /// Name to tell the key apart in listings
+        #[arg(long)]
+        name: String,
+        /// Comma-separated scopes: links:read, links:write, stats:read, admin
+        #[arg(long, value_delimiter = ',', default_value = "admin")]
+        scopes: Vec<String>,
+        /// RFC 3339 timestamp after which the key stops working
+        #[arg(long)]
+        expires_at: Option<String>,
+        /// User the key acts for, created if it does not exist yet
+        #[arg(long)]
+        user: Option<String>,
+    },
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -22,29 +70,84 @@ async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
     // Initialize the logger (env_logger logs info to stdout)
-    env_logger::init();
+    loggers::init_logging();
+
+    let cli = Cli::parse();
 
-    // Retrieve the DATABASE_URL from the environment. The application will panic if it's not set.
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    // The application will panic if DATABASE_URL is not set.
+    let config = Config::from_env();
 
     // Establish a connection pool using the provided database URL
-    let pool = establish_connection_pool(&database_url);
-
-    // Define the server address to bind to (listening on port 8080)
-    let server_address = "0.0.0.0:8080";
-    println!("Starting server at: {}", server_address);
-
-    // Create and run the HTTP server using Actix-web
-    HttpServer::new(move || {
-        App::new()
-            // Share the database pool across all application routes
-            .app_data(web::Data::new(pool.clone()))
-            // Use default logging middleware to log HTTP requests
-            .wrap(Logger::default())
-            // Configure the application routes defined in the routes module
-            .configure(routes::init_routes)
-    })
-    .bind(server_address)?
-    .run()
-    .await
+    let pool = establish_connection_pool(&config.database_url);
+    run_migrations(&pool);
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            // Define the server address to bind to (listening on port 8080)
+            let server_address = "0.0.0.0:8080";
+            println!("Starting server at: {}", server_address);
+
+            let listener = TcpListener::bind(server_address)?;
+            server::run(listener, pool, config)?.await
+        },
+        Command::Export {
+            format,
+            from,
+            to,
+            code,
+            output,
+        } => {
+            let query = ExportQuery {
+                format: Some(format),
+                from,
+                to,
+                code,
+            };
+            let entry = Actor::cli()
+                .entry(audit::CLICKS_EXPORTED)
+                .short_code(query.code.as_deref())
+                .after(&serde_json::json!(query));
+            let (format, filter) = query.parse().map_err(io::Error::other)?;
+            let mut out: Box<dyn Write> = match output {
+                Some(path) => Box::new(File::create(path)?),
+                None => Box::new(io::stdout().lock()),
+            };
+            let mut conn = pool.get().map_err(io::Error::other)?;
+            let count =
+                export_clicks(&mut conn, &filter, format, &mut out).map_err(io::Error::other)?;
+            out.flush()?;
+            entry.record(&mut conn).map_err(io::Error::other)?;
+            log::info!("Exported {} clicks", count);
+            Ok(())
+        },
+        Command::CreateApiKey {
+            name,
+            scopes,
+            expires_at,
+            user,
+        } => {
+            let scopes = parse_scopes(&scopes).map_err(io::Error::other)?;
+            let expires_at = match expires_at {
+                Some(value) => Some(parse_timestamp(&value).ok_or_else(|| {
+                    io::Error::other(format!("expires_at '{}' is not a timestamp", value))
+                })?),
+                None => None,
+            };
+            let mut conn = pool.get().map_err(io::Error::other)?;
+            let user_id = match user {
+                Some(username) => {
+                    validate_username(&username).map_err(io::Error::other)?;
+                    let user =
+                        find_or_create_user(&mut conn, &username).map_err(io::Error::other)?;
+                    Some(user.id)
+                },
+                None => None,
+            };
+            let (row, key) = create_key(&mut conn, &name, &scopes, expires_at, user_id)
+                .map_err(io::Error::other)?;
+            log::info!("Created API key {} ({})", row.id, row.name);
+            println!("{}", key);
+            Ok(())
+        },
+    }
 }
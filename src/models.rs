@@ -1,5 +1,13 @@
-use crate::schema::urls;
+use std::collections::HashMap;
+
+use crate::schema::{
+    api_keys, audit_log, interstitial_domains, link_reports, link_screenings, moderation_actions,
+    rate_limit_buckets, redirect_stats, sessions,
+    tag_utm_templates, url_tags, urls, users, webhook_deliveries, webhooks, workspace_members,
+    workspaces,
+};
 use chrono::NaiveDateTime;
+use diesel::{QueryResult, SqliteConnection};
 use serde::{Deserialize, Serialize};
 
 #[derive(Queryable, Serialize)]
@@ -8,11 +16,513 @@ pub struct Url {
     pub original_url: String,
     pub short_code: String,
     pub created_at: NaiveDateTime,
+    pub expiration_date: Option<NaiveDateTime>,
+    /// Set once the `link.expired` webhook event has been queued.
+    #[serde(skip_serializing)]
+    pub expiry_notified: bool,
+    /// Per-link redirect status; `None` uses the configured default.
+    pub redirect_status: Option<i32>,
+    /// Merge the visitor's query string into the destination.
+    pub forward_query: bool,
+    /// Append any path after the short code to the destination.
+    pub forward_path: bool,
+    /// UTM template as JSON; see `crate::utm::UtmTemplate`.
+    #[serde(skip_serializing)]
+    pub utm_template: Option<String>,
+    /// Per-platform destinations as JSON; see `crate::routing::PlatformDestinations`.
+    #[serde(skip_serializing)]
+    pub platform_destinations: Option<String>,
+    /// Weighted A/B destinations as JSON; see `crate::routing::SplitTest`.
+    #[serde(skip_serializing)]
+    pub split_test: Option<String>,
+    /// Conditional redirect rules as JSON; see `crate::rules::Rule`.
+    #[serde(skip_serializing)]
+    pub rules: Option<String>,
+    /// The link redirects from this time on.
+    pub activates_at: Option<NaiveDateTime>,
+    /// The link stops redirecting at this time.
+    pub deactivates_at: Option<NaiveDateTime>,
+    /// Where visitors go while the link is not active.
+    pub fallback_url: Option<String>,
+    /// Number of redirects after which the link is used up.
+    pub max_clicks: Option<i32>,
+    /// Redirects counted against `max_clicks`.
+    pub clicks_used: i32,
+    /// Argon2 hash of the password visitors must enter, if any.
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+    /// Always show a warning page before redirecting.
+    pub interstitial: bool,
+    /// User the link belongs to; `None` for links only admins manage.
+    pub owner_id: Option<i32>,
+    /// Workspace the link belongs to; its members' roles decide who may
+    /// manage it.
+    pub workspace_id: Option<i32>,
+    /// A destination matched a threat feed; visitors get a warning page
+    /// until an admin releases the link.
+    pub quarantined: bool,
+    /// When a moderator disabled the link; visitors get the suspended page.
+    pub disabled_at: Option<NaiveDateTime>,
+    /// Why the link was disabled.
+    pub disabled_reason: Option<String>,
+}
+
+impl Url {
+    /// The public representation of a link, as returned by the API and sent
+    /// in webhook payloads.
+    pub fn to_json(&self, tags: &[String], base_url: &str) -> serde_json::Value {
+        serde_json::json!({
+            "original_url": self.original_url,
+            "short_code": self.short_code,
+            "short_url": format!("{}/{}", base_url, self.short_code),
+            "created_at": self.created_at,
+            "expiration_date": self.expiration_date,
+            "redirect_status": self.redirect_status,
+            "forward_query": self.forward_query,
+            "forward_path": self.forward_path,
+            "utm": self.utm(),
+            "platform_destinations": self.platform_destinations(),
+            "split": self.split(),
+            "rules": self.rules(),
+            "activates_at": self.activates_at,
+            "deactivates_at": self.deactivates_at,
+            "fallback_url": self.fallback_url,
+            "max_clicks": self.max_clicks,
+            "remaining_clicks": self.remaining_clicks(),
+            "password_protected": self.is_password_protected(),
+            "interstitial": self.interstitial,
+            "owner_id": self.owner_id,
+            "workspace_id": self.workspace_id,
+            "quarantined": self.quarantined,
+            "disabled_at": self.disabled_at,
+            "disabled_reason": self.disabled_reason,
+            "tags": tags
+        })
+    }
 }
 
-#[derive(Insertable, Deserialize)]
+#[derive(Insertable, Deserialize, Default)]
 #[diesel(table_name = urls)]
 pub struct NewUrl {
     pub original_url: String,
     pub short_code: String,
+    pub expiration_date: Option<NaiveDateTime>,
+    pub redirect_status: Option<i32>,
+    pub forward_query: bool,
+    pub forward_path: bool,
+    pub utm_template: Option<String>,
+    pub platform_destinations: Option<String>,
+    pub split_test: Option<String>,
+    pub rules: Option<String>,
+    pub activates_at: Option<NaiveDateTime>,
+    pub deactivates_at: Option<NaiveDateTime>,
+    pub fallback_url: Option<String>,
+    pub max_clicks: Option<i32>,
+    pub password_hash: Option<String>,
+    pub interstitial: bool,
+    pub owner_id: Option<i32>,
+    pub workspace_id: Option<i32>,
+}
+
+/// Partial update of a link. `None` leaves a column unchanged.
+#[derive(AsChangeset, Default, PartialEq)]
+#[diesel(table_name = urls)]
+pub struct UrlChanges {
+    pub original_url: Option<String>,
+    pub expiration_date: Option<Option<NaiveDateTime>>,
+    pub expiry_notified: Option<bool>,
+    pub redirect_status: Option<Option<i32>>,
+    pub forward_query: Option<bool>,
+    pub forward_path: Option<bool>,
+    pub utm_template: Option<Option<String>>,
+    pub platform_destinations: Option<Option<String>>,
+    pub split_test: Option<Option<String>>,
+    pub rules: Option<Option<String>>,
+    pub activates_at: Option<Option<NaiveDateTime>>,
+    pub deactivates_at: Option<Option<NaiveDateTime>>,
+    pub fallback_url: Option<Option<String>>,
+    pub max_clicks: Option<Option<i32>>,
+    pub password_hash: Option<Option<String>>,
+    pub interstitial: Option<bool>,
+    pub owner_id: Option<Option<i32>>,
+    pub workspace_id: Option<Option<i32>>,
+}
+
+/// A single recorded click on a short link.
+#[derive(Queryable, Serialize)]
+pub struct RedirectStat {
+    pub id: i32,
+    pub url_id: i32,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub accessed_at: NaiveDateTime,
+    /// Destination the visitor was sent to, e.g. `ios` or `default`.
+    pub branch: Option<String>,
+    /// A/B variant the visitor was sent to.
+    pub variant: Option<String>,
+    /// How the visitor arrived: `qr` for a QR code scan, otherwise unset.
+    pub source: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = redirect_stats)]
+pub struct NewRedirectStat {
+    pub url_id: i32,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub branch: Option<String>,
+    pub variant: Option<String>,
+    pub source: Option<String>,
+}
+
+/// A free-form label attached to a short link, used for grouping and filtering.
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = url_tags)]
+pub struct UrlTag {
+    pub url_id: i32,
+    pub tag: String,
+}
+
+impl UrlTag {
+    /// Loads the tags of a link in alphabetical order.
+    pub fn for_url(conn: &mut SqliteConnection, id: i32) -> QueryResult<Vec<String>> {
+        use diesel::prelude::*;
+
+        url_tags::table
+            .filter(url_tags::url_id.eq(id))
+            .select(url_tags::tag)
+            .order(url_tags::tag.asc())
+            .load(conn)
+    }
+
+    /// Loads the tags of several links at once, in alphabetical order per
+    /// link. Links without tags are left out.
+    pub fn for_urls(
+        conn: &mut SqliteConnection,
+        ids: &[i32],
+    ) -> QueryResult<HashMap<i32, Vec<String>>> {
+        use diesel::prelude::*;
+
+        let rows: Vec<(i32, String)> = url_tags::table
+            .filter(url_tags::url_id.eq_any(ids))
+            .select((url_tags::url_id, url_tags::tag))
+            .order((url_tags::url_id.asc(), url_tags::tag.asc()))
+            .load(conn)?;
+        let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+        for (id, tag) in rows {
+            tags.entry(id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
+}
+
+/// A key for the management API. The key itself is only shown when created.
+#[derive(Queryable)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    /// Public part of the key, used to look it up.
+    pub prefix: String,
+    /// SHA-256 of the full key, hex-encoded.
+    pub key_hash: String,
+    /// JSON array of scopes; see `crate::auth::Scope`.
+    pub scopes: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    /// User the key acts for; `None` for keys not tied to a user.
+    pub user_id: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = api_keys)]
+pub struct NewApiKey {
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scopes: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub user_id: Option<i32>,
+}
+
+/// A signed-in browser. The session token itself only lives in the cookie.
+#[derive(Queryable)]
+pub struct Session {
+    pub id: i32,
+    /// SHA-256 of the session token, hex-encoded.
+    pub token_hash: String,
+    pub user_id: i32,
+    /// JSON array of scopes granted at sign-in; see `crate::auth::Scope`.
+    pub scopes: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = sessions)]
+pub struct NewSession {
+    pub token_hash: String,
+    pub user_id: i32,
+    pub scopes: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// An account that owns links and API keys.
+#[derive(Queryable, Serialize)]
+pub struct User {
+    pub id: i32,
+    pub username: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = users)]
+pub struct NewUser {
+    pub username: String,
+}
+
+/// A group of users sharing links, with settings applied to its links.
+#[derive(Queryable)]
+pub struct Workspace {
+    pub id: i32,
+    pub name: String,
+    /// Length of generated short codes; the server default when `None`.
+    pub default_code_length: Option<i32>,
+    /// Links created without an expiration date expire this long after
+    /// creation.
+    pub default_expiry_secs: Option<i64>,
+    /// JSON array of domains links may point to; any when `None`.
+    pub allowed_domains: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = workspaces)]
+pub struct NewWorkspace {
+    pub name: String,
+    pub default_code_length: Option<i32>,
+    pub default_expiry_secs: Option<i64>,
+    pub allowed_domains: Option<String>,
+}
+
+/// Partial update of a workspace. `None` leaves a column unchanged.
+#[derive(AsChangeset, Default, PartialEq)]
+#[diesel(table_name = workspaces)]
+pub struct WorkspaceChanges {
+    pub name: Option<String>,
+    pub default_code_length: Option<Option<i32>>,
+    pub default_expiry_secs: Option<Option<i64>>,
+    pub allowed_domains: Option<Option<String>>,
+}
+
+/// A user's role in a workspace; see `crate::workspaces::Role`.
+#[derive(Queryable, Serialize)]
+pub struct WorkspaceMember {
+    pub workspace_id: i32,
+    pub user_id: i32,
+    pub role: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = workspace_members)]
+pub struct NewWorkspaceMember {
+    pub workspace_id: i32,
+    pub user_id: i32,
+    pub role: String,
+}
+
+/// Destination domain whose links always show a warning page first.
+#[derive(Queryable, Serialize)]
+pub struct InterstitialDomain {
+    pub domain: String,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = interstitial_domains)]
+pub struct NewInterstitialDomain {
+    pub domain: String,
+    pub reason: Option<String>,
+}
+
+/// UTM template applied to every link carrying `tag`.
+#[derive(Queryable)]
+pub struct TagUtmTemplate {
+    pub tag: String,
+    /// JSON object; see `crate::utm::UtmTemplate`.
+    pub template: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = tag_utm_templates)]
+pub struct NewTagUtmTemplate {
+    pub tag: String,
+    pub template: String,
+}
+
+/// An endpoint that receives signed event notifications.
+#[derive(Queryable, Serialize)]
+pub struct Webhook {
+    pub id: i32,
+    pub target_url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// JSON array of subscribed event types; empty means all.
+    pub events: String,
+    /// JSON array of click counts that trigger `link.click_milestone`.
+    pub click_milestones: String,
+    pub active: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhooks)]
+pub struct NewWebhook {
+    pub target_url: String,
+    pub secret: String,
+    pub events: String,
+    pub click_milestones: String,
+}
+
+/// One queued or completed attempt to notify a webhook of an event.
+#[derive(Queryable, Serialize)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhook_id: i32,
+    pub event_type: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct NewWebhookDelivery {
+    pub webhook_id: i32,
+    pub event_type: String,
+    pub payload: String,
+}
+
+/// Token bucket of the rate limiter's shared store.
+#[derive(Queryable, Insertable)]
+#[diesel(table_name = rate_limit_buckets)]
+pub struct RateLimitBucket {
+    pub key: String,
+    pub tokens: f64,
+    /// Milliseconds since the Unix epoch.
+    pub updated_at: i64,
+}
+
+/// A link destination that matched a threat feed, awaiting or after admin
+/// review.
+#[derive(Queryable, Serialize)]
+pub struct LinkScreening {
+    pub id: i32,
+    pub url_id: i32,
+    pub destination: String,
+    /// Name of the feed that listed the destination.
+    pub feed: String,
+    /// `pending`, `confirmed` or `released`.
+    pub status: String,
+    pub flagged_at: NaiveDateTime,
+    pub reviewed_at: Option<NaiveDateTime>,
+    pub reviewed_by: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = link_screenings)]
+pub struct NewLinkScreening {
+    pub url_id: i32,
+    pub destination: String,
+    pub feed: String,
+}
+
+/// A visitor's report of an abusive link.
+#[derive(Queryable, Serialize)]
+pub struct LinkReport {
+    pub id: i32,
+    pub url_id: i32,
+    pub reason: String,
+    pub reporter_ip: Option<String>,
+    /// `open`, `actioned` or `dismissed`.
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub resolved_by: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = link_reports)]
+pub struct NewLinkReport {
+    pub url_id: i32,
+    pub reason: String,
+    pub reporter_ip: Option<String>,
+}
+
+/// One entry of a link's moderation trail.
+#[derive(Queryable, Serialize)]
+pub struct ModerationAction {
+    pub id: i32,
+    pub url_id: i32,
+    pub short_code: String,
+    /// `reported`, `disabled`, `enabled` or `dismissed`.
+    pub action: String,
+    pub reason: Option<String>,
+    pub report_id: Option<i32>,
+    /// Name of the moderator's credential, or `visitor` for reports.
+    pub actor: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = moderation_actions)]
+pub struct NewModerationAction {
+    pub url_id: i32,
+    pub short_code: String,
+    pub action: String,
+    pub reason: Option<String>,
+    pub report_id: Option<i32>,
+    pub actor: String,
+}
+
+/// An entry of the append-only audit log of management actions.
+#[derive(Queryable, Serialize)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub created_at: NaiveDateTime,
+    pub actor: String,
+    pub actor_user_id: Option<i32>,
+    pub ip_address: Option<String>,
+    pub action: String,
+    pub url_id: Option<i32>,
+    pub short_code: Option<String>,
+    /// JSON of the link before the action, if it existed. The API returns
+    /// it parsed, as `before`.
+    #[serde(skip_serializing)]
+    pub old_value: Option<String>,
+    /// JSON of the link, or the export parameters, after the action.
+    #[serde(skip_serializing)]
+    pub new_value: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = audit_log)]
+pub struct NewAuditEntry {
+    pub actor: String,
+    pub actor_user_id: Option<i32>,
+    pub ip_address: Option<String>,
+    pub action: String,
+    pub url_id: Option<i32>,
+    pub short_code: Option<String>,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
 }
@@ -0,0 +1,408 @@
+// src/moderation.rs
+// Abuse reports from visitors and the moderation of reported links.
+//
+// Anyone can report a link with `POST /{code}/report`. Moderators work through
+// the open reports, disable links (visitors then get the suspended page
+// instead of a redirect), re-enable them or dismiss reports. Every report and
+// decision is recorded in the moderation trail, which outlives the link.
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, Actor};
+use crate::auth::Principal;
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::error_pages::{error_response, ErrorPage};
+use crate::models::{
+    LinkReport, ModerationAction, NewLinkReport, NewModerationAction, Url, UrlTag,
+};
+use crate::visitor::client_ip;
+use crate::workspaces::{authorize_url, Permission};
+
+/// Statuses of a report.
+pub const OPEN: &str = "open";
+pub const ACTIONED: &str = "actioned";
+pub const DISMISSED: &str = "dismissed";
+
+/// Longest reason accepted from reports and moderators.
+const MAX_REASON_LENGTH: usize = 1000;
+
+/// Actor recorded for reports, which visitors send without credentials.
+const VISITOR: &str = "visitor";
+
+/// Trims a reason and checks its length.
+fn parse_reason(value: Option<&str>, required: bool) -> Result<Option<String>, AppError> {
+    let value = value.map(str::trim).filter(|value| !value.is_empty());
+    match value {
+        None if required => Err(AppError::InvalidInput("reason is required".to_string())),
+        Some(value) if value.chars().count() > MAX_REASON_LENGTH => Err(AppError::InvalidInput(
+            format!("reason must be at most {} characters", MAX_REASON_LENGTH),
+        )),
+        value => Ok(value.map(str::to_string)),
+    }
+}
+
+/// Adds an entry to the moderation trail of `url_entry`.
+fn record(
+    conn: &mut SqliteConnection,
+    url_entry: &Url,
+    action: &str,
+    reason: Option<&str>,
+    report_id: Option<i32>,
+    actor: &str,
+) -> QueryResult<()> {
+    use crate::schema::moderation_actions;
+
+    diesel::insert_into(moderation_actions::table)
+        .values(&NewModerationAction {
+            url_id: url_entry.id,
+            short_code: url_entry.short_code.clone(),
+            action: action.to_string(),
+            reason: reason.map(str::to_string),
+            report_id,
+            actor: actor.to_string(),
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    pub reason: Option<String>,
+}
+
+/// Handler for `POST /{code}/report`: a visitor reports a link as abusive.
+/// Takes a JSON body or a form, so a plain HTML form can post to it.
+pub async fn report_handler(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    item: web::Either<web::Json<ReportRequest>, web::Form<ReportRequest>>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::{link_reports, urls};
+
+    let code = path.into_inner();
+    let item = match item {
+        web::Either::Left(json) => json.into_inner(),
+        web::Either::Right(form) => form.into_inner(),
+    };
+    let reason = parse_reason(item.reason.as_deref(), true)?.unwrap_or_default();
+    let reporter_ip = client_ip(&req);
+    let reported = web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let Some(url_entry) = urls::table
+                .filter(urls::short_code.eq(&code))
+                .first::<Url>(conn)
+                .optional()?
+            else {
+                return Ok(false);
+            };
+            diesel::insert_into(link_reports::table)
+                .values(&NewLinkReport {
+                    url_id: url_entry.id,
+                    reason: reason.clone(),
+                    reporter_ip,
+                })
+                .execute(conn)?;
+            let report_id = link_reports::table
+                .select(link_reports::id)
+                .order(link_reports::id.desc())
+                .first::<i32>(conn)?;
+            record(
+                conn,
+                &url_entry,
+                "reported",
+                Some(&reason),
+                Some(report_id),
+                VISITOR,
+            )?;
+            Ok::<_, AppError>(true)
+        })
+    })
+    .await??;
+    if !reported {
+        return Ok(error_response(&req, ErrorPage::NotFound, "URL not found"));
+    }
+
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "message": "Thank you, the report will be reviewed"
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct ListReportsQuery {
+    /// Only reports with this status, e.g. `open`.
+    pub status: Option<String>,
+    /// Only reports of the link with this short code.
+    pub code: Option<String>,
+}
+
+/// A report with the short code of its link.
+#[derive(Serialize)]
+struct ReportEntry {
+    #[serde(flatten)]
+    report: LinkReport,
+    short_code: String,
+}
+
+/// Handler for listing reports, newest first.
+pub async fn list_reports_handler(
+    pool: web::Data<DbPool>,
+    params: web::Query<ListReportsQuery>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::{link_reports, urls};
+
+    let params = params.into_inner();
+    if let Some(wanted) = &params.status {
+        if ![OPEN, ACTIONED, DISMISSED].contains(&wanted.as_str()) {
+            return Err(AppError::InvalidInput(format!(
+                "status must be {}, {} or {}",
+                OPEN, ACTIONED, DISMISSED
+            )));
+        }
+    }
+    let reports = web::block(move || {
+        let mut conn = pool.get()?;
+        let mut query = link_reports::table
+            .inner_join(urls::table)
+            .select((link_reports::all_columns, urls::short_code))
+            .order(link_reports::id.desc())
+            .into_boxed();
+        if let Some(wanted) = params.status {
+            query = query.filter(link_reports::status.eq(wanted));
+        }
+        if let Some(code) = params.code {
+            query = query.filter(urls::short_code.eq(code));
+        }
+        query
+            .load::<(LinkReport, String)>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+
+    let reports: Vec<ReportEntry> = reports
+        .into_iter()
+        .map(|(report, short_code)| ReportEntry { report, short_code })
+        .collect();
+    Ok(HttpResponse::Ok().json(reports))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ModerationRequest {
+    /// Why the moderator acted; required to disable a link.
+    pub reason: Option<String>,
+}
+
+/// Handler for disabling a link. Its open reports are marked as actioned.
+pub async fn disable_url_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    principal: Principal,
+    actor: Actor,
+    path: web::Path<String>,
+    item: web::Json<ModerationRequest>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::{link_reports, urls};
+
+    let code = path.into_inner();
+    let reason = parse_reason(item.into_inner().reason.as_deref(), true)?;
+    let base_url = config.base_url.clone();
+    let body = web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let url_entry = authorize_url(conn, &principal, &code, Permission::EditLinks)?;
+            let now = Utc::now().naive_utc();
+            diesel::update(urls::table.find(url_entry.id))
+                .set((
+                    urls::disabled_at.eq(Some(now)),
+                    urls::disabled_reason.eq(&reason),
+                ))
+                .execute(conn)?;
+            diesel::update(
+                link_reports::table
+                    .filter(link_reports::url_id.eq(url_entry.id))
+                    .filter(link_reports::status.eq(OPEN)),
+            )
+            .set((
+                link_reports::status.eq(ACTIONED),
+                link_reports::resolved_at.eq(Some(now)),
+                link_reports::resolved_by.eq(Some(&principal.name)),
+            ))
+            .execute(conn)?;
+            record(
+                conn,
+                &url_entry,
+                "disabled",
+                reason.as_deref(),
+                None,
+                &principal.name,
+            )?;
+            let updated = urls::table.find(url_entry.id).first::<Url>(conn)?;
+            let tags = UrlTag::for_url(conn, updated.id)?;
+            let body = updated.to_json(&tags, &base_url);
+            actor
+                .entry(audit::LINK_DISABLED)
+                .link(&updated)
+                .before(&url_entry.to_json(&tags, &base_url))
+                .after(&body)
+                .record(conn)?;
+            Ok::<_, AppError>(body)
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Handler for re-enabling a disabled link.
+pub async fn enable_url_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    principal: Principal,
+    actor: Actor,
+    path: web::Path<String>,
+    item: Option<web::Json<ModerationRequest>>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::urls;
+
+    let code = path.into_inner();
+    let reason = parse_reason(
+        item.map(|item| item.into_inner())
+            .unwrap_or_default()
+            .reason
+            .as_deref(),
+        false,
+    )?;
+    let base_url = config.base_url.clone();
+    let body = web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let url_entry = authorize_url(conn, &principal, &code, Permission::EditLinks)?;
+            if url_entry.disabled_at.is_none() {
+                return Err(AppError::InvalidInput(format!("{} is not disabled", code)));
+            }
+            diesel::update(urls::table.find(url_entry.id))
+                .set((
+                    urls::disabled_at.eq(None::<chrono::NaiveDateTime>),
+                    urls::disabled_reason.eq(None::<String>),
+                ))
+                .execute(conn)?;
+            record(
+                conn,
+                &url_entry,
+                "enabled",
+                reason.as_deref(),
+                None,
+                &principal.name,
+            )?;
+            let updated = urls::table.find(url_entry.id).first::<Url>(conn)?;
+            let tags = UrlTag::for_url(conn, updated.id)?;
+            let body = updated.to_json(&tags, &base_url);
+            actor
+                .entry(audit::LINK_ENABLED)
+                .link(&updated)
+                .before(&url_entry.to_json(&tags, &base_url))
+                .after(&body)
+                .record(conn)?;
+            Ok::<_, AppError>(body)
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Handler for dismissing a report without acting on the link.
+pub async fn dismiss_report_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    path: web::Path<i32>,
+    item: Option<web::Json<ModerationRequest>>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::{link_reports, urls};
+
+    let report_id = path.into_inner();
+    let reason = parse_reason(
+        item.map(|item| item.into_inner())
+            .unwrap_or_default()
+            .reason
+            .as_deref(),
+        false,
+    )?;
+    let report = web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let report = link_reports::table
+                .find(report_id)
+                .first::<LinkReport>(conn)
+                .optional()?
+                .ok_or_else(|| AppError::NotFound(format!("report {}", report_id)))?;
+            if report.status != OPEN {
+                return Err(AppError::InvalidInput(format!(
+                    "report {} is already {}",
+                    report_id, report.status
+                )));
+            }
+            diesel::update(link_reports::table.find(report.id))
+                .set((
+                    link_reports::status.eq(DISMISSED),
+                    link_reports::resolved_at.eq(Some(Utc::now().naive_utc())),
+                    link_reports::resolved_by.eq(Some(&principal.name)),
+                ))
+                .execute(conn)?;
+            let url_entry = urls::table.find(report.url_id).first::<Url>(conn)?;
+            record(
+                conn,
+                &url_entry,
+                "dismissed",
+                reason.as_deref(),
+                Some(report.id),
+                &principal.name,
+            )?;
+            link_reports::table
+                .find(report.id)
+                .first::<LinkReport>(conn)
+                .map_err(AppError::from)
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[derive(Deserialize)]
+pub struct ModerationLogQuery {
+    /// Only entries of the link with this short code.
+    pub code: Option<String>,
+}
+
+/// Handler for the moderation trail, newest first.
+pub async fn moderation_log_handler(
+    pool: web::Data<DbPool>,
+    params: web::Query<ModerationLogQuery>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::moderation_actions;
+
+    let code = params.into_inner().code;
+    let entries = web::block(move || {
+        let mut conn = pool.get()?;
+        let mut query = moderation_actions::table
+            .order(moderation_actions::id.desc())
+            .into_boxed();
+        if let Some(code) = code {
+            query = query.filter(moderation_actions::short_code.eq(code));
+        }
+        query
+            .load::<ModerationAction>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
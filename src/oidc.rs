@@ -0,0 +1,599 @@
+// src/oidc.rs
+// "Sign in with SSO" through an OpenID Connect provider.
+//
+// `/auth/login` sends the browser to the provider with the authorization-code
+// flow and PKCE; the state, nonce and code verifier travel in a short-lived
+// signed cookie. `/auth/callback` exchanges the code, checks the ID token
+// against the provider's JWKS, provisions the user from its claims, syncs
+// their workspace roles from the groups claim and starts a session. The
+// session cookie is accepted by `crate::auth::authenticate` like an API key.
+
+use std::{
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use actix_web::{
+    cookie::{time::Duration as CookieDuration, Cookie, SameSite},
+    http::header,
+    web, HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    auth::{Principal, Scope},
+    config::Config,
+    db::DbPool,
+    error::AppError,
+    jwt::{check_lifetime, has_audience, parse_jwks, SignedToken, VerificationKey, DEFAULT_SCOPES},
+    models::{NewSession, NewWorkspaceMember, Session, User, Workspace},
+    users::{find_or_create_user, validate_username},
+    utils::generate_short_code,
+    workspaces::Role,
+};
+
+/// Cookie holding the session of a signed-in browser.
+pub const SESSION_COOKIE: &str = "session";
+/// Cookie carrying a sign-in from `/auth/login` to `/auth/callback`.
+const LOGIN_COOKIE: &str = "oidc_login";
+/// How long a browser may take to sign in at the provider.
+const LOGIN_TTL_SECS: i64 = 600;
+const SESSION_TOKEN_LENGTH: usize = 48;
+/// Clock skew tolerated on the ID token's `exp` and `nbf`, in seconds.
+const ID_TOKEN_LEEWAY_SECS: i64 = 60;
+
+/// Members of an identity provider group get `role` in `workspace`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupRole {
+    pub group: String,
+    pub workspace: String,
+    pub role: Role,
+}
+
+/// Parses `group=workspace:role`.
+impl FromStr for GroupRole {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not group=workspace:role", value);
+        let (group, target) = value.split_once('=').ok_or_else(invalid)?;
+        let (workspace, role) = target.rsplit_once(':').ok_or_else(invalid)?;
+        let (group, workspace) = (group.trim(), workspace.trim());
+        if group.is_empty() || workspace.is_empty() {
+            return Err(invalid());
+        }
+        Ok(GroupRole {
+            group: group.to_string(),
+            workspace: workspace.to_string(),
+            role: Role::parse(role.trim()).ok_or_else(|| format!("unknown role in '{}'", value))?,
+        })
+    }
+}
+
+/// Parses a comma-separated list of `group=workspace:role` mappings.
+pub fn parse_group_roles(value: &str) -> Result<Vec<GroupRole>, String> {
+    value
+        .split(',')
+        .filter(|mapping| !mapping.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+/// What was learned about the provider from its discovery document.
+struct Provider {
+    discovery: Discovery,
+    keys: Vec<VerificationKey>,
+}
+
+/// Talks to the provider. Its discovery document and keys are fetched on
+/// the first sign-in and kept; the keys are fetched again when an ID token
+/// is signed with a key not seen yet.
+#[derive(Default)]
+pub struct OidcClient {
+    http: reqwest::Client,
+    provider: RwLock<Option<Arc<Provider>>>,
+}
+
+fn provider_error(err: impl std::fmt::Display) -> AppError {
+    AppError::InternalError(format!("cannot reach the identity provider: {}", err))
+}
+
+impl OidcClient {
+    async fn fetch_provider(&self, issuer: &str) -> Result<Provider, AppError> {
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let discovery: Discovery = self
+            .http
+            .get(&discovery_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(provider_error)?
+            .json()
+            .await
+            .map_err(provider_error)?;
+        if discovery.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+            return Err(provider_error(format!(
+                "discovery document is for issuer {}",
+                discovery.issuer
+            )));
+        }
+        let jwks = self
+            .http
+            .get(&discovery.jwks_uri)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(provider_error)?
+            .text()
+            .await
+            .map_err(provider_error)?;
+        let keys = parse_jwks(&jwks).map_err(provider_error)?;
+        Ok(Provider { discovery, keys })
+    }
+
+    /// The provider, fetched if not known yet or if `refresh` is set.
+    async fn provider(&self, issuer: &str, refresh: bool) -> Result<Arc<Provider>, AppError> {
+        if !refresh {
+            if let Some(provider) = self.provider.read().unwrap().clone() {
+                return Ok(provider);
+            }
+        }
+        let provider = Arc::new(self.fetch_provider(issuer).await?);
+        *self.provider.write().unwrap() = Some(provider.clone());
+        Ok(provider)
+    }
+
+    /// Checks the ID token's signature, lifetime, issuer, audience and
+    /// nonce, and returns its claims.
+    async fn verify_id_token(
+        &self,
+        settings: &Settings<'_>,
+        id_token: &str,
+        nonce: &str,
+    ) -> Result<Map<String, Value>, AppError> {
+        let invalid =
+            |reason: &str| AppError::Unauthorized(format!("invalid ID token: {}", reason));
+        let token = SignedToken::parse(id_token)?;
+        let mut provider = self.provider(settings.issuer, false).await?;
+        if token.check(&provider.keys).is_none() {
+            provider = self.provider(settings.issuer, true).await?;
+        }
+        if token.check(&provider.keys) != Some(true) {
+            return Err(invalid("bad signature"));
+        }
+        let claims = token.claims()?;
+        check_lifetime(&claims, Utc::now().timestamp(), ID_TOKEN_LEEWAY_SECS)?;
+        if claims.get("iss").and_then(Value::as_str) != Some(provider.discovery.issuer.as_str()) {
+            return Err(invalid("wrong issuer"));
+        }
+        if !has_audience(&claims, settings.client_id) {
+            return Err(invalid("wrong audience"));
+        }
+        if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+            return Err(invalid("wrong nonce"));
+        }
+        Ok(claims)
+    }
+}
+
+/// The parts of the configuration sign-in needs.
+struct Settings<'a> {
+    issuer: &'a str,
+    client_id: &'a str,
+    redirect_url: String,
+}
+
+impl<'a> Settings<'a> {
+    fn from_config(config: &'a Config) -> Result<Self, AppError> {
+        let (Some(issuer), Some(client_id)) = (&config.oidc_issuer, &config.oidc_client_id) else {
+            return Err(AppError::NotFound(
+                "single sign-on is not configured".to_string(),
+            ));
+        };
+        Ok(Settings {
+            issuer,
+            client_id,
+            redirect_url: config.oidc_redirect_url.clone().unwrap_or_else(|| {
+                format!("{}/auth/callback", config.base_url.trim_end_matches('/'))
+            }),
+        })
+    }
+}
+
+/// A sign-in in progress, kept in a signed cookie between `/auth/login`
+/// and `/auth/callback`.
+#[derive(Serialize, Deserialize)]
+struct LoginState {
+    state: String,
+    nonce: String,
+    verifier: String,
+    return_to: String,
+    expires: i64,
+}
+
+fn login_mac(secret: &str, payload: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(LOGIN_COOKIE.as_bytes());
+    mac.update(b"\n");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+impl LoginState {
+    fn encode(&self, secret: &str) -> String {
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default());
+        let signature = login_mac(secret, &payload).finalize().into_bytes();
+        format!("{}.{}", payload, hex::encode(signature))
+    }
+
+    /// The unexpired sign-in signed with `secret`, if `value` is one.
+    fn decode(secret: &str, value: &str, now: i64) -> Option<LoginState> {
+        let (payload, signature) = value.split_once('.')?;
+        login_mac(secret, payload)
+            .verify_slice(&hex::decode(signature).ok()?)
+            .ok()?;
+        let login: LoginState =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        (login.expires > now).then_some(login)
+    }
+}
+
+fn secure_cookies(config: &Config) -> bool {
+    config.base_url.starts_with("https://")
+}
+
+/// Only paths on this server, so the sign-in cannot be used to send
+/// browsers elsewhere.
+fn safe_return_to(return_to: Option<String>) -> String {
+    return_to
+        .filter(|path| path.starts_with('/') && !path.starts_with("//") && !path.contains('\\'))
+        .unwrap_or_else(|| "/".to_string())
+}
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    /// Path to send the browser to once signed in.
+    pub return_to: Option<String>,
+}
+
+/// Handler starting a sign-in: redirects to the provider.
+pub async fn login_handler(
+    config: web::Data<Config>,
+    oidc: web::Data<OidcClient>,
+    query: web::Query<LoginQuery>,
+) -> Result<HttpResponse, AppError> {
+    let settings = Settings::from_config(&config)?;
+    let provider = oidc.provider(settings.issuer, false).await?;
+    let login = LoginState {
+        state: generate_short_code(32),
+        nonce: generate_short_code(32),
+        verifier: generate_short_code(64),
+        return_to: safe_return_to(query.into_inner().return_to),
+        expires: Utc::now().timestamp() + LOGIN_TTL_SECS,
+    };
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(login.verifier.as_bytes()));
+    let mut location =
+        url::Url::parse(&provider.discovery.authorization_endpoint).map_err(provider_error)?;
+    location
+        .query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", settings.client_id)
+        .append_pair("redirect_uri", &settings.redirect_url)
+        .append_pair("scope", &config.oidc_scopes)
+        .append_pair("state", &login.state)
+        .append_pair("nonce", &login.nonce)
+        .append_pair("code_challenge", &challenge)
+        .append_pair("code_challenge_method", "S256");
+
+    let cookie = Cookie::build(LOGIN_COOKIE, login.encode(&config.cookie_secret))
+        .path("/auth")
+        .max_age(CookieDuration::seconds(LOGIN_TTL_SECS))
+        .http_only(true)
+        .secure(secure_cookies(&config))
+        .same_site(SameSite::Lax)
+        .finish();
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, location.to_string()))
+        .cookie(cookie)
+        .finish())
+}
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The signed-in person, as described by the ID token.
+struct Identity {
+    username: String,
+    groups: Vec<String>,
+}
+
+impl Identity {
+    fn from_claims(config: &Config, claims: &Map<String, Value>) -> Result<Self, AppError> {
+        let username = claims
+            .get(&config.oidc_username_claim)
+            .and_then(Value::as_str)
+            .filter(|username| validate_username(username).is_ok())
+            .ok_or_else(|| {
+                AppError::Unauthorized(format!(
+                    "the ID token has no usable {} claim",
+                    config.oidc_username_claim
+                ))
+            })?;
+        let groups = match claims.get(&config.oidc_groups_claim) {
+            Some(Value::Array(groups)) => groups
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            Some(Value::String(group)) => vec![group.clone()],
+            _ => Vec::new(),
+        };
+        Ok(Identity {
+            username: username.to_string(),
+            groups,
+        })
+    }
+}
+
+/// Handler finishing a sign-in: checks the provider's answer, signs the
+/// user in and redirects to where the sign-in started.
+pub async fn callback_handler(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    oidc: web::Data<OidcClient>,
+    query: web::Query<CallbackQuery>,
+) -> Result<HttpResponse, AppError> {
+    let settings = Settings::from_config(&config)?;
+    let query = query.into_inner();
+    let login = req
+        .cookie(LOGIN_COOKIE)
+        .and_then(|cookie| {
+            LoginState::decode(
+                &config.cookie_secret,
+                cookie.value(),
+                Utc::now().timestamp(),
+            )
+        })
+        .ok_or_else(|| {
+            AppError::Unauthorized(
+                "the sign-in expired or was started in another browser; please sign in again"
+                    .to_string(),
+            )
+        })?;
+    if let Some(error) = query.error {
+        return Err(AppError::Unauthorized(format!(
+            "the identity provider refused the sign-in: {}",
+            query.error_description.unwrap_or(error)
+        )));
+    }
+    if query.state.as_deref() != Some(login.state.as_str()) {
+        return Err(AppError::Unauthorized(
+            "the sign-in state does not match".to_string(),
+        ));
+    }
+    let code = query
+        .code
+        .ok_or_else(|| AppError::InvalidInput("code is required".to_string()))?;
+
+    let provider = oidc.provider(settings.issuer, false).await?;
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", settings.redirect_url.as_str()),
+        ("client_id", settings.client_id),
+        ("code_verifier", login.verifier.as_str()),
+    ];
+    if let Some(secret) = &config.oidc_client_secret {
+        form.push(("client_secret", secret.as_str()));
+    }
+    let response = oidc
+        .http
+        .post(&provider.discovery.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .map_err(provider_error)?;
+    if !response.status().is_success() {
+        return Err(AppError::Unauthorized(
+            "the identity provider did not accept the authorization code".to_string(),
+        ));
+    }
+    let tokens: TokenResponse = response.json().await.map_err(provider_error)?;
+    let claims = oidc
+        .verify_id_token(&settings, &tokens.id_token, &login.nonce)
+        .await?;
+    let identity = Identity::from_claims(&config, &claims)?;
+
+    let session_config = config.clone();
+    let token = web::block(move || {
+        let mut conn = pool.get()?;
+        sign_in(&mut conn, &session_config, identity)
+    })
+    .await??;
+
+    let session = Cookie::build(SESSION_COOKIE, token)
+        .path("/")
+        .max_age(CookieDuration::seconds(config.session_ttl.as_secs() as i64))
+        .http_only(true)
+        .secure(secure_cookies(&config))
+        .same_site(SameSite::Lax)
+        .finish();
+    let mut done = Cookie::build(LOGIN_COOKIE, "").path("/auth").finish();
+    done.make_removal();
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, login.return_to))
+        .cookie(session)
+        .cookie(done)
+        .finish())
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Gives the user `role` in every workspace mapped from one of their
+/// groups, taking the most powerful role when several groups map to the
+/// same workspace, and removes them from mapped workspaces none of their
+/// groups lead to. Workspaces that do not exist are skipped.
+fn sync_group_roles(
+    conn: &mut SqliteConnection,
+    user: i32,
+    groups: &[String],
+    mappings: &[GroupRole],
+) -> QueryResult<()> {
+    use crate::schema::{workspace_members, workspaces};
+
+    let mut names: Vec<&str> = mappings
+        .iter()
+        .map(|mapping| mapping.workspace.as_str())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    for name in names {
+        let Some(workspace) = workspaces::table
+            .filter(workspaces::name.eq(name))
+            .first::<Workspace>(conn)
+            .optional()?
+        else {
+            log::warn!("OIDC group mapping names unknown workspace {}", name);
+            continue;
+        };
+        let membership = workspace_members::table.find((workspace.id, user));
+        let role = mappings
+            .iter()
+            .filter(|mapping| mapping.workspace == name && groups.contains(&mapping.group))
+            .map(|mapping| mapping.role)
+            .min();
+        match role {
+            Some(role) => {
+                diesel::insert_into(workspace_members::table)
+                    .values(&NewWorkspaceMember {
+                        workspace_id: workspace.id,
+                        user_id: user,
+                        role: role.as_str().to_string(),
+                    })
+                    .on_conflict((workspace_members::workspace_id, workspace_members::user_id))
+                    .do_update()
+                    .set(workspace_members::role.eq(role.as_str()))
+                    .execute(conn)?;
+            },
+            None => {
+                diesel::delete(membership).execute(conn)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Provisions the user, syncs their workspace roles and starts a session.
+/// Returns the session token.
+fn sign_in(
+    conn: &mut SqliteConnection,
+    config: &Config,
+    identity: Identity,
+) -> Result<String, AppError> {
+    use crate::schema::sessions;
+
+    let now = Utc::now().naive_utc();
+    let ttl = Duration::try_seconds(config.session_ttl.as_secs() as i64)
+        .unwrap_or_else(|| Duration::hours(8));
+    // Sessions get what tokens get by default; admin groups also get admin
+    let mut scopes = DEFAULT_SCOPES.to_vec();
+    if identity
+        .groups
+        .iter()
+        .any(|group| config.oidc_admin_groups.contains(group))
+    {
+        scopes.push(Scope::Admin);
+    }
+    let token = generate_short_code(SESSION_TOKEN_LENGTH);
+    conn.transaction(|conn| {
+        let user = find_or_create_user(conn, &identity.username)?;
+        sync_group_roles(conn, user.id, &identity.groups, &config.oidc_group_roles)?;
+        diesel::delete(sessions::table.filter(sessions::expires_at.le(now))).execute(conn)?;
+        diesel::insert_into(sessions::table)
+            .values(&NewSession {
+                token_hash: hash_token(&token),
+                user_id: user.id,
+                scopes: serde_json::to_string(&scopes).unwrap_or_default(),
+                expires_at: now + ttl,
+            })
+            .execute(conn)?;
+        Ok::<_, AppError>(())
+    })?;
+    Ok(token)
+}
+
+/// The signed-in user of an unexpired session with `token`.
+pub fn session_principal(
+    conn: &mut SqliteConnection,
+    token: &str,
+) -> QueryResult<Option<Principal>> {
+    use crate::schema::{sessions, users};
+
+    let found = sessions::table
+        .inner_join(users::table.on(users::id.eq(sessions::user_id)))
+        .filter(sessions::token_hash.eq(hash_token(token)))
+        .filter(sessions::expires_at.gt(Utc::now().naive_utc()))
+        .first::<(Session, User)>(conn)
+        .optional()?;
+    Ok(found.map(|(session, user)| Principal {
+        key_id: None,
+        session_id: Some(session.id),
+        name: user.username,
+        scopes: serde_json::from_str(&session.scopes).unwrap_or_default(),
+        user_id: Some(user.id),
+        workspace_id: None,
+    }))
+}
+
+/// Handler signing out: ends the session and removes its cookie.
+pub async fn logout_handler(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::sessions;
+
+    if let Some(cookie) = req.cookie(SESSION_COOKIE) {
+        let token_hash = hash_token(cookie.value());
+        web::block(move || {
+            let mut conn = pool.get()?;
+            diesel::delete(sessions::table.filter(sessions::token_hash.eq(token_hash)))
+                .execute(&mut conn)?;
+            Ok::<_, AppError>(())
+        })
+        .await??;
+    }
+    let mut removal = Cookie::build(SESSION_COOKIE, "").path("/").finish();
+    removal.make_removal();
+    Ok(HttpResponse::NoContent().cookie(removal).finish())
+}
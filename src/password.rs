@@ -0,0 +1,312 @@
+// src/password.rs
+// Password-protected links.
+//
+// Only an Argon2 hash of the password is stored. Visitors who enter the right
+// password get a signed cookie, scoped to the link, that lets them through
+// until it expires.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    cookie::{time::Duration as CookieDuration, Cookie, SameSite},
+    http::header,
+    web, HttpRequest, HttpResponse,
+};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{
+    config::Config,
+    db::DbPool,
+    error::AppError,
+    error_pages::{error_response, ErrorPage},
+    models::Url,
+    visitor::client_ip,
+};
+
+/// Longest accepted password, in bytes.
+pub const MAX_PASSWORD_LENGTH: usize = 256;
+
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.is_empty() {
+        return Err("password must not be empty".to_string());
+    }
+    if password.len() > MAX_PASSWORD_LENGTH {
+        return Err(format!(
+            "password must be at most {} bytes",
+            MAX_PASSWORD_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Validates `password` and returns its Argon2 hash in PHC string format.
+///
+/// Hashing is deliberately slow, so it runs on the blocking thread pool.
+pub async fn hash_password(password: String) -> Result<String, AppError> {
+    validate_password(&password).map_err(AppError::InvalidInput)?;
+    web::block(move || {
+        let salt = SaltString::generate(&mut rand::rngs::OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await
+    .map_err(|err| AppError::InternalError(err.to_string()))?
+    .map_err(|err| AppError::InternalError(format!("cannot hash password: {}", err)))
+}
+
+/// Checks `password` against a hash produced by [`hash_password`].
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|parsed| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok()
+    })
+}
+
+impl Url {
+    pub fn is_password_protected(&self) -> bool {
+        self.password_hash.is_some()
+    }
+}
+
+pub fn unlock_cookie_name(short_code: &str) -> String {
+    format!("pw_{}", short_code)
+}
+
+/// Signature over the link, the expiry time and the current password hash,
+/// so changing the password invalidates cookies already handed out.
+fn unlock_mac(secret: &str, url_entry: &Url, expires: i64) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(url_entry.short_code.as_bytes());
+    mac.update(b"\n");
+    mac.update(expires.to_string().as_bytes());
+    mac.update(b"\n");
+    mac.update(url_entry.password_hash.as_deref().unwrap_or("").as_bytes());
+    mac
+}
+
+/// Cookie proving the visitor entered the password of `url_entry`, valid
+/// for `config.password_cookie_ttl`.
+pub fn unlock_cookie(config: &Config, url_entry: &Url) -> Cookie<'static> {
+    let ttl = config.password_cookie_ttl;
+    let expires = Utc::now().timestamp() + ttl.as_secs() as i64;
+    let signature = unlock_mac(&config.cookie_secret, url_entry, expires)
+        .finalize()
+        .into_bytes();
+    Cookie::build(
+        unlock_cookie_name(&url_entry.short_code),
+        format!("{}.{}", expires, hex::encode(signature)),
+    )
+    .path(format!("/{}", url_entry.short_code))
+    .max_age(CookieDuration::seconds(ttl.as_secs() as i64))
+    .http_only(true)
+    .same_site(SameSite::Lax)
+    .finish()
+}
+
+/// Whether the visitor may follow `url_entry`: the link has no password, or
+/// `cookie` is an unexpired unlock cookie for it.
+pub fn is_unlocked(config: &Config, url_entry: &Url, cookie: Option<&str>) -> bool {
+    if !url_entry.is_password_protected() {
+        return true;
+    }
+    let Some((expires, signature)) = cookie.and_then(|value| value.split_once('.')) else {
+        return false;
+    };
+    let (Ok(expires), Ok(signature)) = (expires.parse::<i64>(), hex::decode(signature)) else {
+        return false;
+    };
+    expires > Utc::now().timestamp()
+        && unlock_mac(&config.cookie_secret, url_entry, expires)
+            .verify_slice(&signature)
+            .is_ok()
+}
+
+/// What failed password attempts are counted against.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum AttemptKey {
+    /// One client address guessing one link.
+    Client(String, String),
+    /// Every guess at one link, wherever it comes from, so a client that
+    /// spreads its guesses over many addresses is still stopped.
+    Link(String),
+}
+
+/// Failed password attempts per client and link and per link, kept in
+/// memory.
+#[derive(Default)]
+pub struct PasswordAttempts {
+    failures: Mutex<HashMap<AttemptKey, Vec<Instant>>>,
+}
+
+impl PasswordAttempts {
+    /// How long `key` must wait before trying again, if it has used up its
+    /// `max_attempts` failures within `window`.
+    fn retry_after(
+        &self,
+        key: &AttemptKey,
+        max_attempts: u32,
+        window: Duration,
+    ) -> Option<Duration> {
+        let mut failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+        let recent = failures.get_mut(key)?;
+        recent.retain(|at| now.duration_since(*at) < window);
+        if recent.len() < max_attempts as usize {
+            if recent.is_empty() {
+                failures.remove(key);
+            }
+            return None;
+        }
+        recent
+            .first()
+            .map(|oldest| window.saturating_sub(now.duration_since(*oldest)))
+    }
+
+    fn record_failure(&self, key: AttemptKey, window: Duration) {
+        let mut failures = self.failures.lock().unwrap();
+        let now = Instant::now();
+        // Drop clients that stopped trying so the map does not grow forever.
+        failures.retain(|_, recent| {
+            recent
+                .last()
+                .is_some_and(|at| now.duration_since(*at) < window)
+        });
+        failures.entry(key).or_default().push(now);
+    }
+
+    fn clear(&self, key: &AttemptKey) {
+        self.failures.lock().unwrap().remove(key);
+    }
+}
+
+/// Page asking for the password of `short_code`. The form posts back to the
+/// URL it was served from, so any path tail and query string are kept.
+pub fn password_form(short_code: &str, error: Option<&str>) -> String {
+    let error = error
+        .map(|message| format!("<p role=\"alert\">{}</p>\n", message))
+        .unwrap_or_default();
+    format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head><meta charset=\"utf-8\"><title>Password required</title></head>\n\
+         <body>\n\
+         <h1>Password required</h1>\n\
+         <p>Enter the password for /{}.</p>\n\
+         {}\
+         <form method=\"post\">\n\
+         <input type=\"password\" name=\"password\" autofocus required>\n\
+         <button type=\"submit\">Continue</button>\n\
+         </form>\n\
+         </body>\n\
+         </html>\n",
+        short_code, error
+    )
+}
+
+/// Response for a protected link the visitor has not unlocked.
+pub fn password_required_response(short_code: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(password_form(short_code, None))
+}
+
+#[derive(Deserialize)]
+pub struct UnlockForm {
+    pub password: String,
+}
+
+/// Handler for the password form of a protected link.
+///
+/// On success the visitor gets an unlock cookie and is sent back to the
+/// short link with `303 See Other`. Each client may fail
+/// `config.password_max_attempts` times per link within
+/// `config.password_attempt_window`, and all clients together
+/// `config.password_max_link_attempts` times, after which attempts get `429
+/// Too Many Requests` until the oldest failure falls out of the window.
+pub async fn unlock_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    attempts: web::Data<PasswordAttempts>,
+    req: HttpRequest,
+    form: web::Form<UnlockForm>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::urls;
+
+    let code = req.match_info().get("code").unwrap_or("").to_string();
+    let client = client_ip(&req).unwrap_or_else(|| "unknown".to_string());
+    let client_key = AttemptKey::Client(client, code.clone());
+    let link_key = AttemptKey::Link(code.clone());
+    let wait = [
+        (&client_key, config.password_max_attempts),
+        (&link_key, config.password_max_link_attempts),
+    ]
+    .into_iter()
+    .filter_map(|(key, max_attempts)| {
+        attempts.retry_after(key, max_attempts, config.password_attempt_window)
+    })
+    .max();
+    if let Some(wait) = wait {
+        let mut response = error_response(
+            &req,
+            ErrorPage::RateLimited,
+            "Too many password attempts, try again later",
+        );
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, wait.as_secs().max(1).into());
+        return Ok(response);
+    }
+
+    let password = form.into_inner().password;
+    let (url_entry, verified) = web::block(move || {
+        let mut conn = pool.get()?;
+        let url_entry = urls::table
+            .filter(urls::short_code.eq(&code))
+            .first::<Url>(&mut conn)
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("short code {}", code)))?;
+        let verified = url_entry
+            .password_hash
+            .as_deref()
+            .is_none_or(|hash| verify_password(hash, &password));
+        Ok::<_, AppError>((url_entry, verified))
+    })
+    .await??;
+
+    if !verified {
+        attempts.record_failure(client_key, config.password_attempt_window);
+        attempts.record_failure(link_key, config.password_attempt_window);
+        return Ok(HttpResponse::Unauthorized()
+            .content_type("text/html; charset=utf-8")
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .body(password_form(
+                &url_entry.short_code,
+                Some("Incorrect password."),
+            )));
+    }
+    attempts.clear(&client_key);
+    let mut response = HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, req.uri().to_string()))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .finish();
+    if url_entry.is_password_protected() {
+        let _ = response.add_cookie(&unlock_cookie(&config, &url_entry));
+    }
+    Ok(response)
+}
@@ -0,0 +1,439 @@
+// src/preview.rs
+// Pages shown instead of an immediate redirect: the preview visitors ask for
+// with `/{code}+`, and warning interstitials forced per link or per domain.
+
+use actix_web::{
+    cookie::{time::Duration as CookieDuration, Cookie, SameSite},
+    http::header,
+    web, HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::error_pages::{error_response, ErrorPage};
+use crate::handlers::forward_to_redirect;
+use crate::models::{InterstitialDomain, NewInterstitialDomain, Url};
+use crate::schedule::LinkState;
+use crate::stats::url_stats;
+use crate::utils::escape_html;
+
+/// How long a visitor who saw a warning page has to continue past it.
+const CONFIRMATION_TTL_SECS: i64 = 10 * 60;
+
+/// Why a warning page is shown before a redirect.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Interstitial {
+    /// Reason recorded for the flagged domain, if any.
+    pub reason: Option<String>,
+}
+
+/// The host of `destination` and each of its parent domains, most specific
+/// first, e.g. `a.example.com` then `example.com` then `com`.
+pub fn domain_candidates(destination: &str) -> Vec<String> {
+    let Some(host) = url::Url::parse(destination)
+        .ok()
+        .and_then(|parsed| parsed.host_str().map(str::to_ascii_lowercase))
+    else {
+        return Vec::new();
+    };
+    let host = host.trim_end_matches('.');
+    let mut candidates = vec![host.to_string()];
+    candidates.extend(
+        host.match_indices('.')
+            .map(|(index, _)| host[index + 1..].to_string()),
+    );
+    candidates
+}
+
+/// The warning to show before sending a visitor of `url_entry` to
+/// `destination`, if the link or the destination's domain (or a parent
+/// domain) is flagged.
+pub fn interstitial_for(
+    conn: &mut SqliteConnection,
+    url_entry: &Url,
+    destination: &str,
+) -> QueryResult<Option<Interstitial>> {
+    use crate::schema::interstitial_domains::dsl::*;
+
+    let candidates = domain_candidates(destination);
+    let flagged = if candidates.is_empty() {
+        Vec::new()
+    } else {
+        interstitial_domains
+            .filter(domain.eq_any(&candidates))
+            .load::<InterstitialDomain>(conn)?
+    };
+    // Prefer the reason given for the most specific domain.
+    let matched = candidates
+        .iter()
+        .find_map(|candidate| flagged.iter().find(|row| &row.domain == candidate));
+    Ok(match matched {
+        Some(row) => Some(Interstitial {
+            reason: row.reason.clone(),
+        }),
+        None if url_entry.interstitial => Some(Interstitial { reason: None }),
+        None => None,
+    })
+}
+
+pub fn confirmation_cookie_name(short_code: &str) -> String {
+    format!("iw_{}", short_code)
+}
+
+/// Signature over the link, the host warned about and the expiry time, so a
+/// confirmation does not carry over to a destination the visitor was not
+/// warned about.
+fn confirmation_mac(
+    secret: &str,
+    url_entry: &Url,
+    destination: &str,
+    expires: i64,
+) -> Hmac<Sha256> {
+    let host = domain_candidates(destination)
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(url_entry.short_code.as_bytes());
+    mac.update(b"\n");
+    mac.update(host.as_bytes());
+    mac.update(b"\n");
+    mac.update(expires.to_string().as_bytes());
+    mac
+}
+
+/// Cookie handed out with a warning page, letting the visitor continue to
+/// `destination` by following the short link again.
+pub fn confirmation_cookie(config: &Config, url_entry: &Url, destination: &str) -> Cookie<'static> {
+    let expires = Utc::now().timestamp() + CONFIRMATION_TTL_SECS;
+    let signature = confirmation_mac(&config.cookie_secret, url_entry, destination, expires)
+        .finalize()
+        .into_bytes();
+    Cookie::build(
+        confirmation_cookie_name(&url_entry.short_code),
+        format!("{}.{}", expires, hex::encode(signature)),
+    )
+    .path(format!("/{}", url_entry.short_code))
+    .max_age(CookieDuration::seconds(CONFIRMATION_TTL_SECS))
+    .http_only(true)
+    .same_site(SameSite::Lax)
+    .finish()
+}
+
+/// Whether `cookie` is an unexpired confirmation of the warning shown for
+/// `url_entry` and `destination`.
+pub fn is_confirmed(
+    config: &Config,
+    url_entry: &Url,
+    destination: &str,
+    cookie: Option<&str>,
+) -> bool {
+    let Some((expires, signature)) = cookie.and_then(|value| value.split_once('.')) else {
+        return false;
+    };
+    let (Ok(expires), Ok(signature)) = (expires.parse::<i64>(), hex::decode(signature)) else {
+        return false;
+    };
+    expires > Utc::now().timestamp()
+        && confirmation_mac(&config.cookie_secret, url_entry, destination, expires)
+            .verify_slice(&signature)
+            .is_ok()
+}
+
+/// Warning page about `location`, continuing through `short_link`. The
+/// click is only counted once the visitor continues.
+pub fn interstitial_response(
+    location: &str,
+    short_link: &str,
+    interstitial: &Interstitial,
+) -> HttpResponse {
+    let host = url::Url::parse(location)
+        .ok()
+        .and_then(|parsed| parsed.host_str().map(str::to_string))
+        .unwrap_or_else(|| location.to_string());
+    let reason = interstitial
+        .reason
+        .as_deref()
+        .map(|reason| format!("<p>{}</p>\n", escape_html(reason)))
+        .unwrap_or_default();
+    let body = format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head><meta charset=\"utf-8\"><title>Before you continue</title></head>\n\
+         <body>\n\
+         <h1>Before you continue</h1>\n\
+         <p>This link leads to <strong>{}</strong>:</p>\n\
+         <p><code>{}</code></p>\n\
+         {}\
+         <p><a href=\"{}\" rel=\"noopener noreferrer nofollow\">Continue to {}</a></p>\n\
+         </body>\n\
+         </html>\n",
+        escape_html(&host),
+        escape_html(location),
+        reason,
+        escape_html(short_link),
+        escape_html(&host),
+    );
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .insert_header((header::REFERRER_POLICY, "no-referrer"))
+        .body(body)
+}
+
+fn status_text(url_entry: &Url) -> String {
+    if url_entry.disabled_at.is_some() {
+        return "Disabled by a moderator".to_string();
+    }
+    match url_entry.state(Utc::now().naive_utc()) {
+        LinkState::Pending(activates_at) => format!(
+            "Not active until {}",
+            activates_at.format("%Y-%m-%d %H:%M UTC")
+        ),
+        LinkState::Deactivated => "No longer active".to_string(),
+        LinkState::Expired => "Expired".to_string(),
+        LinkState::Exhausted => "Click limit reached".to_string(),
+        LinkState::Active if url_entry.remaining_clicks() == Some(0) => {
+            "Click limit reached".to_string()
+        },
+        LinkState::Active => "Active".to_string(),
+    }
+}
+
+fn preview_page(
+    url_entry: &Url,
+    short_url: &str,
+    click_count: i64,
+    interstitial: Option<&Interstitial>,
+) -> String {
+    // Showing the destination must not stand in for a visit the link would
+    // refuse, count or not yet allow.
+    let hidden = if url_entry.disabled_at.is_some() {
+        Some("this link was disabled by a moderator")
+    } else if url_entry.quarantined {
+        Some("this link is held for review")
+    } else if url_entry.is_password_protected() {
+        Some("this link is password-protected")
+    } else if url_entry.max_clicks.is_some() {
+        Some("this link has a click limit")
+    } else if url_entry.state(Utc::now().naive_utc()) != LinkState::Active {
+        Some("this link is not active")
+    } else {
+        None
+    };
+    let destination = match hidden {
+        Some(reason) => format!("<dd>Hidden, {}</dd>", reason),
+        None => format!(
+            "<dd><code>{}</code></dd>",
+            escape_html(&url_entry.original_url)
+        ),
+    };
+    let personalized = !url_entry.rules().is_empty()
+        || url_entry.platform_destinations().is_some()
+        || url_entry.split().is_some();
+    let note = if personalized {
+        "<p>Some visitors are sent to a different destination depending on their device, \
+         location or test group.</p>\n"
+    } else {
+        ""
+    };
+    let warning = interstitial
+        .map(|interstitial| {
+            let reason = interstitial
+                .reason
+                .as_deref()
+                .map(|reason| format!(" {}", escape_html(reason)))
+                .unwrap_or_default();
+            format!(
+                "<p role=\"alert\">Visitors see a warning before continuing.{}</p>\n",
+                reason
+            )
+        })
+        .unwrap_or_default();
+    let warning = if url_entry.quarantined {
+        "<p role=\"alert\">This link may lead to a harmful site and is held for review.</p>\n"
+            .to_string()
+    } else {
+        warning
+    };
+    format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head><meta charset=\"utf-8\"><title>Preview of {short_url}</title></head>\n\
+         <body>\n\
+         <h1>Preview of {short_url}</h1>\n\
+         <dl>\n\
+         <dt>Destination</dt>{destination}\n\
+         <dt>Created</dt><dd>{created}</dd>\n\
+         <dt>Clicks</dt><dd>{click_count}</dd>\n\
+         <dt>Status</dt><dd>{status}</dd>\n\
+         </dl>\n\
+         {note}\
+         {warning}\
+         <p><a href=\"{short_url}\" rel=\"nofollow\">Continue</a></p>\n\
+         </body>\n\
+         </html>\n",
+        short_url = escape_html(short_url),
+        destination = destination,
+        created = url_entry.created_at.format("%Y-%m-%d %H:%M UTC"),
+        click_count = click_count,
+        status = status_text(url_entry),
+        note = note,
+        warning = warning,
+    )
+}
+
+/// Handler for `/{code}+` and `/{code}/preview`: shows where a link leads
+/// without following it or counting a click. On links with `forward_path`,
+/// `/{code}/preview` is forwarded like any other path.
+pub async fn preview_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::urls;
+
+    let code = path.into_inner();
+    let short_url = format!("{}/{}", config.base_url.trim_end_matches('/'), code);
+    let as_path = req.path().ends_with("/preview");
+    let page = web::block(move || {
+        let mut conn = pool.get()?;
+        let Some(url_entry) = urls::table
+            .filter(urls::short_code.eq(&code))
+            .first::<Url>(&mut conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        if as_path && url_entry.forward_path {
+            return Ok(Some(None));
+        }
+        let stats = url_stats(&mut conn, &url_entry)?;
+        let interstitial = interstitial_for(&mut conn, &url_entry, &url_entry.original_url)?;
+        Ok::<_, AppError>(Some(Some(preview_page(
+            &url_entry,
+            &short_url,
+            stats.click_count,
+            interstitial.as_ref(),
+        ))))
+    })
+    .await??;
+    let page = match page {
+        None => return Ok(error_response(&req, ErrorPage::NotFound, "URL not found")),
+        Some(None) => return forward_to_redirect(req).await,
+        Some(Some(page)) => page,
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(page))
+}
+
+/// Lowercases a domain given through the API and checks it is a bare host
+/// name. A leading `*.` is accepted and dropped, since subdomains always
+/// match.
+pub fn normalize_domain(value: &str) -> Result<String, AppError> {
+    let domain = value
+        .trim()
+        .trim_start_matches("*.")
+        .trim_end_matches('.')
+        .to_ascii_lowercase();
+    let valid = !domain.is_empty()
+        && !domain.starts_with('.')
+        && domain
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '.');
+    if !valid {
+        return Err(AppError::InvalidInput(format!(
+            "{} is not a domain name",
+            value
+        )));
+    }
+    Ok(domain)
+}
+
+/// Handler for listing the domains that always show a warning page.
+pub async fn list_interstitial_domains_handler(
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, AppError> {
+    let rows = web::block(move || {
+        use crate::schema::interstitial_domains::dsl::*;
+        let mut conn = pool.get()?;
+        interstitial_domains
+            .order(domain.asc())
+            .load::<InterstitialDomain>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct InterstitialDomainRequest {
+    /// Shown to visitors on the warning page.
+    pub reason: Option<String>,
+}
+
+/// Handler for flagging a domain, or changing the reason of a flagged one.
+pub async fn put_interstitial_domain_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+    item: Option<web::Json<InterstitialDomainRequest>>,
+) -> Result<HttpResponse, AppError> {
+    let row = NewInterstitialDomain {
+        domain: normalize_domain(&path.into_inner())?,
+        reason: item
+            .map(|item| item.into_inner())
+            .unwrap_or_default()
+            .reason
+            .map(|reason| reason.trim().to_string())
+            .filter(|reason| !reason.is_empty()),
+    };
+    let saved = web::block(move || {
+        use crate::schema::interstitial_domains::dsl::*;
+        let mut conn = pool.get()?;
+        diesel::insert_into(interstitial_domains)
+            .values(&row)
+            .on_conflict(domain)
+            .do_update()
+            .set(reason.eq(&row.reason))
+            .execute(&mut conn)?;
+        interstitial_domains
+            .find(&row.domain)
+            .first::<InterstitialDomain>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(saved))
+}
+
+/// Handler for un-flagging a domain.
+pub async fn delete_interstitial_domain_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let name = normalize_domain(&path.into_inner())?;
+    web::block(move || {
+        use crate::schema::interstitial_domains::dsl::*;
+        let mut conn = pool.get()?;
+        match diesel::delete(interstitial_domains.find(&name)).execute(&mut conn)? {
+            0 => Err(AppError::NotFound(format!("interstitial domain {}", name))),
+            _ => Ok(()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
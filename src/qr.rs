@@ -0,0 +1,391 @@
+// src/qr.rs
+// QR codes for short links.
+//
+// The encoded address carries a `qr=1` marker so that redirects coming from a
+// scan can be told apart from ordinary clicks.
+
+use std::{fmt::Write as _, io::Cursor, path::Path};
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use base64::Engine as _;
+use diesel::prelude::*;
+use image::{imageops, ImageFormat, Rgba, RgbaImage};
+use qrcode::{EcLevel, QrCode};
+use serde::Deserialize;
+use url::form_urlencoded;
+
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::handlers::forward_to_redirect;
+use crate::models::Url;
+
+/// Query parameter appended to the short URL encoded in QR codes.
+pub const SCAN_MARKER: (&str, &str) = ("qr", "1");
+
+/// `source` recorded in `redirect_stats` for redirects from a scan.
+pub const SCAN_SOURCE: &str = "qr";
+
+const DEFAULT_SIZE: u32 = 300;
+const MIN_SIZE: u32 = 64;
+const MAX_SIZE: u32 = 2048;
+const DEFAULT_MARGIN: u32 = 4;
+const MAX_MARGIN: u32 = 16;
+
+/// Removes the scan marker from an incoming query string. Returns the
+/// remaining query string and whether the marker was present.
+pub fn strip_scan_marker(query: &str) -> (String, bool) {
+    let pairs: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let is_marker =
+        |(name, value): &(String, String)| (name.as_str(), value.as_str()) == SCAN_MARKER;
+    if !pairs.iter().any(is_marker) {
+        return (query.to_string(), false);
+    }
+    let rest = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs.iter().filter(|pair| !is_marker(pair)))
+        .finish();
+    (rest, true)
+}
+
+/// An sRGB color with alpha, given as `RGB`, `RRGGBB` or `RRGGBBAA` hex digits
+/// with an optional leading `#`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color([u8; 4]);
+
+impl Color {
+    pub const BLACK: Color = Color([0, 0, 0, 255]);
+    pub const WHITE: Color = Color([255, 255, 255, 255]);
+
+    pub fn parse(value: &str) -> Option<Color> {
+        let digits = value.trim_start_matches('#');
+        if !digits.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            return None;
+        }
+        let byte = |index: usize| u8::from_str_radix(&digits[index..index + 2], 16).ok();
+        match digits.len() {
+            3 => {
+                let mut rgba = [255; 4];
+                for (channel, ch) in rgba.iter_mut().zip(digits.chars()) {
+                    *channel = ch.to_digit(16)? as u8 * 17;
+                }
+                Some(Color(rgba))
+            },
+            6 => Some(Color([byte(0)?, byte(2)?, byte(4)?, 255])),
+            8 => Some(Color([byte(0)?, byte(2)?, byte(4)?, byte(6)?])),
+            _ => None,
+        }
+    }
+
+    /// SVG fill value, e.g. `#1a2b3c`, plus the opacity when not opaque.
+    fn svg_fill(&self) -> String {
+        let [r, g, b, a] = self.0;
+        let fill = format!("fill=\"#{:02x}{:02x}{:02x}\"", r, g, b);
+        match a {
+            255 => fill,
+            _ => format!("{} fill-opacity=\"{:.3}\"", fill, f32::from(a) / 255.0),
+        }
+    }
+}
+
+/// Image placed in the middle of QR codes that ask for a logo, loaded once at
+/// startup from `config.qr_logo`.
+#[derive(Default)]
+pub struct QrLogo {
+    logo: Option<(RgbaImage, Vec<u8>)>,
+}
+
+impl QrLogo {
+    /// Loads a PNG logo.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let png = std::fs::read(path).map_err(|err| err.to_string())?;
+        let image = image::load_from_memory_with_format(&png, ImageFormat::Png)
+            .map_err(|err| err.to_string())?
+            .to_rgba8();
+        Ok(QrLogo {
+            logo: Some((image, png)),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QrFormat {
+    Png,
+    Svg,
+}
+
+#[derive(Deserialize)]
+pub struct QrQuery {
+    /// `png` (default) or `svg`.
+    pub format: Option<String>,
+    /// Requested width and height in pixels.
+    pub size: Option<u32>,
+    /// Quiet zone around the code, in modules.
+    pub margin: Option<u32>,
+    /// Error correction level: `L`, `M` (default), `Q` or `H`.
+    pub ecc: Option<String>,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    /// Put the configured logo in the middle of the code.
+    #[serde(default)]
+    pub logo: bool,
+    /// Append the scan marker to the encoded address; on by default.
+    pub marker: Option<bool>,
+}
+
+/// Validated rendering options.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QrOptions {
+    pub format: QrFormat,
+    pub size: u32,
+    pub margin: u32,
+    pub ecc: EcLevel,
+    pub foreground: Color,
+    pub background: Color,
+    pub logo: bool,
+    pub marker: bool,
+}
+
+impl QrQuery {
+    pub fn options(&self) -> Result<QrOptions, AppError> {
+        let invalid = |message: String| AppError::InvalidInput(message);
+        let format = match self
+            .format
+            .as_deref()
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            None | Some("png") => QrFormat::Png,
+            Some("svg") => QrFormat::Svg,
+            Some(other) => {
+                return Err(invalid(format!("format must be png or svg, got {}", other)))
+            },
+        };
+        let size = self.size.unwrap_or(DEFAULT_SIZE);
+        if !(MIN_SIZE..=MAX_SIZE).contains(&size) {
+            return Err(invalid(format!(
+                "size must be between {} and {}",
+                MIN_SIZE, MAX_SIZE
+            )));
+        }
+        let margin = self.margin.unwrap_or(DEFAULT_MARGIN);
+        if margin > MAX_MARGIN {
+            return Err(invalid(format!("margin must be at most {}", MAX_MARGIN)));
+        }
+        let ecc = match self.ecc.as_deref().map(str::to_ascii_uppercase).as_deref() {
+            Some("L") => EcLevel::L,
+            None | Some("M") => EcLevel::M,
+            Some("Q") => EcLevel::Q,
+            Some("H") => EcLevel::H,
+            Some(other) => return Err(invalid(format!("ecc must be L, M, Q or H, got {}", other))),
+        };
+        let color = |name: &str, value: Option<&str>, default: Color| match value {
+            None => Ok(default),
+            Some(value) => Color::parse(value)
+                .ok_or_else(|| invalid(format!("{} must be a hex color, got {}", name, value))),
+        };
+        let foreground = color("fg", self.fg.as_deref(), Color::BLACK)?;
+        let background = color("bg", self.bg.as_deref(), Color::WHITE)?;
+        if foreground == background {
+            return Err(invalid("fg and bg must differ".to_string()));
+        }
+        Ok(QrOptions {
+            format,
+            size,
+            margin,
+            // The logo hides part of the code, which only the highest level
+            // reliably recovers from.
+            ecc: if self.logo { EcLevel::H } else { ecc },
+            foreground,
+            background,
+            logo: self.logo,
+            marker: self.marker.unwrap_or(true),
+        })
+    }
+}
+
+/// Module grid of a QR code, including the quiet zone.
+struct Grid {
+    /// Side length in modules.
+    modules: u32,
+    /// Pixels per module.
+    scale: u32,
+    dark: Vec<bool>,
+}
+
+impl Grid {
+    fn new(data: &str, options: &QrOptions) -> Result<Grid, AppError> {
+        let code = QrCode::with_error_correction_level(data.as_bytes(), options.ecc)
+            .map_err(|err| AppError::InvalidInput(format!("cannot encode {}: {}", data, err)))?;
+        let width = code.width() as u32;
+        let modules = width + 2 * options.margin;
+        let colors = code.to_colors();
+        let dark = (0..modules * modules)
+            .map(|index| {
+                let (x, y) = (index % modules, index / modules);
+                let inside = |v: u32| (options.margin..options.margin + width).contains(&v);
+                inside(x)
+                    && inside(y)
+                    && colors[((y - options.margin) * width + x - options.margin) as usize]
+                        == qrcode::Color::Dark
+            })
+            .collect();
+        Ok(Grid {
+            modules,
+            // Whole pixels per module keep the edges sharp, so the image can be
+            // a little smaller than requested.
+            scale: (options.size / modules).max(1),
+            dark,
+        })
+    }
+
+    fn pixels(&self) -> u32 {
+        self.modules * self.scale
+    }
+
+    /// Square in the middle covered by the logo, as (offset, side) in pixels,
+    /// with a one-module border of background around it.
+    fn logo_area(&self) -> (u32, u32) {
+        let side = self.pixels() / 5;
+        ((self.pixels() - side) / 2, side)
+    }
+}
+
+fn render_png(
+    grid: &Grid,
+    options: &QrOptions,
+    logo: Option<&RgbaImage>,
+) -> Result<Vec<u8>, AppError> {
+    let side = grid.pixels();
+    let mut image = RgbaImage::from_pixel(side, side, Rgba(options.background.0));
+    for (index, _) in grid.dark.iter().enumerate().filter(|(_, dark)| **dark) {
+        let (x, y) = (index as u32 % grid.modules, index as u32 / grid.modules);
+        for dy in 0..grid.scale {
+            for dx in 0..grid.scale {
+                image.put_pixel(
+                    x * grid.scale + dx,
+                    y * grid.scale + dy,
+                    Rgba(options.foreground.0),
+                );
+            }
+        }
+    }
+    if let Some(logo) = logo {
+        let (offset, logo_side) = grid.logo_area();
+        let padded = RgbaImage::from_pixel(
+            logo_side + 2 * grid.scale,
+            logo_side + 2 * grid.scale,
+            Rgba(options.background.0),
+        );
+        let padding = i64::from(offset) - i64::from(grid.scale);
+        imageops::replace(&mut image, &padded, padding, padding);
+        let resized = imageops::resize(logo, logo_side, logo_side, imageops::FilterType::Triangle);
+        imageops::overlay(&mut image, &resized, i64::from(offset), i64::from(offset));
+    }
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|err| AppError::InternalError(format!("cannot encode PNG: {}", err)))?;
+    Ok(png)
+}
+
+fn render_svg(grid: &Grid, options: &QrOptions, logo: Option<&[u8]>) -> String {
+    let modules = grid.modules;
+    let mut path = String::new();
+    for (index, _) in grid.dark.iter().enumerate().filter(|(_, dark)| **dark) {
+        let (x, y) = (index as u32 % modules, index as u32 / modules);
+        let _ = write!(path, "M{} {}h1v1h-1z", x, y);
+    }
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {m} {m}\" width=\"{px}\" height=\"{px}\" \
+         shape-rendering=\"crispEdges\">\
+         <rect width=\"{m}\" height=\"{m}\" {bg}/>\
+         <path d=\"{path}\" {fg}/>",
+        m = modules,
+        px = grid.pixels(),
+        bg = options.background.svg_fill(),
+        fg = options.foreground.svg_fill(),
+        path = path,
+    );
+    if let Some(png) = logo {
+        // Same proportions as the PNG, in modules instead of pixels.
+        let side = f64::from(modules) / 5.0;
+        let offset = (f64::from(modules) - side) / 2.0;
+        let _ = write!(
+            svg,
+            "<rect x=\"{pad_at}\" y=\"{pad_at}\" width=\"{pad}\" height=\"{pad}\" {bg}/>\
+             <image x=\"{at}\" y=\"{at}\" width=\"{side}\" height=\"{side}\" href=\"data:image/png;base64,{data}\"/>",
+            pad_at = offset - 1.0,
+            pad = side + 2.0,
+            at = offset,
+            side = side,
+            bg = options.background.svg_fill(),
+            data = base64::engine::general_purpose::STANDARD.encode(png),
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Handler for `GET /{code}/qr`: a QR code of the link's short URL. On
+/// links with `forward_path`, the path is forwarded instead.
+pub async fn qr_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    logo: web::Data<QrLogo>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::urls;
+
+    let code = path.into_inner();
+    let url_entry = web::block(move || {
+        let mut conn = pool.get()?;
+        urls::table
+            .filter(urls::short_code.eq(&code))
+            .first::<Url>(&mut conn)
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("short code {}", code)))
+    })
+    .await??;
+    if url_entry.forward_path {
+        return forward_to_redirect(req).await;
+    }
+
+    // Parsed only now, as the query belongs to the destination when the
+    // path is forwarded
+    let options = web::Query::<QrQuery>::from_query(req.query_string())
+        .map_err(|err| AppError::InvalidInput(err.to_string()))?
+        .options()?;
+    let logo = match (options.logo, &logo.logo) {
+        (false, _) => None,
+        (true, Some(logo)) => Some(logo),
+        (true, None) => {
+            return Err(AppError::InvalidInput(
+                "no QR logo is configured".to_string(),
+            ))
+        },
+    };
+
+    let mut data = format!("{}/{}", config.base_url, url_entry.short_code);
+    if options.marker {
+        let _ = write!(data, "?{}={}", SCAN_MARKER.0, SCAN_MARKER.1);
+    }
+    let grid = Grid::new(&data, &options)?;
+    let mut response = HttpResponse::Ok();
+    // The image only depends on the short code and the query parameters.
+    response.insert_header((header::CACHE_CONTROL, "public, max-age=86400"));
+    Ok(match options.format {
+        QrFormat::Png => response.content_type("image/png").body(render_png(
+            &grid,
+            &options,
+            logo.map(|(image, _)| image),
+        )?),
+        QrFormat::Svg => response.content_type("image/svg+xml").body(render_svg(
+            &grid,
+            &options,
+            logo.map(|(_, png)| png.as_slice()),
+        )),
+    })
+}
@@ -0,0 +1,460 @@
+// src/ratelimit.rs
+// Token-bucket rate limiting of link creation, redirects and abuse reports.
+//
+// Each client gets a bucket per limit holding up to `count` tokens, refilled
+// at `count` per `period`. Every request takes a token from each of its
+// buckets, or is answered with 429 and takes none once any of them is empty,
+// so being refused by one limit never costs another. Buckets live in memory,
+// or in the database
+// when several instances share it and should share their limits too.
+
+use std::{collections::HashMap, str::FromStr, sync::Mutex, time::Duration};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{
+        header::{self, HeaderMap, HeaderName, HeaderValue},
+        Method,
+    },
+    middleware::Next,
+    web, HttpMessage,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::{
+    auth::Principal,
+    config::Config,
+    db::DbPool,
+    error_pages::{error_response, ErrorPage},
+    models::RateLimitBucket,
+    visitor::client_ip,
+};
+
+/// How often buckets that have refilled completely are dropped.
+const PRUNE_INTERVAL_MS: i64 = 60_000;
+
+/// `count` requests per `period`, parsed from `count/period` where the
+/// period is `s`, `m`, `h` or `d`, optionally with a multiplier: `60/m`,
+/// `1000/h`, `100/15m`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub count: u32,
+    pub period: Duration,
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not count/period, e.g. 60/m", value);
+        let (count, period) = value.trim().split_once('/').ok_or_else(invalid)?;
+        let count: u32 = count.trim().parse().map_err(|_| invalid())?;
+        let period = period.trim();
+        let unit_at = period.len().checked_sub(1).ok_or_else(invalid)?;
+        let (multiplier, unit) = period.split_at(unit_at);
+        let multiplier: u64 = match multiplier {
+            "" => 1,
+            digits => digits.parse().map_err(|_| invalid())?,
+        };
+        let unit_secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        if count == 0 || multiplier == 0 {
+            return Err(invalid());
+        }
+        Ok(RateLimit {
+            count,
+            period: Duration::from_secs(multiplier * unit_secs),
+        })
+    }
+}
+
+/// A bucket's fill level at a point in time (milliseconds since the epoch).
+#[derive(Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: i64,
+}
+
+/// Outcome of taking a token, with what the `X-RateLimit-*` headers report.
+#[derive(Clone, Copy, Debug)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Until the bucket is full again.
+    pub reset: Duration,
+    /// Until the next token, when none was left.
+    pub retry_after: Option<Duration>,
+}
+
+impl Decision {
+    fn apply(&self, headers: &mut HeaderMap) {
+        let secs = |duration: Duration| duration.as_millis().div_ceil(1000) as u64;
+        let mut insert = |name: &'static str, value: u64| {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+        };
+        insert("x-ratelimit-limit", self.limit.into());
+        insert("x-ratelimit-remaining", self.remaining.into());
+        insert("x-ratelimit-reset", secs(self.reset));
+        if let Some(wait) = self.retry_after {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(secs(wait).max(1)));
+        }
+    }
+}
+
+impl RateLimit {
+    /// Tokens refilled per millisecond.
+    fn rate(&self) -> f64 {
+        self.count as f64 / self.period.as_millis().max(1) as f64
+    }
+
+    /// Takes a token from `bucket` (full when `None`) at `now`.
+    fn take(&self, bucket: Option<Bucket>, now: i64) -> (Bucket, Decision) {
+        let capacity = self.count as f64;
+        let mut tokens = match bucket {
+            Some(bucket) => {
+                let elapsed = (now - bucket.updated_at).max(0) as f64;
+                (bucket.tokens + elapsed * self.rate()).min(capacity)
+            },
+            None => capacity,
+        };
+        let allowed = tokens >= 1.0;
+        let retry_after = if allowed {
+            tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_millis(
+                ((1.0 - tokens) / self.rate()).ceil() as u64
+            ))
+        };
+        let decision = Decision {
+            allowed,
+            limit: self.count,
+            remaining: tokens.floor() as u32,
+            reset: Duration::from_millis(((capacity - tokens) / self.rate()).ceil() as u64),
+            retry_after,
+        };
+        (
+            Bucket {
+                tokens,
+                updated_at: now,
+            },
+            decision,
+        )
+    }
+}
+
+/// Where buckets are kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StoreKind {
+    /// In this process; each instance counts on its own.
+    #[default]
+    Memory,
+    /// In the `rate_limit_buckets` table, shared by every instance using
+    /// the database.
+    Database,
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "memory" => Ok(StoreKind::Memory),
+            "database" => Ok(StoreKind::Database),
+            other => Err(format!("unknown rate limit store '{}'", other)),
+        }
+    }
+}
+
+enum Store {
+    Memory(Mutex<HashMap<String, Bucket>>),
+    Database(DbPool),
+}
+
+/// The configured limits and their buckets. Without limits, every request
+/// passes untouched.
+pub struct RateLimiter {
+    create_per_key: Option<RateLimit>,
+    create_per_ip: Option<RateLimit>,
+    redirect_per_ip: Option<RateLimit>,
+    report_per_ip: Option<RateLimit>,
+    store: Store,
+    last_pruned: Mutex<i64>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        RateLimiter {
+            create_per_key: None,
+            create_per_ip: None,
+            redirect_per_ip: None,
+            report_per_ip: None,
+            store: Store::Memory(Mutex::default()),
+            last_pruned: Mutex::new(0),
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn from_config(config: &Config, pool: &DbPool) -> Self {
+        let store = match config.rate_limit_store {
+            StoreKind::Memory => Store::Memory(Mutex::default()),
+            StoreKind::Database => Store::Database(pool.clone()),
+        };
+        RateLimiter {
+            create_per_key: config.rate_limit_create_per_key,
+            create_per_ip: config.rate_limit_create_per_ip,
+            redirect_per_ip: config.rate_limit_redirect_per_ip,
+            report_per_ip: config.rate_limit_report_per_ip,
+            store,
+            ..RateLimiter::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.create_per_key.is_some()
+            || self.create_per_ip.is_some()
+            || self.redirect_per_ip.is_some()
+            || self.report_per_ip.is_some()
+    }
+
+    /// Longest period of the configured limits; buckets untouched for that
+    /// long are full and can be forgotten.
+    fn longest_period(&self) -> i64 {
+        [
+            self.create_per_key,
+            self.create_per_ip,
+            self.redirect_per_ip,
+            self.report_per_ip,
+        ]
+        .iter()
+        .flatten()
+        .map(|limit| limit.period.as_millis() as i64)
+        .max()
+        .unwrap_or(0)
+    }
+
+    /// The buckets a request takes from, keyed by what they count.
+    fn buckets_for(&self, req: &ServiceRequest) -> Vec<(String, RateLimit)> {
+        let Some(pattern) = req.match_pattern() else {
+            return Vec::new();
+        };
+        let ip = client_ip(req.request()).unwrap_or_else(|| "unknown".to_string());
+        let mut buckets = Vec::new();
+        match (req.method(), pattern.as_str()) {
+            (&Method::POST, "/") => {
+                if let Some(limit) = self.create_per_ip {
+                    buckets.push((format!("create:ip:{}", ip), limit));
+                }
+                let credential = req.extensions().get::<Principal>().map(credential_of);
+                if let (Some(limit), Some(credential)) = (self.create_per_key, credential) {
+                    buckets.push((format!("create:{}", credential), limit));
+                }
+            },
+            (
+                &Method::GET,
+                "/{code}" | "/{code}/report" | "/{code}/{tail:.*}" | "/{code}+" | "/{code}/preview"
+                | "/{code}/qr",
+            ) => {
+                if let Some(limit) = self.redirect_per_ip {
+                    buckets.push((format!("redirect:ip:{}", ip), limit));
+                }
+            },
+            (&Method::POST, "/{code}/report") => {
+                if let Some(limit) = self.report_per_ip {
+                    buckets.push((format!("report:ip:{}", ip), limit));
+                }
+            },
+            _ => {},
+        }
+        buckets
+    }
+
+    /// Takes a token from every bucket, or from none when any of them
+    /// refuses, and returns the tightest outcome.
+    fn take_all(&self, buckets: &[(String, RateLimit)]) -> Option<Decision> {
+        let now = Utc::now().timestamp_millis();
+        self.prune(now);
+        let decisions = match &self.store {
+            Store::Memory(stored) => {
+                let mut stored = stored.lock().unwrap();
+                let current = buckets
+                    .iter()
+                    .map(|(key, _)| stored.get(key).copied())
+                    .collect();
+                let (decisions, taken) = take_each(buckets, current, now);
+                for ((key, _), bucket) in buckets.iter().zip(taken.into_iter().flatten()) {
+                    stored.insert(key.clone(), bucket);
+                }
+                decisions
+            },
+            Store::Database(pool) => take_shared(pool, buckets, now).unwrap_or_else(|err| {
+                // Rather let requests through than fail them all.
+                log::warn!("Cannot update rate limit buckets: {}", err);
+                buckets
+                    .iter()
+                    .map(|(_, limit)| limit.take(None, now).1)
+                    .collect()
+            }),
+        };
+        decisions
+            .into_iter()
+            .min_by_key(|decision| (decision.allowed, decision.remaining))
+    }
+
+    /// Drops buckets that have refilled completely, at most once per
+    /// `PRUNE_INTERVAL_MS`.
+    fn prune(&self, now: i64) {
+        {
+            let mut last_pruned = self.last_pruned.lock().unwrap();
+            if now - *last_pruned < PRUNE_INTERVAL_MS {
+                return;
+            }
+            *last_pruned = now;
+        }
+        let cutoff = now - self.longest_period();
+        match &self.store {
+            Store::Memory(buckets) => buckets
+                .lock()
+                .unwrap()
+                .retain(|_, bucket| bucket.updated_at > cutoff),
+            Store::Database(pool) => {
+                use crate::schema::rate_limit_buckets::dsl::*;
+
+                let pruned = pool
+                    .get()
+                    .map_err(|err| err.to_string())
+                    .and_then(|mut conn| {
+                        diesel::delete(rate_limit_buckets.filter(updated_at.le(cutoff)))
+                            .execute(&mut conn)
+                            .map_err(|err| err.to_string())
+                    });
+                if let Err(err) = pruned {
+                    log::warn!("Cannot prune rate limit buckets: {}", err);
+                }
+            },
+        }
+    }
+}
+
+/// What a creation limit counts per: the API key, or the user behind a
+/// token or session.
+fn credential_of(principal: &Principal) -> String {
+    match (principal.key_id, principal.user_id) {
+        (Some(key_id), _) => format!("key:{}", key_id),
+        (None, Some(user_id)) => format!("user:{}", user_id),
+        (None, None) => format!("name:{}", principal.name),
+    }
+}
+
+/// Decides every bucket from its `current` state (full when `None`). The
+/// buckets with their token taken are only returned when all of them allow
+/// the request, so a request refused by one bucket costs the others nothing.
+fn take_each(
+    buckets: &[(String, RateLimit)],
+    current: Vec<Option<Bucket>>,
+    now: i64,
+) -> (Vec<Decision>, Option<Vec<Bucket>>) {
+    let (taken, decisions): (Vec<Bucket>, Vec<Decision>) = buckets
+        .iter()
+        .zip(current)
+        .map(|((_, limit), bucket)| limit.take(bucket, now))
+        .unzip();
+    let allowed = decisions.iter().all(|decision| decision.allowed);
+    (decisions, allowed.then_some(taken))
+}
+
+/// Takes a token from each bucket in the database. The reads and the writes
+/// happen in one transaction under SQLite's write lock, so instances never
+/// take the same token.
+fn take_shared(
+    pool: &DbPool,
+    buckets: &[(String, RateLimit)],
+    now: i64,
+) -> Result<Vec<Decision>, String> {
+    use crate::schema::rate_limit_buckets::dsl::*;
+
+    let mut conn = pool.get().map_err(|err| err.to_string())?;
+    conn.immediate_transaction(|conn| {
+        let mut current = Vec::with_capacity(buckets.len());
+        for (bucket_key, _) in buckets {
+            let stored = rate_limit_buckets
+                .find(bucket_key)
+                .first::<RateLimitBucket>(conn)
+                .optional()?
+                .map(|stored| Bucket {
+                    tokens: stored.tokens,
+                    updated_at: stored.updated_at,
+                });
+            current.push(stored);
+        }
+        let (decisions, taken) = take_each(buckets, current, now);
+        for ((bucket_key, _), bucket) in buckets.iter().zip(taken.into_iter().flatten()) {
+            diesel::insert_into(rate_limit_buckets)
+                .values(&RateLimitBucket {
+                    key: bucket_key.clone(),
+                    tokens: bucket.tokens,
+                    updated_at: bucket.updated_at,
+                })
+                .on_conflict(key)
+                .do_update()
+                .set((tokens.eq(bucket.tokens), updated_at.eq(bucket.updated_at)))
+                .execute(conn)?;
+        }
+        Ok::<_, diesel::result::Error>(decisions)
+    })
+    .map_err(|err| err.to_string())
+}
+
+/// Middleware applying the configured limits. Allowed requests carry the
+/// `X-RateLimit-*` headers of their tightest bucket; refused ones get 429
+/// with `Retry-After`.
+///
+/// Must run after `crate::auth::authenticate`, which identifies the API
+/// key creation limits count per.
+pub async fn limit<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, actix_web::Error> {
+    let limiter = req
+        .app_data::<web::Data<RateLimiter>>()
+        .filter(|limiter| limiter.is_enabled())
+        .cloned();
+    let buckets = limiter
+        .as_ref()
+        .map(|limiter| limiter.buckets_for(&req))
+        .unwrap_or_default();
+    let decision = match limiter {
+        Some(limiter) if matches!(limiter.store, Store::Database(_)) => {
+            web::block(move || limiter.take_all(&buckets)).await?
+        },
+        Some(limiter) => limiter.take_all(&buckets),
+        None => None,
+    };
+    let Some(decision) = decision else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+    let mut response = if decision.allowed {
+        next.call(req).await?.map_into_left_body()
+    } else {
+        let refused = error_response(
+            req.request(),
+            ErrorPage::RateLimited,
+            "Too many requests, try again later",
+        );
+        req.into_response(refused).map_into_right_body()
+    };
+    decision.apply(response.headers_mut());
+    Ok(response)
+}
@@ -0,0 +1,189 @@
+// src/redirect.rs
+// Building the HTTP response for a resolved short link.
+
+use std::fmt;
+
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse,
+};
+use url::form_urlencoded;
+
+use crate::models::Url;
+use crate::utm::UtmTemplate;
+
+/// The redirect status codes a link may use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RedirectStatus {
+    /// 301, cacheable, may turn POST into GET.
+    MovedPermanently,
+    /// 302, not cached, may turn POST into GET.
+    Found,
+    /// 307, not cached, preserves method and body.
+    TemporaryRedirect,
+    /// 308, cacheable, preserves method and body.
+    PermanentRedirect,
+}
+
+impl RedirectStatus {
+    pub const ALLOWED: [u16; 4] = [301, 302, 307, 308];
+
+    pub fn from_code(code: u16) -> Option<Self> {
+        match code {
+            301 => Some(RedirectStatus::MovedPermanently),
+            302 => Some(RedirectStatus::Found),
+            307 => Some(RedirectStatus::TemporaryRedirect),
+            308 => Some(RedirectStatus::PermanentRedirect),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        self.status_code().as_u16()
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            RedirectStatus::MovedPermanently => StatusCode::MOVED_PERMANENTLY,
+            RedirectStatus::Found => StatusCode::FOUND,
+            RedirectStatus::TemporaryRedirect => StatusCode::TEMPORARY_REDIRECT,
+            RedirectStatus::PermanentRedirect => StatusCode::PERMANENT_REDIRECT,
+        }
+    }
+
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            RedirectStatus::MovedPermanently | RedirectStatus::PermanentRedirect
+        )
+    }
+
+    /// Permanent redirects may be cached by browsers and shared caches for
+    /// `max_age` seconds. Temporary ones must reach the server every time so
+    /// the destination can change and each click is counted.
+    ///
+    /// A `personalized` redirect depends on who asked (their platform or A/B
+    /// variant), so only the visitor's own browser may cache it.
+    pub fn cache_control(&self, max_age: u64, personalized: bool) -> String {
+        match (self.is_permanent(), personalized) {
+            (false, _) => "no-store".to_string(),
+            (true, false) => format!("public, max-age={}", max_age),
+            (true, true) => format!("private, max-age={}", max_age),
+        }
+    }
+
+    /// Builds the redirect response to `location`.
+    pub fn respond(&self, location: &str, max_age: u64, personalized: bool) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .insert_header((header::LOCATION, location))
+            .insert_header((
+                header::CACHE_CONTROL,
+                self.cache_control(max_age, personalized),
+            ))
+            .finish()
+    }
+}
+
+impl fmt::Display for RedirectStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// Validates a status code supplied through the API.
+pub fn validate_redirect_status(code: u16) -> Result<RedirectStatus, String> {
+    RedirectStatus::from_code(code).ok_or_else(|| {
+        format!(
+            "redirect_status must be one of {:?}, got {}",
+            RedirectStatus::ALLOWED,
+            code
+        )
+    })
+}
+
+/// Everything after the short code in a request path, still percent-encoded,
+/// e.g. `guide/intro` for `/docs/guide/intro`.
+pub fn path_tail(path: &str) -> &str {
+    path.trim_start_matches('/')
+        .split_once('/')
+        .map_or("", |(_, tail)| tail)
+}
+
+/// Whether a forwarded `tail` has `.` or `..` segments, percent-encoded or
+/// not. Appending them would let `url::Url::set_path` climb out of the
+/// destination's path, so such tails are not forwarded.
+pub fn has_dot_segments(tail: &str) -> bool {
+    tail.split(['/', '\\']).any(|segment| {
+        matches!(
+            segment.to_ascii_lowercase().as_str(),
+            "." | ".." | "%2e" | ".%2e" | "%2e." | "%2e%2e"
+        )
+    })
+}
+
+/// The address a visitor of `url_entry` is sent to, starting from the
+/// destination `base` chosen by `crate::routing::route`.
+///
+/// With `forward_path`, `tail` is appended to the destination path, unless
+/// it has dot segments. UTM
+/// parameters from `utm` are added next, without replacing any the
+/// destination already has. Finally, with `forward_query`, the incoming query
+/// string is merged in, incoming values replacing parameters of the same
+/// name unless the template gives its own values precedence.
+pub fn destination(
+    url_entry: &Url,
+    base: &str,
+    utm: Option<&UtmTemplate>,
+    tail: &str,
+    query: &str,
+) -> String {
+    let forward_path = url_entry.forward_path && !tail.is_empty() && !has_dot_segments(tail);
+    let forward_query = url_entry.forward_query && !query.is_empty();
+    let utm_params = utm.map(UtmTemplate::params).unwrap_or_default();
+    if !forward_path && !forward_query && utm_params.is_empty() {
+        return base.to_string();
+    }
+    let Ok(mut target) = url::Url::parse(base) else {
+        return base.to_string();
+    };
+
+    if forward_path {
+        let path = format!("{}/{}", target.path().trim_end_matches('/'), tail);
+        target.set_path(&path);
+    }
+    if !utm_params.is_empty() {
+        merge_query(&mut target, &utm_params, false);
+    }
+    if forward_query {
+        let mut incoming: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        if utm.is_some_and(|template| !template.incoming_wins()) {
+            incoming.retain(|(name, _)| !utm_params.iter().any(|(key, _)| key == name));
+        }
+        merge_query(&mut target, &incoming, true);
+    }
+    target.to_string()
+}
+
+/// Adds `params` to the query string of `target`. Parameters `target`
+/// already has keep their value unless `replace` is set.
+pub fn merge_query(target: &mut url::Url, params: &[(String, String)], replace: bool) {
+    let existing: Vec<(String, String)> = target.query_pairs().into_owned().collect();
+    let added: Vec<&(String, String)> = params
+        .iter()
+        .filter(|(name, _)| replace || !existing.iter().any(|(key, _)| key == name))
+        .collect();
+    if added.is_empty() {
+        return;
+    }
+
+    let kept = existing
+        .iter()
+        .filter(|(key, _)| !added.iter().any(|(name, _)| name == key));
+    target
+        .query_pairs_mut()
+        .clear()
+        .extend_pairs(kept)
+        .extend_pairs(added);
+}
@@ -2,7 +2,45 @@
 // Route configuration for the URL shortener service
 
 use actix_web::web;
-use crate::handlers::{create_url_handler, list_urls_handler, redirect_handler, health_check_handler};
+use crate::audit::list_audit_log_handler;
+use crate::auth::{
+    create_api_key_handler, list_api_keys_handler, revoke_api_key_handler, rotate_api_key_handler,
+};
+use crate::events::events_handler;
+use crate::export::export_clicks_handler;
+use crate::moderation::{
+    disable_url_handler, dismiss_report_handler, enable_url_handler, list_reports_handler,
+    moderation_log_handler, report_handler,
+};
+use crate::oidc::{callback_handler, login_handler, logout_handler};
+use crate::handlers::{
+    create_url_handler, delete_url_handler, list_urls_handler, redirect_handler,
+    health_check_handler, update_url_handler,
+};
+use crate::password::unlock_handler;
+use crate::preview::{
+    delete_interstitial_domain_handler, list_interstitial_domains_handler, preview_handler,
+    put_interstitial_domain_handler,
+};
+use crate::qr::qr_handler;
+use crate::rules::dry_run_handler;
+use crate::screening::{
+    confirm_screening_handler, list_screenings_handler, release_screening_handler, scan_handler,
+};
+use crate::stats::url_stats_handler;
+use crate::users::{
+    create_user_handler, delete_user_handler, list_users_handler, transfer_url_handler,
+};
+use crate::utm::{delete_tag_utm_handler, get_tag_utm_handler, put_tag_utm_handler};
+use crate::webhooks::{
+    delete_webhook_handler, list_deliveries_handler, list_webhooks_handler,
+    register_webhook_handler, retry_delivery_handler,
+};
+use crate::workspaces::{
+    create_workspace_handler, delete_member_handler, delete_workspace_handler,
+    get_workspace_handler, list_members_handler, list_workspaces_handler, put_member_handler,
+    update_workspace_handler,
+};
 
 /// Initializes and configures all application routes
 ///
@@ -10,7 +48,58 @@ use crate::handlers::{create_url_handler, list_urls_handler, redirect_handler, h
 /// - POST / - Create a new shortened URL
 /// - GET / - List all shortened URLs
 /// - GET /health - Health check endpoint
+/// - GET /stats/{code} - Click statistics of a shortened URL
+/// - PATCH /api/urls/{code} - Edit a shortened URL
+/// - DELETE /api/urls/{code} - Delete a shortened URL
+/// - PUT /api/urls/{code}/owner - Transfer a link to another user
+/// - POST /api/urls/{code}/rules/dry-run - Show which rule a synthetic request would match
+/// - POST /api/urls/{code}/disable - Disable a link, with a moderation reason
+/// - POST /api/urls/{code}/enable - Re-enable a disabled link
+/// - GET /api/reports - List abuse reports
+/// - POST /api/reports/{id}/dismiss - Dismiss a report without acting on the link
+/// - GET /api/moderation - Trail of reports and moderation decisions
+/// - GET /api/audit - Audit log of management actions, filterable and paginated
+/// - GET/PUT/DELETE /api/tags/{tag}/utm - UTM template applied to links with a tag
+/// - GET /api/interstitials - List domains that always show a warning page
+/// - PUT/DELETE /api/interstitials/{domain} - Flag or un-flag a destination domain
+/// - GET /api/quarantine - Review queue of links flagged by threat feeds
+/// - POST /api/quarantine/{id}/release - Release a flagged destination as a false positive
+/// - POST /api/quarantine/{id}/confirm - Confirm a flagged destination as harmful
+/// - POST /api/quarantine/scan - Screen every link against the threat feeds now
+/// - POST /api/webhooks - Register a webhook
+/// - GET /api/webhooks - List webhooks
+/// - DELETE /api/webhooks/{id} - Remove a webhook
+/// - GET /api/webhooks/{id}/deliveries - Delivery log of a webhook
+/// - POST /api/webhooks/deliveries/{id}/retry - Re-queue a delivery
+/// - POST /api/users - Create a user
+/// - GET /api/users - List users
+/// - DELETE /api/users/{id} - Delete a user, optionally transferring their links
+/// - POST /api/keys - Create an API key
+/// - GET /api/keys - List API keys
+/// - DELETE /api/keys/{id} - Revoke an API key
+/// - POST /api/keys/{id}/rotate - Replace an API key with a new one
+/// - POST /api/workspaces - Create a workspace
+/// - GET /api/workspaces - List the caller's workspaces
+/// - GET /api/workspaces/{id} - Get a workspace
+/// - PATCH /api/workspaces/{id} - Change a workspace's name or settings
+/// - DELETE /api/workspaces/{id} - Delete a workspace, keeping its links
+/// - GET /api/workspaces/{id}/members - List a workspace's members
+/// - PUT /api/workspaces/{id}/members/{user_id} - Add a member or change their role
+/// - DELETE /api/workspaces/{id}/members/{user_id} - Remove a member
+/// - GET /auth/login - Sign in through the OpenID Connect provider
+/// - GET /auth/callback - Where the provider sends browsers back after sign-in
+/// - POST /auth/logout - End the browser's session
+/// - GET /api/events - Live stream of redirects (Server-Sent Events or WebSocket)
+/// - GET /api/export/clicks - Stream the click log as CSV, NDJSON or Parquet
+/// - GET /{code}+ and /{code}/preview - Preview a link without following it
+/// - GET /{code}/qr - QR code of the short URL as PNG or SVG
+/// - POST /{code}/report - Report a link as abusive
 /// - GET /{code} - Redirect to the original URL using the short code
+/// - GET /{code}/{tail} - Redirect with the rest of the path appended, for links with path forwarding
+/// - POST /{code} and /{code}/{tail} - Unlock a password-protected link
+///
+/// Which routes need an API key, and with which scope, is decided by
+/// `auth::required_scope`.
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("/")
@@ -21,8 +110,185 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
         web::resource("/health")
             .route(web::get().to(health_check_handler))
     )
+    .service(
+        web::resource("/stats/{code}")
+            .route(web::get().to(url_stats_handler))
+    )
+    .service(
+        web::resource("/api/urls/{code}")
+            .route(web::patch().to(update_url_handler))
+            .route(web::delete().to(delete_url_handler))
+    )
+    .service(
+        web::resource("/api/urls/{code}/owner")
+            .route(web::put().to(transfer_url_handler))
+    )
+    .service(
+        web::resource("/api/urls/{code}/rules/dry-run")
+            .route(web::post().to(dry_run_handler))
+    )
+    .service(
+        web::resource("/api/urls/{code}/disable")
+            .route(web::post().to(disable_url_handler))
+    )
+    .service(
+        web::resource("/api/urls/{code}/enable")
+            .route(web::post().to(enable_url_handler))
+    )
+    .service(
+        web::resource("/api/reports")
+            .route(web::get().to(list_reports_handler))
+    )
+    .service(
+        web::resource("/api/reports/{id}/dismiss")
+            .route(web::post().to(dismiss_report_handler))
+    )
+    .service(
+        web::resource("/api/moderation")
+            .route(web::get().to(moderation_log_handler))
+    )
+    .service(
+        web::resource("/api/audit")
+            .route(web::get().to(list_audit_log_handler))
+    )
+    .service(
+        web::resource("/api/tags/{tag}/utm")
+            .route(web::get().to(get_tag_utm_handler))
+            .route(web::put().to(put_tag_utm_handler))
+            .route(web::delete().to(delete_tag_utm_handler))
+    )
+    .service(
+        web::resource("/api/interstitials")
+            .route(web::get().to(list_interstitial_domains_handler))
+    )
+    .service(
+        web::resource("/api/interstitials/{domain}")
+            .route(web::put().to(put_interstitial_domain_handler))
+            .route(web::delete().to(delete_interstitial_domain_handler))
+    )
+    .service(
+        web::resource("/api/quarantine")
+            .route(web::get().to(list_screenings_handler))
+    )
+    .service(
+        web::resource("/api/quarantine/scan")
+            .route(web::post().to(scan_handler))
+    )
+    .service(
+        web::resource("/api/quarantine/{id}/release")
+            .route(web::post().to(release_screening_handler))
+    )
+    .service(
+        web::resource("/api/quarantine/{id}/confirm")
+            .route(web::post().to(confirm_screening_handler))
+    )
+    .service(
+        web::resource("/api/webhooks")
+            .route(web::post().to(register_webhook_handler))
+            .route(web::get().to(list_webhooks_handler))
+    )
+    .service(
+        web::resource("/api/webhooks/{id}")
+            .route(web::delete().to(delete_webhook_handler))
+    )
+    .service(
+        web::resource("/api/webhooks/{id}/deliveries")
+            .route(web::get().to(list_deliveries_handler))
+    )
+    .service(
+        web::resource("/api/webhooks/deliveries/{id}/retry")
+            .route(web::post().to(retry_delivery_handler))
+    )
+    .service(
+        web::resource("/api/users")
+            .route(web::post().to(create_user_handler))
+            .route(web::get().to(list_users_handler))
+    )
+    .service(
+        web::resource("/api/users/{id}")
+            .route(web::delete().to(delete_user_handler))
+    )
+    .service(
+        web::resource("/api/keys")
+            .route(web::post().to(create_api_key_handler))
+            .route(web::get().to(list_api_keys_handler))
+    )
+    .service(
+        web::resource("/api/keys/{id}")
+            .route(web::delete().to(revoke_api_key_handler))
+    )
+    .service(
+        web::resource("/api/keys/{id}/rotate")
+            .route(web::post().to(rotate_api_key_handler))
+    )
+    .service(
+        web::resource("/api/workspaces")
+            .route(web::post().to(create_workspace_handler))
+            .route(web::get().to(list_workspaces_handler))
+    )
+    .service(
+        web::resource("/api/workspaces/{id}")
+            .route(web::get().to(get_workspace_handler))
+            .route(web::patch().to(update_workspace_handler))
+            .route(web::delete().to(delete_workspace_handler))
+    )
+    .service(
+        web::resource("/api/workspaces/{id}/members")
+            .route(web::get().to(list_members_handler))
+    )
+    .service(
+        web::resource("/api/workspaces/{id}/members/{user_id}")
+            .route(web::put().to(put_member_handler))
+            .route(web::delete().to(delete_member_handler))
+    )
+    .service(
+        web::resource("/auth/login")
+            .route(web::get().to(login_handler))
+    )
+    .service(
+        web::resource("/auth/callback")
+            .route(web::get().to(callback_handler))
+    )
+    .service(
+        web::resource("/auth/logout")
+            .route(web::post().to(logout_handler))
+    )
+    .service(
+        web::resource("/api/events")
+            .route(web::get().to(events_handler))
+    )
+    .service(
+        web::resource("/api/export/clicks")
+            .route(web::get().to(export_clicks_handler))
+    )
+    .service(
+        web::resource("/{code}+")
+            .route(web::get().to(preview_handler))
+    )
+    // On links with path forwarding, these hand the request to the redirect
+    .service(
+        web::resource("/{code}/preview")
+            .route(web::get().to(preview_handler))
+    )
+    .service(
+        web::resource("/{code}/qr")
+            .route(web::get().to(qr_handler))
+    )
+    // Before the wildcard, which would otherwise take reports as unlock
+    // attempts; GET still forwards the path
+    .service(
+        web::resource("/{code}/report")
+            .route(web::post().to(report_handler))
+            .route(web::get().to(redirect_handler))
+    )
     .service(
         web::resource("/{code}")
             .route(web::get().to(redirect_handler))
+            .route(web::post().to(unlock_handler))
+    )
+    .service(
+        web::resource("/{code}/{tail:.*}")
+            .route(web::get().to(redirect_handler))
+            .route(web::post().to(unlock_handler))
     );
 }
@@ -0,0 +1,262 @@
+// src/routing.rs
+// Choosing which of a link's destinations a visitor is sent to.
+//
+// The chosen branch is stored with every click so stats can be split by it.
+
+use std::collections::HashSet;
+
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::models::Url;
+use crate::rules::{first_match, RuleContext};
+use crate::visitor::Platform;
+
+/// Branch recorded when a visitor is sent to `original_url`.
+pub const DEFAULT_BRANCH: &str = "default";
+
+/// Branch recorded when a visitor is sent to an A/B variant.
+pub const VARIANT_BRANCH: &str = "variant";
+
+/// How long a sticky variant assignment is remembered.
+const VARIANT_COOKIE_DAYS: i64 = 30;
+
+/// Alternate destinations by client platform, e.g. an App Store link for iOS
+/// and an intent URL for Android. Platforms left unset use `original_url`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlatformDestinations {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ios: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub android: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desktop: Option<String>,
+}
+
+impl PlatformDestinations {
+    fn entries(&self) -> [(Platform, &Option<String>); 3] {
+        [
+            (Platform::Ios, &self.ios),
+            (Platform::Android, &self.android),
+            (Platform::Desktop, &self.desktop),
+        ]
+    }
+
+    /// Requires at least one destination, each an absolute URL. Any scheme is
+    /// accepted so app links such as `itms-apps://` or `intent://` work.
+    pub fn validate(&self) -> Result<(), AppError> {
+        let entries = self.entries();
+        if entries.iter().all(|(_, target)| target.is_none()) {
+            return Err(AppError::InvalidInput(
+                "platform_destinations must set at least one platform".to_string(),
+            ));
+        }
+        for (platform, target) in entries {
+            if let Some(target) = target {
+                validate_destination(target).map_err(|reason| {
+                    AppError::InvalidInput(format!(
+                        "platform_destinations.{}: {}",
+                        platform.as_str(),
+                        reason
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn for_platform(&self, platform: Platform) -> Option<&str> {
+        self.entries()
+            .into_iter()
+            .find(|(candidate, _)| *candidate == platform)
+            .and_then(|(_, target)| target.as_deref())
+    }
+
+    pub fn to_json_string(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Checks that `target` is an absolute URL.
+pub fn validate_destination(target: &str) -> Result<(), String> {
+    url::Url::parse(target)
+        .map(|_| ())
+        .map_err(|err| format!("'{}' is not an absolute URL ({})", target, err))
+}
+
+/// One destination of an A/B split.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Variant {
+    pub name: String,
+    pub url: String,
+    /// Relative share of traffic; defaults to 1.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Rotates visitors between weighted destinations.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SplitTest {
+    pub variants: Vec<Variant>,
+    /// Remember each visitor's variant in a cookie.
+    #[serde(default)]
+    pub sticky: bool,
+}
+
+impl SplitTest {
+    /// Requires at least one variant, unique non-empty names, positive
+    /// weights and absolute URLs.
+    pub fn validate(&self) -> Result<(), AppError> {
+        let invalid = |message: String| AppError::InvalidInput(format!("split: {}", message));
+        if self.variants.is_empty() {
+            return Err(invalid("at least one variant is required".to_string()));
+        }
+        let mut names = HashSet::new();
+        for variant in &self.variants {
+            if variant.name.trim().is_empty() {
+                return Err(invalid("variant names must not be empty".to_string()));
+            }
+            if !names.insert(variant.name.as_str()) {
+                return Err(invalid(format!("duplicate variant '{}'", variant.name)));
+            }
+            if variant.weight == 0 {
+                return Err(invalid(format!("variant '{}' has weight 0", variant.name)));
+            }
+            validate_destination(&variant.url)
+                .map_err(|reason| invalid(format!("variant '{}': {}", variant.name, reason)))?;
+        }
+        Ok(())
+    }
+
+    pub fn variant(&self, name: &str) -> Option<&Variant> {
+        self.variants.iter().find(|variant| variant.name == name)
+    }
+
+    /// Picks a variant at random, in proportion to the weights.
+    pub fn pick(&self) -> &Variant {
+        let total: u64 = self.variants.iter().map(|v| u64::from(v.weight)).sum();
+        let mut roll = rand::thread_rng().gen_range(0..total);
+        for variant in &self.variants {
+            let weight = u64::from(variant.weight);
+            if roll < weight {
+                return variant;
+            }
+            roll -= weight;
+        }
+        &self.variants[self.variants.len() - 1]
+    }
+
+    pub fn to_json_string(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Name of the cookie holding the sticky variant of `short_code`.
+pub fn variant_cookie_name(short_code: &str) -> String {
+    format!("ab_{}", short_code)
+}
+
+/// Cookie remembering that the visitor was assigned `variant` on `short_code`.
+pub fn variant_cookie(short_code: &str, variant: &str) -> Cookie<'static> {
+    Cookie::build(variant_cookie_name(short_code), variant.to_string())
+        .path(format!("/{}", short_code))
+        .max_age(CookieDuration::days(VARIANT_COOKIE_DAYS))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .finish()
+}
+
+impl Url {
+    pub fn platform_destinations(&self) -> Option<PlatformDestinations> {
+        let value = self.platform_destinations.as_deref()?;
+        serde_json::from_str(value)
+            .map_err(|err| {
+                log::warn!(
+                    "Ignoring malformed platform destinations on {}: {}",
+                    self.short_code,
+                    err
+                )
+            })
+            .ok()
+    }
+
+    pub fn split(&self) -> Option<SplitTest> {
+        let value = self.split_test.as_deref()?;
+        serde_json::from_str(value)
+            .map_err(|err| log::warn!("Ignoring malformed split on {}: {}", self.short_code, err))
+            .ok()
+    }
+}
+
+/// Where a visitor is sent before passthrough and UTM parameters are applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    /// Name of the branch taken, stored with the click.
+    pub branch: String,
+    pub target: String,
+    /// A/B variant, stored with the click.
+    pub variant: Option<String>,
+    /// Whether the variant should be remembered in a cookie.
+    pub sticky: bool,
+    /// Whether another visitor could have been sent elsewhere.
+    pub personalized: bool,
+}
+
+impl Route {
+    fn new(branch: &str, target: &str) -> Self {
+        Route {
+            branch: branch.to_string(),
+            target: target.to_string(),
+            variant: None,
+            sticky: false,
+            personalized: false,
+        }
+    }
+}
+
+/// Picks the destination of `url_entry` for the request described by `ctx`.
+///
+/// The first matching rule wins, then a platform destination matching the
+/// visitor. Otherwise, links with a split send the visitor to a variant: the
+/// one named by `sticky_variant` (the value of their variant cookie) when it
+/// still exists, or a weighted random one. Everyone else goes to
+/// `original_url`.
+pub fn route(url_entry: &Url, ctx: &RuleContext, sticky_variant: Option<&str>) -> Route {
+    let visitor = ctx.visitor;
+    let rules = url_entry.rules();
+    let platforms = url_entry.platform_destinations();
+    let split = url_entry.split();
+    let personalized = !rules.is_empty() || platforms.is_some() || split.is_some();
+
+    let mut route = if let Some((index, rule)) = first_match(&rules, ctx) {
+        Route::new(&format!("rule:{}", rule.label(index)), &rule.destination)
+    } else if let Some(target) = platforms
+        .as_ref()
+        .and_then(|destinations| destinations.for_platform(visitor.platform))
+    {
+        Route::new(visitor.platform.as_str(), target)
+    } else if let Some(split) = &split {
+        let variant = sticky_variant
+            .filter(|_| split.sticky)
+            .and_then(|name| split.variant(name))
+            .unwrap_or_else(|| split.pick());
+        Route {
+            variant: Some(variant.name.clone()),
+            sticky: split.sticky,
+            ..Route::new(VARIANT_BRANCH, &variant.url)
+        }
+    } else {
+        Route::new(DEFAULT_BRANCH, &url_entry.original_url)
+    };
+    route.personalized = personalized;
+    route
+}
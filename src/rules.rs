@@ -0,0 +1,375 @@
+// src/rules.rs
+// Conditional redirects.
+//
+// A link can carry an ordered list of rules, each sending the visitors it
+// matches to its own destination. Rules are evaluated before platform
+// destinations and A/B splits; the first matching rule wins.
+
+use std::collections::BTreeMap;
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded;
+
+use crate::auth::Principal;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::models::Url;
+use crate::routing::{self, validate_destination};
+use crate::utils::parse_timestamp;
+use crate::visitor::{preferred_language, referrer_host, DeviceClass, Platform, Visitor};
+use crate::workspaces::{authorize_url, Permission};
+
+/// Upper bound on rules per link, to keep evaluation cheap.
+pub const MAX_RULES: usize = 50;
+
+/// A destination used when all of its conditions match. A rule without
+/// conditions matches every request.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub when: Conditions,
+    pub destination: String,
+}
+
+/// Conditions of a rule. Each one that is set must match.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Conditions {
+    /// ISO 3166 country codes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub countries: Option<Vec<String>>,
+    /// Language tags matched against the preferred `Accept-Language` entry;
+    /// `pt` also matches `pt-BR`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<TimeWindow>,
+    /// Referrer hosts; `example.com` also matches its subdomains.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrers: Option<Vec<String>>,
+    /// Required query parameters; `null` accepts any value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<BTreeMap<String, Option<String>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub devices: Option<Vec<DeviceClass>>,
+}
+
+/// A time window in UTC. `start`/`end` bound it absolutely, `days` and
+/// `from`/`to` (`HH:MM`, wrapping past midnight when `from` is later)
+/// restrict it to a weekly schedule.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimeWindow {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days: Option<Vec<Weekday>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+/// What rules are evaluated against.
+pub struct RuleContext<'a> {
+    pub visitor: &'a Visitor,
+    pub query: Vec<(String, String)>,
+    pub now: DateTime<Utc>,
+}
+
+impl<'a> RuleContext<'a> {
+    pub fn new(visitor: &'a Visitor, query_string: &str) -> Self {
+        RuleContext {
+            visitor,
+            query: form_urlencoded::parse(query_string.as_bytes())
+                .into_owned()
+                .collect(),
+            now: Utc::now(),
+        }
+    }
+}
+
+fn parse_time_of_day(value: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H:%M").ok()
+}
+
+fn tag_matches(language: &str, tag: &str) -> bool {
+    let tag = tag.to_ascii_lowercase();
+    language == tag
+        || language
+            .strip_prefix(tag.as_str())
+            .is_some_and(|rest| rest.starts_with('-'))
+}
+
+fn host_matches(host: &str, pattern: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    host == pattern
+        || host
+            .strip_suffix(pattern.as_str())
+            .is_some_and(|rest| rest.ends_with('.'))
+}
+
+impl TimeWindow {
+    fn validate(&self) -> Result<(), String> {
+        for (field, value) in [("start", &self.start), ("end", &self.end)] {
+            if value
+                .as_deref()
+                .is_some_and(|v| parse_timestamp(v).is_none())
+            {
+                return Err(format!("time.{} must be an RFC 3339 timestamp", field));
+            }
+        }
+        for (field, value) in [("from", &self.from), ("to", &self.to)] {
+            if value
+                .as_deref()
+                .is_some_and(|v| parse_time_of_day(v).is_none())
+            {
+                return Err(format!("time.{} must be a time of day as HH:MM", field));
+            }
+        }
+        if self.days.as_ref().is_some_and(Vec::is_empty) {
+            return Err("time.days must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    fn matches(&self, now: DateTime<Utc>) -> bool {
+        let now = now.naive_utc();
+        let bound = |value: &Option<String>| value.as_deref().and_then(parse_timestamp);
+        if bound(&self.start).is_some_and(|start| now < start) {
+            return false;
+        }
+        if bound(&self.end).is_some_and(|end| now >= end) {
+            return false;
+        }
+        if self
+            .days
+            .as_ref()
+            .is_some_and(|days| !days.contains(&now.weekday()))
+        {
+            return false;
+        }
+        let time = now.time();
+        let from = self.from.as_deref().and_then(parse_time_of_day);
+        let to = self.to.as_deref().and_then(parse_time_of_day);
+        match (from, to) {
+            (Some(from), Some(to)) if from > to => time >= from || time < to,
+            (from, to) => from.is_none_or(|from| time >= from) && to.is_none_or(|to| time < to),
+        }
+    }
+}
+
+impl Conditions {
+    fn validate(&self) -> Result<(), String> {
+        let non_empty = |field: &str, values: &Option<Vec<String>>| match values {
+            Some(values) if values.is_empty() => Err(format!("{} must not be empty", field)),
+            Some(values) if values.iter().any(|v| v.trim().is_empty()) => {
+                Err(format!("{} must not contain empty values", field))
+            },
+            _ => Ok(()),
+        };
+        non_empty("countries", &self.countries)?;
+        non_empty("languages", &self.languages)?;
+        non_empty("referrers", &self.referrers)?;
+        if let Some(code) = self
+            .countries
+            .iter()
+            .flatten()
+            .find(|code| code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()))
+        {
+            return Err(format!("'{}' is not a two-letter country code", code));
+        }
+        if self.devices.as_ref().is_some_and(Vec::is_empty) {
+            return Err("devices must not be empty".to_string());
+        }
+        if let Some(time) = &self.time {
+            time.validate()?;
+        }
+        Ok(())
+    }
+
+    fn matches(&self, ctx: &RuleContext) -> bool {
+        let visitor = ctx.visitor;
+        if let Some(countries) = &self.countries {
+            let Some(country) = &visitor.country else {
+                return false;
+            };
+            if !countries.iter().any(|c| c.eq_ignore_ascii_case(country)) {
+                return false;
+            }
+        }
+        if let Some(languages) = &self.languages {
+            let Some(language) = &visitor.language else {
+                return false;
+            };
+            if !languages.iter().any(|tag| tag_matches(language, tag)) {
+                return false;
+            }
+        }
+        if let Some(referrers) = &self.referrers {
+            let Some(host) = &visitor.referrer_host else {
+                return false;
+            };
+            if !referrers.iter().any(|pattern| host_matches(host, pattern)) {
+                return false;
+            }
+        }
+        if let Some(query) = &self.query {
+            let satisfied = query.iter().all(|(name, expected)| {
+                ctx.query.iter().any(|(key, value)| {
+                    key == name && expected.as_ref().is_none_or(|expected| expected == value)
+                })
+            });
+            if !satisfied {
+                return false;
+            }
+        }
+        if let Some(devices) = &self.devices {
+            if !devices.contains(&visitor.device) {
+                return false;
+            }
+        }
+        self.time.as_ref().is_none_or(|time| time.matches(ctx.now))
+    }
+}
+
+impl Rule {
+    /// Label used in stats and dry runs: the rule's name, or its position
+    /// counted from 1.
+    pub fn label(&self, index: usize) -> String {
+        self.name.clone().unwrap_or_else(|| (index + 1).to_string())
+    }
+}
+
+/// Checks a rule list before it is saved.
+pub fn validate_rules(rules: &[Rule]) -> Result<(), AppError> {
+    if rules.len() > MAX_RULES {
+        return Err(AppError::InvalidInput(format!(
+            "a link can have at most {} rules",
+            MAX_RULES
+        )));
+    }
+    for (index, rule) in rules.iter().enumerate() {
+        let invalid =
+            |reason: String| AppError::InvalidInput(format!("rules[{}]: {}", index, reason));
+        if rule
+            .name
+            .as_deref()
+            .is_some_and(|name| name.trim().is_empty())
+        {
+            return Err(invalid("name must not be empty".to_string()));
+        }
+        validate_destination(&rule.destination).map_err(invalid)?;
+        rule.when.validate().map_err(invalid)?;
+    }
+    Ok(())
+}
+
+/// The first rule matching `ctx`, with its position.
+pub fn first_match<'r>(rules: &'r [Rule], ctx: &RuleContext) -> Option<(usize, &'r Rule)> {
+    rules
+        .iter()
+        .enumerate()
+        .find(|(_, rule)| rule.when.matches(ctx))
+}
+
+pub fn rules_to_json_string(rules: &[Rule]) -> String {
+    serde_json::to_string(rules).unwrap_or_default()
+}
+
+impl Url {
+    pub fn rules(&self) -> Vec<Rule> {
+        let Some(value) = self.rules.as_deref() else {
+            return Vec::new();
+        };
+        serde_json::from_str(value)
+            .map_err(|err| log::warn!("Ignoring malformed rules on {}: {}", self.short_code, err))
+            .unwrap_or_default()
+    }
+}
+
+/// A synthetic request for the dry-run endpoint.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DryRunRequest {
+    pub country: Option<String>,
+    pub accept_language: Option<String>,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    /// Raw query string, e.g. `ref=partner&lang=de`.
+    #[serde(default)]
+    pub query: String,
+    /// RFC 3339 timestamp; defaults to now.
+    pub time: Option<String>,
+    /// Rules to evaluate instead of the saved ones, to try them out first.
+    pub rules: Option<Vec<Rule>>,
+}
+
+/// Handler showing which rule, and which destination, a synthetic request
+/// to a link would get. Nothing is recorded.
+pub async fn dry_run_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    path: web::Path<String>,
+    item: web::Json<DryRunRequest>,
+) -> Result<HttpResponse, AppError> {
+    let code = path.into_inner();
+    let item = item.into_inner();
+    let now = match item.time.as_deref() {
+        Some(value) => parse_timestamp(value)
+            .ok_or_else(|| {
+                AppError::InvalidInput("time must be an RFC 3339 timestamp".to_string())
+            })?
+            .and_utc(),
+        None => Utc::now(),
+    };
+    if let Some(rules) = &item.rules {
+        validate_rules(rules)?;
+    }
+
+    let mut url_entry = web::block(move || {
+        let mut conn = pool.get()?;
+        authorize_url(&mut conn, &principal, &code, Permission::ViewLinks)
+    })
+    .await??;
+    if let Some(rules) = &item.rules {
+        url_entry.rules = Some(rules_to_json_string(rules));
+    }
+
+    let user_agent = item.user_agent.as_deref();
+    let visitor = Visitor {
+        ip_address: None,
+        user_agent: item.user_agent.clone(),
+        country: item.country.map(|country| country.to_ascii_uppercase()),
+        device: DeviceClass::from_user_agent(user_agent),
+        platform: Platform::from_user_agent(user_agent),
+        language: item.accept_language.as_deref().and_then(preferred_language),
+        referrer_host: item.referrer.as_deref().and_then(referrer_host),
+    };
+    let ctx = RuleContext {
+        now,
+        ..RuleContext::new(&visitor, &item.query)
+    };
+    let rules = url_entry.rules();
+    let matched = first_match(&rules, &ctx).map(|(index, rule)| {
+        serde_json::json!({
+            "index": index,
+            "label": rule.label(index),
+            "rule": rule,
+        })
+    });
+    let route = routing::route(&url_entry, &ctx, None);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "matched_rule": matched,
+        "branch": route.branch,
+        "destination": route.target,
+    })))
+}
@@ -0,0 +1,97 @@
+// src/schedule.rs
+// Activation windows: links that only redirect between two points in time.
+
+use actix_web::{http::header, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+
+use crate::error_pages::{error_response, ErrorPage};
+use crate::models::Url;
+
+/// Whether a link currently redirects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    Active,
+    /// Not active yet; starts redirecting at the given time.
+    Pending(NaiveDateTime),
+    /// Past `deactivates_at`.
+    Deactivated,
+    /// Past `expiration_date`.
+    Expired,
+    /// All of the link's `max_clicks` have been used.
+    Exhausted,
+}
+
+impl Url {
+    /// The state of the link at `now` (UTC). A link ends at the earlier of
+    /// `deactivates_at` and `expiration_date`.
+    pub fn state(&self, now: NaiveDateTime) -> LinkState {
+        if let Some(activates_at) = self.activates_at.filter(|at| now < *at) {
+            return LinkState::Pending(activates_at);
+        }
+        let ends = [
+            (self.deactivates_at, LinkState::Deactivated),
+            (self.expiration_date, LinkState::Expired),
+        ];
+        ends.into_iter()
+            .filter_map(|(end, state)| end.filter(|end| now >= *end).map(|end| (end, state)))
+            .min_by_key(|(end, _)| *end)
+            .map_or(LinkState::Active, |(_, state)| state)
+    }
+}
+
+/// Rejects windows that end before they start.
+pub fn validate_window(
+    activates_at: Option<NaiveDateTime>,
+    deactivates_at: Option<NaiveDateTime>,
+) -> Result<(), String> {
+    match (activates_at, deactivates_at) {
+        (Some(start), Some(end)) if end <= start => {
+            Err("deactivates_at must be later than activates_at".to_string())
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Response for a link that does not redirect right now: a redirect to its
+/// `fallback_url` when it has one, otherwise a coming-soon page before
+/// activation and a `410 Gone` error page once it has ended or is used up.
+pub fn unavailable_response(req: &HttpRequest, url_entry: &Url, state: LinkState) -> HttpResponse {
+    if let Some(fallback) = &url_entry.fallback_url {
+        return HttpResponse::Found()
+            .insert_header((header::LOCATION, fallback.as_str()))
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .finish();
+    }
+    match state {
+        LinkState::Pending(activates_at) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .body(coming_soon_page(activates_at)),
+        LinkState::Exhausted => error_response(
+            req,
+            ErrorPage::Expired,
+            "This link has reached its click limit",
+        ),
+        LinkState::Expired => error_response(req, ErrorPage::Expired, "This link has expired"),
+        LinkState::Active | LinkState::Deactivated => error_response(
+            req,
+            ErrorPage::Disabled,
+            "This link is no longer active",
+        ),
+    }
+}
+
+fn coming_soon_page(activates_at: NaiveDateTime) -> String {
+    let when = activates_at.format("%Y-%m-%d %H:%M UTC");
+    format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head><meta charset=\"utf-8\"><title>Coming soon</title></head>\n\
+         <body>\n\
+         <h1>Coming soon</h1>\n\
+         <p>This link becomes active on {}.</p>\n\
+         </body>\n\
+         </html>\n",
+        when
+    )
+}
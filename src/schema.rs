@@ -1,10 +1,248 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    moderation_actions (id) {
+        id -> Integer,
+        url_id -> Integer,
+        short_code -> Text,
+        action -> Text,
+        reason -> Nullable<Text>,
+        report_id -> Nullable<Integer>,
+        actor -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    rate_limit_buckets (key) {
+        key -> Text,
+        tokens -> Double,
+        updated_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    redirect_stats (id) {
+        id -> Integer,
+        url_id -> Integer,
+        ip_address -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        accessed_at -> Timestamp,
+        branch -> Nullable<Text>,
+        variant -> Nullable<Text>,
+        source -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     urls (id) {
         id -> Integer,
         original_url -> Text,
         short_code -> Text,
         created_at -> Timestamp,
+        expiration_date -> Nullable<Timestamp>,
+        expiry_notified -> Bool,
+        redirect_status -> Nullable<Integer>,
+        forward_query -> Bool,
+        forward_path -> Bool,
+        utm_template -> Nullable<Text>,
+        platform_destinations -> Nullable<Text>,
+        split_test -> Nullable<Text>,
+        rules -> Nullable<Text>,
+        activates_at -> Nullable<Timestamp>,
+        deactivates_at -> Nullable<Timestamp>,
+        fallback_url -> Nullable<Text>,
+        max_clicks -> Nullable<Integer>,
+        clicks_used -> Integer,
+        password_hash -> Nullable<Text>,
+        interstitial -> Bool,
+        owner_id -> Nullable<Integer>,
+        workspace_id -> Nullable<Integer>,
+        quarantined -> Bool,
+        disabled_at -> Nullable<Timestamp>,
+        disabled_reason -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    api_keys (id) {
+        id -> Integer,
+        name -> Text,
+        prefix -> Text,
+        key_hash -> Text,
+        scopes -> Text,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        user_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    audit_log (id) {
+        id -> Integer,
+        created_at -> Timestamp,
+        actor -> Text,
+        actor_user_id -> Nullable<Integer>,
+        ip_address -> Nullable<Text>,
+        action -> Text,
+        url_id -> Nullable<Integer>,
+        short_code -> Nullable<Text>,
+        old_value -> Nullable<Text>,
+        new_value -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    link_reports (id) {
+        id -> Integer,
+        url_id -> Integer,
+        reason -> Text,
+        reporter_ip -> Nullable<Text>,
+        status -> Text,
+        created_at -> Timestamp,
+        resolved_at -> Nullable<Timestamp>,
+        resolved_by -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    link_screenings (id) {
+        id -> Integer,
+        url_id -> Integer,
+        destination -> Text,
+        feed -> Text,
+        status -> Text,
+        flagged_at -> Timestamp,
+        reviewed_at -> Nullable<Timestamp>,
+        reviewed_by -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    interstitial_domains (domain) {
+        domain -> Text,
+        reason -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Integer,
+        token_hash -> Text,
+        user_id -> Integer,
+        scopes -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    tag_utm_templates (tag) {
+        tag -> Text,
+        template -> Text,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    url_tags (url_id, tag) {
+        url_id -> Integer,
+        tag -> Text,
+    }
+}
+
+diesel::table! {
+    usage_logs (id) {
+        id -> Integer,
+        url_id -> Integer,
+        accessed_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (id) {
+        id -> Integer,
+        webhook_id -> Integer,
+        event_type -> Text,
+        payload -> Text,
+        status -> Text,
+        attempts -> Integer,
+        next_attempt_at -> Timestamp,
+        last_status_code -> Nullable<Integer>,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
     }
 }
+
+diesel::table! {
+    webhooks (id) {
+        id -> Integer,
+        target_url -> Text,
+        secret -> Text,
+        events -> Text,
+        click_milestones -> Text,
+        active -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    users (id) {
+        id -> Integer,
+        username -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    workspace_members (workspace_id, user_id) {
+        workspace_id -> Integer,
+        user_id -> Integer,
+        role -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    workspaces (id) {
+        id -> Integer,
+        name -> Text,
+        default_code_length -> Nullable<Integer>,
+        default_expiry_secs -> Nullable<BigInt>,
+        allowed_domains -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::joinable!(link_reports -> urls (url_id));
+diesel::joinable!(link_screenings -> urls (url_id));
+diesel::joinable!(redirect_stats -> urls (url_id));
+diesel::joinable!(url_tags -> urls (url_id));
+diesel::joinable!(usage_logs -> urls (url_id));
+diesel::joinable!(webhook_deliveries -> webhooks (webhook_id));
+diesel::joinable!(workspace_members -> workspaces (workspace_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    audit_log,
+    interstitial_domains,
+    link_reports,
+    link_screenings,
+    moderation_actions,
+    rate_limit_buckets,
+    redirect_stats,
+    sessions,
+    tag_utm_templates,
+    url_tags,
+    urls,
+    usage_logs,
+    users,
+    webhook_deliveries,
+    webhooks,
+    workspace_members,
+    workspaces,
+);
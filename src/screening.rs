@@ -0,0 +1,605 @@
+// src/screening.rs
+// Screening of link destinations against locally mirrored threat feeds.
+//
+// Feeds are files on disk, such as URLhaus or PhishTank dumps, or files of
+// Safe Browsing-style SHA-256 hash prefixes. Destinations are screened when
+// links are created or edited, and every link is screened again in the
+// background so feed updates catch existing links. A flagged link is
+// quarantined: visitors get a warning page instead of a redirect until an
+// admin releases it from the review queue.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use actix_web::{rt::time::interval, web, HttpResponse};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::audit::{self, Actor};
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::domain_policy::modified;
+use crate::error::AppError;
+use crate::models::{LinkScreening, NewLinkScreening, Url};
+
+/// Statuses of a screening in the review queue.
+pub const PENDING: &str = "pending";
+pub const CONFIRMED: &str = "confirmed";
+pub const RELEASED: &str = "released";
+
+/// Links screened per query by the background scan.
+const SCAN_BATCH: i64 = 500;
+
+/// How a feed file lists threats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedFormat {
+    /// One URL per line, or CSV rows whose first `http(s)://` field is the
+    /// URL, as in URLhaus and PhishTank dumps.
+    Urls,
+    /// Hex SHA-256 hash prefixes of URL expressions, one per line.
+    Hashes,
+}
+
+/// A feed file and its format, configured as `urls:<path>` or
+/// `hashes:<path>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeedSource {
+    pub format: FeedFormat,
+    pub path: PathBuf,
+}
+
+impl FromStr for FeedSource {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not urls:<path> or hashes:<path>", value);
+        let (format, path) = value.trim().split_once(':').ok_or_else(invalid)?;
+        let format = match format {
+            "urls" => FeedFormat::Urls,
+            "hashes" => FeedFormat::Hashes,
+            _ => return Err(invalid()),
+        };
+        if path.trim().is_empty() {
+            return Err(invalid());
+        }
+        Ok(FeedSource {
+            format,
+            path: PathBuf::from(path.trim()),
+        })
+    }
+}
+
+/// Parses a comma-separated list of feed files.
+pub fn parse_feed_sources(value: &str) -> Result<Vec<FeedSource>, String> {
+    value
+        .split(',')
+        .filter(|source| !source.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// The expressions a URL is looked up by, as Safe Browsing does: the host
+/// and up to four of its parent domains, each followed by the path and
+/// query, the path alone, the root and up to three leading directories.
+/// `http://a.b.example/1/2.html?x=1` gives `a.b.example/1/2.html?x=1`,
+/// `a.b.example/1/2.html`, `a.b.example/`, `a.b.example/1/` and the same
+/// four for `b.example`. The most specific expression comes first.
+pub fn url_expressions(destination: &str) -> Vec<String> {
+    let Some(parsed) = url::Url::parse(destination)
+        .ok()
+        .filter(|parsed| matches!(parsed.scheme(), "http" | "https"))
+    else {
+        return Vec::new();
+    };
+    let Some(host) = parsed.host_str() else {
+        return Vec::new();
+    };
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+    let mut hosts = vec![host.clone()];
+    if parsed.domain().is_some() {
+        let labels: Vec<&str> = host.split('.').collect();
+        // The last five labels at most, and never the top-level domain alone
+        let first = labels.len().saturating_sub(5).max(1);
+        hosts
+            .extend((first..labels.len().saturating_sub(1)).map(|start| labels[start..].join(".")));
+    }
+
+    let path = parsed.path();
+    let mut paths = Vec::new();
+    if let Some(query) = parsed.query() {
+        paths.push(format!("{}?{}", path, query));
+    }
+    paths.push(path.to_string());
+    let mut prefix = String::from("/");
+    paths.push(prefix.clone());
+    let directories: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    // Leading directories only; the last segment is the full path above
+    for directory in directories
+        .iter()
+        .take(directories.len().saturating_sub(1))
+        .take(3)
+    {
+        prefix.push_str(directory);
+        prefix.push('/');
+        paths.push(prefix.clone());
+    }
+    let mut seen = HashSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+
+    hosts
+        .iter()
+        .flat_map(|host| paths.iter().map(move |path| format!("{}{}", host, path)))
+        .collect()
+}
+
+/// The URL of one line of a URL feed, if it has one: the line itself, or
+/// the first field of a CSV row that is an `http(s)://` URL.
+fn feed_line_url(line: &str) -> Option<&str> {
+    line.split(',')
+        .map(|field| field.trim().trim_matches('"').trim())
+        .find(|field| field.starts_with("http://") || field.starts_with("https://"))
+}
+
+/// The threats listed by one feed.
+#[derive(Debug, Default)]
+pub struct ThreatList {
+    /// Full expressions, as listed by URL feeds.
+    expressions: HashSet<String>,
+    /// Hash prefixes by their length in hex digits.
+    prefixes: HashMap<usize, HashSet<String>>,
+}
+
+impl ThreatList {
+    /// Parses a URL feed. Lines starting with `#` and lines without a URL,
+    /// such as CSV headers, are skipped.
+    pub fn parse_urls(text: &str) -> Self {
+        let expressions = text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .filter_map(feed_line_url)
+            // The most specific expression of a listed URL is the URL itself
+            .filter_map(|listed| url_expressions(listed).into_iter().next())
+            .collect();
+        ThreatList {
+            expressions,
+            prefixes: HashMap::new(),
+        }
+    }
+
+    /// Parses a hash prefix feed: 8 to 64 hex digits per line. Blank lines
+    /// and lines starting with `#` are ignored.
+    pub fn parse_hashes(text: &str) -> Result<Self, String> {
+        let mut prefixes: HashMap<usize, HashSet<String>> = HashMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let prefix = line.trim().to_ascii_lowercase();
+            if prefix.is_empty() || prefix.starts_with('#') {
+                continue;
+            }
+            let valid = (8..=64).contains(&prefix.len())
+                && prefix.len() % 2 == 0
+                && prefix.chars().all(|ch| ch.is_ascii_hexdigit());
+            if !valid {
+                return Err(format!(
+                    "line {}: '{}' is not a hex SHA-256 hash prefix",
+                    index + 1,
+                    line.trim()
+                ));
+            }
+            prefixes.entry(prefix.len()).or_default().insert(prefix);
+        }
+        Ok(ThreatList {
+            expressions: HashSet::new(),
+            prefixes,
+        })
+    }
+
+    pub fn load(source: &FeedSource) -> Result<Self, String> {
+        let text = fs::read_to_string(&source.path)
+            .map_err(|err| format!("cannot read {}: {}", source.path.display(), err))?;
+        match source.format {
+            FeedFormat::Urls => Ok(ThreatList::parse_urls(&text)),
+            FeedFormat::Hashes => ThreatList::parse_hashes(&text)
+                .map_err(|err| format!("{}: {}", source.path.display(), err)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.expressions.len() + self.prefixes.values().map(HashSet::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether any of the expressions of a URL is listed.
+    pub fn matches(&self, expressions: &[String]) -> bool {
+        expressions.iter().any(|expression| {
+            if self.expressions.contains(expression) {
+                return true;
+            }
+            if self.prefixes.is_empty() {
+                return false;
+            }
+            let hash = hex::encode(Sha256::digest(expression.as_bytes()));
+            self.prefixes
+                .iter()
+                .any(|(length, prefixes)| prefixes.contains(&hash[..*length]))
+        })
+    }
+}
+
+/// A feed and the file it is read from.
+struct Feed {
+    /// File stem, recorded with the screenings it flags.
+    name: String,
+    source: FeedSource,
+    list: RwLock<ThreatList>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl Feed {
+    fn open(source: &FeedSource) -> Result<Self, String> {
+        let modified = modified(&source.path);
+        Ok(Feed {
+            name: source
+                .path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| source.path.display().to_string()),
+            source: source.clone(),
+            list: RwLock::new(ThreatList::load(source)?),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// Reads the file again if it changed. A file that became unreadable or
+    /// invalid keeps its previous entries.
+    fn reload(&self) {
+        let current = modified(&self.source.path);
+        let mut loaded = self.modified.lock().unwrap();
+        if *loaded == current {
+            return;
+        }
+        match ThreatList::load(&self.source) {
+            Ok(list) => {
+                log::info!(
+                    "Reloaded {} threat feed entries from {}",
+                    list.len(),
+                    self.source.path.display()
+                );
+                *self.list.write().unwrap() = list;
+                *loaded = current;
+            },
+            Err(err) => log::warn!("Keeping the previous threat feed: {}", err),
+        }
+    }
+}
+
+/// The configured threat feeds. With none configured, nothing is flagged.
+#[derive(Default)]
+pub struct ThreatFeeds {
+    feeds: Vec<Feed>,
+}
+
+impl ThreatFeeds {
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        Ok(ThreatFeeds {
+            feeds: config
+                .threat_feeds
+                .iter()
+                .map(Feed::open)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.feeds.is_empty()
+    }
+
+    /// Name of the first feed listing `destination`.
+    pub fn check(&self, destination: &str) -> Option<String> {
+        let expressions = url_expressions(destination);
+        if expressions.is_empty() {
+            return None;
+        }
+        self.feeds
+            .iter()
+            .find(|feed| feed.list.read().unwrap().matches(&expressions))
+            .map(|feed| feed.name.clone())
+    }
+
+    /// Review queue entries for the destinations of link `url_id` that a
+    /// feed lists.
+    pub fn screen(&self, url_id: i32, destinations: &[String]) -> Vec<NewLinkScreening> {
+        destinations
+            .iter()
+            .filter_map(|destination| {
+                self.check(destination).map(|feed| NewLinkScreening {
+                    url_id,
+                    destination: destination.clone(),
+                    feed,
+                })
+            })
+            .collect()
+    }
+
+    /// Reads the feed files again if they changed.
+    pub fn reload(&self) {
+        self.feeds.iter().for_each(Feed::reload);
+    }
+}
+
+/// Quarantines a link for the flagged destinations, except those an admin
+/// already released for it. Returns whether the link was quarantined.
+pub fn quarantine(conn: &mut SqliteConnection, flags: &[NewLinkScreening]) -> QueryResult<bool> {
+    use crate::schema::{link_screenings, urls};
+
+    let Some(target) = flags.first().map(|flag| flag.url_id) else {
+        return Ok(false);
+    };
+    let released: Vec<String> = link_screenings::table
+        .filter(link_screenings::url_id.eq(target))
+        .filter(link_screenings::status.eq(RELEASED))
+        .select(link_screenings::destination)
+        .load(conn)?;
+    let flags: Vec<&NewLinkScreening> = flags
+        .iter()
+        .filter(|flag| !released.contains(&flag.destination))
+        .collect();
+    if flags.is_empty() {
+        return Ok(false);
+    }
+    conn.transaction(|conn| {
+        for flag in &flags {
+            log::warn!(
+                "Quarantining link {}: {} is listed in threat feed {}",
+                flag.url_id,
+                flag.destination,
+                flag.feed
+            );
+            diesel::insert_into(link_screenings::table)
+                .values(*flag)
+                .execute(conn)?;
+        }
+        diesel::update(urls::table.find(target))
+            .set(urls::quarantined.eq(true))
+            .execute(conn)?;
+        Ok(true)
+    })
+}
+
+/// Outcome of screening every link.
+#[derive(Debug, Default, Serialize)]
+pub struct ScanSummary {
+    /// Links screened; quarantined ones are skipped.
+    pub scanned: usize,
+    /// Links newly quarantined.
+    pub quarantined: usize,
+}
+
+/// Screens every link not already quarantined against the feeds.
+pub fn scan(conn: &mut SqliteConnection, feeds: &ThreatFeeds) -> QueryResult<ScanSummary> {
+    use crate::schema::urls;
+
+    let mut summary = ScanSummary::default();
+    let mut after = 0;
+    loop {
+        let batch = urls::table
+            .filter(urls::quarantined.eq(false))
+            .filter(urls::id.gt(after))
+            .order(urls::id.asc())
+            .limit(SCAN_BATCH)
+            .load::<Url>(conn)?;
+        let Some(last) = batch.last() else {
+            break;
+        };
+        after = last.id;
+        for url_entry in &batch {
+            summary.scanned += 1;
+            let flags = feeds.screen(url_entry.id, &url_entry.destinations());
+            if quarantine(conn, &flags)? {
+                summary.quarantined += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Reloads changed feeds and screens every link every `period`, for the
+/// lifetime of the server. The first scan runs at startup, so feed updates
+/// made while the server was down are applied.
+pub async fn run_scanner(pool: DbPool, feeds: web::Data<ThreatFeeds>, period: Duration) {
+    let mut ticker = interval(period);
+    loop {
+        ticker.tick().await;
+        let pool = pool.clone();
+        let feeds = feeds.clone();
+        let scanned = web::block(move || {
+            feeds.reload();
+            let mut conn = pool.get()?;
+            scan(&mut conn, &feeds).map_err(AppError::from)
+        })
+        .await;
+        match scanned {
+            Ok(Ok(summary)) if summary.quarantined > 0 => log::info!(
+                "Threat feed scan quarantined {} of {} links",
+                summary.quarantined,
+                summary.scanned
+            ),
+            Ok(Ok(_)) => {},
+            Ok(Err(err)) => log::warn!("Threat feed scan failed: {}", err),
+            Err(err) => log::warn!("Threat feed scan failed: {}", err),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListScreeningsQuery {
+    /// Only entries with this status, e.g. `pending`.
+    pub status: Option<String>,
+}
+
+/// A review queue entry with the short code of its link.
+#[derive(Serialize)]
+struct QueueEntry {
+    #[serde(flatten)]
+    screening: LinkScreening,
+    short_code: String,
+}
+
+/// Handler for the review queue, newest first.
+pub async fn list_screenings_handler(
+    pool: web::Data<DbPool>,
+    params: web::Query<ListScreeningsQuery>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::{link_screenings, urls};
+
+    let wanted = params.into_inner().status;
+    if let Some(wanted) = &wanted {
+        if ![PENDING, CONFIRMED, RELEASED].contains(&wanted.as_str()) {
+            return Err(AppError::InvalidInput(format!(
+                "status must be {}, {} or {}",
+                PENDING, CONFIRMED, RELEASED
+            )));
+        }
+    }
+    let entries = web::block(move || {
+        let mut conn = pool.get()?;
+        let mut query = link_screenings::table
+            .inner_join(urls::table)
+            .select((link_screenings::all_columns, urls::short_code))
+            .order(link_screenings::id.desc())
+            .into_boxed();
+        if let Some(wanted) = wanted {
+            query = query.filter(link_screenings::status.eq(wanted));
+        }
+        query
+            .load::<(LinkScreening, String)>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+
+    let entries: Vec<QueueEntry> = entries
+        .into_iter()
+        .map(|(screening, short_code)| QueueEntry {
+            screening,
+            short_code,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// Records an admin's decision on a queue entry. The link stays quarantined
+/// while any of its entries is pending or confirmed.
+fn review(
+    conn: &mut SqliteConnection,
+    actor: &Actor,
+    screening_id: i32,
+    decision: &str,
+) -> Result<LinkScreening, AppError> {
+    use crate::schema::{link_screenings, urls};
+
+    conn.transaction(|conn| {
+        let entry = link_screenings::table
+            .find(screening_id)
+            .first::<LinkScreening>(conn)
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("screening {}", screening_id)))?;
+        diesel::update(link_screenings::table.find(entry.id))
+            .set((
+                link_screenings::status.eq(decision),
+                link_screenings::reviewed_at.eq(Some(Utc::now().naive_utc())),
+                link_screenings::reviewed_by.eq(Some(&actor.name)),
+            ))
+            .execute(conn)?;
+        let outstanding: i64 = link_screenings::table
+            .filter(link_screenings::url_id.eq(entry.url_id))
+            .filter(link_screenings::status.ne(RELEASED))
+            .count()
+            .get_result(conn)?;
+        diesel::update(urls::table.find(entry.url_id))
+            .set(urls::quarantined.eq(outstanding > 0))
+            .execute(conn)?;
+        let reviewed = link_screenings::table
+            .find(entry.id)
+            .first::<LinkScreening>(conn)?;
+        let action = if decision == RELEASED {
+            audit::LINK_RELEASED
+        } else {
+            audit::LINK_CONFIRMED
+        };
+        let url_entry = urls::table.find(entry.url_id).first::<Url>(conn)?;
+        actor
+            .entry(action)
+            .link(&url_entry)
+            .before(&serde_json::json!(entry))
+            .after(&serde_json::json!(reviewed))
+            .record(conn)?;
+        Ok(reviewed)
+    })
+}
+
+/// Handler for releasing a flagged destination: a false positive. The link
+/// redirects again once none of its entries are pending or confirmed, and
+/// the destination is not flagged for it again.
+pub async fn release_screening_handler(
+    pool: web::Data<DbPool>,
+    actor: Actor,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let screening_id = path.into_inner();
+    let entry = web::block(move || {
+        let mut conn = pool.get()?;
+        review(&mut conn, &actor, screening_id, RELEASED)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+/// Handler for confirming a flagged destination as harmful. The link stays
+/// quarantined.
+pub async fn confirm_screening_handler(
+    pool: web::Data<DbPool>,
+    actor: Actor,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let screening_id = path.into_inner();
+    let entry = web::block(move || {
+        let mut conn = pool.get()?;
+        review(&mut conn, &actor, screening_id, CONFIRMED)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+/// Handler for screening every link now, after reloading changed feeds,
+/// rather than waiting for the next background scan.
+pub async fn scan_handler(
+    pool: web::Data<DbPool>,
+    feeds: web::Data<ThreatFeeds>,
+) -> Result<HttpResponse, AppError> {
+    if !feeds.is_enabled() {
+        return Err(AppError::InvalidInput(
+            "No threat feeds are configured".to_string(),
+        ));
+    }
+    let summary = web::block(move || {
+        feeds.reload();
+        let mut conn = pool.get()?;
+        scan(&mut conn, &feeds).map_err(AppError::from)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
@@ -0,0 +1,123 @@
+// src/server.rs
+// HTTP server construction, shared by the binary and the integration tests.
+
+use std::net::TcpListener;
+
+use actix_web::{
+    dev::Server,
+    middleware::{from_fn, Logger},
+    web, App, HttpServer,
+};
+
+use crate::{
+    auth,
+    config::Config,
+    db::DbPool,
+    domain_policy::{self, DomainPolicy},
+    error_pages::ErrorPages,
+    events::EventHub,
+    geo::GeoIp,
+    jwt::JwtVerifier,
+    oidc::OidcClient,
+    password::PasswordAttempts,
+    qr::QrLogo,
+    ratelimit::{self, RateLimiter},
+    routes,
+    screening::{self, ThreatFeeds},
+    webhooks::{self, DeliverySettings},
+};
+
+/// Builds the HTTP server on an already bound listener.
+///
+/// The returned [`Server`] must be awaited (or spawned) for it to start
+/// accepting connections. Must be called from within an Actix runtime, which
+/// also runs the webhook delivery worker, the domain list reloader and the
+/// threat feed scanner.
+pub fn run(listener: TcpListener, pool: DbPool, config: Config) -> std::io::Result<Server> {
+    let geoip = match &config.geoip_database {
+        Some(path) => GeoIp::open(path).map_err(|err| {
+            std::io::Error::other(format!("cannot open {}: {}", path.display(), err))
+        })?,
+        None => GeoIp::default(),
+    };
+    let qr_logo = match &config.qr_logo {
+        Some(path) => QrLogo::open(path).map_err(|err| {
+            std::io::Error::other(format!("cannot open {}: {}", path.display(), err))
+        })?,
+        None => QrLogo::default(),
+    };
+    let error_pages = match &config.error_pages_dir {
+        Some(dir) => ErrorPages::load(dir).map_err(|err| {
+            std::io::Error::other(format!("cannot load {}: {}", dir.display(), err))
+        })?,
+        None => ErrorPages::default(),
+    };
+    let jwt = JwtVerifier::from_config(&config)
+        .map_err(|err| std::io::Error::other(format!("cannot load JWT keys: {}", err)))?;
+    let rate_limiter = RateLimiter::from_config(&config, &pool);
+    let domain_policy = web::Data::new(
+        DomainPolicy::from_config(&config)
+            .map_err(|err| std::io::Error::other(format!("cannot load domain lists: {}", err)))?,
+    );
+    let threat_feeds = web::Data::new(
+        ThreatFeeds::from_config(&config)
+            .map_err(|err| std::io::Error::other(format!("cannot load threat feeds: {}", err)))?,
+    );
+
+    actix_web::rt::spawn(webhooks::run_worker(
+        pool.clone(),
+        DeliverySettings::from_config(&config),
+    ));
+    if domain_policy.is_enabled() {
+        actix_web::rt::spawn(domain_policy::run_reloader(
+            domain_policy.clone(),
+            config.domain_list_reload_interval,
+        ));
+    }
+    if threat_feeds.is_enabled() {
+        actix_web::rt::spawn(screening::run_scanner(
+            pool.clone(),
+            threat_feeds.clone(),
+            config.threat_scan_interval,
+        ));
+    }
+
+    let geoip = web::Data::new(geoip);
+    let qr_logo = web::Data::new(qr_logo);
+    let error_pages = web::Data::new(error_pages);
+    let jwt = web::Data::new(jwt);
+    let rate_limiter = web::Data::new(rate_limiter);
+    let config = web::Data::new(config);
+    // One hub for all workers so subscribers see every redirect
+    let events = web::Data::new(EventHub::default());
+    let password_attempts = web::Data::new(PasswordAttempts::default());
+    let oidc = web::Data::new(OidcClient::default());
+    let server = HttpServer::new(move || {
+        App::new()
+            // Share the database pool across all application routes
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(config.clone())
+            .app_data(events.clone())
+            .app_data(geoip.clone())
+            .app_data(password_attempts.clone())
+            .app_data(qr_logo.clone())
+            .app_data(error_pages.clone())
+            .app_data(jwt.clone())
+            .app_data(oidc.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(domain_policy.clone())
+            .app_data(threat_feeds.clone())
+            // Limit link creation and redirects; runs after authentication,
+            // which identifies the key creation limits count per
+            .wrap(from_fn(ratelimit::limit))
+            // Require API keys, bearer tokens or sessions on the management routes
+            .wrap(from_fn(auth::authenticate))
+            // Use default logging middleware to log HTTP requests
+            .wrap(Logger::default())
+            // Configure the application routes defined in the routes module
+            .configure(routes::init_routes)
+    })
+    .listen(listener)?
+    .run();
+    Ok(server)
+}
@@ -0,0 +1,93 @@
+// src/stats.rs
+// Per-link click statistics.
+
+use std::collections::BTreeMap;
+
+use actix_web::{web, HttpResponse};
+use chrono::NaiveDateTime;
+use diesel::{dsl::count_star, prelude::*};
+use serde::Serialize;
+
+use crate::auth::Principal;
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::models::Url;
+use crate::routing::DEFAULT_BRANCH;
+use crate::workspaces::{authorize_url, Permission};
+
+#[derive(Serialize)]
+pub struct UrlStats {
+    pub short_code: String,
+    pub original_url: String,
+    pub click_count: i64,
+    pub created_at: NaiveDateTime,
+    pub last_accessed: Option<NaiveDateTime>,
+    /// Clicks by the destination branch taken, e.g. `ios` or `default`.
+    pub branches: BTreeMap<String, i64>,
+    /// Clicks by A/B variant, for links with a split.
+    pub variants: BTreeMap<String, i64>,
+    /// Clicks that came from scanning the link's QR code.
+    pub scans: i64,
+}
+
+/// Computes the statistics of `url_entry` from `redirect_stats`.
+pub fn url_stats(conn: &mut SqliteConnection, url_entry: &Url) -> QueryResult<UrlStats> {
+    use crate::schema::redirect_stats::dsl::*;
+
+    let clicks = redirect_stats.filter(url_id.eq(url_entry.id));
+    let last_accessed = clicks
+        .select(diesel::dsl::max(accessed_at))
+        .first::<Option<NaiveDateTime>>(conn)?;
+    let by_branch = clicks
+        .group_by(branch)
+        .select((branch, count_star()))
+        .load::<(Option<String>, i64)>(conn)?;
+
+    let variants = clicks
+        .filter(variant.is_not_null())
+        .group_by(variant)
+        .select((variant.assume_not_null(), count_star()))
+        .load::<(String, i64)>(conn)?
+        .into_iter()
+        .collect();
+
+    let scans = clicks
+        .filter(source.eq(crate::qr::SCAN_SOURCE))
+        .count()
+        .get_result(conn)?;
+
+    let mut branches = BTreeMap::new();
+    for (name, count) in by_branch {
+        // Clicks recorded before branches were tracked all used `original_url`.
+        let name = name.unwrap_or_else(|| DEFAULT_BRANCH.to_string());
+        *branches.entry(name).or_insert(0) += count;
+    }
+
+    Ok(UrlStats {
+        short_code: url_entry.short_code.clone(),
+        original_url: url_entry.original_url.clone(),
+        click_count: branches.values().sum(),
+        created_at: url_entry.created_at,
+        last_accessed,
+        branches,
+        variants,
+        scans,
+    })
+}
+
+/// Handler for the statistics of a single link.
+pub async fn url_stats_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let code = path.into_inner();
+    let stats = web::block(move || {
+        let mut conn = pool.get()?;
+        let url_entry = authorize_url(&mut conn, &principal, &code, Permission::ViewStats)?;
+        url_stats(&mut conn, &url_entry).map_err(AppError::from)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(stats))
+}
@@ -0,0 +1,270 @@
+// src/users.rs
+// User accounts and link ownership.
+//
+// A user owns the links created with API keys tied to them. Callers without
+// the `admin` scope only see and manage their own links; admins see all of
+// them and move links between users.
+
+use actix_web::{web, HttpResponse};
+use diesel::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    audit::{self, Actor},
+    auth::Principal,
+    config::Config,
+    db::DbPool,
+    error::AppError,
+    models::{NewUser, Url, UrlChanges, UrlTag, User},
+    webhooks,
+    workspaces::{authorize_url, Permission},
+};
+
+/// Longest accepted username, in bytes.
+pub const MAX_USERNAME_LENGTH: usize = 64;
+
+/// Usernames are non-empty and made of letters, digits, `.`, `_`, `-`
+/// and `@`, so email addresses work as usernames.
+pub fn validate_username(username: &str) -> Result<(), AppError> {
+    let valid = !username.is_empty()
+        && username.len() <= MAX_USERNAME_LENGTH
+        && username
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '-' | '@'));
+    if !valid {
+        return Err(AppError::InvalidInput(format!(
+            "username must be 1 to {} letters, digits or . _ - @",
+            MAX_USERNAME_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+pub fn find_user_by_name(conn: &mut SqliteConnection, name: &str) -> QueryResult<Option<User>> {
+    use crate::schema::users::dsl::*;
+    users.filter(username.eq(name)).first(conn).optional()
+}
+
+/// Returns the user called `name`, creating it first if needed.
+pub fn find_or_create_user(conn: &mut SqliteConnection, name: &str) -> QueryResult<User> {
+    use crate::schema::users::dsl::*;
+
+    if let Some(user) = find_user_by_name(conn, name)? {
+        return Ok(user);
+    }
+    diesel::insert_into(users)
+        .values(&NewUser {
+            username: name.to_string(),
+        })
+        .execute(conn)?;
+    users.filter(username.eq(name)).first(conn)
+}
+
+fn user_exists(conn: &mut SqliteConnection, user_id: i32) -> QueryResult<bool> {
+    use crate::schema::users::dsl::*;
+    users
+        .find(user_id)
+        .first::<User>(conn)
+        .optional()
+        .map(|user| user.is_some())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateUserRequest {
+    pub username: String,
+}
+
+/// Handler for creating a user.
+pub async fn create_user_handler(
+    pool: web::Data<DbPool>,
+    item: web::Json<CreateUserRequest>,
+) -> Result<HttpResponse, AppError> {
+    let name = item.into_inner().username.trim().to_string();
+    validate_username(&name)?;
+    let user = web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            if find_user_by_name(conn, &name)?.is_some() {
+                return Err(AppError::InvalidInput(format!(
+                    "username {} is taken",
+                    name
+                )));
+            }
+            find_or_create_user(conn, &name).map_err(AppError::from)
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Created().json(user))
+}
+
+/// Handler for listing users.
+pub async fn list_users_handler(pool: web::Data<DbPool>) -> Result<HttpResponse, AppError> {
+    let rows = web::block(move || {
+        use crate::schema::users::dsl::*;
+        let mut conn = pool.get()?;
+        users
+            .order(id.asc())
+            .load::<User>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+#[derive(Deserialize)]
+pub struct DeleteUserQuery {
+    /// User to hand the deleted user's links to; required while they own
+    /// any.
+    pub transfer_to: Option<i32>,
+}
+
+/// Handler for deleting a user. Their API keys are revoked and their links
+/// are transferred to `transfer_to`, which is required while they own any.
+/// Links without an owner are open to every key not tied to a user, so
+/// they are never left behind that way.
+pub async fn delete_user_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    actor: Actor,
+    path: web::Path<i32>,
+    query: web::Query<DeleteUserQuery>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::{api_keys, sessions, urls, users, workspace_members};
+
+    let user_id = path.into_inner();
+    let transfer_to = query.into_inner().transfer_to;
+    if transfer_to == Some(user_id) {
+        return Err(AppError::InvalidInput(
+            "cannot transfer links to the user being deleted".to_string(),
+        ));
+    }
+    let base_url = config.base_url.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            if !user_exists(conn, user_id)? {
+                return Err(AppError::NotFound(format!("user {}", user_id)));
+            }
+            match transfer_to {
+                Some(target) => {
+                    if !user_exists(conn, target)? {
+                        return Err(AppError::InvalidInput(format!(
+                            "user {} does not exist",
+                            target
+                        )));
+                    }
+                },
+                None => {
+                    let owned: i64 = urls::table
+                        .filter(urls::owner_id.eq(user_id))
+                        .count()
+                        .get_result(conn)?;
+                    if owned > 0 {
+                        return Err(AppError::InvalidInput(format!(
+                            "user {} owns {} links; give transfer_to to move them to another user",
+                            user_id, owned
+                        )));
+                    }
+                },
+            }
+            let owned = urls::table
+                .filter(urls::owner_id.eq(user_id))
+                .load::<Url>(conn)?;
+            diesel::update(urls::table.filter(urls::owner_id.eq(user_id)))
+                .set(urls::owner_id.eq(transfer_to))
+                .execute(conn)?;
+            for url_entry in owned {
+                let tags = UrlTag::for_url(conn, url_entry.id)?;
+                let updated = urls::table.find(url_entry.id).first::<Url>(conn)?;
+                let body = updated.to_json(&tags, &base_url);
+                webhooks::enqueue(conn, webhooks::LINK_UPDATED, &body)?;
+                actor
+                    .entry(audit::LINK_TRANSFERRED)
+                    .link(&updated)
+                    .before(&url_entry.to_json(&tags, &base_url))
+                    .after(&body)
+                    .record(conn)?;
+            }
+            diesel::update(
+                api_keys::table
+                    .filter(api_keys::user_id.eq(user_id))
+                    .filter(api_keys::revoked_at.is_null()),
+            )
+            .set(api_keys::revoked_at.eq(chrono::Utc::now().naive_utc()))
+            .execute(conn)?;
+            diesel::update(api_keys::table.filter(api_keys::user_id.eq(user_id)))
+                .set(api_keys::user_id.eq(None::<i32>))
+                .execute(conn)?;
+            diesel::delete(
+                workspace_members::table.filter(workspace_members::user_id.eq(user_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(sessions::table.filter(sessions::user_id.eq(user_id))).execute(conn)?;
+            diesel::delete(users::table.find(user_id)).execute(conn)?;
+            Ok(())
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransferUrlRequest {
+    /// New owner, or `null` to leave the link without one.
+    pub user_id: Option<i32>,
+}
+
+/// Handler for moving a link to another user. Admin only.
+pub async fn transfer_url_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    principal: Principal,
+    actor: Actor,
+    path: web::Path<String>,
+    item: web::Json<TransferUrlRequest>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::urls;
+
+    let code = path.into_inner();
+    let new_owner = item.into_inner().user_id;
+    let base_url = config.base_url.clone();
+    let body = web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let url_entry = authorize_url(conn, &principal, &code, Permission::EditLinks)?;
+            if let Some(target) = new_owner {
+                if !user_exists(conn, target)? {
+                    return Err(AppError::InvalidInput(format!(
+                        "user {} does not exist",
+                        target
+                    )));
+                }
+            }
+            diesel::update(urls::table.find(url_entry.id))
+                .set(&UrlChanges {
+                    owner_id: Some(new_owner),
+                    ..UrlChanges::default()
+                })
+                .execute(conn)?;
+            let updated = urls::table.find(url_entry.id).first::<Url>(conn)?;
+            let tags = UrlTag::for_url(conn, updated.id)?;
+            let body = updated.to_json(&tags, &base_url);
+            webhooks::enqueue(conn, webhooks::LINK_UPDATED, &body)?;
+            actor
+                .entry(audit::LINK_TRANSFERRED)
+                .link(&updated)
+                .before(&url_entry.to_json(&tags, &base_url))
+                .after(&body)
+                .record(conn)?;
+            Ok::<_, AppError>(body)
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(body))
+}
@@ -1,4 +1,6 @@
+use chrono::{DateTime, NaiveDateTime};
 use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Deserializer};
 
 /// Generates a random alphanumeric short code with the specified length.
 pub fn generate_short_code(len: usize) -> String {
@@ -8,3 +10,52 @@ pub fn generate_short_code(len: usize) -> String {
         .map(char::from)
         .collect()
 }
+
+/// Trims, de-duplicates and sorts user-supplied tags, dropping empty ones.
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Parses a timestamp given either as RFC 3339 (`2024-01-15T10:30:00Z`) or as a
+/// naive UTC date-time (`2024-01-15T10:30:00`). Returns the value in UTC, which
+/// is how SQLite's `CURRENT_TIMESTAMP` stores it.
+pub fn parse_timestamp(value: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .ok()
+}
+
+/// Deserializes a field that is present (possibly as `null`) into `Some`.
+/// Combined with `#[serde(default)]` on an `Option<Option<T>>` this tells an
+/// omitted field (`None`) apart from an explicit `null` (`Some(None)`).
+pub fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Escapes text for use in HTML element content and quoted attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,238 @@
+// src/utm.rs
+// UTM parameters appended to destinations on redirect.
+//
+// Templates can be set on a link and on tags. On redirect the link's own
+// template is completed field by field with the templates of its tags, taken
+// in alphabetical tag order, and the result is added to the destination.
+
+use actix_web::{web, HttpResponse};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::db::DbPool;
+use crate::error::AppError;
+use crate::models::{NewTagUtmTemplate, TagUtmTemplate, Url};
+
+/// Which value wins when a forwarded query string carries a UTM parameter
+/// that the template also sets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UtmPrecedence {
+    /// Parameters on the incoming request replace template values.
+    #[default]
+    Incoming,
+    /// Template values are kept; incoming ones are dropped.
+    Template,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UtmTemplate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub medium: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub term: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precedence: Option<UtmPrecedence>,
+}
+
+impl UtmTemplate {
+    fn fields(&self) -> [(&'static str, &Option<String>); 5] {
+        [
+            ("utm_source", &self.source),
+            ("utm_medium", &self.medium),
+            ("utm_campaign", &self.campaign),
+            ("utm_content", &self.content),
+            ("utm_term", &self.term),
+        ]
+    }
+
+    /// Rejects templates that set no parameter or an empty one.
+    pub fn validate(&self) -> Result<(), AppError> {
+        let fields = self.fields();
+        if fields.iter().all(|(_, value)| value.is_none()) {
+            return Err(AppError::InvalidInput(
+                "a UTM template must set at least one parameter".to_string(),
+            ));
+        }
+        if let Some((name, _)) = fields
+            .iter()
+            .find(|(_, value)| value.as_deref().is_some_and(|v| v.trim().is_empty()))
+        {
+            return Err(AppError::InvalidInput(format!(
+                "{} must not be empty",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    /// The query parameters this template adds, e.g. `("utm_source", "mail")`.
+    pub fn params(&self) -> Vec<(String, String)> {
+        self.fields()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.as_ref()?.clone())))
+            .collect()
+    }
+
+    pub fn incoming_wins(&self) -> bool {
+        self.precedence.unwrap_or_default() == UtmPrecedence::Incoming
+    }
+
+    /// Fills fields this template leaves unset from `other`.
+    fn fill_from(&mut self, other: &UtmTemplate) {
+        let slots = [
+            (&mut self.source, &other.source),
+            (&mut self.medium, &other.medium),
+            (&mut self.campaign, &other.campaign),
+            (&mut self.content, &other.content),
+            (&mut self.term, &other.term),
+        ];
+        for (slot, value) in slots {
+            if slot.is_none() {
+                slot.clone_from(value);
+            }
+        }
+        if self.precedence.is_none() {
+            self.precedence = other.precedence;
+        }
+    }
+
+    pub fn to_json_string(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    fn from_json_str(value: &str) -> Option<Self> {
+        serde_json::from_str(value)
+            .map_err(|err| log::warn!("Ignoring malformed UTM template: {}", err))
+            .ok()
+    }
+}
+
+impl Url {
+    /// The link's own UTM template, without tag templates.
+    pub fn utm(&self) -> Option<UtmTemplate> {
+        self.utm_template
+            .as_deref()
+            .and_then(UtmTemplate::from_json_str)
+    }
+}
+
+/// Combines the template of `url_entry` with those of its tags.
+pub fn template_for_url(
+    conn: &mut SqliteConnection,
+    url_entry: &Url,
+) -> QueryResult<Option<UtmTemplate>> {
+    use crate::schema::{tag_utm_templates, url_tags};
+
+    let tag_templates = tag_utm_templates::table
+        .filter(
+            tag_utm_templates::tag.eq_any(
+                url_tags::table
+                    .filter(url_tags::url_id.eq(url_entry.id))
+                    .select(url_tags::tag),
+            ),
+        )
+        .order(tag_utm_templates::tag.asc())
+        .select(tag_utm_templates::template)
+        .load::<String>(conn)?;
+
+    let mut templates = url_entry.utm().into_iter().chain(
+        tag_templates
+            .iter()
+            .filter_map(|t| UtmTemplate::from_json_str(t)),
+    );
+    let Some(mut combined) = templates.next() else {
+        return Ok(None);
+    };
+    for template in templates {
+        combined.fill_from(&template);
+    }
+    Ok(Some(combined))
+}
+
+/// Handler for reading the UTM template of a tag.
+pub async fn get_tag_utm_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let tag_name = path.into_inner();
+    let row = web::block(move || {
+        use crate::schema::tag_utm_templates::dsl::*;
+        let mut conn = pool.get()?;
+        tag_utm_templates
+            .find(&tag_name)
+            .first::<TagUtmTemplate>(&mut conn)
+            .optional()?
+            .ok_or_else(|| AppError::NotFound(format!("UTM template for tag {}", tag_name)))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(tag_template_json(&row.tag, &row.template)))
+}
+
+/// Handler for setting or replacing the UTM template of a tag.
+pub async fn put_tag_utm_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+    item: web::Json<UtmTemplate>,
+) -> Result<HttpResponse, AppError> {
+    let tag_name = path.into_inner().trim().to_string();
+    let item = item.into_inner();
+    item.validate()?;
+
+    let row = NewTagUtmTemplate {
+        tag: tag_name.clone(),
+        template: item.to_json_string(),
+    };
+    let body = tag_template_json(&row.tag, &row.template);
+    web::block(move || {
+        use crate::schema::tag_utm_templates::dsl::*;
+        let mut conn = pool.get()?;
+        diesel::insert_into(tag_utm_templates)
+            .values(&row)
+            .on_conflict(tag)
+            .do_update()
+            .set((template.eq(&row.template), updated_at.eq(diesel::dsl::now)))
+            .execute(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Handler for removing the UTM template of a tag.
+pub async fn delete_tag_utm_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let tag_name = path.into_inner();
+    web::block(move || {
+        use crate::schema::tag_utm_templates::dsl::*;
+        let mut conn = pool.get()?;
+        match diesel::delete(tag_utm_templates.find(&tag_name)).execute(&mut conn)? {
+            0 => Err(AppError::NotFound(format!(
+                "UTM template for tag {}",
+                tag_name
+            ))),
+            _ => Ok(()),
+        }
+    })
+    .await??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn tag_template_json(tag: &str, template: &str) -> serde_json::Value {
+    serde_json::json!({
+        "tag": tag,
+        "utm": UtmTemplate::from_json_str(template),
+    })
+}
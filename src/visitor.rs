@@ -0,0 +1,253 @@
+// src/visitor.rs
+// Information about the client behind a request, derived once per redirect
+// and shared by click recording and the live event stream.
+
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{http::header, web, HttpRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, geo::GeoIp};
+
+/// Headers set by common CDNs and load balancers carrying the client's
+/// ISO 3166 country code.
+const COUNTRY_HEADERS: [&str; 3] = [
+    "CF-IPCountry",
+    "CloudFront-Viewer-Country",
+    "X-Country-Code",
+];
+
+/// Coarse classification of the client device, derived from the User-Agent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceClass {
+    Desktop,
+    Mobile,
+    Tablet,
+    Bot,
+    Unknown,
+}
+
+impl DeviceClass {
+    pub fn from_user_agent(user_agent: Option<&str>) -> Self {
+        let Some(user_agent) = user_agent else {
+            return DeviceClass::Unknown;
+        };
+        let ua = user_agent.to_ascii_lowercase();
+        let contains_any = |needles: &[&str]| needles.iter().any(|needle| ua.contains(needle));
+
+        if contains_any(&[
+            "bot",
+            "crawler",
+            "spider",
+            "curl/",
+            "wget/",
+            "python-requests",
+        ]) {
+            DeviceClass::Bot
+        } else if contains_any(&["ipad", "tablet"])
+            || (ua.contains("android") && !ua.contains("mobile"))
+        {
+            DeviceClass::Tablet
+        } else if contains_any(&["mobile", "iphone", "ipod", "android", "windows phone"]) {
+            DeviceClass::Mobile
+        } else {
+            DeviceClass::Desktop
+        }
+    }
+}
+
+/// Operating system family of the client, used to pick app-specific
+/// destinations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Ios,
+    Android,
+    Desktop,
+    Other,
+}
+
+impl Platform {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::Ios => "ios",
+            Platform::Android => "android",
+            Platform::Desktop => "desktop",
+            Platform::Other => "other",
+        }
+    }
+
+    pub fn from_user_agent(user_agent: Option<&str>) -> Self {
+        let Some(user_agent) = user_agent else {
+            return Platform::Other;
+        };
+        let ua = user_agent.to_ascii_lowercase();
+        let contains_any = |needles: &[&str]| needles.iter().any(|needle| ua.contains(needle));
+
+        if contains_any(&["iphone", "ipad", "ipod"]) {
+            Platform::Ios
+        } else if ua.contains("android") {
+            Platform::Android
+        } else if contains_any(&["windows nt", "macintosh", "mac os x", "x11", "cros"]) {
+            Platform::Desktop
+        } else {
+            Platform::Other
+        }
+    }
+}
+
+/// The client that requested a short link.
+#[derive(Clone, Debug)]
+pub struct Visitor {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    /// Two-letter country code, from the geo database when one is
+    /// configured, otherwise from a fronting proxy.
+    pub country: Option<String>,
+    pub device: DeviceClass,
+    pub platform: Platform,
+    /// Preferred language from `Accept-Language`, lowercased, e.g. `pt-br`.
+    pub language: Option<String>,
+    /// Host of the `Referer`, lowercased.
+    pub referrer_host: Option<String>,
+}
+
+impl Visitor {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let ip_address = client_ip(req);
+        let user_agent = header_value(req, header::USER_AGENT.as_str());
+        let geo_country = req
+            .app_data::<web::Data<GeoIp>>()
+            .zip(ip_address.as_deref())
+            .and_then(|(geoip, ip)| geoip.country(ip));
+        let country = geo_country.or_else(|| {
+            COUNTRY_HEADERS
+                .iter()
+                .find_map(|name| header_value(req, name))
+                .map(|code| code.trim().to_ascii_uppercase())
+                .filter(|code| code.len() == 2 && code != "XX")
+        });
+        let device = DeviceClass::from_user_agent(user_agent.as_deref());
+        let platform = Platform::from_user_agent(user_agent.as_deref());
+        let language = header_value(req, header::ACCEPT_LANGUAGE.as_str())
+            .and_then(|value| preferred_language(&value));
+        let referrer_host = header_value(req, header::REFERER.as_str())
+            .and_then(|value| referrer_host(&value));
+
+        Visitor {
+            ip_address,
+            user_agent,
+            country,
+            device,
+            platform,
+            language,
+            referrer_host,
+        }
+    }
+}
+
+/// Address of the client behind a request.
+///
+/// `Forwarded` and `X-Forwarded-For` are set by whoever sends the request, so
+/// they are only believed on connections from `config.trusted_proxies`. Each
+/// proxy appends the address it was contacted from, so the client is the
+/// last hop that is not itself a trusted proxy. A hop that cannot be parsed
+/// stops the walk and the connecting proxy is taken instead.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    let trusted = req
+        .app_data::<web::Data<Config>>()
+        .map(|config| config.trusted_proxies.as_slice())
+        .unwrap_or_default();
+    if !trusted.contains(&peer) {
+        return Some(peer.to_string());
+    }
+    let hops = forwarded_hops(req);
+    let untrusted = hops
+        .iter()
+        .rev()
+        .find(|hop| !hop.is_some_and(|ip| trusted.contains(&ip)));
+    let client = match untrusted {
+        Some(hop) => hop.unwrap_or(peer),
+        // Every hop is a trusted proxy
+        None => hops.first().copied().flatten().unwrap_or(peer),
+    };
+    Some(client.to_string())
+}
+
+/// The `for=` addresses of `Forwarded`, or else the addresses of
+/// `X-Forwarded-For`, in the order they were added. `None` stands for a hop
+/// that is not an address, such as `unknown` or an obfuscated name.
+fn forwarded_hops(req: &HttpRequest) -> Vec<Option<IpAddr>> {
+    let values = |name: header::HeaderName| {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect::<Vec<_>>()
+    };
+    let forwarded: Vec<Option<IpAddr>> = values(header::FORWARDED)
+        .into_iter()
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                name.trim().eq_ignore_ascii_case("for").then_some(value)
+            })
+        })
+        .map(parse_hop)
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+    values(header::X_FORWARDED_FOR)
+        .into_iter()
+        .map(parse_hop)
+        .collect()
+}
+
+/// Parses `192.0.2.1`, `192.0.2.1:443`, `2001:db8::1`, `[2001:db8::1]` or
+/// `[2001:db8::1]:443`, optionally quoted.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim().trim_matches('"');
+    hop.parse()
+        .ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|address| address.ip()))
+        .or_else(|| hop.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+/// The language tag with the highest quality in an `Accept-Language` value,
+/// ignoring `*` and entries with `q=0`.
+pub fn preferred_language(accept_language: &str) -> Option<String> {
+    let mut best: Option<(f32, &str)> = None;
+    for entry in accept_language.split(',') {
+        let mut parts = entry.split(';');
+        let tag = parts.next().unwrap_or("").trim();
+        let quality = parts
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok());
+        let Some(quality) = quality else { continue };
+        if tag.is_empty() || tag == "*" || quality <= 0.0 {
+            continue;
+        }
+        if best.is_none_or(|(best_quality, _)| quality > best_quality) {
+            best = Some((quality, tag));
+        }
+    }
+    best.map(|(_, tag)| tag.to_ascii_lowercase())
+}
+
+/// The lowercased host of a `Referer` URL.
+pub fn referrer_host(referer: &str) -> Option<String> {
+    url::Url::parse(referer)
+        .ok()?
+        .host_str()
+        .map(str::to_ascii_lowercase)
+}
+
+fn header_value(req: &HttpRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
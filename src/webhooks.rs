@@ -0,0 +1,552 @@
+// src/webhooks.rs
+// Outgoing webhooks for link lifecycle events and click milestones.
+//
+// Events are queued in `webhook_deliveries` by the request that causes them.
+// A background worker posts due deliveries to their endpoints, signing each
+// body with the webhook's secret, and retries failures with exponential
+// backoff until the delivery succeeds or is marked dead.
+
+use std::time::Duration;
+
+use actix_web::{http::header, rt::time::interval, web, HttpResponse};
+use chrono::Utc;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{
+    config::Config,
+    db::DbPool,
+    error::AppError,
+    models::{NewWebhook, NewWebhookDelivery, Url, UrlChanges, UrlTag, Webhook, WebhookDelivery},
+    utils::generate_short_code,
+};
+
+pub const LINK_CREATED: &str = "link.created";
+pub const LINK_UPDATED: &str = "link.updated";
+pub const LINK_DELETED: &str = "link.deleted";
+pub const LINK_EXPIRED: &str = "link.expired";
+pub const LINK_CLICK_MILESTONE: &str = "link.click_milestone";
+
+pub const EVENT_TYPES: [&str; 5] = [
+    LINK_CREATED,
+    LINK_UPDATED,
+    LINK_DELETED,
+    LINK_EXPIRED,
+    LINK_CLICK_MILESTONE,
+];
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_DELIVERED: &str = "delivered";
+pub const STATUS_DEAD: &str = "dead";
+
+/// Header carrying `sha256=<hex HMAC-SHA256 of the body>`.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a claimed delivery is left to the instance sending it. Longer
+/// than an attempt can take, so it only runs out if that instance died.
+const DELIVERY_LEASE: Duration = Duration::from_secs(60);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+const DELIVERY_BATCH: i64 = 50;
+
+/// Computes the signature header value for a payload.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+impl Webhook {
+    pub fn event_list(&self) -> Vec<String> {
+        serde_json::from_str(&self.events).unwrap_or_default()
+    }
+
+    pub fn milestone_list(&self) -> Vec<i64> {
+        serde_json::from_str(&self.click_milestones).unwrap_or_default()
+    }
+
+    fn subscribes_to(&self, event_type: &str) -> bool {
+        let events = self.event_list();
+        events.is_empty() || events.iter().any(|event| event == event_type)
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "target_url": self.target_url,
+            "events": self.event_list(),
+            "click_milestones": self.milestone_list(),
+            "active": self.active,
+            "created_at": self.created_at
+        })
+    }
+}
+
+/// Queues `event_type` for every active webhook subscribed to it.
+pub fn enqueue(
+    conn: &mut SqliteConnection,
+    event_type: &str,
+    data: &serde_json::Value,
+) -> QueryResult<usize> {
+    let hooks = active_webhooks(conn)?;
+    enqueue_for(conn, &hooks, event_type, data)
+}
+
+/// Called after a click is recorded. Queues `link.click_milestone` for the
+/// webhooks whose milestones include the link's new click count.
+pub fn on_click(conn: &mut SqliteConnection, url: &Url) -> QueryResult<()> {
+    use crate::schema::redirect_stats;
+
+    let hooks: Vec<Webhook> = active_webhooks(conn)?
+        .into_iter()
+        .filter(|hook| {
+            hook.subscribes_to(LINK_CLICK_MILESTONE) && !hook.milestone_list().is_empty()
+        })
+        .collect();
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let clicks: i64 = redirect_stats::table
+        .filter(redirect_stats::url_id.eq(url.id))
+        .count()
+        .get_result(conn)?;
+    let reached: Vec<Webhook> = hooks
+        .into_iter()
+        .filter(|hook| hook.milestone_list().contains(&clicks))
+        .collect();
+    if reached.is_empty() {
+        return Ok(());
+    }
+
+    let data = serde_json::json!({
+        "short_code": url.short_code,
+        "original_url": url.original_url,
+        "clicks": clicks
+    });
+    enqueue_for(conn, &reached, LINK_CLICK_MILESTONE, &data).map(|_| ())
+}
+
+fn active_webhooks(conn: &mut SqliteConnection) -> QueryResult<Vec<Webhook>> {
+    use crate::schema::webhooks::dsl::*;
+    webhooks.filter(active.eq(true)).load::<Webhook>(conn)
+}
+
+fn enqueue_for(
+    conn: &mut SqliteConnection,
+    hooks: &[Webhook],
+    event_type: &str,
+    data: &serde_json::Value,
+) -> QueryResult<usize> {
+    use crate::schema::webhook_deliveries;
+
+    let payload = serde_json::json!({
+        "event": event_type,
+        "created_at": Utc::now(),
+        "data": data
+    })
+    .to_string();
+    let rows: Vec<NewWebhookDelivery> = hooks
+        .iter()
+        .filter(|hook| hook.subscribes_to(event_type))
+        .map(|hook| NewWebhookDelivery {
+            webhook_id: hook.id,
+            event_type: event_type.to_string(),
+            payload: payload.clone(),
+        })
+        .collect();
+    diesel::insert_into(webhook_deliveries::table)
+        .values(&rows)
+        .execute(conn)
+}
+
+/// Delivery settings taken from [`Config`] when the server starts.
+#[derive(Clone)]
+pub struct DeliverySettings {
+    pub base_url: String,
+    pub poll_interval: Duration,
+    pub retry_base: Duration,
+    pub max_attempts: i32,
+}
+
+impl DeliverySettings {
+    pub fn from_config(config: &Config) -> Self {
+        DeliverySettings {
+            base_url: config.base_url.clone(),
+            poll_interval: config.webhook_poll_interval,
+            retry_base: config.webhook_retry_base,
+            max_attempts: config.webhook_max_attempts,
+        }
+    }
+
+    /// Delay before the next attempt after `attempts` failures.
+    fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+        self.retry_base
+            .saturating_mul(1 << exponent)
+            .min(MAX_BACKOFF)
+    }
+}
+
+/// Background loop that queues expiry events and sends due deliveries.
+pub async fn run_worker(pool: DbPool, settings: DeliverySettings) {
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .expect("Failed to build webhook HTTP client");
+    let mut ticker = interval(settings.poll_interval);
+    loop {
+        ticker.tick().await;
+        if let Err(err) = process_due(&pool, &client, &settings).await {
+            log::warn!("Webhook worker error: {}", err);
+        }
+    }
+}
+
+async fn process_due(
+    pool: &DbPool,
+    client: &reqwest::Client,
+    settings: &DeliverySettings,
+) -> Result<(), AppError> {
+    let due = {
+        let pool = pool.clone();
+        let base_url = settings.base_url.clone();
+        web::block(move || {
+            let mut conn = pool.get()?;
+            queue_expired_links(&mut conn, &base_url)?;
+            load_due(&mut conn).map_err(AppError::from)
+        })
+        .await??
+    };
+
+    for (delivery, hook) in due {
+        let claimed = {
+            let pool = pool.clone();
+            let delivery_id = delivery.id;
+            web::block(move || {
+                let mut conn = pool.get()?;
+                claim(&mut conn, delivery_id).map_err(AppError::from)
+            })
+            .await??
+        };
+        if !claimed {
+            continue;
+        }
+        let outcome = attempt(client, &hook, &delivery).await;
+        let pool = pool.clone();
+        let settings = settings.clone();
+        web::block(move || {
+            let mut conn = pool.get()?;
+            record_attempt(&mut conn, &delivery, outcome, &settings).map_err(AppError::from)
+        })
+        .await??;
+    }
+    Ok(())
+}
+
+/// Queues `link.expired` for links whose expiration date has passed.
+fn queue_expired_links(conn: &mut SqliteConnection, base_url: &str) -> QueryResult<()> {
+    use crate::schema::urls::dsl::*;
+
+    let now = Utc::now().naive_utc();
+    let expired = urls
+        .filter(expiration_date.le(now))
+        .filter(expiry_notified.eq(false))
+        .load::<Url>(conn)?;
+    for url in expired {
+        conn.transaction(|conn| {
+            let tags = UrlTag::for_url(conn, url.id)?;
+            enqueue(conn, LINK_EXPIRED, &url.to_json(&tags, base_url))?;
+            diesel::update(urls.find(url.id))
+                .set(&UrlChanges {
+                    expiry_notified: Some(true),
+                    ..UrlChanges::default()
+                })
+                .execute(conn)
+                .map(|_| ())
+        })?;
+    }
+    Ok(())
+}
+
+fn load_due(conn: &mut SqliteConnection) -> QueryResult<Vec<(WebhookDelivery, Webhook)>> {
+    use crate::schema::{webhook_deliveries, webhooks};
+
+    webhook_deliveries::table
+        .inner_join(webhooks::table)
+        .filter(webhook_deliveries::status.eq(STATUS_PENDING))
+        .filter(webhook_deliveries::next_attempt_at.le(Utc::now().naive_utc()))
+        .order(webhook_deliveries::id.asc())
+        .limit(DELIVERY_BATCH)
+        .load(conn)
+}
+
+/// Leases a due delivery to this instance by pushing its next attempt past
+/// the lease. Instances sharing the database load the same due deliveries,
+/// but only the one whose update still finds the delivery due sends it.
+fn claim(conn: &mut SqliteConnection, delivery_id: i32) -> QueryResult<bool> {
+    use crate::schema::webhook_deliveries::dsl::*;
+
+    let now = Utc::now().naive_utc();
+    let claimed = diesel::update(
+        webhook_deliveries
+            .find(delivery_id)
+            .filter(status.eq(STATUS_PENDING))
+            .filter(next_attempt_at.le(now)),
+    )
+    .set(next_attempt_at.eq(now + DELIVERY_LEASE))
+    .execute(conn)?;
+    Ok(claimed == 1)
+}
+
+/// Outcome of one POST: the status code, or why it failed.
+type AttemptOutcome = Result<u16, (Option<u16>, String)>;
+
+async fn attempt(
+    client: &reqwest::Client,
+    hook: &Webhook,
+    delivery: &WebhookDelivery,
+) -> AttemptOutcome {
+    let response = client
+        .post(&hook.target_url)
+        .header(header::CONTENT_TYPE.as_str(), "application/json")
+        .header(
+            SIGNATURE_HEADER,
+            sign(&hook.secret, delivery.payload.as_bytes()),
+        )
+        .header(EVENT_HEADER, &delivery.event_type)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => Ok(response.status().as_u16()),
+        Ok(response) => {
+            let code = response.status().as_u16();
+            Err((Some(code), format!("endpoint responded with HTTP {}", code)))
+        },
+        Err(err) => Err((None, err.to_string())),
+    }
+}
+
+fn record_attempt(
+    conn: &mut SqliteConnection,
+    delivery: &WebhookDelivery,
+    outcome: AttemptOutcome,
+    settings: &DeliverySettings,
+) -> QueryResult<()> {
+    use crate::schema::webhook_deliveries::dsl::*;
+
+    let now = Utc::now().naive_utc();
+    let attempt_count = delivery.attempts + 1;
+    let target = webhook_deliveries.find(delivery.id);
+    match outcome {
+        Ok(code) => diesel::update(target)
+            .set((
+                status.eq(STATUS_DELIVERED),
+                attempts.eq(attempt_count),
+                last_status_code.eq(Some(i32::from(code))),
+                last_error.eq(None::<String>),
+                delivered_at.eq(Some(now)),
+            ))
+            .execute(conn)?,
+        Err((code, error)) => {
+            let (new_status, next_attempt) = if attempt_count >= settings.max_attempts {
+                log::warn!(
+                    "Webhook delivery {} is dead after {} attempts",
+                    delivery.id,
+                    attempt_count
+                );
+                (STATUS_DEAD, now)
+            } else {
+                (STATUS_PENDING, now + settings.backoff(attempt_count))
+            };
+            diesel::update(target)
+                .set((
+                    status.eq(new_status),
+                    attempts.eq(attempt_count),
+                    next_attempt_at.eq(next_attempt),
+                    last_status_code.eq(code.map(i32::from)),
+                    last_error.eq(Some(error)),
+                ))
+                .execute(conn)?
+        },
+    };
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct RegisterWebhookRequest {
+    pub target_url: String,
+    /// Event types to receive; all events when omitted or empty.
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Click counts at which `link.click_milestone` is sent.
+    #[serde(default)]
+    pub click_milestones: Vec<i64>,
+    /// Signing secret; generated when omitted.
+    pub secret: Option<String>,
+}
+
+/// Handler for registering a webhook. The secret is only returned here.
+pub async fn register_webhook_handler(
+    pool: web::Data<DbPool>,
+    item: web::Json<RegisterWebhookRequest>,
+) -> Result<HttpResponse, AppError> {
+    let item = item.into_inner();
+    if !(item.target_url.starts_with("http://") || item.target_url.starts_with("https://")) {
+        return Err(AppError::InvalidInput(
+            "target_url must be an http(s) URL".to_string(),
+        ));
+    }
+    if let Some(unknown) = item
+        .events
+        .iter()
+        .find(|event| !EVENT_TYPES.contains(&event.as_str()))
+    {
+        return Err(AppError::InvalidInput(format!(
+            "unknown event type '{}'",
+            unknown
+        )));
+    }
+    if item.click_milestones.iter().any(|milestone| *milestone < 1) {
+        return Err(AppError::InvalidInput(
+            "click_milestones must be positive".to_string(),
+        ));
+    }
+
+    let secret = item
+        .secret
+        .filter(|secret| !secret.is_empty())
+        .unwrap_or_else(|| generate_short_code(32));
+    let new_webhook = NewWebhook {
+        target_url: item.target_url,
+        secret: secret.clone(),
+        events: serde_json::to_string(&item.events).unwrap_or_default(),
+        click_milestones: serde_json::to_string(&item.click_milestones).unwrap_or_default(),
+    };
+
+    let webhook = web::block(move || {
+        use crate::schema::webhooks::dsl::*;
+        let mut conn = pool.get()?;
+        // The write lock is held from the insert to the read, so the newest
+        // row is this one even with other registrations in flight.
+        conn.immediate_transaction(|conn| {
+            diesel::insert_into(webhooks)
+                .values(&new_webhook)
+                .execute(conn)?;
+            webhooks.order(id.desc()).first::<Webhook>(conn)
+        })
+        .map_err(AppError::from)
+    })
+    .await??;
+
+    let mut body = webhook.to_json();
+    body["secret"] = serde_json::Value::String(secret);
+    Ok(HttpResponse::Created().json(body))
+}
+
+/// Handler for listing registered webhooks (without their secrets).
+pub async fn list_webhooks_handler(pool: web::Data<DbPool>) -> Result<HttpResponse, AppError> {
+    let hooks = web::block(move || {
+        use crate::schema::webhooks::dsl::*;
+        let mut conn = pool.get()?;
+        webhooks
+            .order(id.asc())
+            .load::<Webhook>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+    let body: Vec<serde_json::Value> = hooks.iter().map(Webhook::to_json).collect();
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Handler for removing a webhook along with its delivery log.
+pub async fn delete_webhook_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let webhook_id = path.into_inner();
+    web::block(move || {
+        use crate::schema::{webhook_deliveries, webhooks};
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            diesel::delete(
+                webhook_deliveries::table.filter(webhook_deliveries::webhook_id.eq(webhook_id)),
+            )
+            .execute(conn)?;
+            match diesel::delete(webhooks::table.find(webhook_id)).execute(conn)? {
+                0 => Err(AppError::NotFound(format!("webhook {}", webhook_id))),
+                _ => Ok(()),
+            }
+        })
+    })
+    .await??;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize)]
+pub struct DeliveryQuery {
+    /// Only list deliveries in this state (pending, delivered or dead).
+    pub status: Option<String>,
+}
+
+/// Handler for the delivery log of a webhook, newest first.
+pub async fn list_deliveries_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+    query: web::Query<DeliveryQuery>,
+) -> Result<HttpResponse, AppError> {
+    let hook_id = path.into_inner();
+    let status_filter = query.into_inner().status;
+    let deliveries = web::block(move || {
+        use crate::schema::webhook_deliveries::dsl::*;
+        let mut conn = pool.get()?;
+        let mut query = webhook_deliveries
+            .filter(webhook_id.eq(hook_id))
+            .order(id.desc())
+            .limit(100)
+            .into_boxed();
+        if let Some(wanted) = status_filter {
+            query = query.filter(status.eq(wanted));
+        }
+        query
+            .load::<WebhookDelivery>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+/// Handler for re-queueing a delivery, typically one in the dead-letter state.
+pub async fn retry_delivery_handler(
+    pool: web::Data<DbPool>,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let delivery_id = path.into_inner();
+    let delivery = web::block(move || {
+        use crate::schema::webhook_deliveries::dsl::*;
+        let mut conn = pool.get()?;
+        let updated = diesel::update(webhook_deliveries.find(delivery_id))
+            .set((
+                status.eq(STATUS_PENDING),
+                attempts.eq(0),
+                next_attempt_at.eq(Utc::now().naive_utc()),
+            ))
+            .execute(&mut conn)?;
+        if updated == 0 {
+            return Err(AppError::NotFound(format!("delivery {}", delivery_id)));
+        }
+        webhook_deliveries
+            .find(delivery_id)
+            .first::<WebhookDelivery>(&mut conn)
+            .map_err(AppError::from)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(delivery))
+}
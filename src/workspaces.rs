@@ -0,0 +1,788 @@
+// src/workspaces.rs
+// Workspaces: groups of users sharing links, with role-based access.
+//
+// Members act on a workspace's links according to their role. Links outside
+// any workspace belong to their owner alone (see `crate::users`), and admins
+// bypass both. Workspace settings give the workspace's links a default code
+// length and expiry, and restrict the domains they may point to.
+
+use std::{fmt, ops::RangeInclusive};
+
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{self, Actor},
+    auth::Principal,
+    config::Config,
+    db::DbPool,
+    error::AppError,
+    models::{
+        NewWorkspace, NewWorkspaceMember, Url, UrlTag, User, Workspace, WorkspaceChanges,
+        WorkspaceMember,
+    },
+    preview::{domain_candidates, normalize_domain},
+    routing::{PlatformDestinations, SplitTest},
+    rules::Rule,
+    utils::deserialize_some,
+    webhooks,
+};
+
+/// Length of generated short codes outside workspaces with their own.
+pub const DEFAULT_CODE_LENGTH: usize = 7;
+
+/// Accepted values of `default_code_length`.
+pub const CODE_LENGTH_RANGE: RangeInclusive<i32> = 4..=32;
+
+/// Longest accepted workspace name, in bytes.
+pub const MAX_NAME_LENGTH: usize = 100;
+
+/// What a member may do in a workspace. Ordered from most to least
+/// powerful.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Everything, including settings and membership.
+    Owner,
+    /// Create, edit and delete links, and read their statistics.
+    Editor,
+    /// Read links and their statistics.
+    Analyst,
+    /// Read links.
+    Viewer,
+}
+
+/// An action on a workspace or its links, allowed to some roles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    ViewLinks,
+    EditLinks,
+    ViewStats,
+    ManageWorkspace,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Owner, Role::Editor, Role::Analyst, Role::Viewer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+            Role::Analyst => "analyst",
+            Role::Viewer => "viewer",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|role| role.as_str() == value)
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::ViewLinks => true,
+            Permission::ViewStats => *self != Role::Viewer,
+            Permission::EditLinks => matches!(self, Role::Owner | Role::Editor),
+            Permission::ManageWorkspace => *self == Role::Owner,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The role of `user` in `workspace`, if they are a member.
+pub fn role_in(
+    conn: &mut SqliteConnection,
+    workspace: i32,
+    user: Option<i32>,
+) -> QueryResult<Option<Role>> {
+    use crate::schema::workspace_members::dsl::*;
+
+    let Some(user) = user else {
+        return Ok(None);
+    };
+    let member = workspace_members
+        .find((workspace, user))
+        .first::<WorkspaceMember>(conn)
+        .optional()?;
+    Ok(member.and_then(|member| Role::parse(&member.role)))
+}
+
+/// Loads workspace `workspace_id` if the caller may act on it with
+/// `permission`. Workspaces the caller is not a member of are reported as
+/// missing.
+pub fn authorize_workspace(
+    conn: &mut SqliteConnection,
+    principal: &Principal,
+    workspace_id: i32,
+    permission: Permission,
+) -> Result<Workspace, AppError> {
+    use crate::schema::workspaces;
+
+    let not_found = || AppError::NotFound(format!("workspace {}", workspace_id));
+    let workspace = workspaces::table
+        .find(workspace_id)
+        .first::<Workspace>(conn)
+        .optional()?
+        .ok_or_else(not_found)?;
+    if principal.is_admin() {
+        return Ok(workspace);
+    }
+    match role_in(conn, workspace_id, principal.user_id)? {
+        Some(role) if role.allows(permission) => Ok(workspace),
+        Some(role) => Err(AppError::Forbidden(format!(
+            "the {} role does not allow this in workspace {}",
+            role, workspace_id
+        ))),
+        None => Err(not_found()),
+    }
+}
+
+/// Checks that the caller may act on `url_entry` with `permission`. Links
+/// the caller cannot see at all are reported as missing, so their short
+/// codes are not revealed.
+pub fn check_link_access(
+    conn: &mut SqliteConnection,
+    principal: &Principal,
+    url_entry: &Url,
+    permission: Permission,
+) -> Result<(), AppError> {
+    let not_found = || AppError::NotFound(format!("short code {}", url_entry.short_code));
+    if principal.is_admin() {
+        return Ok(());
+    }
+    match url_entry.workspace_id {
+        Some(workspace) => match role_in(conn, workspace, principal.user_id)? {
+            Some(role) if role.allows(permission) => Ok(()),
+            Some(role) => Err(AppError::Forbidden(format!(
+                "the {} role does not allow this in workspace {}",
+                role, workspace
+            ))),
+            None => Err(not_found()),
+        },
+        // Keys not tied to a user act on the links without an owner.
+        None if url_entry.owner_id == principal.user_id => Ok(()),
+        None => Err(not_found()),
+    }
+}
+
+/// Loads the link with `code` if the caller may act on it with
+/// `permission`.
+pub fn authorize_url(
+    conn: &mut SqliteConnection,
+    principal: &Principal,
+    code: &str,
+    permission: Permission,
+) -> Result<Url, AppError> {
+    use crate::schema::urls;
+
+    let url_entry = urls::table
+        .filter(urls::short_code.eq(code))
+        .first::<Url>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::NotFound(format!("short code {}", code)))?;
+    check_link_access(conn, principal, &url_entry, permission)?;
+    Ok(url_entry)
+}
+
+/// The links a caller may act on with some permission: their own links
+/// outside workspaces, and every link of the listed workspaces.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkVisibility {
+    pub user_id: Option<i32>,
+    pub workspace_ids: Vec<i32>,
+}
+
+impl LinkVisibility {
+    pub fn allows(&self, owner_id: Option<i32>, workspace_id: Option<i32>) -> bool {
+        match workspace_id {
+            Some(workspace) => self.workspace_ids.contains(&workspace),
+            None => owner_id == self.user_id,
+        }
+    }
+}
+
+/// The links the caller may act on with `permission`; `None` for admins,
+/// who may act on every link.
+pub fn visible_links(
+    conn: &mut SqliteConnection,
+    principal: &Principal,
+    permission: Permission,
+) -> QueryResult<Option<LinkVisibility>> {
+    use crate::schema::workspace_members::dsl::*;
+
+    if principal.is_admin() {
+        return Ok(None);
+    }
+    let memberships = match principal.user_id {
+        Some(user) => workspace_members
+            .filter(user_id.eq(user))
+            .load::<WorkspaceMember>(conn)?,
+        None => Vec::new(),
+    };
+    Ok(Some(LinkVisibility {
+        user_id: principal.user_id,
+        workspace_ids: memberships
+            .iter()
+            .filter(|member| {
+                Role::parse(&member.role).is_some_and(|member_role| member_role.allows(permission))
+            })
+            .map(|member| member.workspace_id)
+            .collect(),
+    }))
+}
+
+/// Every destination a link may send visitors to.
+pub fn link_destinations(
+    original_url: &str,
+    platform_destinations: Option<&PlatformDestinations>,
+    split: Option<&SplitTest>,
+    rules: &[Rule],
+    fallback_url: Option<&str>,
+) -> Vec<String> {
+    let mut destinations = vec![original_url.to_string()];
+    if let Some(platforms) = platform_destinations {
+        destinations.extend(
+            [&platforms.ios, &platforms.android, &platforms.desktop]
+                .into_iter()
+                .flatten()
+                .cloned(),
+        );
+    }
+    if let Some(split) = split {
+        destinations.extend(split.variants.iter().map(|variant| variant.url.clone()));
+    }
+    destinations.extend(rules.iter().map(|rule| rule.destination.clone()));
+    destinations.extend(fallback_url.map(str::to_string));
+    destinations
+}
+
+impl Url {
+    pub fn destinations(&self) -> Vec<String> {
+        link_destinations(
+            &self.original_url,
+            self.platform_destinations().as_ref(),
+            self.split().as_ref(),
+            &self.rules(),
+            self.fallback_url.as_deref(),
+        )
+    }
+}
+
+impl Workspace {
+    pub fn allowed_domain_list(&self) -> Vec<String> {
+        self.allowed_domains
+            .as_deref()
+            .and_then(|domains| serde_json::from_str(domains).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn code_length(&self) -> usize {
+        self.default_code_length
+            .and_then(|length| usize::try_from(length).ok())
+            .unwrap_or(DEFAULT_CODE_LENGTH)
+    }
+
+    /// Expiration date of a link created at `now` without one.
+    pub fn default_expiration(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.default_expiry_secs
+            .and_then(Duration::try_seconds)
+            .map(|expiry| now + expiry)
+    }
+
+    /// Checks that every destination is on an allowed domain or one of its
+    /// subdomains. Any destination passes when no domains are set.
+    pub fn check_destinations(&self, destinations: &[String]) -> Result<(), String> {
+        let allowed = self.allowed_domain_list();
+        if allowed.is_empty() {
+            return Ok(());
+        }
+        match destinations.iter().find(|destination| {
+            !domain_candidates(destination)
+                .iter()
+                .any(|candidate| allowed.contains(candidate))
+        }) {
+            Some(destination) => Err(format!(
+                "{} is not on a domain allowed in workspace {}",
+                destination, self.name
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "default_code_length": self.default_code_length,
+            "default_expiry_secs": self.default_expiry_secs,
+            "allowed_domains": self.allowed_domain_list(),
+            "created_at": self.created_at
+        })
+    }
+}
+
+fn validate_name(name: &str) -> Result<String, AppError> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(AppError::InvalidInput(format!(
+            "name must be 1 to {} bytes",
+            MAX_NAME_LENGTH
+        )));
+    }
+    Ok(name.to_string())
+}
+
+fn validate_code_length(length: i32) -> Result<i32, AppError> {
+    if !CODE_LENGTH_RANGE.contains(&length) {
+        return Err(AppError::InvalidInput(format!(
+            "default_code_length must be between {} and {}",
+            CODE_LENGTH_RANGE.start(),
+            CODE_LENGTH_RANGE.end()
+        )));
+    }
+    Ok(length)
+}
+
+fn validate_expiry(secs: i64) -> Result<i64, AppError> {
+    if secs < 1 || Duration::try_seconds(secs).is_none() {
+        return Err(AppError::InvalidInput(
+            "default_expiry_secs must be a positive number of seconds".to_string(),
+        ));
+    }
+    Ok(secs)
+}
+
+/// Normalizes the allowed domains and encodes them for storage. An empty
+/// list allows any domain, like no list.
+fn encode_domains(domains: &[String]) -> Result<Option<String>, AppError> {
+    let mut normalized = domains
+        .iter()
+        .map(|domain| normalize_domain(domain))
+        .collect::<Result<Vec<_>, _>>()?;
+    normalized.sort();
+    normalized.dedup();
+    Ok((!normalized.is_empty()).then(|| serde_json::to_string(&normalized).unwrap_or_default()))
+}
+
+fn name_taken(conn: &mut SqliteConnection, workspace_name: &str, except: i32) -> QueryResult<bool> {
+    use crate::schema::workspaces::dsl::*;
+    workspaces
+        .filter(name.eq(workspace_name))
+        .filter(id.ne(except))
+        .first::<Workspace>(conn)
+        .optional()
+        .map(|found| found.is_some())
+}
+
+fn owner_count(conn: &mut SqliteConnection, workspace: i32) -> QueryResult<i64> {
+    use crate::schema::workspace_members::dsl::*;
+    workspace_members
+        .filter(workspace_id.eq(workspace))
+        .filter(role.eq(Role::Owner.as_str()))
+        .count()
+        .get_result(conn)
+}
+
+/// Workspace JSON with the caller's role in it, if any.
+fn workspace_json(workspace: &Workspace, role: Option<Role>) -> serde_json::Value {
+    let mut body = workspace.to_json();
+    body["role"] = serde_json::json!(role);
+    body
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateWorkspaceRequest {
+    pub name: String,
+    /// Length of short codes generated for the workspace's links.
+    pub default_code_length: Option<i32>,
+    /// Links created without an expiration date expire this many seconds
+    /// after creation.
+    pub default_expiry_secs: Option<i64>,
+    /// Domains the workspace's links may point to, subdomains included.
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+}
+
+/// Handler for creating a workspace. The caller's user becomes its owner.
+pub async fn create_workspace_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    item: web::Json<CreateWorkspaceRequest>,
+) -> Result<HttpResponse, AppError> {
+    let item = item.into_inner();
+    if principal.user_id.is_none() && !principal.is_admin() {
+        return Err(AppError::Forbidden(
+            "workspaces can only be created with an API key tied to a user".to_string(),
+        ));
+    }
+    let new_workspace = NewWorkspace {
+        name: validate_name(&item.name)?,
+        default_code_length: item
+            .default_code_length
+            .map(validate_code_length)
+            .transpose()?,
+        default_expiry_secs: item.default_expiry_secs.map(validate_expiry).transpose()?,
+        allowed_domains: encode_domains(&item.allowed_domains)?,
+    };
+
+    let (workspace, role) = web::block(move || {
+        use crate::schema::{workspace_members, workspaces};
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            if name_taken(conn, &new_workspace.name, 0)? {
+                return Err(AppError::InvalidInput(format!(
+                    "workspace name {} is taken",
+                    new_workspace.name
+                )));
+            }
+            diesel::insert_into(workspaces::table)
+                .values(&new_workspace)
+                .execute(conn)?;
+            let workspace = workspaces::table
+                .filter(workspaces::name.eq(&new_workspace.name))
+                .first::<Workspace>(conn)?;
+            let role = match principal.user_id {
+                Some(user) => {
+                    diesel::insert_into(workspace_members::table)
+                        .values(&NewWorkspaceMember {
+                            workspace_id: workspace.id,
+                            user_id: user,
+                            role: Role::Owner.as_str().to_string(),
+                        })
+                        .execute(conn)?;
+                    Some(Role::Owner)
+                },
+                None => None,
+            };
+            Ok((workspace, role))
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Created().json(workspace_json(&workspace, role)))
+}
+
+/// Handler for listing the caller's workspaces, or all of them for admins.
+pub async fn list_workspaces_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+) -> Result<HttpResponse, AppError> {
+    let body = web::block(move || {
+        use crate::schema::{workspace_members, workspaces};
+        let mut conn = pool.get()?;
+        let memberships = match principal.user_id {
+            Some(user) => workspace_members::table
+                .filter(workspace_members::user_id.eq(user))
+                .load::<WorkspaceMember>(&mut conn)?,
+            None => Vec::new(),
+        };
+        let mut query = workspaces::table.order(workspaces::id.asc()).into_boxed();
+        if !principal.is_admin() {
+            let ids: Vec<i32> = memberships
+                .iter()
+                .map(|member| member.workspace_id)
+                .collect();
+            query = query.filter(workspaces::id.eq_any(ids));
+        }
+        let rows = query.load::<Workspace>(&mut conn)?;
+        Ok::<_, AppError>(
+            rows.iter()
+                .map(|workspace| {
+                    let role = memberships
+                        .iter()
+                        .find(|member| member.workspace_id == workspace.id)
+                        .and_then(|member| Role::parse(&member.role));
+                    workspace_json(workspace, role)
+                })
+                .collect::<Vec<_>>(),
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Handler for a single workspace.
+pub async fn get_workspace_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let workspace_id = path.into_inner();
+    let body = web::block(move || {
+        let mut conn = pool.get()?;
+        let workspace =
+            authorize_workspace(&mut conn, &principal, workspace_id, Permission::ViewLinks)?;
+        let role = role_in(&mut conn, workspace_id, principal.user_id)?;
+        Ok::<_, AppError>(workspace_json(&workspace, role))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(body))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateWorkspaceRequest {
+    pub name: Option<String>,
+    /// Code length, or `null` for the server default.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub default_code_length: Option<Option<i32>>,
+    /// Expiry in seconds, or `null` for none.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub default_expiry_secs: Option<Option<i64>>,
+    /// Replaces the allowed domains; `null` or `[]` allows any.
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub allowed_domains: Option<Option<Vec<String>>>,
+}
+
+/// Handler for changing a workspace's name or settings. Owners only.
+/// Settings apply to links created or edited afterwards.
+pub async fn update_workspace_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    path: web::Path<i32>,
+    item: web::Json<UpdateWorkspaceRequest>,
+) -> Result<HttpResponse, AppError> {
+    let workspace_id = path.into_inner();
+    let item = item.into_inner();
+    let changes = WorkspaceChanges {
+        name: item.name.as_deref().map(validate_name).transpose()?,
+        default_code_length: item
+            .default_code_length
+            .map(|length| length.map(validate_code_length).transpose())
+            .transpose()?,
+        default_expiry_secs: item
+            .default_expiry_secs
+            .map(|secs| secs.map(validate_expiry).transpose())
+            .transpose()?,
+        allowed_domains: item
+            .allowed_domains
+            .map(|domains| encode_domains(&domains.unwrap_or_default()))
+            .transpose()?,
+    };
+
+    let body = web::block(move || {
+        use crate::schema::workspaces;
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            authorize_workspace(conn, &principal, workspace_id, Permission::ManageWorkspace)?;
+            if let Some(new_name) = &changes.name {
+                if name_taken(conn, new_name, workspace_id)? {
+                    return Err(AppError::InvalidInput(format!(
+                        "workspace name {} is taken",
+                        new_name
+                    )));
+                }
+            }
+            // Diesel rejects an update without any column to set.
+            if changes != WorkspaceChanges::default() {
+                diesel::update(workspaces::table.find(workspace_id))
+                    .set(&changes)
+                    .execute(conn)?;
+            }
+            let workspace = workspaces::table
+                .find(workspace_id)
+                .first::<Workspace>(conn)?;
+            let role = role_in(conn, workspace_id, principal.user_id)?;
+            Ok(workspace_json(&workspace, role))
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Handler for deleting a workspace. Owners only. Its links stay, as
+/// personal links of the users who own them; each is announced and audited
+/// like a transfer.
+pub async fn delete_workspace_handler(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    principal: Principal,
+    actor: Actor,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    use crate::schema::{urls, workspace_members, workspaces};
+
+    let workspace_id = path.into_inner();
+    let base_url = config.base_url.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            authorize_workspace(conn, &principal, workspace_id, Permission::ManageWorkspace)?;
+            let detached = urls::table
+                .filter(urls::workspace_id.eq(workspace_id))
+                .load::<Url>(conn)?;
+            diesel::update(urls::table.filter(urls::workspace_id.eq(workspace_id)))
+                .set(urls::workspace_id.eq(None::<i32>))
+                .execute(conn)?;
+            for url_entry in detached {
+                let tags = UrlTag::for_url(conn, url_entry.id)?;
+                let updated = urls::table.find(url_entry.id).first::<Url>(conn)?;
+                let body = updated.to_json(&tags, &base_url);
+                webhooks::enqueue(conn, webhooks::LINK_UPDATED, &body)?;
+                actor
+                    .entry(audit::LINK_TRANSFERRED)
+                    .link(&updated)
+                    .before(&url_entry.to_json(&tags, &base_url))
+                    .after(&body)
+                    .record(conn)?;
+            }
+            diesel::delete(
+                workspace_members::table.filter(workspace_members::workspace_id.eq(workspace_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(workspaces::table.find(workspace_id)).execute(conn)?;
+            Ok::<_, AppError>(())
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Handler for listing the members of a workspace.
+pub async fn list_members_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    path: web::Path<i32>,
+) -> Result<HttpResponse, AppError> {
+    let workspace_id = path.into_inner();
+    let body = web::block(move || {
+        use crate::schema::{users, workspace_members};
+        let mut conn = pool.get()?;
+        authorize_workspace(&mut conn, &principal, workspace_id, Permission::ViewLinks)?;
+        let members = workspace_members::table
+            .inner_join(users::table.on(users::id.eq(workspace_members::user_id)))
+            .filter(workspace_members::workspace_id.eq(workspace_id))
+            .order(users::username.asc())
+            .load::<(WorkspaceMember, User)>(&mut conn)?;
+        Ok::<_, AppError>(
+            members
+                .iter()
+                .map(|(member, user)| {
+                    serde_json::json!({
+                        "user_id": member.user_id,
+                        "username": user.username,
+                        "role": member.role,
+                        "created_at": member.created_at
+                    })
+                })
+                .collect::<Vec<_>>(),
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(body))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemberRequest {
+    pub role: String,
+}
+
+/// Handler for adding a member or changing their role. Owners only.
+pub async fn put_member_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    path: web::Path<(i32, i32)>,
+    item: web::Json<MemberRequest>,
+) -> Result<HttpResponse, AppError> {
+    let (workspace_id, member_id) = path.into_inner();
+    let new_role = item.into_inner().role;
+    let new_role = Role::parse(new_role.trim()).ok_or_else(|| {
+        AppError::InvalidInput(format!(
+            "unknown role '{}', expected owner, editor, analyst or viewer",
+            new_role
+        ))
+    })?;
+
+    let member = web::block(move || {
+        use crate::schema::{users, workspace_members};
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            authorize_workspace(conn, &principal, workspace_id, Permission::ManageWorkspace)?;
+            users::table
+                .find(member_id)
+                .first::<User>(conn)
+                .optional()?
+                .ok_or_else(|| {
+                    AppError::InvalidInput(format!("user {} does not exist", member_id))
+                })?;
+            let current = role_in(conn, workspace_id, Some(member_id))?;
+            if current == Some(Role::Owner)
+                && new_role != Role::Owner
+                && owner_count(conn, workspace_id)? == 1
+            {
+                return Err(AppError::InvalidInput(
+                    "a workspace needs at least one owner".to_string(),
+                ));
+            }
+            diesel::insert_into(workspace_members::table)
+                .values(&NewWorkspaceMember {
+                    workspace_id,
+                    user_id: member_id,
+                    role: new_role.as_str().to_string(),
+                })
+                .on_conflict((workspace_members::workspace_id, workspace_members::user_id))
+                .do_update()
+                .set(workspace_members::role.eq(new_role.as_str()))
+                .execute(conn)?;
+            workspace_members::table
+                .find((workspace_id, member_id))
+                .first::<WorkspaceMember>(conn)
+                .map_err(AppError::from)
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(member))
+}
+
+/// Handler for removing a member. Owners may remove anyone, and members may
+/// remove themselves, as long as an owner remains.
+pub async fn delete_member_handler(
+    pool: web::Data<DbPool>,
+    principal: Principal,
+    path: web::Path<(i32, i32)>,
+) -> Result<HttpResponse, AppError> {
+    let (workspace_id, member_id) = path.into_inner();
+    web::block(move || {
+        use crate::schema::workspace_members;
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| {
+            let permission = if principal.user_id == Some(member_id) {
+                Permission::ViewLinks
+            } else {
+                Permission::ManageWorkspace
+            };
+            authorize_workspace(conn, &principal, workspace_id, permission)?;
+            let Some(current) = role_in(conn, workspace_id, Some(member_id))? else {
+                return Err(AppError::NotFound(format!(
+                    "member {} of workspace {}",
+                    member_id, workspace_id
+                )));
+            };
+            if current == Role::Owner && owner_count(conn, workspace_id)? == 1 {
+                return Err(AppError::InvalidInput(
+                    "a workspace needs at least one owner".to_string(),
+                ));
+            }
+            diesel::delete(workspace_members::table.find((workspace_id, member_id)))
+                .execute(conn)?;
+            Ok(())
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::NoContent().finish())
+}
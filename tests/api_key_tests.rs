@@ -0,0 +1,210 @@
+mod common;
+
+use serde_json::json;
+
+fn create_key(app: &common::TestApp, body: serde_json::Value) -> serde_json::Value {
+    let response = app
+        .client()
+        .post(app.url("/api/keys"))
+        .json(&body)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    response.json().unwrap()
+}
+
+#[test]
+fn test_management_routes_require_a_key_but_redirects_do_not() {
+    let app = common::spawn_app();
+    let created = app.create_url("https://example.com/public");
+    let code = created["short_code"].as_str().unwrap();
+    let anonymous = app.anonymous_client();
+
+    let response = anonymous
+        .post(app.url("/"))
+        .json(&json!({ "original_url": "https://example.com" }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+
+    assert_eq!(anonymous.get(app.url("/")).send().unwrap().status(), 401);
+    let stats = anonymous
+        .get(app.url(&format!("/stats/{}", code)))
+        .send()
+        .unwrap();
+    assert_eq!(stats.status(), 401);
+    let invalid = app
+        .client_with_key("usk_AAAAAAAA_BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB")
+        .get(app.url("/"))
+        .send()
+        .unwrap();
+    assert_eq!(invalid.status(), 401);
+
+    let redirect = anonymous
+        .get(app.url(&format!("/{}", code)))
+        .send()
+        .unwrap();
+    assert_eq!(redirect.status(), 302);
+    assert_eq!(
+        anonymous.get(app.url("/health")).send().unwrap().status(),
+        200
+    );
+
+    // The key can also be sent in the X-API-Key header
+    let listed = anonymous
+        .get(app.url("/"))
+        .header("X-API-Key", &app.api_key)
+        .send()
+        .unwrap();
+    assert_eq!(listed.status(), 200);
+}
+
+#[test]
+fn test_key_is_shown_once_and_scopes_are_enforced() {
+    let app = common::spawn_app();
+    let created = create_key(
+        &app,
+        json!({ "name": "dashboard", "scopes": ["links:read"] }),
+    );
+    let key = created["key"].as_str().unwrap();
+    assert!(key.starts_with("usk_"));
+    assert_eq!(created["scopes"], json!(["links:read"]));
+    assert_eq!(created["status"], "active");
+
+    let listed: serde_json::Value = app
+        .client()
+        .get(app.url("/api/keys"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let entry = listed
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["id"] == created["id"])
+        .unwrap();
+    assert!(entry.get("key").is_none());
+    assert!(!listed.to_string().contains(key));
+
+    let reader = app.client_with_key(key);
+    assert_eq!(reader.get(app.url("/")).send().unwrap().status(), 200);
+    let write = reader
+        .post(app.url("/"))
+        .json(&json!({ "original_url": "https://example.com" }))
+        .send()
+        .unwrap();
+    assert_eq!(write.status(), 403);
+    assert_eq!(
+        reader.get(app.url("/api/keys")).send().unwrap().status(),
+        403
+    );
+
+    let invalid = app
+        .client()
+        .post(app.url("/api/keys"))
+        .json(&json!({ "name": "bad", "scopes": ["links:delete"] }))
+        .send()
+        .unwrap();
+    assert_eq!(invalid.status(), 400);
+}
+
+#[test]
+fn test_rotation_and_revocation() {
+    let app = common::spawn_app();
+    let created = create_key(&app, json!({ "name": "ci", "scopes": ["links:read"] }));
+    let old_key = created["key"].as_str().unwrap().to_string();
+    let id = created["id"].as_i64().unwrap();
+
+    let rotated: serde_json::Value = app
+        .client()
+        .post(app.url(&format!("/api/keys/{}/rotate", id)))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let new_key = rotated["key"].as_str().unwrap().to_string();
+    assert_ne!(new_key, old_key);
+    assert_eq!(rotated["name"], "ci");
+    assert_eq!(rotated["scopes"], json!(["links:read"]));
+
+    let status = |key: &str| {
+        app.client_with_key(key)
+            .get(app.url("/"))
+            .send()
+            .unwrap()
+            .status()
+    };
+    assert_eq!(status(&old_key), 401);
+    assert_eq!(status(&new_key), 200);
+
+    // With a grace period the old key keeps working for a while
+    let graced: serde_json::Value = app
+        .client()
+        .post(app.url(&format!("/api/keys/{}/rotate", rotated["id"])))
+        .json(&json!({ "grace_period_secs": 3600 }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(status(&new_key), 200);
+    assert_eq!(status(graced["key"].as_str().unwrap()), 200);
+
+    let revoked = app
+        .client()
+        .delete(app.url(&format!("/api/keys/{}", rotated["id"])))
+        .send()
+        .unwrap();
+    assert_eq!(revoked.status(), 204);
+    assert_eq!(status(&new_key), 401);
+
+    let missing = app
+        .client()
+        .delete(app.url("/api/keys/9999"))
+        .send()
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+}
+
+#[test]
+fn test_expired_key_is_rejected() {
+    let app = common::spawn_app();
+    let past = app
+        .client()
+        .post(app.url("/api/keys"))
+        .json(&json!({ "name": "old", "scopes": ["admin"], "expires_at": "2020-01-01T00:00:00Z" }))
+        .send()
+        .unwrap();
+    assert_eq!(past.status(), 400);
+
+    let created = create_key(&app, json!({ "name": "short-lived", "scopes": ["admin"] }));
+    let key = created["key"].as_str().unwrap();
+    {
+        use diesel::prelude::*;
+        use rust_url_shortener::schema::api_keys::dsl::*;
+        let yesterday = chrono::Utc::now().naive_utc() - chrono::Duration::days(1);
+        diesel::update(api_keys.find(created["id"].as_i64().unwrap() as i32))
+            .set(expires_at.eq(yesterday))
+            .execute(&mut app.pool.get().unwrap())
+            .unwrap();
+    }
+
+    let response = app.client_with_key(key).get(app.url("/")).send().unwrap();
+    assert_eq!(response.status(), 401);
+
+    let listed: serde_json::Value = app
+        .client()
+        .get(app.url("/api/keys"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let entry = listed
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["id"] == created["id"])
+        .unwrap();
+    assert_eq!(entry["status"], "expired");
+}
@@ -0,0 +1,282 @@
+mod common;
+
+use serde_json::{json, Value};
+
+fn code(link: &Value) -> String {
+    link["short_code"].as_str().unwrap().to_string()
+}
+
+fn audit(app: &common::TestApp, query: &str) -> Value {
+    let response = app
+        .client()
+        .get(app.url(&format!("/api/audit{}", query)))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    response.json().unwrap()
+}
+
+/// Creates a user and a key acting for them, returning the user id and a
+/// client sending that key.
+fn user_client(app: &common::TestApp, username: &str) -> (i64, reqwest::blocking::Client) {
+    let user: Value = app
+        .client()
+        .post(app.url("/api/users"))
+        .json(&json!({ "username": username }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let user_id = user["id"].as_i64().unwrap();
+    let key: Value = app
+        .client()
+        .post(app.url("/api/keys"))
+        .json(&json!({
+            "name": username,
+            "scopes": ["links:read", "links:write"],
+            "user_id": user_id
+        }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    (user_id, app.client_with_key(key["key"].as_str().unwrap()))
+}
+
+fn actions(page: &Value) -> Vec<&str> {
+    page["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn test_link_changes_are_recorded_with_before_and_after() {
+    let app = common::spawn_app();
+    let link = app.create_url("https://example.com/before");
+    let edited = app
+        .client()
+        .patch(app.url(&format!("/api/urls/{}", code(&link))))
+        // Not believed from a client that is not a trusted proxy
+        .header("x-forwarded-for", "203.0.113.7")
+        .json(&json!({ "original_url": "https://example.com/after" }))
+        .send()
+        .unwrap();
+    assert_eq!(edited.status(), 200);
+    let deleted = app
+        .client()
+        .delete(app.url(&format!("/api/urls/{}", code(&link))))
+        .send()
+        .unwrap();
+    assert_eq!(deleted.status(), 204);
+
+    // The log outlives the link
+    let page = audit(&app, &format!("?code={}", code(&link)));
+    assert_eq!(page["total"], 3);
+    assert_eq!(
+        actions(&page),
+        ["link.deleted", "link.updated", "link.created"]
+    );
+    let entries = page["entries"].as_array().unwrap();
+    let updated = &entries[1];
+    assert_eq!(updated["actor"], "tests");
+    assert_eq!(updated["ip_address"], "127.0.0.1");
+    assert_eq!(updated["short_code"], code(&link));
+    assert_eq!(
+        updated["before"]["original_url"],
+        "https://example.com/before"
+    );
+    assert_eq!(
+        updated["after"]["original_url"],
+        "https://example.com/after"
+    );
+    assert!(updated["created_at"].is_string());
+    assert_eq!(
+        entries[0]["before"]["original_url"],
+        "https://example.com/after"
+    );
+    assert!(entries[0]["after"].is_null());
+    assert!(entries[2]["before"].is_null());
+    assert_eq!(entries[2]["after"]["short_code"], code(&link));
+}
+
+#[test]
+fn test_moderation_and_exports_are_recorded() {
+    let app = common::spawn_app();
+    let link = app.create_url("https://example.com/abuse");
+    app.client()
+        .post(app.url(&format!("/api/urls/{}/disable", code(&link))))
+        .json(&json!({ "reason": "Malware" }))
+        .send()
+        .unwrap();
+    let export = app
+        .client()
+        .get(app.url(&format!(
+            "/api/export/clicks?format=ndjson&code={}",
+            code(&link)
+        )))
+        .send()
+        .unwrap();
+    assert_eq!(export.status(), 200);
+
+    let disabled = audit(&app, "?action=link.disabled");
+    assert_eq!(disabled["total"], 1);
+    let entry = &disabled["entries"][0];
+    assert!(entry["before"]["disabled_at"].is_null());
+    assert_eq!(entry["after"]["disabled_reason"], "Malware");
+
+    let exported = audit(&app, "?action=clicks.exported");
+    assert_eq!(exported["total"], 1);
+    assert_eq!(exported["entries"][0]["short_code"], code(&link));
+    assert_eq!(exported["entries"][0]["after"]["format"], "ndjson");
+}
+
+#[test]
+fn test_bulk_link_changes_are_recorded_per_link() {
+    let app = common::spawn_app();
+    let (alice_id, alice) = user_client(&app, "alice");
+    let (bob_id, _) = user_client(&app, "bob");
+    let registered = app
+        .client()
+        .post(app.url("/api/webhooks"))
+        .json(&json!({ "target_url": "http://127.0.0.1:9/hook", "events": ["link.updated"] }))
+        .send()
+        .unwrap();
+    assert_eq!(registered.status(), 201);
+    let workspace: Value = alice
+        .post(app.url("/api/workspaces"))
+        .json(&json!({ "name": "Marketing" }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let link: Value = alice
+        .post(app.url("/"))
+        .json(&json!({
+            "original_url": "https://example.com/campaign",
+            "workspace_id": workspace["id"]
+        }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+
+    // Deleting the workspace detaches its links
+    let deleted = alice
+        .delete(app.url(&format!("/api/workspaces/{}", workspace["id"])))
+        .send()
+        .unwrap();
+    assert_eq!(deleted.status(), 204);
+    let detached = audit(
+        &app,
+        &format!("?code={}&action=link.transferred", code(&link)),
+    );
+    assert_eq!(detached["total"], 1);
+    let entry = &detached["entries"][0];
+    assert_eq!(entry["actor"], "alice");
+    assert_eq!(entry["before"]["workspace_id"], workspace["id"]);
+    assert!(entry["after"]["workspace_id"].is_null());
+
+    // Deleting a user hands their links over
+    let deleted = app
+        .client()
+        .delete(app.url(&format!("/api/users/{}?transfer_to={}", alice_id, bob_id)))
+        .send()
+        .unwrap();
+    assert_eq!(deleted.status(), 204);
+    let moved = audit(
+        &app,
+        &format!("?code={}&action=link.transferred", code(&link)),
+    );
+    assert_eq!(moved["total"], 2);
+    let entry = &moved["entries"][0];
+    assert_eq!(entry["actor"], "tests");
+    assert_eq!(entry["before"]["owner_id"], alice_id);
+    assert_eq!(entry["after"]["owner_id"], bob_id);
+
+    // Subscribers hear about both changes
+    use diesel::prelude::*;
+    use rust_url_shortener::schema::webhook_deliveries::dsl::*;
+    let payloads: Vec<String> = webhook_deliveries
+        .filter(event_type.eq("link.updated"))
+        .order(id.asc())
+        .select(payload)
+        .load(&mut app.pool.get().unwrap())
+        .unwrap();
+    assert_eq!(payloads.len(), 2);
+    let first: Value = serde_json::from_str(&payloads[0]).unwrap();
+    assert_eq!(first["data"]["short_code"], code(&link));
+    assert!(first["data"]["workspace_id"].is_null());
+    let second: Value = serde_json::from_str(&payloads[1]).unwrap();
+    assert_eq!(second["data"]["owner_id"], bob_id);
+}
+
+#[test]
+fn test_audit_log_is_filtered_and_paginated() {
+    let app = common::spawn_app();
+    for index in 0..5 {
+        app.create_url(&format!("https://example.com/{}", index));
+    }
+
+    let first = audit(&app, "?per_page=2");
+    assert_eq!(first["total"], 5);
+    assert_eq!(first["page"], 1);
+    assert_eq!(first["entries"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        first["entries"][0]["after"]["original_url"],
+        "https://example.com/4"
+    );
+    let last = audit(&app, "?per_page=2&page=3");
+    assert_eq!(last["entries"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        last["entries"][0]["after"]["original_url"],
+        "https://example.com/0"
+    );
+
+    assert_eq!(audit(&app, "?actor=someone-else")["total"], 0);
+    assert_eq!(audit(&app, "?actor=tests&action=link.created")["total"], 5);
+    assert_eq!(audit(&app, "?to=2000-01-01T00:00:00Z")["total"], 0);
+    assert_eq!(audit(&app, "?from=2000-01-01T00:00:00Z")["total"], 5);
+
+    for query in [
+        "?from=yesterday",
+        "?page=0",
+        "?per_page=501",
+        "?page=9223372036854775807",
+    ] {
+        let response = app
+            .client()
+            .get(app.url(&format!("/api/audit{}", query)))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 400, "{}", query);
+    }
+    assert_eq!(
+        app.anonymous_client()
+            .get(app.url("/api/audit"))
+            .send()
+            .unwrap()
+            .status(),
+        401
+    );
+}
+
+#[test]
+fn test_audit_log_is_append_only() {
+    use diesel::prelude::*;
+    use rust_url_shortener::schema::audit_log::dsl::*;
+
+    let app = common::spawn_app();
+    app.create_url("https://example.com/");
+    let mut conn = app.pool.get().unwrap();
+
+    let tampered = diesel::update(audit_log)
+        .set(actor.eq("someone-else"))
+        .execute(&mut conn);
+    assert!(tampered.unwrap_err().to_string().contains("append-only"));
+    let erased = diesel::delete(audit_log).execute(&mut conn);
+    assert!(erased.unwrap_err().to_string().contains("append-only"));
+    assert_eq!(audit(&app, "?actor=tests")["total"], 1);
+}
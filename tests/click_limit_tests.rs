@@ -0,0 +1,93 @@
+mod common;
+
+use std::thread;
+
+use serde_json::json;
+
+fn visit(app: &common::TestApp, code: &str) -> reqwest::blocking::Response {
+    app.client()
+        .get(app.url(&format!("/{}", code)))
+        .send()
+        .unwrap()
+}
+
+#[test]
+fn test_one_time_links_redirect_once() {
+    let app = common::spawn_app();
+    let link = app.create_url_with(json!({
+        "original_url": "https://example.com/download",
+        "max_clicks": 1
+    }));
+    assert_eq!(link["max_clicks"], 1);
+    assert_eq!(link["remaining_clicks"], 1);
+    let code = link["short_code"].as_str().unwrap();
+
+    let first = visit(&app, code);
+    assert_eq!(first.status(), 302);
+    assert_eq!(first.headers()["location"], "https://example.com/download");
+    assert_eq!(first.headers()["cache-control"], "no-store");
+
+    let second = visit(&app, code);
+    assert_eq!(second.status(), 410);
+    let body: serde_json::Value = second.json().unwrap();
+    assert_eq!(body["error"], "This link has reached its click limit");
+}
+
+#[test]
+fn test_concurrent_visits_never_exceed_the_limit() {
+    let app = common::spawn_app();
+    let code = app.create_url_with(json!({
+        "original_url": "https://example.com/download",
+        "max_clicks": 3
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let statuses: Vec<u16> = thread::scope(|scope| {
+        let visits: Vec<_> = (0..10)
+            .map(|_| scope.spawn(|| visit(&app, &code).status().as_u16()))
+            .collect();
+        visits.into_iter().map(|v| v.join().unwrap()).collect()
+    });
+    assert_eq!(statuses.iter().filter(|&&s| s == 302).count(), 3);
+    assert_eq!(statuses.iter().filter(|&&s| s == 410).count(), 7);
+}
+
+#[test]
+fn test_max_clicks_must_be_positive() {
+    let app = common::spawn_app();
+    let response = app
+        .client()
+        .post(app.url("/"))
+        .json(&json!({ "original_url": "https://example.com", "max_clicks": 0 }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[test]
+fn test_raising_the_limit_reenables_a_used_up_link() {
+    let app = common::spawn_app();
+    let code = app.create_url_with(json!({
+        "original_url": "https://example.com/download",
+        "max_clicks": 1
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(visit(&app, &code).status(), 302);
+    assert_eq!(visit(&app, &code).status(), 410);
+
+    let updated: serde_json::Value = app
+        .client()
+        .patch(app.url(&format!("/api/urls/{}", code)))
+        .json(&json!({ "max_clicks": 2 }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(updated["remaining_clicks"], 1);
+    assert_eq!(visit(&app, &code).status(), 302);
+    assert_eq!(visit(&app, &code).status(), 410);
+}
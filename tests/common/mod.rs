@@ -0,0 +1,145 @@
+// Shared helpers for the integration tests.
+//
+// Each test gets its own server on a random local port, backed by a fresh
+// SQLite database in a temporary directory.
+#![allow(dead_code)]
+
+use std::{net::TcpListener, thread};
+
+use reqwest::{
+    blocking::Client,
+    header::{HeaderMap, HeaderValue, AUTHORIZATION},
+};
+use rust_url_shortener::{
+    auth::{self, Scope},
+    config::Config,
+    db::{establish_connection_pool, run_migrations, DbPool},
+    server,
+};
+use serde_json::json;
+use tempfile::TempDir;
+
+pub struct TestApp {
+    pub address: String,
+    pub pool: DbPool,
+    /// Admin key sent by [`TestApp::client`].
+    pub api_key: String,
+    pub database_url: String,
+    /// Removed with the last instance using it; `None` for instances
+    /// started by [`spawn_app_sharing`].
+    _db_dir: Option<TempDir>,
+}
+
+/// Starts the application with the default configuration.
+pub fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {})
+}
+
+/// Starts the application after letting the caller adjust the configuration.
+pub fn spawn_app_with(configure: impl FnOnce(&mut Config)) -> TestApp {
+    let db_dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let database_url = db_dir.path().join("test.db").to_string_lossy().to_string();
+    start_app(database_url, Some(db_dir), configure)
+}
+
+/// Starts a second instance on the database of `other`, like another
+/// replica behind the same load balancer. `other` must outlive it.
+pub fn spawn_app_sharing(other: &TestApp, configure: impl FnOnce(&mut Config)) -> TestApp {
+    start_app(other.database_url.clone(), None, configure)
+}
+
+fn start_app(
+    database_url: String,
+    db_dir: Option<TempDir>,
+    configure: impl FnOnce(&mut Config),
+) -> TestApp {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
+    let address = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+
+    let mut config = Config {
+        database_url: database_url.clone(),
+        base_url: address.clone(),
+        ..Config::default()
+    };
+    configure(&mut config);
+
+    let pool = establish_connection_pool(&database_url);
+    run_migrations(&pool);
+    let (_, api_key) = auth::create_key(
+        &mut pool.get().unwrap(),
+        "tests",
+        &[Scope::Admin],
+        None,
+        None,
+    )
+    .expect("Failed to create API key");
+
+    let server_pool = pool.clone();
+    thread::spawn(move || {
+        actix_web::rt::System::new().block_on(async move {
+            server::run(listener, server_pool, config)
+                .expect("Failed to start server")
+                .await
+        })
+    });
+
+    TestApp {
+        address,
+        pool,
+        api_key,
+        database_url,
+        _db_dir: db_dir,
+    }
+}
+
+impl TestApp {
+    /// A client that does not follow redirects, so tests can inspect them.
+    /// It sends the admin API key with every request.
+    pub fn client(&self) -> Client {
+        self.client_with_key(&self.api_key)
+    }
+
+    /// Like [`TestApp::client`], but authenticating with `key`.
+    pub fn client_with_key(&self, key: &str) -> Client {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", key)).unwrap(),
+        );
+        Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .default_headers(headers)
+            .build()
+            .expect("Failed to build client")
+    }
+
+    /// Like [`TestApp::client`], but without an API key.
+    pub fn anonymous_client(&self) -> Client {
+        Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build client")
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.address, path)
+    }
+
+    /// Creates a short link and returns the JSON response body.
+    pub fn create_url(&self, original_url: &str) -> serde_json::Value {
+        self.create_url_with(json!({ "original_url": original_url }))
+    }
+
+    /// Creates a short link from a full request body and returns the JSON
+    /// response body.
+    pub fn create_url_with(&self, body: serde_json::Value) -> serde_json::Value {
+        let response = self
+            .client()
+            .post(self.url("/"))
+            .json(&body)
+            .send()
+            .expect("Failed to send POST request");
+        assert_eq!(response.status(), 201, "Expected status 201 Created");
+        response.json().expect("Failed to parse JSON response")
+    }
+}
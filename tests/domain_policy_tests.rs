@@ -0,0 +1,151 @@
+mod common;
+
+use std::{path::Path, thread, time::Duration};
+
+use rust_url_shortener::domain_policy::DomainList;
+use serde_json::json;
+
+fn create(app: &common::TestApp, body: serde_json::Value) -> reqwest::blocking::Response {
+    app.client().post(app.url("/")).json(&body).send().unwrap()
+}
+
+fn create_url(app: &common::TestApp, url: &str) -> u16 {
+    create(app, json!({ "original_url": url }))
+        .status()
+        .as_u16()
+}
+
+fn write_list(path: &Path, text: &str) {
+    // Let the modification time move on even on coarse filesystems
+    thread::sleep(Duration::from_millis(20));
+    std::fs::write(path, text).unwrap();
+}
+
+#[test]
+fn test_domain_list_syntax() {
+    let list = DomainList::parse(
+        "# phishing\n\nbad.example\n*.evil.test\n.worse.test\nregex:.*paypa1.*\n",
+    )
+    .unwrap();
+    assert_eq!(list.len(), 4);
+    assert!(list.matches("bad.example"));
+    assert!(!list.matches("sub.bad.example"));
+    assert!(list.matches("evil.test"));
+    assert!(list.matches("a.b.evil.test"));
+    assert!(!list.matches("notevil.test"));
+    assert!(list.matches("login.worse.test"));
+    assert!(list.matches("secure-paypa1.com"));
+    assert!(!list.matches("paypal.com"));
+
+    let err = DomainList::parse("ok.example\nregex:(unclosed\n").unwrap_err();
+    assert!(err.contains("line 2"), "{}", err);
+    assert!(DomainList::parse("https://example.com/path").is_err());
+}
+
+#[test]
+fn test_blocklist_rejects_new_links_and_edits() {
+    let dir = tempfile::tempdir().unwrap();
+    let blocklist = dir.path().join("blocklist.txt");
+    write_list(
+        &blocklist,
+        "phish.example\n*.scam.test\nregex:login-[a-z]+\\.com\n",
+    );
+    let app = common::spawn_app_with(|config| config.domain_blocklist_file = Some(blocklist));
+
+    assert_eq!(create_url(&app, "https://phish.example/login"), 400);
+    assert_eq!(create_url(&app, "https://PHISH.example./login"), 400);
+    assert_eq!(create_url(&app, "https://scam.test/"), 400);
+    assert_eq!(create_url(&app, "https://pay.scam.test/"), 400);
+    assert_eq!(create_url(&app, "https://login-bank.com/"), 400);
+    assert_eq!(create_url(&app, "https://sub.phish.example/"), 201);
+    let response = create(&app, json!({ "original_url": "https://phish.example/" }));
+    assert!(response
+        .text()
+        .unwrap()
+        .contains("phish.example/ is on a blocked domain"));
+
+    // Every destination of a link is checked
+    let with_rule = create(
+        &app,
+        json!({
+            "original_url": "https://example.com/",
+            "rules": [{ "when": { "countries": ["DE"] }, "destination": "https://x.scam.test/de" }]
+        }),
+    );
+    assert_eq!(with_rule.status(), 400);
+
+    let link = app.create_url("https://example.com/fine");
+    let edit_url = app.url(&format!(
+        "/api/urls/{}",
+        link["short_code"].as_str().unwrap()
+    ));
+    let edit = |body: serde_json::Value| app.client().patch(&edit_url).json(&body).send().unwrap();
+    assert_eq!(
+        edit(json!({ "original_url": "https://a.scam.test/" })).status(),
+        400
+    );
+    assert_eq!(edit(json!({ "tags": ["ok"] })).status(), 200);
+}
+
+#[test]
+fn test_allowlist_limits_destinations_and_blocklist_wins() {
+    let dir = tempfile::tempdir().unwrap();
+    let allowlist = dir.path().join("allowlist.txt");
+    let blocklist = dir.path().join("blocklist.txt");
+    write_list(&allowlist, ".example.com\n");
+    write_list(&blocklist, "uploads.example.com\n");
+    let app = common::spawn_app_with(|config| {
+        config.domain_allowlist_file = Some(allowlist);
+        config.domain_blocklist_file = Some(blocklist);
+    });
+
+    assert_eq!(create_url(&app, "https://example.com/"), 201);
+    assert_eq!(create_url(&app, "https://docs.example.com/"), 201);
+    assert_eq!(create_url(&app, "https://example.org/"), 400);
+    assert_eq!(create_url(&app, "https://uploads.example.com/"), 400);
+    let response = create(&app, json!({ "original_url": "https://example.org/" }));
+    assert!(response
+        .text()
+        .unwrap()
+        .contains("not on an allowed domain"));
+}
+
+#[test]
+fn test_reloaded_blocklist_stops_existing_links() {
+    let dir = tempfile::tempdir().unwrap();
+    let blocklist = dir.path().join("blocklist.txt");
+    write_list(&blocklist, "# nothing blocked yet\n");
+    let reload_path = blocklist.clone();
+    let app = common::spawn_app_with(|config| {
+        config.domain_blocklist_file = Some(reload_path);
+        config.domain_list_reload_interval = Duration::from_millis(100);
+    });
+    let code = app.create_url("https://turned-bad.example/page")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let visit = |accept: &str| {
+        app.anonymous_client()
+            .get(app.url(&format!("/{}", code)))
+            .header("accept", accept)
+            .send()
+            .unwrap()
+    };
+    assert_eq!(visit("*/*").status(), 302);
+
+    write_list(&blocklist, "turned-bad.example\n");
+    let blocked = (0..50).any(|_| {
+        thread::sleep(Duration::from_millis(100));
+        visit("*/*").status() == 410
+    });
+    assert!(blocked, "the reloaded blocklist was not applied");
+    let page = visit("text/html");
+    assert_eq!(page.status(), 410);
+    assert!(page.text().unwrap().contains("blocked domain"));
+
+    // A broken file keeps the previous patterns
+    write_list(&blocklist, "regex:(\n");
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(visit("*/*").status(), 410);
+    assert_eq!(create_url(&app, "https://turned-bad.example/"), 400);
+}
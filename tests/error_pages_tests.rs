@@ -0,0 +1,96 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use serde_json::json;
+
+fn get(app: &common::TestApp, path: &str, accept: &str) -> reqwest::blocking::Response {
+    app.client()
+        .get(app.url(path))
+        .header("Accept", accept)
+        .send()
+        .unwrap()
+}
+
+const BROWSER_ACCEPT: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+
+#[test]
+fn test_browsers_get_a_not_found_page_and_api_clients_get_json() {
+    let app = common::spawn_app();
+
+    let page = get(&app, "/missing", BROWSER_ACCEPT);
+    assert_eq!(page.status(), 404);
+    assert_eq!(page.headers()["vary"], "Accept");
+    assert!(page.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    assert!(page.text().unwrap().contains("<h1>Link not found</h1>"));
+
+    let api = get(&app, "/missing", "application/json");
+    assert_eq!(api.status(), 404);
+    let body: serde_json::Value = api.json().unwrap();
+    assert_eq!(body, json!({ "error": "URL not found" }));
+
+    assert_eq!(
+        get(&app, "/missing", "*/*").headers()["content-type"],
+        "application/json"
+    );
+}
+
+#[test]
+fn test_expired_and_deactivated_links_get_their_own_pages() {
+    let app = common::spawn_app();
+    let past = (Utc::now() - Duration::hours(1)).to_rfc3339();
+    let expired = app.create_url_with(json!({
+        "original_url": "https://example.com/sale",
+        "expiration_date": past
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let deactivated = app.create_url_with(json!({
+        "original_url": "https://example.com/sale",
+        "deactivates_at": past
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let page = get(&app, &format!("/{}", expired), BROWSER_ACCEPT);
+    assert_eq!(page.status(), 410);
+    assert!(page.text().unwrap().contains("<h1>Link expired</h1>"));
+
+    let page = get(&app, &format!("/{}", deactivated), BROWSER_ACCEPT);
+    assert_eq!(page.status(), 410);
+    assert!(page.text().unwrap().contains("<h1>Link disabled</h1>"));
+}
+
+#[test]
+fn test_templates_are_loaded_from_the_configured_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("not_found.html"),
+        "<p>{{status}}: nothing at /{{short_code}} ({{message}})</p>",
+    )
+    .unwrap();
+    let pages_dir = dir.path().to_path_buf();
+    let app = common::spawn_app_with(|config| config.error_pages_dir = Some(pages_dir));
+
+    let page = get(&app, "/nothing", BROWSER_ACCEPT);
+    assert_eq!(
+        page.text().unwrap(),
+        "<p>404: nothing at /nothing (URL not found)</p>"
+    );
+
+    // Pages without a file of their own keep the built-in template.
+    let past = (Utc::now() - Duration::hours(1)).to_rfc3339();
+    let code = app.create_url_with(json!({
+        "original_url": "https://example.com/sale",
+        "expiration_date": past
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let page = get(&app, &format!("/{}", code), BROWSER_ACCEPT);
+    assert!(page.text().unwrap().contains("<h1>Link expired</h1>"));
+}
@@ -0,0 +1,204 @@
+mod common;
+
+use std::{
+    io::{BufRead, BufReader},
+    time::Duration,
+};
+
+use actix_web::{test::TestRequest, web};
+use rust_url_shortener::{
+    config::Config,
+    events::{ClickEvent, EventFilter, EventHub, SUBSCRIBER_BUFFER},
+    visitor::{DeviceClass, Platform, Visitor},
+};
+use serde_json::json;
+use tungstenite::client::IntoClientRequest;
+
+const IPHONE_UA: &str =
+    "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 Mobile/15E148";
+
+fn create_tagged(app: &common::TestApp, original_url: &str, tags: &[&str]) -> String {
+    let response = app
+        .client()
+        .post(app.url("/"))
+        .json(&json!({ "original_url": original_url, "tags": tags }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let body: serde_json::Value = response.json().unwrap();
+    body["short_code"].as_str().unwrap().to_string()
+}
+
+fn click(app: &common::TestApp, code: &str) {
+    let response = app
+        .client()
+        .get(app.url(&format!("/{}", code)))
+        .header("User-Agent", IPHONE_UA)
+        .header("CF-IPCountry", "de")
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 302);
+}
+
+/// Reads the SSE stream until the next `data:` line and parses it.
+fn next_event(reader: &mut impl BufRead) -> serde_json::Value {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        assert!(reader.read_line(&mut line).unwrap() > 0, "stream ended");
+        if let Some(data) = line.strip_prefix("data: ") {
+            return serde_json::from_str(data.trim()).unwrap();
+        }
+    }
+}
+
+fn subscribe(app: &common::TestApp, query: &str) -> BufReader<reqwest::blocking::Response> {
+    let response = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap()
+        .get(app.url(&format!("/api/events{}", query)))
+        .bearer_auth(&app.api_key)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    BufReader::new(response)
+}
+
+#[test]
+fn test_sse_stream_filters_by_code() {
+    let app = common::spawn_app();
+    let watched = create_tagged(&app, "https://example.com/watched", &[]);
+    let other = create_tagged(&app, "https://example.com/other", &[]);
+
+    let mut stream = subscribe(&app, &format!("?code={}", watched));
+    click(&app, &other);
+    click(&app, &watched);
+
+    let event = next_event(&mut stream);
+    assert_eq!(event["short_code"], watched.as_str());
+    assert_eq!(event["device"], "mobile");
+    assert_eq!(event["country"], "DE");
+    assert!(event["timestamp"].is_string());
+}
+
+#[test]
+fn test_sse_stream_filters_by_tag() {
+    let app = common::spawn_app();
+    let spring = create_tagged(
+        &app,
+        "https://example.com/spring",
+        &["spring-sale", "email"],
+    );
+    let plain = create_tagged(&app, "https://example.com/plain", &["email"]);
+
+    let mut stream = subscribe(&app, "?tag=spring-sale");
+    click(&app, &plain);
+    click(&app, &spring);
+
+    let event = next_event(&mut stream);
+    assert_eq!(event["short_code"], spring.as_str());
+    assert_eq!(event["tags"], json!(["email", "spring-sale"]));
+}
+
+#[test]
+fn test_websocket_stream_receives_clicks() {
+    let app = common::spawn_app();
+    let code = create_tagged(&app, "https://example.com/ws", &["live"]);
+
+    let ws_url = format!(
+        "{}/api/events?tag=live",
+        app.address.replacen("http", "ws", 1)
+    );
+    let mut request = ws_url.into_client_request().unwrap();
+    request.headers_mut().insert(
+        "Authorization",
+        format!("Bearer {}", app.api_key).parse().unwrap(),
+    );
+    let (mut socket, _) = tungstenite::connect(request).expect("WebSocket handshake failed");
+    click(&app, &code);
+
+    let message = socket.read().unwrap();
+    let event: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+    assert_eq!(event["short_code"], code.as_str());
+    socket.close(None).unwrap();
+}
+
+#[test]
+fn test_visitor_address_only_trusts_forwarding_from_proxies() {
+    let visitor_ip = |peer: &str, headers: &[(&str, &str)]| {
+        let config = Config {
+            trusted_proxies: vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()],
+            ..Config::default()
+        };
+        let mut request = TestRequest::default()
+            .peer_addr(peer.parse().unwrap())
+            .app_data(web::Data::new(config));
+        for header in headers {
+            request = request.insert_header(*header);
+        }
+        Visitor::from_request(&request.to_http_request()).ip_address
+    };
+
+    let spoofed = [("X-Forwarded-For", "203.0.113.7")];
+    assert_eq!(
+        visitor_ip("198.51.100.20:5000", &spoofed).as_deref(),
+        Some("198.51.100.20")
+    );
+    assert_eq!(
+        visitor_ip("10.0.0.1:5000", &spoofed).as_deref(),
+        Some("203.0.113.7")
+    );
+    // The client can only prepend addresses; the proxies' additions win
+    let chained = [("X-Forwarded-For", "203.0.113.7, 198.51.100.1, 10.0.0.2")];
+    assert_eq!(
+        visitor_ip("10.0.0.1:5000", &chained).as_deref(),
+        Some("198.51.100.1")
+    );
+    let forwarded = [("Forwarded", "for=\"[2001:db8::1]:4711\";proto=https")];
+    assert_eq!(
+        visitor_ip("10.0.0.1:5000", &forwarded).as_deref(),
+        Some("2001:db8::1")
+    );
+    let unknown = [("X-Forwarded-For", "203.0.113.7, unknown")];
+    assert_eq!(
+        visitor_ip("10.0.0.1:5000", &unknown).as_deref(),
+        Some("10.0.0.1")
+    );
+}
+
+#[test]
+fn test_slow_subscriber_does_not_block_publishing() {
+    let hub = EventHub::default();
+    let visitor = Visitor {
+        ip_address: None,
+        user_agent: Some(IPHONE_UA.to_string()),
+        country: None,
+        device: DeviceClass::Mobile,
+        platform: Platform::Ios,
+        language: None,
+        referrer_host: None,
+    };
+
+    // This subscriber never reads; the other one drains as it goes.
+    let mut stalled = hub.subscribe(EventFilter::default());
+    let mut active = hub.subscribe(EventFilter::default());
+    let mut received = 0;
+    for _ in 0..SUBSCRIBER_BUFFER * 4 {
+        hub.publish(&ClickEvent::new("abc123", &visitor, Vec::new()));
+        while active.try_recv().is_ok() {
+            received += 1;
+        }
+    }
+
+    assert_eq!(received, SUBSCRIBER_BUFFER * 4);
+    let mut buffered = 0;
+    while stalled.try_recv().is_ok() {
+        buffered += 1;
+    }
+    assert_eq!(
+        buffered, SUBSCRIBER_BUFFER,
+        "excess events are dropped, not queued"
+    );
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use parquet::file::reader::{FileReader, SerializedFileReader};
+
+/// Follows a short link once so a click gets recorded.
+fn click(app: &common::TestApp, code: &str) {
+    let response = app
+        .client()
+        .get(app.url(&format!("/{}", code)))
+        .header("User-Agent", "export-test")
+        .send()
+        .expect("Failed to send GET request");
+    assert_eq!(response.status(), 302);
+}
+
+#[test]
+fn test_export_csv_filters_by_code() {
+    let app = common::spawn_app();
+    let first = app.create_url("https://example.com/first");
+    let second = app.create_url("https://example.com/second");
+    let first_code = first["short_code"].as_str().unwrap();
+    click(&app, first_code);
+    click(&app, first_code);
+    click(&app, second["short_code"].as_str().unwrap());
+
+    let response = app
+        .client()
+        .get(app.url(&format!("/api/export/clicks?format=csv&code={}", first_code)))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/csv"));
+
+    let body = response.text().unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines[0], "id,short_code,original_url,ip_address,user_agent,accessed_at");
+    assert_eq!(lines.len(), 3, "header plus two clicks: {}", body);
+    assert!(lines[1..].iter().all(|line| line.contains("https://example.com/first")));
+    assert!(lines[1].contains("export-test"));
+}
+
+#[test]
+fn test_export_ndjson_respects_time_range() {
+    let app = common::spawn_app();
+    let created = app.create_url("https://example.com/ndjson");
+    click(&app, created["short_code"].as_str().unwrap());
+
+    let client = app.client();
+    let body = client
+        .get(app.url("/api/export/clicks?format=ndjson"))
+        .send()
+        .unwrap()
+        .text()
+        .unwrap();
+    let rows: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["short_code"], created["short_code"]);
+    assert_eq!(rows[0]["original_url"], "https://example.com/ndjson");
+
+    let body = client
+        .get(app.url("/api/export/clicks?format=ndjson&to=2000-01-01T00:00:00Z"))
+        .send()
+        .unwrap()
+        .text()
+        .unwrap();
+    assert!(body.is_empty(), "no clicks before 2000: {}", body);
+}
+
+#[test]
+fn test_export_parquet_is_readable() {
+    let app = common::spawn_app();
+    let created = app.create_url("https://example.com/parquet");
+    for _ in 0..3 {
+        click(&app, created["short_code"].as_str().unwrap());
+    }
+
+    let response = app
+        .client()
+        .get(app.url("/api/export/clicks?format=parquet"))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.bytes().unwrap();
+    assert_eq!(&body[..4], b"PAR1");
+    assert_eq!(&body[body.len() - 4..], b"PAR1");
+    let reader = SerializedFileReader::new(body).expect("Export should be valid Parquet");
+    assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+}
+
+#[test]
+fn test_export_rejects_unknown_format() {
+    let app = common::spawn_app();
+    let response = app
+        .client()
+        .get(app.url("/api/export/clicks?format=xml"))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().unwrap();
+    assert!(body["error"].as_str().unwrap().contains("xml"));
+}
+
@@ -1,20 +1,17 @@
+mod common;
 
-use reqwest;
 use serde_json::json;
-use std::{thread, time};
 
 /// This test sends a POST request to create a shortened URL and verifies
 /// that the response contains the expected fields.
 #[test]
 fn test_create_url() {
-    // Wait briefly to ensure the server is up (adjust if needed)
-    thread::sleep(time::Duration::from_secs(2));
-
-    let client = reqwest::blocking::Client::new();
+    let app = common::spawn_app();
+    let client = app.client();
 
     // Send a POST request to create a shortened URL
     let response = client
-        .post("http://localhost:8080/")
+        .post(app.url("/"))
         .json(&json!({ "original_url": "https://example.com" }))
         .send()
         .expect("Failed to send POST request");
@@ -39,14 +36,12 @@ fn test_create_url() {
 /// that the response is a JSON array.
 #[test]
 fn test_list_urls() {
-    // Wait briefly to ensure the server is up (adjust if needed)
-    thread::sleep(time::Duration::from_secs(2));
-
-    let client = reqwest::blocking::Client::new();
+    let app = common::spawn_app();
+    let client = app.client();
 
     // Send a GET request to fetch all shortened URLs
     let response = client
-        .get("http://localhost:8080/")
+        .get(app.url("/"))
         .send()
         .expect("Failed to send GET request");
 
@@ -58,3 +53,38 @@ fn test_list_urls() {
     assert!(urls.is_array(), "Response should be a JSON array");
     println!("List of URLs: {}", urls);
 }
+
+/// Listed links have the same shape as the link returned on creation,
+/// tags included.
+#[test]
+fn test_list_urls_matches_the_created_link() {
+    let app = common::spawn_app();
+    let created = app.create_url_with(json!({
+        "original_url": "https://example.com/listed",
+        "tags": ["spring", "campaign"],
+        "utm": { "source": "newsletter" }
+    }));
+    let untagged = app.create_url("https://example.com/untagged");
+
+    let urls: serde_json::Value = app
+        .client()
+        .get(app.url("/"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let listed = |short_code: &serde_json::Value| {
+        urls.as_array()
+            .unwrap()
+            .iter()
+            .find(|link| &link["short_code"] == short_code)
+            .cloned()
+            .expect("created link is listed")
+    };
+    assert_eq!(listed(&created["short_code"]), created);
+    assert_eq!(
+        listed(&created["short_code"])["tags"],
+        json!(["campaign", "spring"])
+    );
+    assert_eq!(listed(&untagged["short_code"]), untagged);
+}
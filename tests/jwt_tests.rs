@@ -0,0 +1,288 @@
+mod common;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use openssl::{
+    bn::BigNumContext,
+    ec::{EcGroup, EcKey, PointConversionForm},
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    sign::Signer,
+};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+const SECRET: &str = "gateway-shared-secret";
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+fn encode(value: &Value) -> String {
+    URL_SAFE_NO_PAD.encode(value.to_string())
+}
+
+fn hs256(header: Value, claims: Value, secret: &str) -> String {
+    let signed = format!("{}.{}", encode(&header), encode(&claims));
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(signed.as_bytes());
+    format!(
+        "{}.{}",
+        signed,
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    )
+}
+
+fn rs256(key: &PKey<Private>, claims: Value) -> String {
+    let signed = format!("{}.{}", encode(&json!({ "alg": "RS256" })), encode(&claims));
+    let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+    signer.update(signed.as_bytes()).unwrap();
+    format!(
+        "{}.{}",
+        signed,
+        URL_SAFE_NO_PAD.encode(signer.sign_to_vec().unwrap())
+    )
+}
+
+fn es256(key: &EcKey<Private>, kid: &str, claims: Value) -> String {
+    let header = json!({ "alg": "ES256", "kid": kid });
+    let signed = format!("{}.{}", encode(&header), encode(&claims));
+    let sig = EcdsaSig::sign(&openssl::sha::sha256(signed.as_bytes()), key).unwrap();
+    let mut raw = sig.r().to_vec_padded(32).unwrap();
+    raw.extend(sig.s().to_vec_padded(32).unwrap());
+    format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(raw))
+}
+
+fn p256_key() -> EcKey<Private> {
+    EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap()
+}
+
+fn jwk(key: &EcKey<Private>, kid: &str) -> Value {
+    let mut ctx = BigNumContext::new().unwrap();
+    let point = key
+        .public_key()
+        .to_bytes(key.group(), PointConversionForm::UNCOMPRESSED, &mut ctx)
+        .unwrap();
+    json!({
+        "kty": "EC",
+        "crv": "P-256",
+        "kid": kid,
+        "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+        "y": URL_SAFE_NO_PAD.encode(&point[33..65])
+    })
+}
+
+fn create_link(app: &common::TestApp, client: &Client) -> reqwest::blocking::Response {
+    client
+        .post(app.url("/"))
+        .json(&json!({ "original_url": "https://example.com/from-gateway" }))
+        .send()
+        .unwrap()
+}
+
+fn error_of(response: reqwest::blocking::Response) -> String {
+    response.json::<Value>().unwrap()["error"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_hs256_tokens_map_to_users() {
+    let app = common::spawn_app_with(|config| {
+        config.jwt_secret = Some(SECRET.to_string());
+        config.jwt_issuer = Some("https://gateway.example.com".to_string());
+        config.jwt_audience = Some("shortener".to_string());
+    });
+    let header = json!({ "alg": "HS256", "typ": "JWT" });
+    let claims = json!({
+        "sub": "dana@example.com",
+        "iss": "https://gateway.example.com",
+        "aud": ["shortener", "other"],
+        "exp": now() + 300
+    });
+
+    let token = hs256(header.clone(), claims.clone(), SECRET);
+    let response = create_link(&app, &app.client_with_key(&token));
+    assert_eq!(response.status(), 201);
+    let link: Value = response.json().unwrap();
+    let users: Value = app
+        .client()
+        .get(app.url("/api/users"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(users[0]["username"], "dana@example.com");
+    assert_eq!(link["owner_id"], users[0]["id"]);
+
+    // Tokens without a scope claim cannot reach admin endpoints
+    let keys = app
+        .client_with_key(&token)
+        .get(app.url("/api/keys"))
+        .send()
+        .unwrap();
+    assert_eq!(keys.status(), 403);
+    assert!(error_of(keys).contains("token lacks the admin scope"));
+
+    let rejected = |claims: Value, secret: &str| {
+        let response = create_link(
+            &app,
+            &app.client_with_key(&hs256(header.clone(), claims, secret)),
+        );
+        assert_eq!(response.status(), 401);
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+        error_of(response)
+    };
+    let mut expired = claims.clone();
+    expired["exp"] = json!(now() - 3600);
+    assert!(rejected(expired, SECRET).contains("token has expired"));
+    let mut other_audience = claims.clone();
+    other_audience["aud"] = json!("someone-else");
+    assert!(rejected(other_audience, SECRET).contains("audience"));
+    let mut other_issuer = claims.clone();
+    other_issuer["iss"] = json!("https://evil.example.com");
+    assert!(rejected(other_issuer, SECRET).contains("issuer"));
+    assert!(rejected(claims.clone(), "wrong-secret").contains("signature"));
+
+    let unsigned = format!("{}.{}.", encode(&json!({ "alg": "none" })), encode(&claims));
+    let response = create_link(&app, &app.client_with_key(&unsigned));
+    assert_eq!(response.status(), 401);
+
+    // API keys keep working next to tokens
+    assert_eq!(create_link(&app, &app.client()).status(), 201);
+}
+
+#[test]
+fn test_rs256_public_key_and_scope_claim() {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let pem_path = dir.path().join("gateway.pem");
+    std::fs::write(&pem_path, key.public_key_to_pem().unwrap()).unwrap();
+    let app = common::spawn_app_with(|config| config.jwt_public_key = Some(pem_path.clone()));
+
+    let reader = rs256(
+        &key,
+        json!({ "sub": "erin", "exp": now() + 300, "scope": "links:read" }),
+    );
+    let reader = app.client_with_key(&reader);
+    assert_eq!(reader.get(app.url("/")).send().unwrap().status(), 200);
+    assert_eq!(create_link(&app, &reader).status(), 403);
+
+    let writer = rs256(
+        &key,
+        json!({ "sub": "erin", "exp": now() + 300, "scopes": ["links:write"] }),
+    );
+    assert_eq!(
+        create_link(&app, &app.client_with_key(&writer)).status(),
+        201
+    );
+
+    // An HS256 token is not accepted when only an RSA key is configured
+    let forged = hs256(
+        json!({ "alg": "HS256" }),
+        json!({ "sub": "erin", "exp": now() + 300 }),
+        SECRET,
+    );
+    let response = create_link(&app, &app.client_with_key(&forged));
+    assert_eq!(response.status(), 401);
+    assert!(error_of(response).contains("no key matches"));
+}
+
+#[test]
+fn test_es256_jwks_file_is_reloaded_for_new_keys() {
+    let first = p256_key();
+    let second = p256_key();
+    let dir = tempfile::tempdir().unwrap();
+    let jwks_path = dir.path().join("jwks.json");
+    std::fs::write(
+        &jwks_path,
+        json!({ "keys": [jwk(&first, "2024-01")] }).to_string(),
+    )
+    .unwrap();
+    let app = common::spawn_app_with(|config| config.jwt_jwks_file = Some(jwks_path.clone()));
+    let claims = json!({ "sub": "frank", "exp": now() + 300 });
+
+    let token = es256(&first, "2024-01", claims.clone());
+    assert_eq!(
+        create_link(&app, &app.client_with_key(&token)).status(),
+        201
+    );
+    let unknown = es256(&second, "2024-02", claims.clone());
+    assert_eq!(
+        create_link(&app, &app.client_with_key(&unknown)).status(),
+        401
+    );
+
+    // The gateway rotates its keys by publishing a new JWKS
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    let jwks = json!({ "keys": [jwk(&first, "2024-01"), jwk(&second, "2024-02")] });
+    std::fs::write(&jwks_path, jwks.to_string()).unwrap();
+    assert_eq!(
+        create_link(&app, &app.client_with_key(&unknown)).status(),
+        201
+    );
+
+    // A key id naming one key does not verify with another
+    let mislabeled = es256(&second, "2024-01", claims);
+    assert_eq!(
+        create_link(&app, &app.client_with_key(&mislabeled)).status(),
+        401
+    );
+}
+
+#[test]
+fn test_workspace_claim_sets_the_default_workspace() {
+    let app = common::spawn_app_with(|config| config.jwt_secret = Some(SECRET.to_string()));
+    let admin = app.client();
+    let user: Value = admin
+        .post(app.url("/api/users"))
+        .json(&json!({ "username": "gina" }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let workspace: Value = admin
+        .post(app.url("/api/workspaces"))
+        .json(&json!({ "name": "growth" }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let member = admin
+        .put(app.url(&format!(
+            "/api/workspaces/{}/members/{}",
+            workspace["id"], user["id"]
+        )))
+        .json(&json!({ "role": "editor" }))
+        .send()
+        .unwrap();
+    assert_eq!(member.status(), 200);
+
+    let token = |workspace: Value| {
+        hs256(
+            json!({ "alg": "HS256" }),
+            json!({ "sub": "gina", "exp": now() + 300, "workspace": workspace }),
+            SECRET,
+        )
+    };
+    let by_name = create_link(&app, &app.client_with_key(&token(json!("growth"))));
+    assert_eq!(by_name.status(), 201);
+    assert_eq!(
+        by_name.json::<Value>().unwrap()["workspace_id"],
+        workspace["id"]
+    );
+    let by_id = create_link(&app, &app.client_with_key(&token(workspace["id"].clone())));
+    assert_eq!(
+        by_id.json::<Value>().unwrap()["workspace_id"],
+        workspace["id"]
+    );
+
+    let unknown = create_link(&app, &app.client_with_key(&token(json!("nope"))));
+    assert_eq!(unknown.status(), 401);
+    assert!(error_of(unknown).contains("unknown workspace"));
+}
@@ -0,0 +1,238 @@
+mod common;
+
+use reqwest::blocking::Response;
+use serde_json::{json, Value};
+
+fn code(link: &Value) -> String {
+    link["short_code"].as_str().unwrap().to_string()
+}
+
+fn report(app: &common::TestApp, short_code: &str, body: Value) -> Response {
+    app.anonymous_client()
+        .post(app.url(&format!("/{}/report", short_code)))
+        // Ignored, as the test client is not a trusted proxy
+        .header("x-forwarded-for", "203.0.113.7")
+        .json(&body)
+        .send()
+        .unwrap()
+}
+
+fn moderate(app: &common::TestApp, path: &str, body: Value) -> Response {
+    app.client().post(app.url(path)).json(&body).send().unwrap()
+}
+
+fn list(app: &common::TestApp, path: &str) -> Vec<Value> {
+    app.client()
+        .get(app.url(path))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap()
+}
+
+#[test]
+fn test_visitors_can_report_links() {
+    let app = common::spawn_app();
+    let link = app.create_url("https://example.com/spam");
+
+    let sent = report(&app, &code(&link), json!({ "reason": " Phishing page " }));
+    assert_eq!(sent.status(), 202);
+    // Plain HTML forms can report too
+    let form = app
+        .anonymous_client()
+        .post(app.url(&format!("/{}/report", code(&link))))
+        .form(&[("reason", "Spam")])
+        .send()
+        .unwrap();
+    assert_eq!(form.status(), 202);
+
+    assert_eq!(
+        report(&app, &code(&link), json!({ "reason": "  " })).status(),
+        400
+    );
+    assert_eq!(
+        report(&app, &code(&link), json!({ "reason": "x".repeat(1001) })).status(),
+        400
+    );
+    assert_eq!(
+        report(&app, "missing", json!({ "reason": "Spam" })).status(),
+        404
+    );
+
+    let reports = list(&app, "/api/reports?status=open");
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[1]["reason"], "Phishing page");
+    assert_eq!(reports[1]["short_code"], code(&link));
+    assert_eq!(reports[1]["reporter_ip"], "127.0.0.1");
+    assert_eq!(
+        app.anonymous_client()
+            .get(app.url("/api/reports"))
+            .send()
+            .unwrap()
+            .status(),
+        401
+    );
+
+    // The link still redirects until a moderator acts
+    let visit = app
+        .anonymous_client()
+        .get(app.url(&format!("/{}", code(&link))))
+        .send()
+        .unwrap();
+    assert_eq!(visit.status(), 302);
+}
+
+#[test]
+fn test_disabled_links_show_the_suspended_page_until_enabled() {
+    let app = common::spawn_app();
+    let link = app.create_url("https://example.com/abuse");
+    let other = app.create_url("https://example.com/fine");
+    report(&app, &code(&link), json!({ "reason": "Malware" }));
+    report(&app, &code(&other), json!({ "reason": "Spam" }));
+
+    let missing_reason = moderate(
+        &app,
+        &format!("/api/urls/{}/disable", code(&link)),
+        json!({}),
+    );
+    assert_eq!(missing_reason.status(), 400);
+    let disabled: Value = moderate(
+        &app,
+        &format!("/api/urls/{}/disable", code(&link)),
+        json!({ "reason": "Confirmed malware" }),
+    )
+    .json()
+    .unwrap();
+    assert_eq!(disabled["disabled_reason"], "Confirmed malware");
+    assert!(disabled["disabled_at"].is_string());
+
+    let visit = |accept: &str| {
+        app.anonymous_client()
+            .get(app.url(&format!("/{}", code(&link))))
+            .header("accept", accept)
+            .send()
+            .unwrap()
+    };
+    let refused = visit("*/*");
+    assert_eq!(refused.status(), 410);
+    assert!(refused.headers().get("location").is_none());
+    let page = visit("text/html");
+    assert_eq!(page.status(), 410);
+    let page = page.text().unwrap();
+    assert!(page.contains("Link suspended"));
+    assert!(page.contains("disabled by a moderator"));
+    let preview = app
+        .anonymous_client()
+        .get(app.url(&format!("/{}+", code(&link))))
+        .send()
+        .unwrap()
+        .text()
+        .unwrap();
+    assert!(!preview.contains("example.com/abuse"));
+
+    // Disabling resolves the link's open reports only
+    let open = list(&app, "/api/reports?status=open");
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0]["short_code"], code(&other));
+    let actioned = list(&app, &format!("/api/reports?code={}", code(&link)));
+    assert_eq!(actioned[0]["status"], "actioned");
+    assert_eq!(actioned[0]["resolved_by"], "tests");
+
+    let enabled: Value = moderate(
+        &app,
+        &format!("/api/urls/{}/enable", code(&link)),
+        json!({ "reason": "Cleaned up by the owner" }),
+    )
+    .json()
+    .unwrap();
+    assert!(enabled["disabled_at"].is_null());
+    assert_eq!(visit("*/*").status(), 302);
+    assert_eq!(
+        moderate(
+            &app,
+            &format!("/api/urls/{}/enable", code(&link)),
+            json!({})
+        )
+        .status(),
+        400
+    );
+}
+
+#[test]
+fn test_dismissals_and_moderation_trail() {
+    let app = common::spawn_app();
+    let link = app.create_url("https://example.com/reported");
+    report(&app, &code(&link), json!({ "reason": "Looks odd" }));
+    let report_id = list(&app, "/api/reports")[0]["id"].clone();
+
+    let dismissed: Value = moderate(
+        &app,
+        &format!("/api/reports/{}/dismiss", report_id),
+        json!({ "reason": "Legitimate site" }),
+    )
+    .json()
+    .unwrap();
+    assert_eq!(dismissed["status"], "dismissed");
+    assert_eq!(
+        moderate(
+            &app,
+            &format!("/api/reports/{}/dismiss", report_id),
+            json!({})
+        )
+        .status(),
+        400
+    );
+    assert_eq!(
+        moderate(&app, "/api/reports/999/dismiss", json!({})).status(),
+        404
+    );
+
+    moderate(
+        &app,
+        &format!("/api/urls/{}/disable", code(&link)),
+        json!({ "reason": "Second look" }),
+    );
+    let deleted = app
+        .client()
+        .delete(app.url(&format!("/api/urls/{}", code(&link))))
+        .send()
+        .unwrap();
+    assert_eq!(deleted.status(), 204);
+
+    // The trail outlives the link
+    let trail = list(&app, &format!("/api/moderation?code={}", code(&link)));
+    let actions: Vec<&str> = trail
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, ["disabled", "dismissed", "reported"]);
+    assert_eq!(trail[0]["actor"], "tests");
+    assert_eq!(trail[0]["reason"], "Second look");
+    assert_eq!(trail[1]["report_id"], report_id);
+    assert_eq!(trail[2]["actor"], "visitor");
+    assert!(list(&app, "/api/reports").is_empty());
+}
+
+#[test]
+fn test_reports_are_rate_limited_per_ip() {
+    let app = common::spawn_app_with(|config| {
+        config.rate_limit_report_per_ip = Some("2/m".parse().unwrap())
+    });
+    let link = app.create_url("https://example.com/popular");
+    for _ in 0..2 {
+        assert_eq!(
+            report(&app, &code(&link), json!({ "reason": "Spam" })).status(),
+            202
+        );
+    }
+    assert_eq!(
+        report(&app, &code(&link), json!({ "reason": "Spam" })).status(),
+        429
+    );
+    let visit = app
+        .anonymous_client()
+        .get(app.url(&format!("/{}", code(&link))))
+        .send()
+        .unwrap();
+    assert_eq!(visit.status(), 302);
+}
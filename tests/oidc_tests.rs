@@ -0,0 +1,486 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    net::TcpListener,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use actix_web::{http::header, web, App, HttpResponse, HttpServer};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use openssl::{
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    sign::Signer,
+};
+use reqwest::blocking::{Client, Response};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+const CLIENT_ID: &str = "shortener";
+
+/// An authorization request waiting for its code to be exchanged.
+struct Pending {
+    challenge: String,
+    nonce: String,
+    redirect_uri: String,
+}
+
+/// A minimal identity provider: discovery, an authorize endpoint that signs
+/// everyone in at once, a token endpoint checking PKCE, and a JWKS.
+struct MockIdp {
+    issuer: String,
+    key: PKey<Private>,
+    /// Claims of whoever signs in next.
+    claims: Mutex<Value>,
+    pending: Mutex<HashMap<String, Pending>>,
+    issued: AtomicUsize,
+}
+
+impl MockIdp {
+    fn sign_in_as(&self, claims: Value) {
+        *self.claims.lock().unwrap() = claims;
+    }
+
+    fn id_token(&self, nonce: &str) -> String {
+        let now = chrono::Utc::now().timestamp();
+        let mut claims = json!({
+            "iss": self.issuer,
+            "aud": CLIENT_ID,
+            "iat": now,
+            "exp": now + 300,
+            "nonce": nonce
+        });
+        for (name, value) in self.claims.lock().unwrap().as_object().unwrap() {
+            claims[name] = value.clone();
+        }
+        let header = json!({ "alg": "RS256", "kid": "idp-1" });
+        let signed = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key).unwrap();
+        signer.update(signed.as_bytes()).unwrap();
+        format!(
+            "{}.{}",
+            signed,
+            URL_SAFE_NO_PAD.encode(signer.sign_to_vec().unwrap())
+        )
+    }
+}
+
+async fn discovery(idp: web::Data<MockIdp>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer)
+    }))
+}
+
+async fn authorize(
+    idp: web::Data<MockIdp>,
+    query: web::Query<HashMap<String, String>>,
+) -> HttpResponse {
+    assert_eq!(query["response_type"], "code");
+    assert_eq!(query["client_id"], CLIENT_ID);
+    assert_eq!(query["code_challenge_method"], "S256");
+    let code = format!("code-{}", idp.issued.fetch_add(1, Ordering::SeqCst));
+    idp.pending.lock().unwrap().insert(
+        code.clone(),
+        Pending {
+            challenge: query["code_challenge"].clone(),
+            nonce: query["nonce"].clone(),
+            redirect_uri: query["redirect_uri"].clone(),
+        },
+    );
+    let mut location = url::Url::parse(&query["redirect_uri"]).unwrap();
+    location
+        .query_pairs_mut()
+        .append_pair("code", &code)
+        .append_pair("state", &query["state"]);
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location.to_string()))
+        .finish()
+}
+
+async fn token(idp: web::Data<MockIdp>, form: web::Form<HashMap<String, String>>) -> HttpResponse {
+    let Some(pending) = idp.pending.lock().unwrap().remove(&form["code"]) else {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+    };
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+    if challenge != pending.challenge || form["redirect_uri"] != pending.redirect_uri {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+    }
+    HttpResponse::Ok().json(json!({
+        "access_token": "opaque",
+        "token_type": "Bearer",
+        "id_token": idp.id_token(&pending.nonce)
+    }))
+}
+
+async fn jwks(idp: web::Data<MockIdp>) -> HttpResponse {
+    let rsa = idp.key.rsa().unwrap();
+    HttpResponse::Ok().json(json!({ "keys": [{
+        "kty": "RSA",
+        "kid": "idp-1",
+        "use": "sig",
+        "alg": "RS256",
+        "n": URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+        "e": URL_SAFE_NO_PAD.encode(rsa.e().to_vec())
+    }]}))
+}
+
+fn spawn_idp() -> Arc<MockIdp> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let idp = Arc::new(MockIdp {
+        issuer: format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port()),
+        key: PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap(),
+        claims: Mutex::new(json!({})),
+        pending: Mutex::new(HashMap::new()),
+        issued: AtomicUsize::new(0),
+    });
+    let data = web::Data::from(idp.clone());
+    thread::spawn(move || {
+        actix_web::rt::System::new().block_on(async move {
+            HttpServer::new(move || {
+                App::new()
+                    .app_data(data.clone())
+                    .route(
+                        "/.well-known/openid-configuration",
+                        web::get().to(discovery),
+                    )
+                    .route("/authorize", web::get().to(authorize))
+                    .route("/token", web::post().to(token))
+                    .route("/jwks", web::get().to(jwks))
+            })
+            .workers(1)
+            .listen(listener)
+            .unwrap()
+            .run()
+            .await
+        })
+    });
+    idp
+}
+
+fn spawn_app_for(
+    idp: &MockIdp,
+    configure: impl FnOnce(&mut rust_url_shortener::config::Config),
+) -> common::TestApp {
+    let issuer = idp.issuer.clone();
+    common::spawn_app_with(move |config| {
+        config.oidc_issuer = Some(issuer);
+        config.oidc_client_id = Some(CLIENT_ID.to_string());
+        configure(config);
+    })
+}
+
+/// Value of the cookie `name` set by `response`.
+fn set_cookie(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| value.strip_prefix(&format!("{}=", name)))
+        .map(|value| value.split(';').next().unwrap().to_string())
+}
+
+fn location(response: &Response) -> String {
+    response.headers()["location"].to_str().unwrap().to_string()
+}
+
+/// The steps of a sign-in, walked by hand so tests can tamper with them.
+struct Login {
+    cookie: String,
+    callback: String,
+}
+
+fn start_login(app: &common::TestApp, client: &Client, return_to: &str) -> Login {
+    let login = client
+        .get(app.url("/auth/login"))
+        .query(&[("return_to", return_to)])
+        .send()
+        .unwrap();
+    assert_eq!(login.status(), 302);
+    let cookie = set_cookie(&login, "oidc_login").unwrap();
+    let authorize = client.get(location(&login)).send().unwrap();
+    assert_eq!(authorize.status(), 302);
+    Login {
+        cookie,
+        callback: location(&authorize),
+    }
+}
+
+fn finish_login(client: &Client, cookie: &str, callback: &str) -> Response {
+    client
+        .get(callback)
+        .header("cookie", format!("oidc_login={}", cookie))
+        .send()
+        .unwrap()
+}
+
+/// Signs in and returns the session cookie.
+fn sign_in(app: &common::TestApp, client: &Client) -> String {
+    let login = start_login(app, client, "/");
+    let response = finish_login(client, &login.cookie, &login.callback);
+    assert_eq!(response.status(), 302);
+    set_cookie(&response, "session").unwrap()
+}
+
+fn with_session(client: &Client, method: reqwest::Method, url: String, session: &str) -> Response {
+    client
+        .request(method, url)
+        .header("cookie", format!("session={}", session))
+        .send()
+        .unwrap()
+}
+
+#[test]
+fn test_sso_sign_in_provisions_user_and_starts_session() {
+    let idp = spawn_idp();
+    let app = spawn_app_for(&idp, |_| {});
+    let browser = app.anonymous_client();
+    idp.sign_in_as(json!({ "sub": "u-1", "email": "hana@example.com" }));
+
+    let login = browser
+        .get(app.url("/auth/login?return_to=/dashboard"))
+        .send()
+        .unwrap();
+    let authorize = url::Url::parse(&location(&login)).unwrap();
+    let params: HashMap<_, _> = authorize.query_pairs().into_owned().collect();
+    assert_eq!(params["redirect_uri"], app.url("/auth/callback"));
+    assert_eq!(params["scope"], "openid profile email");
+    let cookie = set_cookie(&login, "oidc_login").unwrap();
+    let callback = location(&browser.get(authorize.as_str()).send().unwrap());
+    let done = finish_login(&browser, &cookie, &callback);
+    assert_eq!(done.status(), 302);
+    assert_eq!(location(&done), "/dashboard");
+    let header = done
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .find(|value| value.starts_with("session="))
+        .unwrap()
+        .to_string();
+    assert!(header.contains("HttpOnly"));
+    assert!(header.contains("SameSite=Lax"));
+    let session = set_cookie(&done, "session").unwrap();
+
+    let created = browser
+        .post(app.url("/"))
+        .header("cookie", format!("session={}", session))
+        .json(&json!({ "original_url": "https://example.com/sso" }))
+        .send()
+        .unwrap();
+    assert_eq!(created.status(), 201);
+    let users: Value = app
+        .client()
+        .get(app.url("/api/users"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(users[0]["username"], "hana@example.com");
+    assert_eq!(created.json::<Value>().unwrap()["owner_id"], users[0]["id"]);
+
+    let keys = with_session(
+        &browser,
+        reqwest::Method::GET,
+        app.url("/api/keys"),
+        &session,
+    );
+    assert_eq!(keys.status(), 403);
+    assert!(keys
+        .text()
+        .unwrap()
+        .contains("session lacks the admin scope"));
+
+    // Signing in again finds the same user
+    sign_in(&app, &browser);
+    let users: Value = app
+        .client()
+        .get(app.url("/api/users"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(users.as_array().unwrap().len(), 1);
+
+    // Return paths elsewhere are ignored
+    let login = start_login(&app, &browser, "//evil.example.com/");
+    let done = finish_login(&browser, &login.cookie, &login.callback);
+    assert_eq!(location(&done), "/");
+
+    let logout = with_session(
+        &browser,
+        reqwest::Method::POST,
+        app.url("/auth/logout"),
+        &session,
+    );
+    assert_eq!(logout.status(), 204);
+    let after = with_session(&browser, reqwest::Method::GET, app.url("/"), &session);
+    assert_eq!(after.status(), 401);
+}
+
+#[test]
+fn test_groups_claim_maps_to_workspace_roles() {
+    let idp = spawn_idp();
+    let app = spawn_app_for(&idp, |config| {
+        config.oidc_group_roles = rust_url_shortener::oidc::parse_group_roles(
+            "eng=platform:editor, leads=platform:owner, analytics=growth:analyst, x=missing:viewer",
+        )
+        .unwrap();
+        config.oidc_admin_groups = vec!["ops".to_string()];
+    });
+    for name in ["platform", "growth"] {
+        let response = app
+            .client()
+            .post(app.url("/api/workspaces"))
+            .json(&json!({ "name": name }))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 201);
+    }
+    let browser = app.anonymous_client();
+    let roles = |session: &str| {
+        let workspaces: Value = with_session(
+            &browser,
+            reqwest::Method::GET,
+            app.url("/api/workspaces"),
+            session,
+        )
+        .json()
+        .unwrap();
+        workspaces
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|workspace| {
+                format!(
+                    "{}:{}",
+                    workspace["name"].as_str().unwrap(),
+                    workspace["role"].as_str().unwrap()
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    idp.sign_in_as(json!({ "email": "ivan", "groups": ["eng", "leads", "analytics", "x"] }));
+    let mut granted = roles(&sign_in(&app, &browser));
+    granted.sort();
+    assert_eq!(granted, ["growth:analyst", "platform:owner"]);
+
+    // Roles follow the groups at every sign-in
+    idp.sign_in_as(json!({ "email": "ivan", "groups": ["eng"] }));
+    let session = sign_in(&app, &browser);
+    assert_eq!(roles(&session), ["platform:editor"]);
+    let keys = with_session(
+        &browser,
+        reqwest::Method::GET,
+        app.url("/api/keys"),
+        &session,
+    );
+    assert_eq!(keys.status(), 403);
+
+    idp.sign_in_as(json!({ "email": "judy", "groups": "ops" }));
+    let admin = sign_in(&app, &browser);
+    let keys = with_session(&browser, reqwest::Method::GET, app.url("/api/keys"), &admin);
+    assert_eq!(keys.status(), 200);
+}
+
+#[test]
+fn test_callback_rejects_forged_and_stale_sign_ins() {
+    let unconfigured = common::spawn_app();
+    let login = unconfigured
+        .anonymous_client()
+        .get(unconfigured.url("/auth/login"))
+        .send()
+        .unwrap();
+    assert_eq!(login.status(), 404);
+
+    let idp = spawn_idp();
+    let app = spawn_app_for(&idp, |_| {});
+    let browser = app.anonymous_client();
+    idp.sign_in_as(json!({ "email": "kim" }));
+
+    // No sign-in cookie, or a tampered one
+    let login = start_login(&app, &browser, "/");
+    let response = browser.get(&login.callback).send().unwrap();
+    assert_eq!(response.status(), 401);
+    let tampered = format!("x{}", login.cookie);
+    assert_eq!(
+        finish_login(&browser, &tampered, &login.callback).status(),
+        401
+    );
+
+    // A callback belonging to another sign-in
+    let other = start_login(&app, &browser, "/");
+    assert_eq!(
+        finish_login(&browser, &login.cookie, &other.callback).status(),
+        401
+    );
+
+    // A code issued for another sign-in fails the PKCE check, even with
+    // the right state
+    let code = url::Url::parse(&other.callback)
+        .unwrap()
+        .query_pairs()
+        .find(|(name, _)| name == "code")
+        .unwrap()
+        .1
+        .into_owned();
+    let third = start_login(&app, &browser, "/");
+    let state = url::Url::parse(&third.callback)
+        .unwrap()
+        .query_pairs()
+        .find(|(name, _)| name == "state")
+        .unwrap()
+        .1
+        .into_owned();
+    let swapped = format!(
+        "{}?code={}&state={}",
+        app.url("/auth/callback"),
+        code,
+        state
+    );
+    assert_eq!(
+        finish_login(&browser, &third.cookie, &swapped).status(),
+        401
+    );
+
+    // The provider refusing the sign-in
+    let refused = format!("{}?error=access_denied", app.url("/auth/callback"));
+    assert_eq!(
+        finish_login(&browser, &third.cookie, &refused).status(),
+        401
+    );
+
+    // Expired ID tokens and unusable usernames
+    idp.sign_in_as(json!({ "email": "kim", "exp": chrono::Utc::now().timestamp() - 3600 }));
+    let login = start_login(&app, &browser, "/");
+    assert_eq!(
+        finish_login(&browser, &login.cookie, &login.callback).status(),
+        401
+    );
+    idp.sign_in_as(json!({ "sub": "no-email" }));
+    let login = start_login(&app, &browser, "/");
+    assert_eq!(
+        finish_login(&browser, &login.cookie, &login.callback).status(),
+        401
+    );
+
+    // A valid sign-in still works afterwards
+    idp.sign_in_as(json!({ "email": "kim" }));
+    let session = sign_in(&app, &browser);
+    let links = with_session(&browser, reqwest::Method::GET, app.url("/"), &session);
+    assert_eq!(links.status(), 200);
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use reqwest::blocking::Client;
+use serde_json::json;
+
+const MEMBER_SCOPES: [&str; 3] = ["links:read", "links:write", "stats:read"];
+
+/// Creates a user and a key acting for them, returning the user id and a
+/// client sending that key.
+fn user_client(app: &common::TestApp, username: &str) -> (i64, Client) {
+    let user: serde_json::Value = app
+        .client()
+        .post(app.url("/api/users"))
+        .json(&json!({ "username": username }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let user_id = user["id"].as_i64().unwrap();
+    let key: serde_json::Value = app
+        .client()
+        .post(app.url("/api/keys"))
+        .json(&json!({ "name": username, "scopes": MEMBER_SCOPES, "user_id": user_id }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    (user_id, app.client_with_key(key["key"].as_str().unwrap()))
+}
+
+fn create_link(app: &common::TestApp, client: &Client, original_url: &str) -> serde_json::Value {
+    let response = client
+        .post(app.url("/"))
+        .json(&json!({ "original_url": original_url }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    response.json().unwrap()
+}
+
+fn listed_codes(app: &common::TestApp, client: &Client) -> Vec<String> {
+    let links: serde_json::Value = client.get(app.url("/")).send().unwrap().json().unwrap();
+    links
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|link| link["short_code"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_users_only_see_and_edit_their_own_links() {
+    let app = common::spawn_app();
+    let (alice_id, alice) = user_client(&app, "alice");
+    let (_, bob) = user_client(&app, "bob");
+
+    let link = create_link(&app, &alice, "https://example.com/alice");
+    let code = link["short_code"].as_str().unwrap();
+    assert_eq!(link["owner_id"], alice_id);
+
+    assert_eq!(listed_codes(&app, &alice), vec![code.to_string()]);
+    assert!(listed_codes(&app, &bob).is_empty());
+    assert!(listed_codes(&app, &app.client()).contains(&code.to_string()));
+
+    let edit_url = app.url(&format!("/api/urls/{}", code));
+    let stats_url = app.url(&format!("/stats/{}", code));
+    let patch = json!({ "original_url": "https://example.com/bob" });
+    assert_eq!(
+        bob.patch(&edit_url).json(&patch).send().unwrap().status(),
+        404
+    );
+    assert_eq!(bob.get(&stats_url).send().unwrap().status(), 404);
+    assert_eq!(bob.delete(&edit_url).send().unwrap().status(), 404);
+
+    assert_eq!(alice.get(&stats_url).send().unwrap().status(), 200);
+    let patch = json!({ "original_url": "https://example.com/alice2" });
+    assert_eq!(
+        alice.patch(&edit_url).json(&patch).send().unwrap().status(),
+        200
+    );
+    // Admins manage every link
+    assert_eq!(app.client().get(&stats_url).send().unwrap().status(), 200);
+}
+
+#[test]
+fn test_export_only_includes_own_clicks() {
+    let app = common::spawn_app();
+    let (_, alice) = user_client(&app, "alice");
+    let (_, bob) = user_client(&app, "bob");
+    let alice_code = create_link(&app, &alice, "https://example.com/a")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let bob_code = create_link(&app, &bob, "https://example.com/b")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    for code in [&alice_code, &bob_code] {
+        let response = app
+            .anonymous_client()
+            .get(app.url(&format!("/{}", code)))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 302);
+    }
+
+    let export = |client: &Client| {
+        client
+            .get(app.url("/api/export/clicks?format=ndjson"))
+            .send()
+            .unwrap()
+            .text()
+            .unwrap()
+    };
+    let bobs = export(&bob);
+    assert_eq!(bobs.lines().count(), 1);
+    assert!(bobs.contains(&bob_code));
+    assert!(!bobs.contains(&alice_code));
+    assert_eq!(export(&app.client()).lines().count(), 2);
+}
+
+#[test]
+fn test_admin_transfers_a_link() {
+    let app = common::spawn_app();
+    let (_, alice) = user_client(&app, "alice");
+    let (bob_id, bob) = user_client(&app, "bob");
+    let code = create_link(&app, &alice, "https://example.com/handover")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let owner_url = app.url(&format!("/api/urls/{}/owner", code));
+
+    let response = alice
+        .put(&owner_url)
+        .json(&json!({ "user_id": bob_id }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = app
+        .client()
+        .put(&owner_url)
+        .json(&json!({ "user_id": bob_id }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().unwrap();
+    assert_eq!(body["owner_id"], bob_id);
+    assert_eq!(listed_codes(&app, &bob), vec![code.clone()]);
+    assert!(listed_codes(&app, &alice).is_empty());
+
+    let response = app
+        .client()
+        .put(&owner_url)
+        .json(&json!({ "user_id": 9999 }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[test]
+fn test_deleting_a_user_revokes_keys_and_moves_links() {
+    let app = common::spawn_app();
+    let (alice_id, alice) = user_client(&app, "alice");
+    let (bob_id, bob) = user_client(&app, "bob");
+    let code = create_link(&app, &alice, "https://example.com/leaver")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let duplicate = app
+        .client()
+        .post(app.url("/api/users"))
+        .json(&json!({ "username": "alice" }))
+        .send()
+        .unwrap();
+    assert_eq!(duplicate.status(), 400);
+
+    // Links are never left without an owner, where every key not tied to a
+    // user could manage them
+    let response = app
+        .client()
+        .delete(app.url(&format!("/api/users/{}", alice_id)))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(listed_codes(&app, &alice), vec![code.clone()]);
+
+    let response = app
+        .client()
+        .delete(app.url(&format!("/api/users/{}?transfer_to={}", alice_id, bob_id)))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    assert_eq!(alice.get(app.url("/")).send().unwrap().status(), 401);
+    assert_eq!(listed_codes(&app, &bob), vec![code]);
+
+    let users: serde_json::Value = app
+        .client()
+        .get(app.url("/api/users"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(users.as_array().unwrap().len(), 1);
+    assert_eq!(users[0]["username"], "bob");
+
+    // Users without links need no transfer_to
+    let (carol_id, _) = user_client(&app, "carol");
+    let response = app
+        .client()
+        .delete(app.url(&format!("/api/users/{}", carol_id)))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 204);
+}
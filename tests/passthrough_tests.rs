@@ -0,0 +1,179 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use serde_json::json;
+
+fn location(app: &common::TestApp, path: &str) -> (u16, Option<String>) {
+    let response = app.client().get(app.url(path)).send().unwrap();
+    let location = response
+        .headers()
+        .get("location")
+        .map(|value| value.to_str().unwrap().to_string());
+    (response.status().as_u16(), location)
+}
+
+#[test]
+fn test_query_string_is_ignored_unless_enabled() {
+    let app = common::spawn_app();
+    let link = app.create_url("https://example.com/landing?ref=short");
+    let code = link["short_code"].as_str().unwrap();
+
+    assert_eq!(
+        location(&app, &format!("/{}?utm_source=mail", code)),
+        (
+            302,
+            Some("https://example.com/landing?ref=short".to_string())
+        )
+    );
+}
+
+#[test]
+fn test_incoming_query_is_merged_into_the_destination() {
+    let app = common::spawn_app();
+    let link = app.create_url_with(json!({
+        "original_url": "https://example.com/landing?ref=short&lang=en",
+        "forward_query": true
+    }));
+    assert_eq!(link["forward_query"], true);
+    let code = link["short_code"].as_str().unwrap();
+
+    assert_eq!(
+        location(&app, &format!("/{}?lang=de&q=a+b", code)),
+        (
+            302,
+            Some("https://example.com/landing?ref=short&lang=de&q=a+b".to_string())
+        )
+    );
+    assert_eq!(
+        location(&app, &format!("/{}", code)),
+        (
+            302,
+            Some("https://example.com/landing?ref=short&lang=en".to_string())
+        )
+    );
+}
+
+#[test]
+fn test_wildcard_links_append_the_rest_of_the_path() {
+    let app = common::spawn_app();
+    let link = app.create_url_with(json!({
+        "original_url": "https://docs.example.com/v2/",
+        "forward_path": true,
+        "forward_query": true
+    }));
+    let code = link["short_code"].as_str().unwrap();
+
+    assert_eq!(
+        location(&app, &format!("/{}/guide/intro%20page?tab=2", code)),
+        (
+            302,
+            Some("https://docs.example.com/v2/guide/intro%20page?tab=2".to_string())
+        )
+    );
+    assert_eq!(
+        location(&app, &format!("/{}", code)),
+        (302, Some("https://docs.example.com/v2/".to_string()))
+    );
+}
+
+#[test]
+fn test_extra_path_is_not_found_without_path_forwarding() {
+    let app = common::spawn_app();
+    let client = app.client();
+    let code = app.create_url("https://example.com")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    assert_eq!(location(&app, &format!("/{}/anything", code)).0, 404);
+
+    let response = client
+        .patch(app.url(&format!("/api/urls/{}", code)))
+        .json(&json!({ "forward_path": true }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        location(&app, &format!("/{}/anything", code)),
+        (302, Some("https://example.com/anything".to_string()))
+    );
+}
+
+/// Sends `path` exactly as given; HTTP clients resolve dot segments before
+/// sending.
+fn raw_get(app: &common::TestApp, path: &str) -> String {
+    let host = app.address.trim_start_matches("http://");
+    let mut stream = TcpStream::connect(host).unwrap();
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_forwarded_paths_cannot_climb_out_of_the_destination() {
+    let app = common::spawn_app();
+    let link = app.create_url_with(json!({
+        "original_url": "https://example.com/docs/",
+        "forward_path": true
+    }));
+    let code = link["short_code"].as_str().unwrap();
+
+    for tail in [
+        "../../admin",
+        "guide/../../admin",
+        "%2e%2e/admin",
+        "%2E%2e/%2e%2E/admin",
+        ".%2e/admin",
+        "./admin",
+        "..\\admin",
+    ] {
+        let response = raw_get(&app, &format!("/{}/{}", code, tail));
+        assert!(response.starts_with("HTTP/1.1 404"), "{}: {}", tail, response);
+        assert!(!response.to_ascii_lowercase().contains("location:"), "{}", tail);
+    }
+    let response = raw_get(&app, &format!("/{}/guide/..intro", code));
+    assert!(response.starts_with("HTTP/1.1 302"), "{}", response);
+    assert!(response.contains("https://example.com/docs/guide/..intro"));
+}
+
+#[test]
+fn test_forwarding_links_forward_preview_and_qr_paths() {
+    let app = common::spawn_app();
+    let link = app.create_url_with(json!({
+        "original_url": "https://docs.example.com/v2/",
+        "forward_path": true,
+        "forward_query": true
+    }));
+    let code = link["short_code"].as_str().unwrap();
+
+    assert_eq!(
+        location(&app, &format!("/{}/preview", code)),
+        (
+            302,
+            Some("https://docs.example.com/v2/preview".to_string())
+        )
+    );
+    assert_eq!(
+        location(&app, &format!("/{}/qr?size=big", code)),
+        (
+            302,
+            Some("https://docs.example.com/v2/qr?size=big".to_string())
+        )
+    );
+    // The preview stays available under `+`
+    let preview = app
+        .client()
+        .get(app.url(&format!("/{}+", code)))
+        .send()
+        .unwrap();
+    assert_eq!(preview.status(), 200);
+    assert!(preview.text().unwrap().contains("docs.example.com/v2/"));
+}
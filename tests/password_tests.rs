@@ -0,0 +1,175 @@
+mod common;
+
+use std::time::Duration;
+
+use serde_json::json;
+
+fn visit(app: &common::TestApp, path: &str, cookie: Option<&str>) -> reqwest::blocking::Response {
+    let mut request = app.client().get(app.url(path));
+    if let Some(cookie) = cookie {
+        request = request.header("Cookie", cookie);
+    }
+    request.send().unwrap()
+}
+
+fn submit(app: &common::TestApp, path: &str, password: &str) -> reqwest::blocking::Response {
+    app.client()
+        .post(app.url(path))
+        .form(&[("password", password)])
+        .send()
+        .unwrap()
+}
+
+/// The `name=value` part of the response's `Set-Cookie` header.
+fn cookie_pair(response: &reqwest::blocking::Response) -> String {
+    let set_cookie = response.headers()["set-cookie"].to_str().unwrap();
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+fn submit_from(
+    app: &common::TestApp,
+    path: &str,
+    forwarded_for: &str,
+) -> reqwest::blocking::Response {
+    app.client()
+        .post(app.url(path))
+        .header("x-forwarded-for", forwarded_for)
+        .form(&[("password", "guess")])
+        .send()
+        .unwrap()
+}
+
+fn protected_link(app: &common::TestApp) -> String {
+    let link = app.create_url_with(json!({
+        "original_url": "https://intranet.example.com/handbook",
+        "password": "hunter2"
+    }));
+    assert_eq!(link["password_protected"], true);
+    assert!(link.get("password_hash").is_none());
+    link["short_code"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn test_protected_links_ask_for_the_password() {
+    let app = common::spawn_app();
+    let code = protected_link(&app);
+
+    let response = visit(&app, &format!("/{}", code), None);
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["cache-control"], "no-store");
+    assert!(response.text().unwrap().contains("<form method=\"post\">"));
+}
+
+#[test]
+fn test_the_right_password_unlocks_the_link() {
+    let app = common::spawn_app();
+    let code = protected_link(&app);
+    let path = format!("/{}?ref=mail", code);
+
+    let unlocked = submit(&app, &path, "hunter2");
+    assert_eq!(unlocked.status(), 303);
+    assert_eq!(unlocked.headers()["location"], path.as_str());
+    let cookie = cookie_pair(&unlocked);
+    assert!(cookie.starts_with(&format!("pw_{}=", code)));
+
+    let response = visit(&app, &path, Some(&cookie));
+    assert_eq!(response.status(), 302);
+    assert_eq!(
+        response.headers()["location"],
+        "https://intranet.example.com/handbook"
+    );
+
+    // A forged signature is not accepted.
+    let (name, value) = cookie.split_once('=').unwrap();
+    let (expires, _) = value.split_once('.').unwrap();
+    let forged = format!("{}={}.{}", name, expires, "00".repeat(32));
+    assert_eq!(visit(&app, &path, Some(&forged)).status(), 200);
+}
+
+#[test]
+fn test_repeated_wrong_passwords_are_rate_limited() {
+    let app = common::spawn_app_with(|config| {
+        config.password_max_attempts = 2;
+        config.password_attempt_window = Duration::from_secs(60);
+    });
+    let code = protected_link(&app);
+    let path = format!("/{}", code);
+
+    let wrong = submit(&app, &path, "guess");
+    assert_eq!(wrong.status(), 401);
+    assert!(wrong.text().unwrap().contains("Incorrect password"));
+    assert_eq!(submit(&app, &path, "guess").status(), 401);
+
+    let limited = submit(&app, &path, "hunter2");
+    assert_eq!(limited.status(), 429);
+    let retry_after: u64 = limited.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+}
+
+#[test]
+fn test_rotating_forwarded_addresses_does_not_reset_the_limit() {
+    let app = common::spawn_app_with(|config| config.password_max_attempts = 2);
+    let code = protected_link(&app);
+    let path = format!("/{}", code);
+
+    assert_eq!(submit_from(&app, &path, "198.51.100.1").status(), 401);
+    assert_eq!(submit_from(&app, &path, "198.51.100.2").status(), 401);
+    assert_eq!(submit_from(&app, &path, "198.51.100.3").status(), 429);
+}
+
+#[test]
+fn test_wrong_passwords_are_also_limited_per_link() {
+    let app = common::spawn_app_with(|config| {
+        config.password_max_attempts = 2;
+        config.password_max_link_attempts = 3;
+        config.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    });
+    let code = protected_link(&app);
+    let other = protected_link(&app);
+
+    // Behind a trusted proxy every address has its own budget...
+    for client in ["198.51.100.1", "198.51.100.2", "198.51.100.3"] {
+        assert_eq!(
+            submit_from(&app, &format!("/{}", code), client).status(),
+            401
+        );
+    }
+    // ...but the link as a whole has had enough
+    assert_eq!(
+        submit_from(&app, &format!("/{}", code), "198.51.100.4").status(),
+        429
+    );
+    assert_eq!(
+        submit_from(&app, &format!("/{}", other), "198.51.100.4").status(),
+        401
+    );
+}
+
+#[test]
+fn test_changing_the_password_revokes_unlock_cookies() {
+    let app = common::spawn_app();
+    let code = protected_link(&app);
+    let path = format!("/{}", code);
+    let cookie = cookie_pair(&submit(&app, &path, "hunter2"));
+
+    let patch = |body: serde_json::Value| {
+        let response = app
+            .client()
+            .patch(app.url(&format!("/api/urls/{}", code)))
+            .json(&body)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        response.json::<serde_json::Value>().unwrap()
+    };
+    patch(json!({ "password": "correct horse" }));
+    assert_eq!(visit(&app, &path, Some(&cookie)).status(), 200);
+
+    let updated = patch(json!({ "password": null }));
+    assert_eq!(updated["password_protected"], false);
+    assert_eq!(visit(&app, &path, None).status(), 302);
+}
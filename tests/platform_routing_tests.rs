@@ -0,0 +1,137 @@
+mod common;
+
+use rust_url_shortener::visitor::Platform;
+use serde_json::json;
+
+const IPHONE_UA: &str =
+    "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) AppleWebKit/605.1.15 Mobile/15E148";
+const ANDROID_UA: &str =
+    "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 Chrome/120.0 Mobile Safari/537.36";
+const DESKTOP_UA: &str =
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 Chrome/120.0 Safari/537.36";
+
+fn location_for(app: &common::TestApp, code: &str, user_agent: &str) -> String {
+    let response = app
+        .client()
+        .get(app.url(&format!("/{}", code)))
+        .header("User-Agent", user_agent)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 302);
+    response.headers()["location"].to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_platforms_are_detected_from_the_user_agent() {
+    assert_eq!(Platform::from_user_agent(Some(IPHONE_UA)), Platform::Ios);
+    assert_eq!(
+        Platform::from_user_agent(Some(ANDROID_UA)),
+        Platform::Android
+    );
+    assert_eq!(
+        Platform::from_user_agent(Some(DESKTOP_UA)),
+        Platform::Desktop
+    );
+    assert_eq!(Platform::from_user_agent(Some("curl/8.0")), Platform::Other);
+    assert_eq!(Platform::from_user_agent(None), Platform::Other);
+}
+
+#[test]
+fn test_visitors_are_sent_to_their_platform_destination() {
+    let app = common::spawn_app();
+    let link = app.create_url_with(json!({
+        "original_url": "https://example.com/app",
+        "platform_destinations": {
+            "ios": "https://apps.apple.com/app/id123",
+            "android": "intent://open#Intent;scheme=example;package=com.example;end"
+        }
+    }));
+    assert_eq!(
+        link["platform_destinations"]["ios"],
+        "https://apps.apple.com/app/id123"
+    );
+    let code = link["short_code"].as_str().unwrap();
+
+    assert_eq!(
+        location_for(&app, code, IPHONE_UA),
+        "https://apps.apple.com/app/id123"
+    );
+    assert_eq!(
+        location_for(&app, code, ANDROID_UA),
+        "intent://open#Intent;scheme=example;package=com.example;end"
+    );
+    assert_eq!(
+        location_for(&app, code, DESKTOP_UA),
+        "https://example.com/app"
+    );
+    assert_eq!(
+        location_for(&app, code, IPHONE_UA),
+        "https://apps.apple.com/app/id123"
+    );
+
+    let stats: serde_json::Value = app
+        .client()
+        .get(app.url(&format!("/stats/{}", code)))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(stats["click_count"], 4);
+    assert_eq!(
+        stats["branches"],
+        json!({ "ios": 2, "android": 1, "default": 1 })
+    );
+    assert!(stats["last_accessed"].is_string());
+}
+
+#[test]
+fn test_platform_destinations_are_validated_and_can_be_removed() {
+    let app = common::spawn_app();
+    let client = app.client();
+
+    for destinations in [
+        json!({}),
+        json!({ "ios": "not a url" }),
+        json!({ "tv": "https://x" }),
+    ] {
+        let response = client
+            .post(app.url("/"))
+            .json(&json!({
+                "original_url": "https://example.com",
+                "platform_destinations": destinations
+            }))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 400, "{}", destinations);
+    }
+
+    let code = app.create_url_with(json!({
+        "original_url": "https://example.com/web",
+        "platform_destinations": { "desktop": "https://example.com/desktop" }
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        location_for(&app, &code, DESKTOP_UA),
+        "https://example.com/desktop"
+    );
+
+    let response = client
+        .patch(app.url(&format!("/api/urls/{}", code)))
+        .json(&json!({ "platform_destinations": null }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        location_for(&app, &code, DESKTOP_UA),
+        "https://example.com/web"
+    );
+}
+
+#[test]
+fn test_stats_of_unknown_code_is_not_found() {
+    let app = common::spawn_app();
+    let response = app.client().get(app.url("/stats/missing")).send().unwrap();
+    assert_eq!(response.status(), 404);
+}
@@ -0,0 +1,188 @@
+mod common;
+
+use serde_json::json;
+
+fn get(app: &common::TestApp, path: &str) -> reqwest::blocking::Response {
+    app.client().get(app.url(path)).send().unwrap()
+}
+
+fn short_code(link: &serde_json::Value) -> String {
+    link["short_code"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn test_preview_shows_the_destination_without_following_it() {
+    let app = common::spawn_app();
+    let code = short_code(&app.create_url("https://example.com/a?b=<c>"));
+    assert_eq!(get(&app, &format!("/{}", code)).status(), 302);
+
+    for path in [format!("/{}+", code), format!("/{}/preview", code)] {
+        let response = get(&app, &path);
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["cache-control"], "no-store");
+        let page = response.text().unwrap();
+        assert!(page.contains("<code>https://example.com/a?b=&lt;c&gt;</code>"));
+        assert!(page.contains("<dt>Clicks</dt><dd>1</dd>"));
+        assert!(page.contains("<dd>Active</dd>"));
+    }
+    assert_eq!(get(&app, "/missing+").status(), 404);
+}
+
+#[test]
+fn test_preview_hides_the_destination_of_protected_links() {
+    let app = common::spawn_app();
+    let code = short_code(&app.create_url_with(json!({
+        "original_url": "https://intranet.example.com/secret",
+        "password": "hunter2"
+    })));
+
+    let page = get(&app, &format!("/{}+", code)).text().unwrap();
+    assert!(page.contains("password-protected"));
+    assert!(!page.contains("intranet.example.com"));
+}
+
+#[test]
+fn test_preview_hides_the_destination_of_click_limited_links() {
+    let app = common::spawn_app();
+    let code = short_code(&app.create_url_with(json!({
+        "original_url": "https://files.example.com/one-time-download",
+        "max_clicks": 1
+    })));
+
+    let page = get(&app, &format!("/{}/preview", code)).text().unwrap();
+    assert!(page.contains("Hidden, this link has a click limit"));
+    assert!(!page.contains("files.example.com"));
+    // Previewing does not use up the click
+    assert_eq!(get(&app, &format!("/{}", code)).status(), 302);
+}
+
+#[test]
+fn test_preview_hides_the_destination_of_links_not_yet_active() {
+    let app = common::spawn_app();
+    let activates_at = (chrono::Utc::now() + chrono::Duration::days(1)).to_rfc3339();
+    let code = short_code(&app.create_url_with(json!({
+        "original_url": "https://example.com/launch",
+        "activates_at": activates_at
+    })));
+
+    let page = get(&app, &format!("/{}+", code)).text().unwrap();
+    assert!(page.contains("Hidden, this link is not active"));
+    assert!(page.contains("Not active until"));
+    assert!(!page.contains("example.com/launch"));
+}
+
+#[test]
+fn test_preview_hides_the_destination_of_quarantined_links() {
+    use diesel::prelude::*;
+    use rust_url_shortener::schema::urls;
+
+    let app = common::spawn_app();
+    let code = short_code(&app.create_url("https://malware.example.com/payload"));
+    diesel::update(urls::table.filter(urls::short_code.eq(&code)))
+        .set(urls::quarantined.eq(true))
+        .execute(&mut app.pool.get().unwrap())
+        .unwrap();
+
+    let page = get(&app, &format!("/{}+", code)).text().unwrap();
+    assert!(page.contains("Hidden, this link is held for review"));
+    assert!(!page.contains("malware.example.com"));
+}
+
+#[test]
+fn test_links_can_force_an_interstitial() {
+    let app = common::spawn_app();
+    let link = app.create_url_with(json!({
+        "original_url": "https://example.com/download",
+        "interstitial": true
+    }));
+    assert_eq!(link["interstitial"], true);
+
+    let response = get(&app, &format!("/{}", short_code(&link)));
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["cache-control"], "no-store");
+    let page = response.text().unwrap();
+    assert!(page.contains("Before you continue"));
+    assert!(page.contains("<code>https://example.com/download</code>"));
+    assert!(page.contains(&format!("<a href=\"/{}\"", short_code(&link))));
+}
+
+#[test]
+fn test_interstitials_count_the_click_only_when_the_visitor_continues() {
+    let app = common::spawn_app();
+    app.client()
+        .put(app.url("/api/interstitials/example.com"))
+        .json(&json!({ "reason": "Downloads are not scanned." }))
+        .send()
+        .unwrap();
+    let code = short_code(&app.create_url_with(json!({
+        "original_url": "https://example.com/once",
+        "max_clicks": 1
+    })));
+    let path = format!("/{}", code);
+
+    // Showing the warning does not use up the one-time link
+    assert_eq!(get(&app, &path).status(), 200);
+    let warned = get(&app, &path);
+    assert_eq!(warned.status(), 200);
+    let set_cookie = warned.headers()["set-cookie"].to_str().unwrap().to_string();
+    assert!(set_cookie.starts_with(&format!("iw_{}=", code)));
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+    let stats: serde_json::Value = get(&app, &format!("/stats/{}", code)).json().unwrap();
+    assert_eq!(stats["click_count"], 0);
+
+    // Continuing redirects and counts the click once
+    let continued = app
+        .client()
+        .get(app.url(&path))
+        .header("Cookie", &cookie)
+        .send()
+        .unwrap();
+    assert_eq!(continued.status(), 302);
+    assert_eq!(continued.headers()["location"], "https://example.com/once");
+    let stats: serde_json::Value = get(&app, &format!("/stats/{}", code)).json().unwrap();
+    assert_eq!(stats["click_count"], 1);
+    assert_eq!(get(&app, &path).status(), 410);
+
+    // The confirmation only covers the link it was given for
+    let other = short_code(&app.create_url("https://example.com/other"));
+    let forged = cookie.replacen(&code, &other, 1);
+    let response = app
+        .client()
+        .get(app.url(&format!("/{}", other)))
+        .header("Cookie", &forged)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[test]
+fn test_flagged_domains_show_an_interstitial_for_their_subdomains() {
+    let app = common::spawn_app();
+    let code = short_code(&app.create_url("https://files.example.com/setup.exe"));
+    let other = short_code(&app.create_url("https://example.org/"));
+
+    let flagged: serde_json::Value = app
+        .client()
+        .put(app.url("/api/interstitials/*.Example.com"))
+        .json(&json!({ "reason": "Downloads are not scanned." }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(flagged["domain"], "example.com");
+
+    let page = get(&app, &format!("/{}", code)).text().unwrap();
+    assert!(page.contains("Downloads are not scanned."));
+    assert_eq!(get(&app, &format!("/{}", other)).status(), 302);
+
+    let listed: serde_json::Value = get(&app, "/api/interstitials").json().unwrap();
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+
+    let deleted = app
+        .client()
+        .delete(app.url("/api/interstitials/example.com"))
+        .send()
+        .unwrap();
+    assert_eq!(deleted.status(), 204);
+    assert_eq!(get(&app, &format!("/{}", code)).status(), 302);
+}
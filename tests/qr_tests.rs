@@ -0,0 +1,131 @@
+mod common;
+
+use image::{ImageFormat, Rgba, RgbaImage};
+use serde_json::json;
+
+fn get(app: &common::TestApp, path: &str) -> reqwest::blocking::Response {
+    app.client().get(app.url(path)).send().unwrap()
+}
+
+fn png(response: reqwest::blocking::Response) -> RgbaImage {
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    let bytes = response.bytes().unwrap();
+    image::load_from_memory_with_format(&bytes, ImageFormat::Png)
+        .unwrap()
+        .to_rgba8()
+}
+
+#[test]
+fn test_png_codes_use_the_requested_size_margin_and_colors() {
+    let app = common::spawn_app();
+    let code = app.create_url("https://example.com/menu")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let image = png(get(
+        &app,
+        &format!("/{}/qr?size=400&margin=2&fg=%23ff0000&bg=00f", code),
+    ));
+    assert_eq!(image.width(), image.height());
+    assert!(image.width() <= 400 && image.width() > 300);
+    assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 255, 255]));
+    // The top-left finder pattern starts right after the two module margin,
+    // and every module is the same whole number of pixels.
+    let finder = (0..image.width())
+        .find(|&offset| *image.get_pixel(offset, offset) == Rgba([255, 0, 0, 255]))
+        .unwrap();
+    assert_eq!(finder % 2, 0);
+    assert_eq!(image.width() % (finder / 2), 0);
+
+    let unmarked = png(get(
+        &app,
+        &format!(
+            "/{}/qr?size=400&margin=2&fg=%23ff0000&bg=00f&marker=false",
+            code
+        ),
+    ));
+    assert_ne!(image, unmarked);
+}
+
+#[test]
+fn test_svg_codes_are_available() {
+    let app = common::spawn_app();
+    let code = app.create_url("https://example.com/menu")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = get(&app, &format!("/{}/qr?format=svg&ecc=H&fg=123456", code));
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "image/svg+xml");
+    let svg = response.text().unwrap();
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    assert!(svg.contains("fill=\"#123456\""));
+}
+
+#[test]
+fn test_invalid_parameters_are_rejected() {
+    let app = common::spawn_app();
+    let code = app.create_url("https://example.com/menu")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    for query in [
+        "format=gif",
+        "size=10",
+        "margin=40",
+        "ecc=X",
+        "fg=red",
+        "fg=000&bg=000000",
+        "logo=true",
+    ] {
+        let response = get(&app, &format!("/{}/qr?{}", code, query));
+        assert_eq!(response.status(), 400, "{}", query);
+    }
+    assert_eq!(get(&app, "/missing/qr").status(), 404);
+}
+
+#[test]
+fn test_logos_are_placed_in_the_middle() {
+    let logo_dir = tempfile::tempdir().unwrap();
+    let logo_path = logo_dir.path().join("logo.png");
+    RgbaImage::from_pixel(16, 16, Rgba([0, 128, 0, 255]))
+        .save_with_format(&logo_path, ImageFormat::Png)
+        .unwrap();
+    let app = common::spawn_app_with(|config| config.qr_logo = Some(logo_path.clone()));
+    let code = app.create_url("https://example.com/menu")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let image = png(get(&app, &format!("/{}/qr?logo=true", code)));
+    let center = image.width() / 2;
+    assert_eq!(*image.get_pixel(center, center), Rgba([0, 128, 0, 255]));
+}
+
+#[test]
+fn test_scans_are_counted_separately_and_the_marker_is_not_forwarded() {
+    let app = common::spawn_app();
+    let code = app.create_url_with(json!({
+        "original_url": "https://example.com/menu",
+        "forward_query": true
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let scanned = get(&app, &format!("/{}?qr=1&table=4", code));
+    assert_eq!(scanned.status(), 302);
+    assert_eq!(
+        scanned.headers()["location"],
+        "https://example.com/menu?table=4"
+    );
+    assert_eq!(get(&app, &format!("/{}", code)).status(), 302);
+
+    let stats: serde_json::Value = get(&app, &format!("/stats/{}", code)).json().unwrap();
+    assert_eq!(stats["click_count"], 2);
+    assert_eq!(stats["scans"], 1);
+}
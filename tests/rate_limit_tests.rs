@@ -0,0 +1,224 @@
+mod common;
+
+use reqwest::blocking::{Client, Response};
+use rust_url_shortener::ratelimit::{RateLimit, StoreKind};
+use serde_json::json;
+
+fn limit(value: &str) -> Option<RateLimit> {
+    Some(value.parse().unwrap())
+}
+
+fn create(app: &common::TestApp, client: &Client) -> Response {
+    client
+        .post(app.url("/"))
+        .json(&json!({ "original_url": "https://example.com/limited" }))
+        .send()
+        .unwrap()
+}
+
+fn header(response: &Response, name: &str) -> u64 {
+    response.headers()[name].to_str().unwrap().parse().unwrap()
+}
+
+fn new_key(app: &common::TestApp, name: &str) -> Client {
+    let key: serde_json::Value = app
+        .client()
+        .post(app.url("/api/keys"))
+        .json(&json!({ "name": name, "scopes": ["links:write"] }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    app.client_with_key(key["key"].as_str().unwrap())
+}
+
+#[test]
+fn test_rate_limit_parsing() {
+    let parsed: RateLimit = "100/15m".parse().unwrap();
+    assert_eq!(parsed.count, 100);
+    assert_eq!(parsed.period.as_secs(), 15 * 60);
+    assert_eq!("60/m".parse::<RateLimit>().unwrap().period.as_secs(), 60);
+    assert_eq!("5/d".parse::<RateLimit>().unwrap().period.as_secs(), 86400);
+    for invalid in ["", "60", "0/m", "60/", "60/w", "x/m", "60/0s"] {
+        assert!(invalid.parse::<RateLimit>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_creation_is_limited_per_key() {
+    let app = common::spawn_app_with(|config| config.rate_limit_create_per_key = limit("3/m"));
+    let client = app.client();
+
+    for remaining in [2, 1, 0] {
+        let response = create(&app, &client);
+        assert_eq!(response.status(), 201);
+        assert_eq!(header(&response, "x-ratelimit-limit"), 3);
+        assert_eq!(header(&response, "x-ratelimit-remaining"), remaining);
+    }
+    let refused = create(&app, &client);
+    assert_eq!(refused.status(), 429);
+    let retry_after = header(&refused, "retry-after");
+    assert!((1..=20).contains(&retry_after), "{}", retry_after);
+    assert_eq!(header(&refused, "x-ratelimit-remaining"), 0);
+    assert_eq!(
+        refused.json::<serde_json::Value>().unwrap()["error"],
+        "Too many requests, try again later"
+    );
+
+    // Other keys have their own budget, and other endpoints are not limited
+    assert_eq!(create(&app, &new_key(&app, "second")).status(), 201);
+    let listed = client.get(app.url("/")).send().unwrap();
+    assert_eq!(listed.status(), 200);
+    assert!(listed.headers().get("x-ratelimit-limit").is_none());
+}
+
+#[test]
+fn test_creation_is_limited_per_ip() {
+    let app = common::spawn_app_with(|config| {
+        config.rate_limit_create_per_key = limit("10/m");
+        config.rate_limit_create_per_ip = limit("2/m");
+    });
+    assert_eq!(create(&app, &new_key(&app, "first")).status(), 201);
+    let second = create(&app, &new_key(&app, "second"));
+    assert_eq!(second.status(), 201);
+    // The tighter of the two buckets is reported
+    assert_eq!(header(&second, "x-ratelimit-limit"), 2);
+    assert_eq!(create(&app, &new_key(&app, "third")).status(), 429);
+}
+
+#[test]
+fn test_refused_requests_take_from_no_bucket() {
+    let app = common::spawn_app_with(|config| {
+        config.rate_limit_create_per_key = limit("2/m");
+        config.rate_limit_create_per_ip = limit("1/m");
+        config.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    });
+    let client = new_key(&app, "shared");
+    let create_from = |forwarded_for: &str| {
+        client
+            .post(app.url("/"))
+            .header("x-forwarded-for", forwarded_for)
+            .json(&json!({ "original_url": "https://example.com/limited" }))
+            .send()
+            .unwrap()
+    };
+
+    assert_eq!(create_from("198.51.100.1").status(), 201);
+    // Refused by the address limit, without draining the key's budget
+    for _ in 0..3 {
+        assert_eq!(create_from("198.51.100.1").status(), 429);
+    }
+    let other = create_from("198.51.100.2");
+    assert_eq!(other.status(), 201);
+    assert_eq!(header(&other, "x-ratelimit-remaining"), 0);
+    assert_eq!(create_from("198.51.100.3").status(), 429);
+}
+
+#[test]
+fn test_redirects_are_limited_per_ip_and_refill() {
+    let app = common::spawn_app_with(|config| config.rate_limit_redirect_per_ip = limit("2/s"));
+    let code = app.create_url("https://example.com/hot")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let visitor = app.anonymous_client();
+    let visit = || visitor.get(app.url(&format!("/{}", code))).send().unwrap();
+
+    assert_eq!(visit().status(), 302);
+    assert_eq!(visit().status(), 302);
+    let refused = visitor
+        .get(app.url(&format!("/{}", code)))
+        .header("accept", "text/html")
+        .send()
+        .unwrap();
+    assert_eq!(refused.status(), 429);
+    assert_eq!(header(&refused, "retry-after"), 1);
+    assert!(refused.text().unwrap().contains("Too many requests"));
+
+    std::thread::sleep(std::time::Duration::from_millis(600));
+    assert_eq!(visit().status(), 302);
+
+    // Previews and QR codes take from the same bucket
+    std::thread::sleep(std::time::Duration::from_millis(1000));
+    let fetch = |path: &str| {
+        visitor
+            .get(app.url(&format!("/{}{}", code, path)))
+            .send()
+            .unwrap()
+            .status()
+    };
+    assert_eq!(fetch("+"), 200);
+    assert_eq!(fetch("/qr"), 200);
+    assert_eq!(fetch("/preview"), 429);
+    assert_eq!(fetch("/qr"), 429);
+
+    // Creation is not limited by the redirect limit
+    for _ in 0..3 {
+        assert_eq!(create(&app, &app.client()).status(), 201);
+    }
+}
+
+#[test]
+fn test_forwarded_addresses_are_only_believed_from_trusted_proxies() {
+    let visit = |app: &common::TestApp, code: &str, forwarded_for: &str| {
+        app.anonymous_client()
+            .get(app.url(&format!("/{}", code)))
+            .header("x-forwarded-for", forwarded_for)
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    // Rotating the header does not give a direct client a new bucket
+    let app = common::spawn_app_with(|config| config.rate_limit_redirect_per_ip = limit("2/m"));
+    let code = app.create_url("https://example.com/spoofed")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(visit(&app, &code, "198.51.100.1"), 302);
+    assert_eq!(visit(&app, &code, "198.51.100.2"), 302);
+    assert_eq!(visit(&app, &code, "198.51.100.3"), 429);
+
+    // Behind a trusted proxy, the address it appended counts
+    let app = common::spawn_app_with(|config| {
+        config.rate_limit_redirect_per_ip = limit("2/m");
+        config.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    });
+    let code = app.create_url("https://example.com/proxied")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(visit(&app, &code, "198.51.100.1"), 302);
+    assert_eq!(visit(&app, &code, "198.51.100.1"), 302);
+    assert_eq!(visit(&app, &code, "198.51.100.1"), 429);
+    assert_eq!(visit(&app, &code, "198.51.100.2"), 302);
+    // Addresses the client put in front of the proxy's are ignored
+    assert_eq!(visit(&app, &code, "203.0.113.9, 198.51.100.1"), 429);
+}
+
+#[test]
+fn test_database_store_is_shared_between_instances() {
+    let configure = |config: &mut rust_url_shortener::config::Config| {
+        config.rate_limit_redirect_per_ip = limit("3/m");
+        config.rate_limit_store = StoreKind::Database;
+    };
+    let first = common::spawn_app_with(configure);
+    let second = common::spawn_app_sharing(&first, configure);
+    let code = first.create_url("https://example.com/shared")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let visit = |app: &common::TestApp| {
+        app.anonymous_client()
+            .get(app.url(&format!("/{}", code)))
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    assert_eq!(visit(&first), 302);
+    assert_eq!(visit(&second), 302);
+    assert_eq!(visit(&first), 302);
+    assert_eq!(visit(&second), 429);
+    assert_eq!(visit(&first), 429);
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use rust_url_shortener::redirect::RedirectStatus;
+use serde_json::json;
+
+fn cache_control(response: &reqwest::blocking::Response) -> &str {
+    response
+        .headers()
+        .get("cache-control")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_links_default_to_an_uncached_302() {
+    let app = common::spawn_app();
+    let link = app.create_url("https://example.com/default");
+    assert_eq!(link["redirect_status"], json!(null));
+
+    let response = app
+        .client()
+        .get(app.url(&format!("/{}", link["short_code"].as_str().unwrap())))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 302);
+    assert_eq!(
+        response.headers()["location"],
+        "https://example.com/default"
+    );
+    assert_eq!(cache_control(&response), "no-store");
+}
+
+#[test]
+fn test_per_link_status_overrides_the_configured_default() {
+    let app = common::spawn_app_with(|config| {
+        config.default_redirect_status = RedirectStatus::TemporaryRedirect;
+        config.redirect_cache_max_age = 600;
+    });
+    let client = app.client();
+
+    let default_link = app.create_url("https://example.com/a");
+    let response = client
+        .get(app.url(&format!(
+            "/{}",
+            default_link["short_code"].as_str().unwrap()
+        )))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 307);
+    assert_eq!(cache_control(&response), "no-store");
+
+    let permanent = app.create_url_with(json!({
+        "original_url": "https://example.com/b",
+        "redirect_status": 301
+    }));
+    assert_eq!(permanent["redirect_status"], 301);
+    let response = client
+        .get(app.url(&format!("/{}", permanent["short_code"].as_str().unwrap())))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 301);
+    assert_eq!(cache_control(&response), "public, max-age=600");
+}
+
+#[test]
+fn test_redirect_status_can_be_changed_and_cleared() {
+    let app = common::spawn_app();
+    let client = app.client();
+    let code = app.create_url("https://example.com/c")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let patch = |body: serde_json::Value| {
+        client
+            .patch(app.url(&format!("/api/urls/{}", code)))
+            .json(&body)
+            .send()
+            .unwrap()
+    };
+    let status_of = || {
+        client
+            .get(app.url(&format!("/{}", code)))
+            .send()
+            .unwrap()
+            .status()
+    };
+
+    let response = patch(json!({ "redirect_status": 308 }));
+    assert_eq!(response.status(), 200);
+    assert_eq!(status_of(), 308);
+
+    let response = patch(json!({ "redirect_status": null }));
+    assert_eq!(response.status(), 200);
+    assert_eq!(status_of(), 302);
+}
+
+#[test]
+fn test_unsupported_status_codes_are_rejected() {
+    let app = common::spawn_app();
+    let client = app.client();
+
+    let response = client
+        .post(app.url("/"))
+        .json(&json!({ "original_url": "https://example.com", "redirect_status": 200 }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let code = app.create_url("https://example.com/d")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let response = client
+        .patch(app.url(&format!("/api/urls/{}", code)))
+        .json(&json!({ "redirect_status": 303 }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use serde_json::json;
+
+fn rules_link(app: &common::TestApp) -> String {
+    app.create_url_with(json!({
+        "original_url": "https://example.com/",
+        "rules": [
+            {
+                "name": "german",
+                "when": { "countries": ["de", "AT"], "languages": ["de"] },
+                "destination": "https://example.de/"
+            },
+            {
+                "name": "partner",
+                "when": { "referrers": ["partner.com"], "query": { "promo": null } },
+                "destination": "https://example.com/partner"
+            },
+            {
+                "when": { "devices": ["bot"] },
+                "destination": "https://example.com/bots"
+            }
+        ]
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_first_matching_rule_decides_the_destination() {
+    let app = common::spawn_app();
+    let client = app.client();
+    let code = rules_link(&app);
+    let location = |path: &str, headers: &[(&str, &str)]| {
+        let mut request = client.get(app.url(path));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let response = request.send().unwrap();
+        assert_eq!(response.status(), 302);
+        response.headers()["location"].to_str().unwrap().to_string()
+    };
+
+    let german = [
+        ("CF-IPCountry", "AT"),
+        ("Accept-Language", "en;q=0.5, de-AT"),
+    ];
+    assert_eq!(
+        location(&format!("/{}", code), &german),
+        "https://example.de/"
+    );
+
+    // Country matches but the preferred language does not.
+    let english = [
+        ("CF-IPCountry", "DE"),
+        ("Accept-Language", "en-US,de;q=0.9"),
+    ];
+    assert_eq!(
+        location(&format!("/{}", code), &english),
+        "https://example.com/"
+    );
+
+    let partner = [("Referer", "https://blog.partner.com/post")];
+    assert_eq!(
+        location(&format!("/{}?promo=spring", code), &partner),
+        "https://example.com/partner"
+    );
+    assert_eq!(
+        location(&format!("/{}", code), &partner),
+        "https://example.com/"
+    );
+
+    assert_eq!(
+        location(&format!("/{}", code), &[("User-Agent", "curl/8.0")]),
+        "https://example.com/bots"
+    );
+
+    let stats: serde_json::Value = client
+        .get(app.url(&format!("/stats/{}", code)))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(
+        stats["branches"],
+        json!({ "default": 2, "rule:3": 1, "rule:german": 1, "rule:partner": 1 })
+    );
+}
+
+#[test]
+fn test_dry_run_reports_the_matching_rule() {
+    let app = common::spawn_app();
+    let client = app.client();
+    let code = rules_link(&app);
+    let dry_run = |body: serde_json::Value| -> serde_json::Value {
+        let response = client
+            .post(app.url(&format!("/api/urls/{}/rules/dry-run", code)))
+            .json(&body)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        response.json().unwrap()
+    };
+
+    let result = dry_run(json!({ "country": "de", "accept_language": "de-DE" }));
+    assert_eq!(result["matched_rule"]["index"], 0);
+    assert_eq!(result["matched_rule"]["label"], "german");
+    assert_eq!(result["branch"], "rule:german");
+    assert_eq!(result["destination"], "https://example.de/");
+
+    let result = dry_run(json!({ "country": "FR" }));
+    assert_eq!(result["matched_rule"], json!(null));
+    assert_eq!(result["destination"], "https://example.com/");
+
+    // Unsaved rules can be tried out, here a weekend night window.
+    let weekend_nights = json!([{
+        "when": { "time": { "days": ["sat", "sun"], "from": "22:00", "to": "06:00" } },
+        "destination": "https://example.com/night"
+    }]);
+    let saturday_late = dry_run(json!({ "time": "2026-10-17T23:30:00Z", "rules": weekend_nights }));
+    assert_eq!(saturday_late["destination"], "https://example.com/night");
+    let saturday_noon = dry_run(json!({ "time": "2026-10-17T12:00:00Z", "rules": weekend_nights }));
+    assert_eq!(saturday_noon["destination"], "https://example.com/");
+    let monday_late = dry_run(json!({ "time": "2026-10-19T23:30:00Z", "rules": weekend_nights }));
+    assert_eq!(monday_late["destination"], "https://example.com/");
+}
+
+#[test]
+fn test_invalid_rules_are_rejected_on_save() {
+    let app = common::spawn_app();
+    let client = app.client();
+    let code = app.create_url("https://example.com")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    for rule in [
+        json!({ "destination": "not a url" }),
+        json!({ "when": { "countries": ["Germany"] }, "destination": "https://x.de" }),
+        json!({ "when": { "languages": [] }, "destination": "https://x.de" }),
+        json!({ "when": { "time": { "from": "25:00" } }, "destination": "https://x.de" }),
+        json!({ "when": { "devices": ["fridge"] }, "destination": "https://x.de" }),
+        json!({ "when": { "weather": "rain" }, "destination": "https://x.de" }),
+    ] {
+        let response = client
+            .patch(app.url(&format!("/api/urls/{}", code)))
+            .json(&json!({ "rules": [rule] }))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 400, "{}", rule);
+    }
+
+    let response = client
+        .patch(app.url(&format!("/api/urls/{}", code)))
+        .json(&json!({ "rules": [{ "destination": "https://example.com/always" }] }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = response.json().unwrap();
+    assert_eq!(
+        body["rules"][0]["destination"],
+        "https://example.com/always"
+    );
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use serde_json::json;
+
+fn in_hours(hours: i64) -> String {
+    (Utc::now() + Duration::hours(hours)).to_rfc3339()
+}
+
+fn visit(app: &common::TestApp, code: &str) -> reqwest::blocking::Response {
+    app.client()
+        .get(app.url(&format!("/{}", code)))
+        .send()
+        .unwrap()
+}
+
+fn click_count(app: &common::TestApp, code: &str) -> i64 {
+    let stats: serde_json::Value = app
+        .client()
+        .get(app.url(&format!("/stats/{}", code)))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    stats["click_count"].as_i64().unwrap()
+}
+
+#[test]
+fn test_links_before_activation_show_a_coming_soon_page() {
+    let app = common::spawn_app();
+    let link = app.create_url_with(json!({
+        "original_url": "https://example.com/launch",
+        "activates_at": in_hours(2)
+    }));
+    assert!(link["activates_at"].is_string());
+    let code = link["short_code"].as_str().unwrap();
+
+    let response = visit(&app, code);
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["cache-control"], "no-store");
+    assert!(response.text().unwrap().contains("Coming soon"));
+    assert_eq!(click_count(&app, code), 0);
+}
+
+#[test]
+fn test_links_inside_their_window_redirect() {
+    let app = common::spawn_app();
+    let code = app.create_url_with(json!({
+        "original_url": "https://example.com/launch",
+        "activates_at": in_hours(-1),
+        "deactivates_at": in_hours(1)
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = visit(&app, &code);
+    assert_eq!(response.status(), 302);
+    assert_eq!(response.headers()["location"], "https://example.com/launch");
+    assert_eq!(click_count(&app, &code), 1);
+}
+
+#[test]
+fn test_deactivated_and_expired_links_are_gone() {
+    let app = common::spawn_app();
+    for field in ["deactivates_at", "expiration_date"] {
+        let code = app.create_url_with(json!({
+            "original_url": "https://example.com/old",
+            field: in_hours(-1)
+        }))["short_code"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(visit(&app, &code).status(), 410, "{}", field);
+    }
+}
+
+#[test]
+fn test_fallback_url_is_used_outside_the_window() {
+    let app = common::spawn_app();
+    let client = app.client();
+    let code = app.create_url_with(json!({
+        "original_url": "https://example.com/launch",
+        "activates_at": in_hours(1),
+        "fallback_url": "https://example.com/waitlist"
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = visit(&app, &code);
+    assert_eq!(response.status(), 302);
+    assert_eq!(
+        response.headers()["location"],
+        "https://example.com/waitlist"
+    );
+
+    // Clearing the activation time makes the link live immediately.
+    let response = client
+        .patch(app.url(&format!("/api/urls/{}", code)))
+        .json(&json!({ "activates_at": null }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        visit(&app, &code).headers()["location"],
+        "https://example.com/launch"
+    );
+}
+
+#[test]
+fn test_windows_must_end_after_they_start() {
+    let app = common::spawn_app();
+    let client = app.client();
+
+    let response = client
+        .post(app.url("/"))
+        .json(&json!({
+            "original_url": "https://example.com",
+            "activates_at": in_hours(2),
+            "deactivates_at": in_hours(1)
+        }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let code = app.create_url_with(json!({
+        "original_url": "https://example.com",
+        "activates_at": in_hours(2)
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let response = client
+        .patch(app.url(&format!("/api/urls/{}", code)))
+        .json(&json!({ "deactivates_at": in_hours(1) }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = client.get(app.url("/")).send().unwrap().json().unwrap();
+    assert_eq!(body[0]["deactivates_at"], json!(null));
+}
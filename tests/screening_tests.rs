@@ -0,0 +1,245 @@
+mod common;
+
+use std::{path::Path, thread, time::Duration};
+
+use rust_url_shortener::screening::{parse_feed_sources, url_expressions, ThreatList};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+fn write_feed(path: &Path, text: &str) {
+    // Let the modification time move on even on coarse filesystems
+    thread::sleep(Duration::from_millis(20));
+    std::fs::write(path, text).unwrap();
+}
+
+fn sha256_hex(text: &str) -> String {
+    hex::encode(Sha256::digest(text.as_bytes()))
+}
+
+fn code(link: &Value) -> String {
+    link["short_code"].as_str().unwrap().to_string()
+}
+
+fn visit(app: &common::TestApp, short_code: &str, accept: &str) -> reqwest::blocking::Response {
+    app.anonymous_client()
+        .get(app.url(&format!("/{}", short_code)))
+        .header("accept", accept)
+        .send()
+        .unwrap()
+}
+
+fn queue(app: &common::TestApp, query: &str) -> Vec<Value> {
+    app.client()
+        .get(app.url(&format!("/api/quarantine{}", query)))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap()
+}
+
+#[test]
+fn test_url_expressions_and_feed_syntax() {
+    let expressions = url_expressions("http://a.b.example/1/2.html?x=1");
+    assert_eq!(expressions[0], "a.b.example/1/2.html?x=1");
+    for expected in [
+        "a.b.example/1/2.html",
+        "a.b.example/",
+        "a.b.example/1/",
+        "b.example/1/2.html?x=1",
+        "b.example/",
+    ] {
+        assert!(expressions.contains(&expected.to_string()), "{}", expected);
+    }
+    assert!(!expressions
+        .iter()
+        .any(|expression| expression.starts_with("example/")));
+    assert!(url_expressions("mailto:someone@example.com").is_empty());
+
+    let list = ThreatList::parse_urls(
+        "# URLhaus dump\n\
+         \"id\",\"dateadded\",\"url\",\"url_status\"\n\
+         \"1\",\"2026-10-01\",\"http://malware.test/payload.exe\",\"online\"\n\
+         https://phish.test/\n",
+    );
+    assert_eq!(list.len(), 2);
+    assert!(list.matches(&url_expressions("http://malware.test/payload.exe")));
+    assert!(!list.matches(&url_expressions("http://malware.test/other")));
+    assert!(list.matches(&url_expressions("https://login.phish.test/account?id=1")));
+
+    assert!(ThreatList::parse_hashes("# prefixes\ndeadbeef\n").is_ok());
+    let err = ThreatList::parse_hashes("deadbeef\nnot-hex\n").unwrap_err();
+    assert!(err.contains("line 2"), "{}", err);
+    assert!(ThreatList::parse_hashes("abc").is_err());
+
+    let sources = parse_feed_sources("urls:/feeds/urlhaus.csv, hashes:/feeds/sb.txt").unwrap();
+    assert_eq!(sources.len(), 2);
+    assert!(parse_feed_sources("csv:/feeds/x").is_err());
+}
+
+#[test]
+fn test_flagged_links_are_quarantined_and_released() {
+    let dir = tempfile::tempdir().unwrap();
+    let feed = dir.path().join("urlhaus.csv");
+    write_feed(
+        &feed,
+        "\"1\",\"2026-10-01\",\"http://malware.test/\",\"online\"\n",
+    );
+    let app = common::spawn_app_with(|config| {
+        config.threat_feeds = parse_feed_sources(&format!("urls:{}", feed.display())).unwrap()
+    });
+
+    let flagged = app.create_url("http://cdn.malware.test/download.exe");
+    assert_eq!(flagged["quarantined"], true);
+    let clean = app.create_url("https://example.com/");
+    assert_eq!(clean["quarantined"], false);
+    assert_eq!(visit(&app, &code(&clean), "*/*").status(), 302);
+
+    let refused = visit(&app, &code(&flagged), "*/*");
+    assert_eq!(refused.status(), 403);
+    assert!(refused.headers().get("location").is_none());
+    let page = visit(&app, &code(&flagged), "text/html");
+    assert_eq!(page.status(), 403);
+    assert!(page.text().unwrap().contains("held for review"));
+
+    let pending = queue(&app, "?status=pending");
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0]["short_code"], code(&flagged));
+    assert_eq!(pending[0]["feed"], "urlhaus");
+    assert_eq!(
+        pending[0]["destination"],
+        "http://cdn.malware.test/download.exe"
+    );
+
+    let released: Value = app
+        .client()
+        .post(app.url(&format!("/api/quarantine/{}/release", pending[0]["id"])))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(released["status"], "released");
+    assert_eq!(released["reviewed_by"], "tests");
+    assert_eq!(visit(&app, &code(&flagged), "*/*").status(), 302);
+    assert!(queue(&app, "?status=pending").is_empty());
+
+    // A released destination is not flagged again by later scans
+    let scanned: Value = app
+        .client()
+        .post(app.url("/api/quarantine/scan"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(scanned["quarantined"], 0);
+    assert_eq!(visit(&app, &code(&flagged), "*/*").status(), 302);
+
+    assert_eq!(
+        app.client()
+            .get(app.url("/api/quarantine?status=unknown"))
+            .send()
+            .unwrap()
+            .status(),
+        400
+    );
+    assert_eq!(
+        app.client()
+            .post(app.url("/api/quarantine/999/confirm"))
+            .send()
+            .unwrap()
+            .status(),
+        404
+    );
+}
+
+#[test]
+fn test_hash_prefix_feed_flags_edits_and_confirmation_keeps_quarantine() {
+    let dir = tempfile::tempdir().unwrap();
+    let feed = dir.path().join("safe-browsing.txt");
+    write_feed(
+        &feed,
+        &format!("# prefixes\n{}\n", &sha256_hex("bad.example/")[..8]),
+    );
+    let app = common::spawn_app_with(|config| {
+        config.threat_feeds = parse_feed_sources(&format!("hashes:{}", feed.display())).unwrap()
+    });
+
+    let link = app.create_url("https://good.example/");
+    assert_eq!(link["quarantined"], false);
+    let edited: Value = app
+        .client()
+        .patch(app.url(&format!("/api/urls/{}", code(&link))))
+        .json(&json!({ "fallback_url": "https://www.bad.example/x/y" }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(edited["quarantined"], true);
+
+    let entry = &queue(&app, "")[0];
+    assert_eq!(entry["feed"], "safe-browsing");
+    let confirmed: Value = app
+        .client()
+        .post(app.url(&format!("/api/quarantine/{}/confirm", entry["id"])))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(confirmed["status"], "confirmed");
+    assert_eq!(visit(&app, &code(&link), "*/*").status(), 403);
+
+    // Deleting a quarantined link removes its queue entries
+    let deleted = app
+        .client()
+        .delete(app.url(&format!("/api/urls/{}", code(&link))))
+        .send()
+        .unwrap();
+    assert_eq!(deleted.status(), 204);
+    assert!(queue(&app, "").is_empty());
+}
+
+#[test]
+fn test_background_scan_quarantines_existing_links() {
+    let dir = tempfile::tempdir().unwrap();
+    let feed = dir.path().join("phishtank.csv");
+    write_feed(&feed, "phish_id,url\n");
+    let feed_path = feed.clone();
+    let app = common::spawn_app_with(|config| {
+        config.threat_feeds = parse_feed_sources(&format!("urls:{}", feed_path.display())).unwrap();
+        config.threat_scan_interval = Duration::from_millis(100);
+    });
+    let link = app.create_url("https://turned-bad.example/login");
+    assert_eq!(link["quarantined"], false);
+    assert_eq!(visit(&app, &code(&link), "*/*").status(), 302);
+
+    write_feed(&feed, "phish_id,url\n42,https://turned-bad.example/login\n");
+    let quarantined = (0..50).any(|_| {
+        thread::sleep(Duration::from_millis(100));
+        visit(&app, &code(&link), "*/*").status() == 403
+    });
+    assert!(
+        quarantined,
+        "the background scan did not quarantine the link"
+    );
+    assert_eq!(queue(&app, "?status=pending").len(), 1);
+}
+
+#[test]
+fn test_scan_requires_feeds_and_admin() {
+    let app = common::spawn_app();
+    assert_eq!(
+        app.client()
+            .post(app.url("/api/quarantine/scan"))
+            .send()
+            .unwrap()
+            .status(),
+        400
+    );
+    assert_eq!(
+        app.anonymous_client()
+            .get(app.url("/api/quarantine"))
+            .send()
+            .unwrap()
+            .status(),
+        401
+    );
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use serde_json::json;
+
+fn split_link(app: &common::TestApp, sticky: bool) -> String {
+    app.create_url_with(json!({
+        "original_url": "https://example.com/",
+        "split": {
+            "variants": [
+                { "name": "a", "url": "https://example.com/a", "weight": 1 },
+                { "name": "b", "url": "https://example.com/b", "weight": 1 }
+            ],
+            "sticky": sticky
+        }
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_clicks_rotate_between_variants_and_are_counted() {
+    let app = common::spawn_app();
+    let client = app.client();
+    let code = split_link(&app, false);
+
+    for _ in 0..20 {
+        let response = client.get(app.url(&format!("/{}", code))).send().unwrap();
+        assert_eq!(response.status(), 302);
+        assert!(response.headers().get("set-cookie").is_none());
+        let location = response.headers()["location"].to_str().unwrap();
+        assert!(
+            location == "https://example.com/a" || location == "https://example.com/b",
+            "{}",
+            location
+        );
+    }
+
+    let stats: serde_json::Value = client
+        .get(app.url(&format!("/stats/{}", code)))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(stats["click_count"], 20);
+    assert_eq!(stats["branches"], json!({ "variant": 20 }));
+    let a = stats["variants"]["a"].as_i64().unwrap_or(0);
+    let b = stats["variants"]["b"].as_i64().unwrap_or(0);
+    assert_eq!(a + b, 20);
+    assert!(a > 0 && b > 0, "both variants should be picked: {}", stats);
+}
+
+#[test]
+fn test_sticky_variants_are_remembered_in_a_cookie() {
+    let app = common::spawn_app();
+    let client = app.client();
+    let code = split_link(&app, true);
+
+    let response = client.get(app.url(&format!("/{}", code))).send().unwrap();
+    let first = response.headers()["location"].to_str().unwrap().to_string();
+    let cookie = response.headers()["set-cookie"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let variant = first.rsplit('/').next().unwrap();
+    assert!(
+        cookie.starts_with(&format!("ab_{}={};", code, variant)),
+        "{}",
+        cookie
+    );
+    assert!(cookie.contains(&format!("Path=/{}", code)));
+
+    for _ in 0..5 {
+        let response = client
+            .get(app.url(&format!("/{}", code)))
+            .header("Cookie", format!("ab_{}={}", code, variant))
+            .send()
+            .unwrap();
+        assert_eq!(response.headers()["location"], first.as_str());
+    }
+
+    // A variant that no longer exists is replaced by a fresh assignment.
+    let response = client
+        .get(app.url(&format!("/{}", code)))
+        .header("Cookie", format!("ab_{}=removed", code))
+        .send()
+        .unwrap();
+    let cookie = response.headers()["set-cookie"].to_str().unwrap();
+    assert!(!cookie.contains("=removed"));
+}
+
+#[test]
+fn test_permanent_split_redirects_are_only_cached_privately() {
+    let app = common::spawn_app();
+    let code = app.create_url_with(json!({
+        "original_url": "https://example.com/",
+        "redirect_status": 301,
+        "split": { "variants": [{ "name": "only", "url": "https://example.com/only" }] }
+    }))["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = app
+        .client()
+        .get(app.url(&format!("/{}", code)))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 301);
+    assert_eq!(response.headers()["location"], "https://example.com/only");
+    assert_eq!(response.headers()["cache-control"], "private, max-age=3600");
+}
+
+#[test]
+fn test_invalid_splits_are_rejected() {
+    let app = common::spawn_app();
+    let client = app.client();
+    let variant = |name: &str, weight: u32| json!({ "name": name, "url": "https://example.com", "weight": weight });
+
+    for split in [
+        json!({ "variants": [] }),
+        json!({ "variants": [variant("a", 1), variant("a", 2)] }),
+        json!({ "variants": [variant("a", 0)] }),
+        json!({ "variants": [{ "name": "a", "url": "example.com" }] }),
+    ] {
+        let response = client
+            .post(app.url("/"))
+            .json(&json!({ "original_url": "https://example.com", "split": split }))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 400, "{}", split);
+    }
+}
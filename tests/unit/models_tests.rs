@@ -9,6 +9,7 @@ mod tests {
         let new_url = NewUrl {
             original_url: "https://example.com".to_string(),
             short_code: "abc123".to_string(),
+            ..Default::default()
         };
 
         assert_eq!(new_url.original_url, "https://example.com");
@@ -21,6 +22,7 @@ mod tests {
         let new_url = NewUrl {
             original_url: long_url.clone(),
             short_code: "test123".to_string(),
+            ..Default::default()
         };
 
         assert_eq!(new_url.original_url, long_url);
@@ -32,6 +34,7 @@ mod tests {
         let new_url = NewUrl {
             original_url: url_with_params.to_string(),
             short_code: "xyz789".to_string(),
+            ..Default::default()
         };
 
         assert_eq!(new_url.original_url, url_with_params);
@@ -0,0 +1,138 @@
+mod common;
+
+use serde_json::json;
+
+fn location(app: &common::TestApp, path: &str) -> String {
+    let response = app.client().get(app.url(path)).send().unwrap();
+    assert_eq!(response.status(), 302);
+    response.headers()["location"].to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_link_template_is_appended_without_replacing_destination_params() {
+    let app = common::spawn_app();
+    let link = app.create_url_with(json!({
+        "original_url": "https://example.com/sale?utm_source=print",
+        "utm": { "source": "newsletter", "medium": "email", "campaign": "spring sale" }
+    }));
+    assert_eq!(link["utm"]["medium"], "email");
+    let code = link["short_code"].as_str().unwrap();
+
+    assert_eq!(
+        location(&app, &format!("/{}", code)),
+        "https://example.com/sale?utm_source=print&utm_medium=email&utm_campaign=spring+sale"
+    );
+}
+
+#[test]
+fn test_tag_templates_fill_fields_the_link_leaves_unset() {
+    let app = common::spawn_app();
+    let client = app.client();
+    for (tag, template) in [
+        (
+            "email",
+            json!({ "source": "newsletter", "medium": "email" }),
+        ),
+        (
+            "spring",
+            json!({ "campaign": "spring", "medium": "ignored" }),
+        ),
+    ] {
+        let response = client
+            .put(app.url(&format!("/api/tags/{}/utm", tag)))
+            .json(&template)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 200);
+    }
+    let body: serde_json::Value = client
+        .get(app.url("/api/tags/email/utm"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(body["utm"]["source"], "newsletter");
+
+    let link = app.create_url_with(json!({
+        "original_url": "https://example.com/",
+        "tags": ["spring", "email"],
+        "utm": { "source": "vip" }
+    }));
+    let code = link["short_code"].as_str().unwrap();
+    assert_eq!(
+        location(&app, &format!("/{}", code)),
+        "https://example.com/?utm_source=vip&utm_medium=email&utm_campaign=spring"
+    );
+
+    let response = client
+        .delete(app.url("/api/tags/spring/utm"))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 204);
+    assert_eq!(
+        location(&app, &format!("/{}", code)),
+        "https://example.com/?utm_source=vip&utm_medium=email"
+    );
+}
+
+#[test]
+fn test_precedence_decides_between_incoming_and_template_params() {
+    let app = common::spawn_app();
+    let incoming = app.create_url_with(json!({
+        "original_url": "https://example.com/",
+        "forward_query": true,
+        "utm": { "source": "template", "medium": "email" }
+    }));
+    let template = app.create_url_with(json!({
+        "original_url": "https://example.com/",
+        "forward_query": true,
+        "utm": { "source": "template", "medium": "email", "precedence": "template" }
+    }));
+
+    assert_eq!(
+        location(
+            &app,
+            &format!(
+                "/{}?utm_source=partner&x=1",
+                incoming["short_code"].as_str().unwrap()
+            )
+        ),
+        "https://example.com/?utm_medium=email&utm_source=partner&x=1"
+    );
+    assert_eq!(
+        location(
+            &app,
+            &format!(
+                "/{}?utm_source=partner&x=1",
+                template["short_code"].as_str().unwrap()
+            )
+        ),
+        "https://example.com/?utm_source=template&utm_medium=email&x=1"
+    );
+}
+
+#[test]
+fn test_invalid_templates_are_rejected() {
+    let app = common::spawn_app();
+    let client = app.client();
+
+    for template in [json!({}), json!({ "source": " " }), json!({ "sauce": "x" })] {
+        let response = client
+            .put(app.url("/api/tags/email/utm"))
+            .json(&template)
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 400, "{}", template);
+    }
+
+    let code = app.create_url("https://example.com/")["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let response = client
+        .patch(app.url(&format!("/api/urls/{}", code)))
+        .json(&json!({ "utm": { "campaign": "" } }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
@@ -0,0 +1,368 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rust_url_shortener::webhooks::{sign, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER};
+use serde_json::json;
+
+/// A request captured by the stand-in webhook receiver.
+struct Captured {
+    headers: HashMap<String, String>,
+    body: String,
+}
+
+/// Minimal local HTTP endpoint standing in for a webhook consumer.
+/// Replies to every request with `status`.
+struct Receiver {
+    url: String,
+    requests: Arc<Mutex<Vec<Captured>>>,
+}
+
+impl Receiver {
+    fn start(status: u16) -> Self {
+        Self::start_slow(status, Duration::ZERO)
+    }
+
+    /// Like [`Receiver::start`], but taking `delay` to answer each request.
+    fn start_slow(status: u16, delay: Duration) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let captured = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = HashMap::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(':') else {
+                        break;
+                    };
+                    headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+                }
+                let length = headers
+                    .get("content-length")
+                    .map_or(0, |len| len.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                captured.lock().unwrap().push(Captured {
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+                thread::sleep(delay);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+            }
+        });
+        Receiver { url, requests }
+    }
+
+    /// Waits until at least `count` requests have arrived.
+    fn wait_for(&self, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while self.requests.lock().unwrap().len() < count {
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for {} webhook calls",
+                count
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn events(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.headers[&EVENT_HEADER.to_ascii_lowercase()].clone())
+            .collect()
+    }
+}
+
+fn spawn_app() -> common::TestApp {
+    common::spawn_app_with(|config| {
+        config.webhook_poll_interval = Duration::from_millis(50);
+        config.webhook_retry_base = Duration::from_millis(50);
+        config.webhook_max_attempts = 3;
+    })
+}
+
+fn register(app: &common::TestApp, body: serde_json::Value) -> serde_json::Value {
+    let response = app
+        .client()
+        .post(app.url("/api/webhooks"))
+        .json(&body)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    response.json().unwrap()
+}
+
+fn deliveries(
+    app: &common::TestApp,
+    webhook_id: &serde_json::Value,
+    query: &str,
+) -> Vec<serde_json::Value> {
+    app.client()
+        .get(app.url(&format!("/api/webhooks/{}/deliveries{}", webhook_id, query)))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap()
+}
+
+#[test]
+fn test_created_event_is_signed_and_logged() {
+    let app = spawn_app();
+    let receiver = Receiver::start(200);
+    let webhook = register(
+        &app,
+        json!({ "target_url": receiver.url, "secret": "topsecret" }),
+    );
+    assert_eq!(webhook["secret"], "topsecret");
+
+    let created = app.create_url("https://example.com/hooked");
+    receiver.wait_for(1);
+
+    let requests = receiver.requests.lock().unwrap();
+    let request = &requests[0];
+    assert_eq!(
+        request.headers[&EVENT_HEADER.to_ascii_lowercase()],
+        "link.created"
+    );
+    assert_eq!(
+        request.headers[&SIGNATURE_HEADER.to_ascii_lowercase()],
+        sign("topsecret", request.body.as_bytes())
+    );
+    let payload: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(payload["event"], "link.created");
+    assert_eq!(payload["data"]["short_code"], created["short_code"]);
+    drop(requests);
+
+    // The worker records the outcome right after the request completes.
+    thread::sleep(Duration::from_millis(200));
+    let log = deliveries(&app, &webhook["id"], "");
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0]["status"], "delivered");
+    assert_eq!(log[0]["last_status_code"], 200);
+    assert_eq!(
+        receiver.requests.lock().unwrap()[0].headers[&DELIVERY_HEADER.to_ascii_lowercase()],
+        log[0]["id"].to_string()
+    );
+}
+
+#[test]
+fn test_update_and_delete_events_respect_subscription() {
+    let app = spawn_app();
+    let receiver = Receiver::start(204);
+    register(
+        &app,
+        json!({ "target_url": receiver.url, "events": ["link.updated", "link.deleted"] }),
+    );
+
+    let created = app.create_url("https://example.com/before");
+    let code = created["short_code"].as_str().unwrap();
+    let response = app
+        .client()
+        .patch(app.url(&format!("/api/urls/{}", code)))
+        .json(&json!({ "original_url": "https://example.com/after", "tags": ["edited"] }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let updated: serde_json::Value = response.json().unwrap();
+    assert_eq!(updated["original_url"], "https://example.com/after");
+    assert_eq!(updated["tags"], json!(["edited"]));
+
+    let response = app
+        .client()
+        .delete(app.url(&format!("/api/urls/{}", code)))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 204);
+    let response = app
+        .client()
+        .get(app.url(&format!("/{}", code)))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    receiver.wait_for(2);
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(receiver.events(), vec!["link.updated", "link.deleted"]);
+}
+
+#[test]
+fn test_failed_deliveries_are_retried_then_dead_lettered() {
+    let app = spawn_app();
+    let receiver = Receiver::start(500);
+    let webhook = register(&app, json!({ "target_url": receiver.url }));
+
+    app.create_url("https://example.com/unreachable");
+    receiver.wait_for(3);
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(
+        receiver.requests.lock().unwrap().len(),
+        3,
+        "no attempts after the limit"
+    );
+
+    let dead = deliveries(&app, &webhook["id"], "?status=dead");
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0]["attempts"], 3);
+    assert_eq!(dead[0]["last_status_code"], 500);
+
+    let response = app
+        .client()
+        .post(app.url(&format!("/api/webhooks/deliveries/{}/retry", dead[0]["id"])))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    receiver.wait_for(4);
+}
+
+#[test]
+fn test_click_milestone_fires_once() {
+    let app = spawn_app();
+    let receiver = Receiver::start(200);
+    register(
+        &app,
+        json!({
+            "target_url": receiver.url,
+            "events": ["link.click_milestone"],
+            "click_milestones": [2]
+        }),
+    );
+
+    let created = app.create_url("https://example.com/popular");
+    let code = created["short_code"].as_str().unwrap();
+    for _ in 0..3 {
+        let response = app
+            .client()
+            .get(app.url(&format!("/{}", code)))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 302);
+    }
+
+    receiver.wait_for(1);
+    thread::sleep(Duration::from_millis(300));
+    let requests = receiver.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    let payload: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+    assert_eq!(payload["data"]["clicks"], 2);
+}
+
+#[test]
+fn test_expired_links_are_announced() {
+    let app = spawn_app();
+    let receiver = Receiver::start(200);
+    register(
+        &app,
+        json!({ "target_url": receiver.url, "events": ["link.expired"] }),
+    );
+
+    let response = app
+        .client()
+        .post(app.url("/"))
+        .json(&json!({
+            "original_url": "https://example.com/old",
+            "expiration_date": "2020-01-01T00:00:00Z"
+        }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    receiver.wait_for(1);
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(receiver.events(), vec!["link.expired"]);
+}
+
+#[test]
+fn test_register_rejects_unknown_events() {
+    let app = spawn_app();
+    let response = app
+        .client()
+        .post(app.url("/api/webhooks"))
+        .json(&json!({ "target_url": "http://127.0.0.1:9/hook", "events": ["link.exploded"] }))
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[test]
+fn test_instances_sharing_a_database_send_each_delivery_once() {
+    let configure = |config: &mut rust_url_shortener::config::Config| {
+        config.webhook_poll_interval = Duration::from_millis(10);
+        config.webhook_retry_base = Duration::from_millis(50);
+    };
+    let first = common::spawn_app_with(configure);
+    let _second = common::spawn_app_sharing(&first, configure);
+    // While one instance waits on the slow receiver, the other finds the same
+    // deliveries due
+    let receiver = Receiver::start_slow(200, Duration::from_millis(50));
+    register(&first, json!({ "target_url": receiver.url }));
+
+    for index in 0..10 {
+        first.create_url(&format!("https://example.com/{}", index));
+    }
+    receiver.wait_for(10);
+    thread::sleep(Duration::from_millis(300));
+    let mut ids: Vec<String> = receiver
+        .requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|request| request.headers[&DELIVERY_HEADER.to_ascii_lowercase()].clone())
+        .collect();
+    assert_eq!(ids.len(), 10);
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 10);
+}
+
+#[test]
+fn test_concurrent_registrations_return_their_own_webhook() {
+    let app = spawn_app();
+    let responses: Vec<serde_json::Value> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..8)
+            .map(|index| {
+                let app = &app;
+                scope.spawn(move || {
+                    register(
+                        app,
+                        json!({
+                            "target_url": format!("http://127.0.0.1:9/hook/{}", index),
+                            "secret": format!("secret-{}", index)
+                        }),
+                    )
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    for response in &responses {
+        let index = response["secret"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches("secret-");
+        assert_eq!(
+            response["target_url"],
+            format!("http://127.0.0.1:9/hook/{}", index)
+        );
+    }
+}
@@ -0,0 +1,336 @@
+mod common;
+
+use reqwest::blocking::Client;
+use serde_json::json;
+
+const MEMBER_SCOPES: [&str; 3] = ["links:read", "links:write", "stats:read"];
+
+/// Creates a user and a key acting for them, returning the user id and a
+/// client sending that key.
+fn user_client(app: &common::TestApp, username: &str) -> (i64, Client) {
+    let user: serde_json::Value = app
+        .client()
+        .post(app.url("/api/users"))
+        .json(&json!({ "username": username }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    let user_id = user["id"].as_i64().unwrap();
+    let key: serde_json::Value = app
+        .client()
+        .post(app.url("/api/keys"))
+        .json(&json!({ "name": username, "scopes": MEMBER_SCOPES, "user_id": user_id }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    (user_id, app.client_with_key(key["key"].as_str().unwrap()))
+}
+
+fn create_workspace(
+    app: &common::TestApp,
+    client: &Client,
+    body: serde_json::Value,
+) -> serde_json::Value {
+    let response = client
+        .post(app.url("/api/workspaces"))
+        .json(&body)
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    response.json().unwrap()
+}
+
+fn set_role(app: &common::TestApp, client: &Client, workspace: i64, user: i64, role: &str) -> u16 {
+    client
+        .put(app.url(&format!("/api/workspaces/{}/members/{}", workspace, user)))
+        .json(&json!({ "role": role }))
+        .send()
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[test]
+fn test_roles_decide_what_members_may_do() {
+    let app = common::spawn_app();
+    let (_, owner) = user_client(&app, "owner");
+    let (editor_id, editor) = user_client(&app, "editor");
+    let (analyst_id, analyst) = user_client(&app, "analyst");
+    let (viewer_id, viewer) = user_client(&app, "viewer");
+    let (_, outsider) = user_client(&app, "outsider");
+
+    let workspace = create_workspace(&app, &owner, json!({ "name": "marketing" }));
+    assert_eq!(workspace["role"], "owner");
+    let id = workspace["id"].as_i64().unwrap();
+    assert_eq!(set_role(&app, &owner, id, editor_id, "editor"), 200);
+    assert_eq!(set_role(&app, &owner, id, analyst_id, "analyst"), 200);
+    assert_eq!(set_role(&app, &owner, id, viewer_id, "viewer"), 200);
+    assert_eq!(set_role(&app, &owner, id, viewer_id, "superuser"), 400);
+    assert_eq!(set_role(&app, &editor, id, viewer_id, "owner"), 403);
+
+    let create = |client: &Client| {
+        client
+            .post(app.url("/"))
+            .json(&json!({ "original_url": "https://example.com/team", "workspace_id": id }))
+            .send()
+            .unwrap()
+    };
+    let response = create(&editor);
+    assert_eq!(response.status(), 201);
+    let link: serde_json::Value = response.json().unwrap();
+    assert_eq!(link["workspace_id"], id);
+    let code = link["short_code"].as_str().unwrap().to_string();
+    assert_eq!(create(&viewer).status(), 403);
+    assert_eq!(create(&outsider).status(), 404);
+
+    let edit_url = app.url(&format!("/api/urls/{}", code));
+    let stats_url = app.url(&format!("/stats/{}", code));
+    let patch = json!({ "original_url": "https://example.com/team2" });
+    let edit = |client: &Client| {
+        client
+            .patch(&edit_url)
+            .json(&patch)
+            .send()
+            .unwrap()
+            .status()
+    };
+    let stats = |client: &Client| client.get(&stats_url).send().unwrap().status();
+
+    assert_eq!(edit(&owner), 200);
+    assert_eq!(edit(&editor), 200);
+    assert_eq!(edit(&analyst), 403);
+    assert_eq!(edit(&viewer), 403);
+    assert_eq!(edit(&outsider), 404);
+    assert_eq!(stats(&analyst), 200);
+    assert_eq!(stats(&viewer), 403);
+    assert_eq!(stats(&outsider), 404);
+
+    let listed = |client: &Client| {
+        let links: serde_json::Value = client.get(app.url("/")).send().unwrap().json().unwrap();
+        links.as_array().unwrap().len()
+    };
+    assert_eq!(listed(&viewer), 1);
+    assert_eq!(listed(&outsider), 0);
+
+    let workspaces: serde_json::Value = viewer
+        .get(app.url("/api/workspaces"))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(workspaces[0]["name"], "marketing");
+    assert_eq!(workspaces[0]["role"], "viewer");
+    let rename = editor
+        .patch(app.url(&format!("/api/workspaces/{}", id)))
+        .json(&json!({ "name": "sales" }))
+        .send()
+        .unwrap();
+    assert_eq!(rename.status(), 403);
+    assert_eq!(viewer.delete(&edit_url).send().unwrap().status(), 403);
+    assert_eq!(editor.delete(&edit_url).send().unwrap().status(), 204);
+}
+
+#[test]
+fn test_workspace_settings_apply_to_new_links() {
+    let app = common::spawn_app();
+    let (_, owner) = user_client(&app, "owner");
+    let workspace = create_workspace(
+        &app,
+        &owner,
+        json!({
+            "name": "docs",
+            "default_code_length": 12,
+            "default_expiry_secs": 86400,
+            "allowed_domains": ["Example.com"]
+        }),
+    );
+    assert_eq!(workspace["allowed_domains"], json!(["example.com"]));
+    let id = workspace["id"].as_i64().unwrap();
+
+    let create = |body: serde_json::Value| owner.post(app.url("/")).json(&body).send().unwrap();
+    let response =
+        create(json!({ "original_url": "https://docs.example.com/a", "workspace_id": id }));
+    assert_eq!(response.status(), 201);
+    let link: serde_json::Value = response.json().unwrap();
+    assert_eq!(link["short_code"].as_str().unwrap().len(), 12);
+    assert!(!link["expiration_date"].is_null());
+
+    let outside = create(json!({ "original_url": "https://other.org/a", "workspace_id": id }));
+    assert_eq!(outside.status(), 400);
+    let rule_outside = create(json!({
+        "original_url": "https://example.com/a",
+        "workspace_id": id,
+        "rules": [{ "destination": "https://other.org/de" }]
+    }));
+    assert_eq!(rule_outside.status(), 400);
+
+    // Links outside workspaces keep the server defaults
+    let personal = create(json!({ "original_url": "https://other.org/a" }));
+    let personal: serde_json::Value = personal.json().unwrap();
+    assert_eq!(personal["short_code"].as_str().unwrap().len(), 7);
+    assert!(personal["expiration_date"].is_null());
+
+    // Moving a link into the workspace checks its destinations too
+    let moved = owner
+        .patch(app.url(&format!(
+            "/api/urls/{}",
+            personal["short_code"].as_str().unwrap()
+        )))
+        .json(&json!({ "workspace_id": id }))
+        .send()
+        .unwrap();
+    assert_eq!(moved.status(), 400);
+
+    let invalid = owner
+        .patch(app.url(&format!("/api/workspaces/{}", id)))
+        .json(&json!({ "default_code_length": 2 }))
+        .send()
+        .unwrap();
+    assert_eq!(invalid.status(), 400);
+    let cleared: serde_json::Value = owner
+        .patch(app.url(&format!("/api/workspaces/{}", id)))
+        .json(&json!({ "allowed_domains": null, "default_expiry_secs": null }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(cleared["allowed_domains"], json!([]));
+    assert!(cleared["default_expiry_secs"].is_null());
+    assert_eq!(cleared["default_code_length"], 12);
+    let response = create(json!({ "original_url": "https://other.org/b", "workspace_id": id }));
+    assert_eq!(response.status(), 201);
+}
+
+#[test]
+fn test_membership_keeps_an_owner() {
+    let app = common::spawn_app();
+    let (owner_id, owner) = user_client(&app, "owner");
+    let (editor_id, editor) = user_client(&app, "editor");
+    let id = create_workspace(&app, &owner, json!({ "name": "ops" }))["id"]
+        .as_i64()
+        .unwrap();
+    let duplicate = owner
+        .post(app.url("/api/workspaces"))
+        .json(&json!({ "name": "ops" }))
+        .send()
+        .unwrap();
+    assert_eq!(duplicate.status(), 400);
+
+    assert_eq!(set_role(&app, &owner, id, owner_id, "editor"), 400);
+    assert_eq!(set_role(&app, &owner, id, 9999, "viewer"), 400);
+    assert_eq!(set_role(&app, &owner, id, editor_id, "editor"), 200);
+
+    let members: serde_json::Value = editor
+        .get(app.url(&format!("/api/workspaces/{}/members", id)))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
+    assert_eq!(members.as_array().unwrap().len(), 2);
+    assert_eq!(members[0]["username"], "editor");
+    assert_eq!(members[0]["role"], "editor");
+
+    let member_url = |user: i64| app.url(&format!("/api/workspaces/{}/members/{}", id, user));
+    assert_eq!(
+        editor.delete(member_url(owner_id)).send().unwrap().status(),
+        403
+    );
+    assert_eq!(
+        owner.delete(member_url(owner_id)).send().unwrap().status(),
+        400
+    );
+    // Members may leave on their own
+    assert_eq!(
+        editor
+            .delete(member_url(editor_id))
+            .send()
+            .unwrap()
+            .status(),
+        204
+    );
+    let gone = editor
+        .get(app.url(&format!("/api/workspaces/{}", id)))
+        .send()
+        .unwrap();
+    assert_eq!(gone.status(), 404);
+
+    // Deleting the workspace leaves its links with their creators
+    let code = owner
+        .post(app.url("/"))
+        .json(&json!({ "original_url": "https://example.com/ops", "workspace_id": id }))
+        .send()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .unwrap()["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let deleted = owner
+        .delete(app.url(&format!("/api/workspaces/{}", id)))
+        .send()
+        .unwrap();
+    assert_eq!(deleted.status(), 204);
+    let stats = owner
+        .get(app.url(&format!("/stats/{}", code)))
+        .send()
+        .unwrap();
+    assert_eq!(stats.status(), 200);
+}
+
+#[test]
+fn test_export_includes_clicks_on_workspace_links() {
+    let app = common::spawn_app();
+    let (_, owner) = user_client(&app, "owner");
+    let (analyst_id, analyst) = user_client(&app, "analyst");
+    let (viewer_id, viewer) = user_client(&app, "viewer");
+    let id = create_workspace(&app, &owner, json!({ "name": "growth" }))["id"]
+        .as_i64()
+        .unwrap();
+    assert_eq!(set_role(&app, &owner, id, analyst_id, "analyst"), 200);
+    assert_eq!(set_role(&app, &owner, id, viewer_id, "viewer"), 200);
+
+    let shared = owner
+        .post(app.url("/"))
+        .json(&json!({ "original_url": "https://example.com/shared", "workspace_id": id }))
+        .send()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .unwrap()["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let private = owner
+        .post(app.url("/"))
+        .json(&json!({ "original_url": "https://example.com/private" }))
+        .send()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .unwrap()["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    for code in [&shared, &private] {
+        let response = app
+            .anonymous_client()
+            .get(app.url(&format!("/{}", code)))
+            .send()
+            .unwrap();
+        assert_eq!(response.status(), 302);
+    }
+
+    let export = |client: &Client| {
+        client
+            .get(app.url("/api/export/clicks?format=ndjson"))
+            .send()
+            .unwrap()
+            .text()
+            .unwrap()
+    };
+    let analysts = export(&analyst);
+    assert_eq!(analysts.lines().count(), 1);
+    assert!(analysts.contains(&shared));
+    assert!(export(&viewer).is_empty());
+    assert_eq!(export(&owner).lines().count(), 2);
+}